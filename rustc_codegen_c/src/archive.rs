@@ -0,0 +1,221 @@
+//! Archive builder using the `ar` crate.
+//!
+//! `link_binary` uses this to bundle object files into rlibs and static
+//! libraries. Archives are written by the `ar` crate, which doesn't write a
+//! symbol table, so the target's archiver adds one afterwards if rustc asks
+//! for it (`ar s`, like `ranlib`).
+
+use metadata::METADATA_FILENAME;
+
+use ar::{Archive, Builder, GnuBuilder, Header};
+use rustc::session::search_paths::PathKind;
+use rustc::session::Session;
+use rustc_codegen_ssa::back::archive::{find_library, ArchiveBuilder};
+use rustc_codegen_ssa::{looks_like_rust_object_file, RLIB_BYTECODE_EXTENSION};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use syntax::symbol::Symbol;
+
+/// A member of an archive being built.
+struct Member {
+    /// The name of the member in the archive.
+    name: String,
+    data: Vec<u8>,
+}
+
+impl Member {
+    fn header(&self) -> Header {
+        Header::new(self.name.clone().into_bytes(), self.data.len() as u64)
+    }
+}
+
+/// Builds an archive in memory, and writes it out in `build`.
+///
+/// This is the counterpart of `LlvmArchiveBuilder`.
+pub struct ArArchiveBuilder<'a> {
+    sess: &'a Session,
+    dst: PathBuf,
+    /// The members taken from the archive the builder was created from.
+    src_members: Vec<String>,
+    members: Vec<Member>,
+    /// Whether the archive needs a symbol table.
+    update_symbols: bool,
+}
+
+impl<'a> ArArchiveBuilder<'a> {
+    /// Adds the members of the archive `archive` for which `skip` returns
+    /// `false`.
+    fn add_archive<F>(&mut self, archive: &Path, mut skip: F) -> io::Result<()>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        for member in read_archive(archive)? {
+            if !skip(&member.name) {
+                self.members.push(member);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the archiver, which rustc lets `-C ar` override.
+    fn archiver(&self) -> &str {
+        self.sess.opts.cg.ar.as_ref().map_or("ar", |ar| ar)
+    }
+
+    /// Writes the members to the destination, in the format of the target's
+    /// archiver.
+    fn write(&self) -> io::Result<()> {
+        let file = File::create(&self.dst)?;
+        if self.sess.target.target.options.archive_format == "bsd" {
+            let mut builder = Builder::new(file);
+            for member in &self.members {
+                builder.append(&member.header(), &*member.data)?;
+            }
+        } else {
+            let names = self
+                .members
+                .iter()
+                .map(|member| member.name.clone().into_bytes())
+                .collect();
+            let mut builder = GnuBuilder::new(file, names);
+            for member in &self.members {
+                builder.append(&member.header(), &*member.data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ArchiveBuilder<'a> for ArArchiveBuilder<'a> {
+    fn new(sess: &'a Session, output: &Path, input: Option<&Path>) -> Self {
+        let members = match input {
+            Some(input) => read_archive(input).unwrap_or_else(|e| {
+                sess.fatal(&format!(
+                    "failed to read archive {}: {}",
+                    input.display(),
+                    e
+                ))
+            }),
+            None => Vec::new(),
+        };
+        ArArchiveBuilder {
+            sess,
+            dst: output.to_path_buf(),
+            src_members: members.iter().map(|member| member.name.clone()).collect(),
+            members,
+            update_symbols: false,
+        }
+    }
+
+    fn add_file(&mut self, file: &Path) {
+        let name = file.file_name().unwrap().to_str().unwrap().to_string();
+        match fs::read(file) {
+            Ok(data) => self.members.push(Member { name, data }),
+            Err(e) => self.sess.fatal(&format!(
+                "failed to add {} to the archive: {}",
+                file.display(),
+                e
+            )),
+        }
+    }
+
+    fn remove_file(&mut self, name: &str) {
+        self.members.retain(|member| member.name != name);
+        self.src_members.retain(|member| member != name);
+    }
+
+    fn src_files(&mut self) -> Vec<String> {
+        self.src_members.clone()
+    }
+
+    fn add_rlib(
+        &mut self,
+        rlib: &Path,
+        name: &str,
+        lto: bool,
+        skip_objects: bool,
+    ) -> io::Result<()> {
+        // Keep the same members as the LLVM backend
+        let obj_start = name.to_owned();
+        self.add_archive(rlib, move |fname: &str| {
+            // Metadata and bytecode are only used by rustc
+            if fname.ends_with(RLIB_BYTECODE_EXTENSION) || fname == METADATA_FILENAME {
+                return true;
+            }
+            // LTO links the crate's bytecode instead of its objects
+            if lto && looks_like_rust_object_file(fname) {
+                return true;
+            }
+            // Only the objects of the crate itself can be skipped
+            skip_objects && (!fname.starts_with(&obj_start) || !fname.ends_with(".o"))
+        })
+    }
+
+    fn add_native_library(&mut self, name: Symbol) {
+        let search_paths: Vec<_> = self
+            .sess
+            .target_filesearch(PathKind::Native)
+            .search_paths()
+            .map(|search_path| search_path.dir.clone())
+            .collect();
+        let location = find_library(name, &search_paths, self.sess);
+        self.add_archive(&location, |_| false).unwrap_or_else(|e| {
+            self.sess.fatal(&format!(
+                "failed to add native library {}: {}",
+                location.display(),
+                e
+            ))
+        });
+    }
+
+    fn update_symbols(&mut self) {
+        self.update_symbols = true;
+    }
+
+    fn build(self) {
+        if let Err(e) = self.write() {
+            self.sess.fatal(&format!(
+                "failed to build archive {}: {}",
+                self.dst.display(),
+                e
+            ));
+        }
+
+        // An archive without a symbol table can't be linked
+        if self.update_symbols {
+            let mut cmd = Command::new(self.archiver());
+            cmd.arg("s").arg(&self.dst);
+            debug!("adding a symbol table: {:?}", cmd);
+            match cmd.output() {
+                Ok(ref output) if output.status.success() => {}
+                Ok(output) => self.sess.fatal(&format!(
+                    "failed to add a symbol table to {} ({}): {}",
+                    self.dst.display(),
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                )),
+                Err(e) => self.sess.fatal(&format!(
+                    "couldn't run the archiver `{}`: {}",
+                    self.archiver(),
+                    e
+                )),
+            }
+        }
+    }
+}
+
+/// Reads the members of the archive `path`.
+fn read_archive(path: &Path) -> io::Result<Vec<Member>> {
+    let mut archive = Archive::new(File::open(path)?);
+    let mut members = Vec::new();
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(entry.header().identifier()).into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        members.push(Member { name, data });
+    }
+    Ok(members)
+}
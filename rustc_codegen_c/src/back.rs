@@ -0,0 +1,370 @@
+//! Compiling the generated C code to object files.
+//!
+//! The C compiler takes the role of LLVM's target machine: codegen options
+//! that would configure LLVM (`-C opt-level`, `-C target-cpu`, ...) are
+//! translated to the equivalent GCC/Clang flags once per crate, and passed to
+//! every invocation of the C compiler. Compilers that don't accept GCC's
+//! flags only get told the optimization level and whether to emit debuginfo.
+//!
+//! Instead of LLVM passes, `-C passes` names the C compiler's optimizations:
+//! every pass `<name>` is passed as `-f<name>` (so `no-<name>` disables one),
+//! as long as the compiler lists it among its optimizations.
+
+use builder::dialect::Dialect;
+use codegen::Module;
+use compiler::{self, Compiler, CompilerFamily};
+use print;
+use target_features;
+use CCodegenBackend;
+
+use rustc::session::config::{DebugInfo, OptLevel, OutputType};
+use rustc::session::Session;
+use rustc_codegen_ssa::back::write::CodegenContext;
+use rustc_codegen_ssa::{CompiledModule, ModuleCodegen};
+use rustc_errors::{FatalError, Handler};
+use std::fs;
+
+/// The C compiler configuration shared by all modules of a crate.
+#[derive(Clone, Debug)]
+pub struct CcConfig {
+    /// The C compiler to invoke.
+    pub compiler: Compiler,
+    /// The C dialect the modules are written in.
+    pub dialect: Dialect,
+    /// Flags passed to the C compiler when compiling a module.
+    pub flags: Vec<String>,
+}
+
+/// The relocation models that can be translated to GCC flags, with the flag
+/// selecting them (if the compiler's default doesn't do).
+pub const RELOCATION_MODELS: &[(&str, Option<&str>)] = &[
+    ("default", None),
+    ("pic", Some("-fPIC")),
+    ("static", Some("-fno-pic")),
+    // Only supported for Mach-O
+    ("dynamic-no-pic", Some("-mdynamic-no-pic")),
+];
+
+/// The code models that can be selected with GCC's `-mcmodel`, which uses
+/// the same names as LLVM. Each architecture only supports some of them.
+pub const CODE_MODELS: &[&str] = &["tiny", "small", "kernel", "medium", "large"];
+
+/// Returns the GCC flag selecting the CPU to compile for on `arch`.
+pub fn target_cpu_flag(arch: &str) -> &'static str {
+    // x86 selects the CPU with `-march`, most other targets with `-mcpu`
+    match arch {
+        "x86" | "x86_64" => "-march",
+        _ => "-mcpu",
+    }
+}
+
+/// Translates the codegen options of `sess` and the optimization level
+/// `opt_level` to flags for the C compiler `compiler`.
+pub fn cc_config(
+    sess: &Session,
+    compiler: &Compiler,
+    opt_level: OptLevel,
+) -> Result<CcConfig, String> {
+    let flags = if compiler.family.is_like_gnu() {
+        gnu_flags(sess, opt_level, compiler)?
+    } else {
+        generic_flags(sess, opt_level, compiler)?
+    };
+    Ok(CcConfig {
+        dialect: compiler.dialect,
+        compiler: compiler.clone(),
+        flags,
+    })
+}
+
+/// Returns the flags for a compiler that accepts GCC's flags.
+fn gnu_flags(
+    sess: &Session,
+    opt_level: OptLevel,
+    compiler: &Compiler,
+) -> Result<Vec<String>, String> {
+    let mut flags = gnu_target_flags(sess, compiler.dialect)?;
+
+    // Rust has no type-based alias analysis, and the generated code accesses
+    // memory through pointers of unrelated types
+    flags.push("-fno-strict-aliasing".to_string());
+
+    flags.push(
+        match opt_level {
+            OptLevel::No => "-O0",
+            OptLevel::Less => "-O1",
+            OptLevel::Default => "-O2",
+            OptLevel::Aggressive => "-O3",
+            OptLevel::Size => "-Os",
+            OptLevel::SizeMin if supports_oz(compiler) => "-Oz",
+            OptLevel::SizeMin => "-Os",
+        }
+        .to_string(),
+    );
+
+    flags.extend(pass_flags(sess, compiler)?);
+
+    match sess.opts.debuginfo {
+        DebugInfo::None => {}
+        DebugInfo::Limited => flags.push("-g1".to_string()),
+        DebugInfo::Full => flags.push("-g".to_string()),
+    }
+
+    Ok(flags)
+}
+
+/// Returns whether `compiler` accepts `-Oz`, which Clang always did, and GCC
+/// added in version 12.
+fn supports_oz(compiler: &Compiler) -> bool {
+    match compiler.family {
+        CompilerFamily::Clang => true,
+        CompilerFamily::Gcc => compiler.version.as_ref().map_or(false, |version| {
+            version.family == Some(CompilerFamily::Gcc)
+                && version.version.map_or(false, |(major, _, _)| major >= 12)
+        }),
+        CompilerFamily::Tcc | CompilerFamily::C99 => false,
+    }
+}
+
+/// Returns the flags selecting the dialect and target of a compiler that
+/// accepts GCC's flags.
+///
+/// These affect which extensions the compiler supports, so the compiler is
+/// probed with them as well (see `probe`).
+pub fn gnu_target_flags(sess: &Session, dialect: Dialect) -> Result<Vec<String>, String> {
+    let target = &sess.target.target;
+    let arch = &*target.arch;
+
+    let mut flags = vec![format!("-std={}", dialect.name())];
+    if compiler::is_freestanding(sess)? {
+        flags.push("-ffreestanding".to_string());
+    }
+
+    if let Some(ref cpu) = sess.opts.cg.target_cpu {
+        flags.push(format!("{}={}", target_cpu_flag(arch), cpu));
+    }
+
+    for feature in sess.opts.cg.target_feature.split(',') {
+        flags.extend(target_feature_flag(feature, arch));
+    }
+
+    let relocation_model = sess
+        .opts
+        .cg
+        .relocation_model
+        .as_ref()
+        .unwrap_or(&target.options.relocation_model);
+    match RELOCATION_MODELS
+        .iter()
+        .find(|&&(model, _)| model == &**relocation_model)
+    {
+        Some(&(_, flag)) => flags.extend(flag.map(String::from)),
+        None => {
+            return Err(format!(
+                "the C codegen backend doesn't support the `{}` relocation model",
+                relocation_model
+            ))
+        }
+    }
+
+    let code_model = sess
+        .opts
+        .cg
+        .code_model
+        .as_ref()
+        .or_else(|| target.options.code_model.as_ref());
+    match code_model.map(|model| &**model) {
+        None | Some("default") => {}
+        Some(model) if CODE_MODELS.contains(&model) => flags.push(format!("-mcmodel={}", model)),
+        Some(model) => {
+            return Err(format!(
+                "the C codegen backend doesn't support the `{}` code model",
+                model
+            ))
+        }
+    }
+
+    Ok(flags)
+}
+
+/// Returns the flags for a compiler that only accepts the flags POSIX
+/// specifies for `c99`.
+///
+/// Codegen options that can't be passed to such a compiler are rejected,
+/// unless they were left at the target's default.
+fn generic_flags(
+    sess: &Session,
+    opt_level: OptLevel,
+    compiler: &Compiler,
+) -> Result<Vec<String>, String> {
+    let cg = &sess.opts.cg;
+    let unsupported = [
+        ("target-cpu", cg.target_cpu.is_some()),
+        ("target-feature", !cg.target_feature.is_empty()),
+        ("relocation-model", cg.relocation_model.is_some()),
+        ("code-model", cg.code_model.is_some()),
+        ("passes", !cg.passes.is_empty()),
+    ];
+    if let Some(&(option, _)) = unsupported.iter().find(|&&(_, set)| set) {
+        return Err(format!(
+            "`-C {}` can't be passed to C compilers of the `{}` family",
+            option,
+            compiler.family.name()
+        ));
+    }
+
+    let mut flags = Vec::new();
+    if opt_level != OptLevel::No {
+        flags.push("-O".to_string());
+    }
+    if sess.opts.debuginfo != DebugInfo::None {
+        flags.push("-g".to_string());
+    }
+    Ok(flags)
+}
+
+/// Splits an entry of `-C target-feature` (`+feature` or `-feature`) into
+/// whether it enables the feature, and the feature's name.
+fn parse_target_feature(feature: &str) -> (bool, &str) {
+    match feature.chars().next() {
+        Some('+') => (true, &feature[1..]),
+        Some('-') => (false, &feature[1..]),
+        _ => (true, feature),
+    }
+}
+
+/// Translates an entry of `-C target-feature` to a C compiler flag.
+///
+/// Returns `None` for features that need no flag, and for unknown features,
+/// which are ignored (like LLVM does) after `check_target_features` warned
+/// about them.
+fn target_feature_flag(feature: &str, arch: &str) -> Option<String> {
+    let (enable, name) = parse_target_feature(feature);
+    let feature = target_features::find(arch, name)?;
+    if enable {
+        Some(format!("-m{}", feature.gcc_name()))
+    } else {
+        Some(format!("-mno-{}", feature.gcc_name()))
+    }
+}
+
+/// Warns about the entries of `-C target-feature` that are ignored, since
+/// the backend doesn't know them.
+pub fn check_target_features(sess: &Session) {
+    let arch = &*sess.target.target.arch;
+    for feature in sess.opts.cg.target_feature.split(',') {
+        let (_, name) = parse_target_feature(feature);
+        // `crt-static` is handled by rustc when linking
+        if !name.is_empty() && name != "crt-static" && target_features::find(arch, name).is_none() {
+            sess.warn(&format!(
+                "unknown target feature `{}` for {} (ignoring it)",
+                name, arch
+            ));
+        }
+    }
+}
+
+/// Translates the passes of `-C passes` to flags, checking that `compiler`
+/// has them.
+fn pass_flags(sess: &Session, compiler: &Compiler) -> Result<Vec<String>, String> {
+    if sess.opts.cg.passes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let known = print::optimization_passes(compiler)?;
+    sess.opts
+        .cg
+        .passes
+        .iter()
+        .map(|pass| {
+            let name = pass.trim_start_matches("no-");
+            let name = name.split('=').next().unwrap();
+            if known.iter().any(|(known, _)| known == name) {
+                Ok(format!("-f{}", pass))
+            } else {
+                Err(format!(
+                    "the C compiler has no optimization pass `{}`",
+                    name
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Compiles the C source of `module` to an object file.
+///
+/// `rustc_codegen_ssa` calls this on one of its worker threads, each of
+/// which runs on a jobserver token its coordinator acquired for it (or on
+/// rustc's implicit token). Modules are thus compiled in parallel, and the C
+/// compiler compiling a module counts as one job. The token is held until
+/// this returns, so the C compiler must not acquire another one: with no
+/// tokens to spare (eg. `cargo build -j1`), that would deadlock.
+pub(crate) fn compile_module(
+    cgcx: &CodegenContext<CCodegenBackend>,
+    diag_handler: &Handler,
+    module: ModuleCodegen<Module>,
+) -> Result<CompiledModule, FatalError> {
+    let config = (cgcx.tm_factory.0)().map_err(|e| diag_handler.fatal(&e))?;
+    let src = cgcx.output_filenames.temp_path_ext("c", Some(&module.name));
+    let obj = cgcx
+        .output_filenames
+        .temp_path(OutputType::Object, Some(&module.name));
+
+    fs::write(&src, &module.module_llvm.source)
+        .map_err(|e| diag_handler.fatal(&format!("couldn't write {}: {}", src.display(), e)))?;
+
+    let mut cmd = config
+        .compiler
+        .command()
+        .map_err(|e| diag_handler.fatal(&e))?;
+    cmd.args(&config.flags)
+        .arg("-c")
+        .arg(&src)
+        .arg("-o")
+        .arg(&obj);
+    debug!("compiling module {}: {:?}", module.name, cmd);
+
+    let output = cmd
+        .output()
+        .map_err(|e| diag_handler.fatal(&format!("couldn't run the C compiler: {}", e)))?;
+    if !output.status.success() {
+        diag_handler
+            .struct_err(&format!(
+                "compiling module {} with the C compiler failed ({})",
+                module.name, output.status
+            ))
+            .note(&String::from_utf8_lossy(&output.stderr))
+            .emit();
+        return Err(FatalError);
+    }
+
+    Ok(CompiledModule {
+        name: module.name.clone(),
+        kind: module.kind,
+        object: Some(obj),
+        bytecode: None,
+        bytecode_compressed: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_feature_flags() {
+        assert_eq!(
+            target_feature_flag("+avx2", "x86_64"),
+            Some("-mavx2".into())
+        );
+        assert_eq!(target_feature_flag("bmi1", "x86_64"), Some("-mbmi".into()));
+        assert_eq!(
+            target_feature_flag("-sse3", "x86"),
+            Some("-mno-sse3".into())
+        );
+        // Unknown features are ignored
+        assert_eq!(target_feature_flag("+neon", "x86_64"), None);
+        assert_eq!(target_feature_flag("+avx2", "aarch64"), None);
+        assert_eq!(target_feature_flag("+crt-static", "x86_64"), None);
+        assert_eq!(target_feature_flag("", "x86_64"), None);
+    }
+}
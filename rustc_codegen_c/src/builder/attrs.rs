@@ -0,0 +1,227 @@
+//! Attributes attached to C function and global declarations.
+
+use super::dialect::Dialect;
+use utils::WriteStr;
+
+use std::io;
+use syntax::symbol::Symbol;
+
+/// Symbol visibility of a function or global, for ELF and Mach-O targets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Visibility {
+    Default,
+    Hidden,
+    Protected,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Default
+    }
+}
+
+/// How a function should be inlined.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InlineHint {
+    /// Leave it to the C compiler.
+    None,
+    /// Inlining is encouraged (`#[inline]`).
+    Hint,
+    /// The function is always inlined (`#[inline(always)]`).
+    Always,
+    /// The function is never inlined (`#[inline(never)]`).
+    Never,
+}
+
+impl Default for InlineHint {
+    fn default() -> Self {
+        InlineHint::None
+    }
+}
+
+/// Writes `__attribute__((..))` with the comma-separated `attrs`, followed by
+/// a space, unless `attrs` is empty.
+fn write_attributes<W: WriteStr>(attrs: &[String], w: &mut W) -> io::Result<()> {
+    if !attrs.is_empty() {
+        write!(w, "__attribute__(({})) ", attrs.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Returns the GCC attributes shared by functions and globals.
+fn linkage_attributes(weak: bool, visibility: Visibility, section: Option<Symbol>) -> Vec<String> {
+    let mut attrs = Vec::new();
+    if weak {
+        attrs.push("weak".to_string());
+    }
+    match visibility {
+        Visibility::Default => {}
+        Visibility::Hidden => attrs.push("visibility(\"hidden\")".to_string()),
+        Visibility::Protected => attrs.push("visibility(\"protected\")".to_string()),
+    }
+    if let Some(section) = section {
+        attrs.push(format!("section(\"{}\")", section));
+    }
+    attrs
+}
+
+/// Attributes of a declared or defined function.
+///
+/// These are emitted as a prefix of the function declaration. Since C
+/// requires the linkage of all declarations of a function to agree, the
+/// same attributes must be used to declare and to define it.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FnAttrs {
+    /// The function never returns to its caller.
+    pub noreturn: bool,
+    /// The function is private to the translation unit (`static`).
+    pub internal: bool,
+    /// The definition may be replaced by another one at link time, and
+    /// several TUs may define the function.
+    pub weak: bool,
+    pub visibility: Visibility,
+    /// The object file section to place the function in.
+    pub section: Option<Symbol>,
+    /// The function is unlikely to be called.
+    pub cold: bool,
+    pub inline: InlineHint,
+    /// The function has no prologue or epilogue (its body is assembly).
+    pub naked: bool,
+    /// Additional target features enabled for the function, comma-separated
+    /// and named as by GCC's `target` attribute.
+    pub target_features: Option<Symbol>,
+}
+
+impl FnAttrs {
+    /// Writes the attributes in front of a function declaration.
+    ///
+    /// The output ends with a space if any attribute was written, so the
+    /// declaration can follow immediately. Inline hints are dropped if
+    /// `dialect` has no `inline`.
+    pub fn write_prefix<W: WriteStr>(&self, dialect: Dialect, w: &mut W) -> io::Result<()> {
+        let mut attrs = linkage_attributes(self.weak, self.visibility, self.section);
+        if self.noreturn {
+            attrs.push("noreturn".to_string());
+        }
+        if self.cold {
+            attrs.push("cold".to_string());
+        }
+        match self.inline {
+            InlineHint::None | InlineHint::Hint => {}
+            InlineHint::Always => attrs.push("always_inline".to_string()),
+            InlineHint::Never => attrs.push("noinline".to_string()),
+        }
+        if self.naked {
+            attrs.push("naked".to_string());
+        }
+        if let Some(features) = self.target_features {
+            attrs.push(format!("target(\"{}\")", features));
+        }
+        write_attributes(&attrs, w)?;
+
+        if self.internal {
+            write!(w, "static ")?;
+            // C's `inline` only has the desired semantics on internal
+            // functions: an external `inline` definition doesn't emit a
+            // symbol
+            if let (InlineHint::Hint, Some(keyword)) | (InlineHint::Always, Some(keyword)) =
+                (self.inline, dialect.inline_keyword())
+            {
+                write!(w, "{} ", keyword)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A thread-local storage model, selected with GCC's `tls_model` attribute.
+///
+/// These are the same models rustc accepts for `-Z tls-model`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TlsModel {
+    GlobalDynamic,
+    LocalDynamic,
+    InitialExec,
+    LocalExec,
+}
+
+impl TlsModel {
+    pub const ALL: [TlsModel; 4] = [
+        TlsModel::GlobalDynamic,
+        TlsModel::LocalDynamic,
+        TlsModel::InitialExec,
+        TlsModel::LocalExec,
+    ];
+
+    /// Returns the name of the model, as used by both rustc and GCC.
+    pub fn name(self) -> &'static str {
+        match self {
+            TlsModel::GlobalDynamic => "global-dynamic",
+            TlsModel::LocalDynamic => "local-dynamic",
+            TlsModel::InitialExec => "initial-exec",
+            TlsModel::LocalExec => "local-exec",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|model| model.name() == name)
+    }
+}
+
+/// Attributes of a declared or defined global variable.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct GlobalAttrs {
+    /// The global is private to the translation unit (`static`).
+    pub internal: bool,
+    /// The global is never written to (`const`).
+    pub constant: bool,
+    /// Every thread has its own instance of the global.
+    pub thread_local: bool,
+    /// The TLS model of a thread-local global, or `None` to let the C
+    /// compiler pick one.
+    pub tls_model: Option<TlsModel>,
+    /// The definition may be replaced by another one at link time. Weak
+    /// declarations may also be left undefined, which makes their address
+    /// null.
+    pub weak: bool,
+    pub visibility: Visibility,
+    /// The object file section to place the global in.
+    pub section: Option<Symbol>,
+}
+
+impl GlobalAttrs {
+    /// Writes the attributes, storage class and qualifiers in front of a
+    /// global's declaration (`extern` if `external`).
+    ///
+    /// The thread-local specifier is spelled as required by `dialect`, which
+    /// must support thread-local storage if the global is thread-local.
+    pub fn write_prefix<W: WriteStr>(
+        &self,
+        dialect: Dialect,
+        external: bool,
+        w: &mut W,
+    ) -> io::Result<()> {
+        let mut attrs = linkage_attributes(self.weak, self.visibility, self.section);
+        if let (true, Some(model)) = (self.thread_local, self.tls_model) {
+            attrs.push(format!("tls_model(\"{}\")", model.name()));
+        }
+        write_attributes(&attrs, w)?;
+
+        if external {
+            write!(w, "extern ")?;
+        } else if self.internal {
+            write!(w, "static ")?;
+        }
+        // GCC requires `__thread` to follow `static` or `extern` directly
+        if self.thread_local {
+            let keyword = dialect
+                .thread_local_keyword()
+                .expect("thread-local global in a dialect without thread-local storage");
+            write!(w, "{} ", keyword)?;
+        }
+        if self.constant {
+            write!(w, "const ")?;
+        }
+        Ok(())
+    }
+}
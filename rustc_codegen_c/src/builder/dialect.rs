@@ -0,0 +1,130 @@
+//! The C language dialect the generated code is written in, and the kind of
+//! C implementation it is compiled by.
+//!
+//! C89 dialects get fallbacks for everything C99 and C11 added that the
+//! generated code relies on: the fixed-width integer types and `bool` are
+//! defined by the preamble, declarations are hoisted to the start of the
+//! function body, and static assertions are emulated with array typedefs.
+//! All dialects assume that `long long` is available, which C89 compilers
+//! provide as an extension.
+
+/// A C standard, possibly with compiler extensions, that the generated code
+/// may rely on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dialect {
+    /// ISO C89 (aka C90).
+    C89,
+    /// C89 with GNU extensions (`-std=gnu89`).
+    Gnu89,
+    /// ISO C99.
+    C99,
+    /// C99 with GNU extensions (`-std=gnu99`).
+    Gnu99,
+    /// ISO C11.
+    C11,
+    /// C11 with GNU extensions (`-std=gnu11`).
+    Gnu11,
+}
+
+impl Default for Dialect {
+    /// C11 is needed for `_Static_assert`, which the generated code uses to
+    /// check the data layout.
+    fn default() -> Self {
+        Dialect::C11
+    }
+}
+
+impl Dialect {
+    pub const ALL: [Dialect; 6] = [
+        Dialect::C89,
+        Dialect::Gnu89,
+        Dialect::C99,
+        Dialect::Gnu99,
+        Dialect::C11,
+        Dialect::Gnu11,
+    ];
+
+    /// Returns the name of the dialect, as passed to GCC's `-std`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::C89 => "c89",
+            Dialect::Gnu89 => "gnu89",
+            Dialect::C99 => "c99",
+            Dialect::Gnu99 => "gnu99",
+            Dialect::C11 => "c11",
+            Dialect::Gnu11 => "gnu11",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|dialect| dialect.name() == name)
+    }
+
+    /// Returns whether the dialect includes C99's features (`<stdint.h>`,
+    /// `<stdbool.h>` and declarations after statements).
+    pub fn is_c99(self) -> bool {
+        match self {
+            Dialect::C89 | Dialect::Gnu89 => false,
+            _ => true,
+        }
+    }
+
+    /// Returns whether the dialect supports C11's `_Static_assert`.
+    pub fn has_static_assert(self) -> bool {
+        match self {
+            Dialect::C11 | Dialect::Gnu11 => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the function specifier declaring inline functions, if the
+    /// dialect has one.
+    pub fn inline_keyword(self) -> Option<&'static str> {
+        match self {
+            Dialect::C89 => None,
+            // GCC's spelling, which doesn't conflict with user identifiers
+            Dialect::Gnu89 => Some("__inline__"),
+            _ => Some("inline"),
+        }
+    }
+
+    /// Returns the storage class specifier declaring thread-local variables,
+    /// if the dialect supports them.
+    pub fn thread_local_keyword(self) -> Option<&'static str> {
+        match self {
+            Dialect::C89 | Dialect::C99 => None,
+            Dialect::Gnu89 | Dialect::Gnu99 => Some("__thread"),
+            Dialect::C11 | Dialect::Gnu11 => Some("_Thread_local"),
+        }
+    }
+}
+
+/// The kind of C implementation the generated code is compiled by.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Environment {
+    /// A hosted implementation, which provides the whole C standard library.
+    Hosted,
+    /// A freestanding implementation (eg. for bare-metal targets), which
+    /// only provides the headers that don't declare any functions.
+    Freestanding {
+        /// Whether the TU has to define `memcpy` itself, since the crate
+        /// doesn't provide it.
+        memcpy_fallback: bool,
+        /// Whether the TU has to define `memmove` itself.
+        memmove_fallback: bool,
+        /// Whether the TU has to define `memset` itself.
+        memset_fallback: bool,
+        /// Whether the definitions can be weak (with GCC's `weak`
+        /// attribute), rather than `static`.
+        weak_fallbacks: bool,
+    },
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::Hosted
+    }
+}
@@ -0,0 +1,172 @@
+//! C expressions.
+//!
+//! Expressions are built up as a tree and written out when they're used in a
+//! statement or initializer. Compound expressions are always parenthesized, so
+//! there's no need to worry about C's operator precedence.
+
+use super::function::Variable;
+use super::types::{PtrMeta, TypeRef, FAT_PTR_DATA};
+use super::{Function, Global};
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// A C expression.
+#[derive(Debug, Clone)]
+pub enum Expr<'a> {
+    /// A variable, function or global, referred to by name.
+    Ident(Cow<'a, str>),
+    /// An integer literal.
+    ///
+    /// Values that don't fit in 64 bits use `unsigned __int128`, so codegen
+    /// creates those through `CodegenCx::uint_literal`, which checks that the
+    /// C compiler supports it.
+    Uint(u128),
+    /// A finite `float` literal.
+    Float(f32),
+    /// A finite `double` literal.
+    Double(f64),
+    /// `&expr`
+    AddrOf(Box<Expr<'a>>),
+    /// `*expr`
+    Deref(Box<Expr<'a>>),
+    /// `expr.field`
+    Field(Box<Expr<'a>>, Cow<'a, str>),
+    /// `expr[index]`
+    Index(Box<Expr<'a>>, Box<Expr<'a>>),
+    /// `(type) expr`
+    Cast(TypeRef<'a>, Box<Expr<'a>>),
+    /// `callee(args...)`
+    Call(Box<Expr<'a>>, Vec<Expr<'a>>),
+    /// `op expr`, where `op` is a unary C operator.
+    Unary(&'static str, Box<Expr<'a>>),
+    /// `lhs op rhs`, where `op` is a binary C operator.
+    Binary(&'static str, Box<Expr<'a>>, Box<Expr<'a>>),
+    /// `cond ? then : otherwise`
+    Cond(Box<Expr<'a>>, Box<Expr<'a>>, Box<Expr<'a>>),
+    /// A braced initializer list `{ a, b, ... }`.
+    ///
+    /// This is only valid as the initializer of a variable or global.
+    InitList(Vec<Expr<'a>>),
+}
+
+impl<'a> Expr<'a> {
+    pub fn addr_of(self) -> Self {
+        Expr::AddrOf(Box::new(self))
+    }
+
+    pub fn deref(self) -> Self {
+        Expr::Deref(Box::new(self))
+    }
+
+    pub fn field(self, field: impl Into<Cow<'a, str>>) -> Self {
+        Expr::Field(Box::new(self), field.into())
+    }
+
+    pub fn index(self, index: Expr<'a>) -> Self {
+        Expr::Index(Box::new(self), Box::new(index))
+    }
+
+    pub fn cast(self, ty: TypeRef<'a>) -> Self {
+        Expr::Cast(ty, Box::new(self))
+    }
+
+    pub fn call(self, args: Vec<Expr<'a>>) -> Self {
+        Expr::Call(Box::new(self), args)
+    }
+
+    pub fn unary(self, op: &'static str) -> Self {
+        Expr::Unary(op, Box::new(self))
+    }
+
+    pub fn binary(self, op: &'static str, rhs: Expr<'a>) -> Self {
+        Expr::Binary(op, Box::new(self), Box::new(rhs))
+    }
+
+    pub fn cond(self, then: Expr<'a>, otherwise: Expr<'a>) -> Self {
+        Expr::Cond(Box::new(self), Box::new(then), Box::new(otherwise))
+    }
+
+    /// Accesses the (untyped) data pointer of a fat pointer.
+    pub fn fat_data(self) -> Self {
+        self.field(FAT_PTR_DATA)
+    }
+
+    /// Accesses the metadata of a fat pointer.
+    pub fn fat_meta(self, meta: PtrMeta) -> Self {
+        self.field(meta.field())
+    }
+
+    /// Accesses the vtable of a fat pointer to a trait object.
+    pub fn fat_vtable(self) -> Self {
+        self.fat_meta(PtrMeta::Vtable)
+    }
+}
+
+impl<'a> From<Variable<'a>> for Expr<'a> {
+    fn from(var: Variable<'a>) -> Self {
+        Expr::Ident(var.name().into())
+    }
+}
+
+impl<'a> From<Function<'a>> for Expr<'a> {
+    fn from(f: Function<'a>) -> Self {
+        Expr::Ident(f.name().into())
+    }
+}
+
+impl<'a> From<Global<'a>> for Expr<'a> {
+    fn from(global: Global<'a>) -> Self {
+        Expr::Ident(global.name().into())
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, exprs: &[Expr<'_>]) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i != 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", expr)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Expr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Ident(name) => f.write_str(name),
+            // Use the smallest suffix that makes the literal fit
+            Expr::Uint(val) if *val <= u128::from(u32::max_value()) => write!(f, "{}u", val),
+            Expr::Uint(val) if *val <= u128::from(u64::max_value()) => write!(f, "{}ull", val),
+            // C has no 128-bit literals, so they are built from two halves
+            Expr::Uint(val) => write!(
+                f,
+                "((((unsigned __int128) {}ull) << 64) | {}ull)",
+                val >> 64,
+                *val as u64
+            ),
+            // `Debug` prints the shortest representation that round-trips,
+            // which is also valid C (`1.0`, `1e300`)
+            Expr::Float(val) => write!(f, "({:?}f)", val),
+            Expr::Double(val) => write!(f, "({:?})", val),
+            Expr::AddrOf(expr) => write!(f, "(&{})", expr),
+            Expr::Deref(expr) => write!(f, "(*{})", expr),
+            Expr::Field(expr, field) => write!(f, "{}.{}", expr, field),
+            Expr::Index(expr, index) => write!(f, "{}[{}]", expr, index),
+            Expr::Cast(ty, expr) => write!(f, "(({}) {})", ty.name(), expr),
+            Expr::Call(callee, args) => {
+                write!(f, "{}(", callee)?;
+                write_list(f, args)?;
+                f.write_str(")")
+            }
+            Expr::Unary(op, expr) => write!(f, "({}{})", op, expr),
+            Expr::Binary(op, lhs, rhs) => write!(f, "({} {} {})", lhs, op, rhs),
+            Expr::Cond(cond, then, otherwise) => write!(f, "({} ? {} : {})", cond, then, otherwise),
+            Expr::InitList(exprs) => {
+                f.write_str("{ ")?;
+                write_list(f, exprs)?;
+                f.write_str(" }")
+            }
+        }
+    }
+}
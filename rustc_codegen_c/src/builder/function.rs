@@ -1,13 +1,17 @@
 //! Function builder.
 
+use super::attrs::FnAttrs;
+use super::dialect::Dialect;
+use super::expr::Expr;
 use super::types::{FnSig, TypeRef};
-use super::Name;
-use utils::WriteStr;
+use super::{escape_comment, Name};
+use utils::{StringWriter, WriteStr};
 
 use hashbrown::HashSet;
 use toolshed::Arena;
 
 use std::io;
+use std::thread;
 
 /// A local variable.
 ///
@@ -21,14 +25,75 @@ pub struct Variable<'a> {
     ty: TypeRef<'a>,
 }
 
+impl<'a> Variable<'a> {
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+}
+
+/// An operand of an inline assembly statement.
+#[derive(Debug, Clone)]
+pub struct AsmOperand<'e> {
+    /// The GCC operand constraint, eg. `"=r"`.
+    pub constraint: String,
+    /// The value of an input operand, or the lvalue written by an output
+    /// operand.
+    pub expr: Expr<'e>,
+}
+
+/// Writes `s` as a C string literal.
+pub(super) fn write_c_string<W: WriteStr>(s: &str, w: &mut W) -> io::Result<()> {
+    write!(w, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            '\t' => write!(w, "\\t")?,
+            // Avoids trigraphs
+            '?' => write!(w, "\\?")?,
+            c if c.is_ascii_graphic() || c == ' ' => write!(w, "{}", c)?,
+            // Octal escapes can't be continued by the following character
+            c => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    write!(w, "\\{:03o}", byte)?;
+                }
+            }
+        }
+    }
+    write!(w, "\"")
+}
+
+/// Returns `name`, or `name` with the first numeric suffix that makes it
+/// unique among the `declared` names.
+fn unique_name(declared: &HashSet<String>, name: String) -> String {
+    if !declared.contains(&name) {
+        return name;
+    }
+    (1..)
+        .map(|i| format!("{}_{}", name, i))
+        .find(|candidate| !declared.contains(candidate))
+        .unwrap()
+}
+
 /// Builder for function bodies.
 ///
 /// Created by `TranslationUnitBuilder::define_function`.
+///
+/// The body is buffered until `finish` is called, so that C89 dialects can
+/// get all local variable declarations at the start of the body.
 pub struct FunctionBuilder<'a, W: WriteStr> {
-    /// Output statements are written to this writer.
+    /// The function is written to this writer.
     writer: &'a mut W,
+    /// Local variable declarations, if they have to precede all statements.
+    decls: Option<StringWriter>,
+    /// Output statements are written to this buffer.
+    body: StringWriter,
     arena: &'a Arena,
     declared_locals: HashSet<String>,
+    /// Nesting depth of the statements being written (for indentation).
+    depth: usize,
     finished: bool,
 
     /// Function arguments, available as declared `Variable`s.
@@ -38,38 +103,70 @@ pub struct FunctionBuilder<'a, W: WriteStr> {
 impl<'a, W: WriteStr> FunctionBuilder<'a, W> {
     /// Creates a function builder, writing the function's header to `writer`.
     ///
+    /// The arguments are named after `arg_names`, or `_1`, `_2`, ... if a
+    /// name is `None` or missing. Names that are already taken get a numeric
+    /// suffix, like in `declare_variable`.
+    ///
     /// **Note**: You *must* call `finish` on the builder to close the function
     /// being built, or this will panic on drop.
     pub fn create(
         writer: &'a mut W,
         arena: &'a Arena,
+        dialect: Dialect,
         name: Name<'_>,
         proto: FnSig<'a>,
+        attrs: FnAttrs,
+        arg_names: &[Option<String>],
     ) -> io::Result<Self> {
-        proto.declare(&*name.mangled(), writer)?;
+        let mut declared_locals = HashSet::new();
+        let arg_names = (0..proto.args.len())
+            .map(|i| {
+                let name = match arg_names.get(i) {
+                    Some(Some(name)) => name.clone(),
+                    _ => format!("_{}", i + 1),
+                };
+                let name = unique_name(&declared_locals, name);
+                declared_locals.insert(name.clone());
+                name
+            })
+            .collect::<Vec<_>>();
+
+        attrs.write_prefix(dialect, writer)?;
+        proto.declare_with_arg_names(&*name.mangled(), &arg_names, writer)?;
         writeln!(writer)?;
         writeln!(writer, "{{")?;
+
+        let args = proto
+            .args
+            .iter()
+            .zip(&arg_names)
+            .map(|(&ty, name)| Variable {
+                name: arena.alloc_str(name),
+                ty,
+            })
+            .collect::<Vec<_>>();
         Ok(Self {
             writer,
+            decls: if dialect.is_c99() {
+                None
+            } else {
+                Some(StringWriter(String::new()))
+            },
+            body: StringWriter(String::new()),
             arena,
-            declared_locals: HashSet::new(),
+            declared_locals,
+            depth: 1,
             finished: false,
-            args: arena.alloc_slice(
-                &proto
-                    .args
-                    .iter()
-                    .enumerate()
-                    .map(|(i, &ty)| Variable {
-                        name: arena.alloc_str(&format!("_{}", i)),
-                        ty,
-                    })
-                    .collect::<Vec<_>>(),
-            ),
+            args: arena.alloc_slice(&args),
         })
     }
 
     /// Closes the in-progress function definition, consuming `self`.
     pub fn finish(mut self) -> io::Result<()> {
+        if let Some(decls) = &self.decls {
+            self.writer.write_str(decls)?;
+        }
+        self.writer.write_str(&self.body)?;
         writeln!(self.writer, "}}")?;
         writeln!(self.writer)?;
         self.finished = true;
@@ -77,35 +174,267 @@ impl<'a, W: WriteStr> FunctionBuilder<'a, W> {
     }
 
     fn indent(&mut self) -> io::Result<()> {
-        write!(self.writer, "    ")
+        for _ in 0..self.depth {
+            write!(self.body, "    ")?;
+        }
+        Ok(())
     }
 
     /// Declares a new local variable at the current position.
+    ///
+    /// If `name` is already used by another variable or argument of the
+    /// function, a numeric suffix is appended to make it unique.
     pub fn declare_variable<'b>(
         &mut self,
         name: impl AsRef<str>,
         ty: TypeRef<'a>,
         comment: impl Into<Option<&'b str>>,
     ) -> io::Result<Variable<'a>> {
-        let name = name.as_ref();
-        self.indent()?;
-        ty.declare_variable(name, self.writer)?;
-        write!(self.writer, ";")?;
+        let name = unique_name(&self.declared_locals, name.as_ref().to_string());
+        self.declared_locals.insert(name.clone());
+        let name = &*name;
+
+        // Hoisted declarations are at the top level of the body
+        let depth = if self.decls.is_some() { 1 } else { self.depth };
+        let out = self.decls.as_mut().unwrap_or(&mut self.body);
+        for _ in 0..depth {
+            write!(out, "    ")?;
+        }
+        ty.declare_variable(name, out)?;
+        write!(out, ";")?;
         if let Some(comment) = comment.into() {
-            write!(self.writer, "  /* {} */", comment)?;
+            write!(out, "  /* {} */", escape_comment(comment))?;
         }
-        writeln!(self.writer)?;
+        writeln!(out)?;
 
         Ok(Variable {
             name: self.arena.alloc_str(name),
             ty,
         })
     }
+
+    /// Assigns the value of `value` to the lvalue `place`.
+    pub fn assign(&mut self, place: &Expr<'_>, value: &Expr<'_>) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "{} = {};", place, value)
+    }
+
+    /// Evaluates `expr` for its side effects, discarding its value.
+    pub fn eval(&mut self, expr: &Expr<'_>) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "{};", expr)
+    }
+
+    /// Copies `size` bytes from the memory `src` points to to the memory `dst`
+    /// points to.
+    pub fn memcpy(&mut self, dst: &Expr<'_>, src: &Expr<'_>, size: u64) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "memcpy({}, {}, {});", dst, src, size)
+    }
+
+    /// Emits a GNU C extended inline assembly statement.
+    ///
+    /// `template` refers to the operands as `%0`, `%1`, ..., outputs first.
+    pub fn inline_asm(
+        &mut self,
+        template: &str,
+        volatile: bool,
+        outputs: &[AsmOperand<'_>],
+        inputs: &[AsmOperand<'_>],
+        clobbers: &[String],
+    ) -> io::Result<()> {
+        self.indent()?;
+        write!(self.body, "__asm__ ")?;
+        if volatile {
+            write!(self.body, "volatile ")?;
+        }
+        write!(self.body, "(")?;
+        write_c_string(template, &mut self.body)?;
+        // The outputs' colon is always needed, since basic `asm` statements
+        // don't have operands and don't turn `%%` into `%`
+        let clobbers = clobbers.iter().map(|clobber| (&**clobber, None));
+        let outputs = outputs.iter().map(|op| (&*op.constraint, Some(&op.expr)));
+        let inputs = inputs.iter().map(|op| (&*op.constraint, Some(&op.expr)));
+        let mut sections = vec![outputs.collect::<Vec<_>>(), inputs.collect()];
+        sections.push(clobbers.collect());
+        while sections.len() > 1 && sections.last().unwrap().is_empty() {
+            sections.pop();
+        }
+        for section in sections {
+            write!(self.body, " :")?;
+            for (i, (string, expr)) in section.into_iter().enumerate() {
+                write!(self.body, "{} ", if i == 0 { "" } else { "," })?;
+                write_c_string(string, &mut self.body)?;
+                if let Some(expr) = expr {
+                    write!(self.body, " ({})", expr)?;
+                }
+            }
+        }
+        writeln!(self.body, ");")
+    }
+
+    /// Assigns a shuffle of the lanes of the vectors `a` and `b` to `dest`,
+    /// which has the same type.
+    ///
+    /// Lane `i` of the result is lane `indices[i]` of the concatenation of
+    /// `a` and `b`. `mask_ty` is a vector of unsigned integers with the same
+    /// lane count and size as `a`, which GCC needs for the indices.
+    pub fn shuffle<'b>(
+        &mut self,
+        dest: &Expr<'_>,
+        a: &Expr<'b>,
+        b: &Expr<'b>,
+        indices: &[u32],
+        mask_ty: TypeRef<'b>,
+    ) -> io::Result<()> {
+        let indices = indices
+            .iter()
+            .map(|&index| Expr::Uint(index.into()))
+            .collect::<Vec<_>>();
+
+        // Clang doesn't implement `__builtin_shuffle`, and GCC doesn't
+        // implement `__builtin_shufflevector`
+        writeln!(self.body, "#ifdef __clang__")?;
+        let mut args = vec![a.clone(), b.clone()];
+        args.extend(indices.iter().cloned());
+        let shuffle = Expr::Ident("__builtin_shufflevector".into()).call(args);
+        self.assign(dest, &shuffle)?;
+        writeln!(self.body, "#else")?;
+        // A cast of an initializer list is a compound literal
+        let mask = Expr::InitList(indices).cast(mask_ty);
+        let shuffle =
+            Expr::Ident("__builtin_shuffle".into()).call(vec![a.clone(), b.clone(), mask]);
+        self.assign(dest, &shuffle)?;
+        writeln!(self.body, "#endif")
+    }
+
+    /// Assigns the wrapped result of `a op b` to `result`, and whether it
+    /// overflowed to `overflowed`, where `op` is `add`, `sub` or `mul`.
+    ///
+    /// This uses GCC's `__builtin_<op>_overflow`, which works for all integer
+    /// types.
+    pub fn overflow_op(
+        &mut self,
+        op: &str,
+        result: &Expr<'_>,
+        overflowed: &Expr<'_>,
+        a: &Expr<'_>,
+        b: &Expr<'_>,
+    ) -> io::Result<()> {
+        self.indent()?;
+        writeln!(
+            self.body,
+            "{} = __builtin_{}_overflow({}, {}, &{});",
+            overflowed, op, a, b, result
+        )
+    }
+
+    /// Emits a `#line` directive, which makes the C compiler (and debuggers)
+    /// attribute the next line of output to line `line` of the file `file`.
+    ///
+    /// The lines after that are attributed to the following lines of `file`.
+    pub fn line_directive(&mut self, line: usize, file: &str) -> io::Result<()> {
+        write!(self.body, "#line {} ", line)?;
+        write_c_string(file, &mut self.body)?;
+        writeln!(self.body)
+    }
+
+    /// Places a label at the current position.
+    ///
+    /// The label is followed by an empty statement, so it is valid even if
+    /// nothing else follows it in the function body.
+    pub fn label(&mut self, label: &str) -> io::Result<()> {
+        writeln!(self.body, "{}:;", label)
+    }
+
+    /// Emits an unconditional jump to `label`.
+    pub fn goto(&mut self, label: &str) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "goto {};", label)
+    }
+
+    /// Emits a `switch` on `value`, jumping to the label of the case matching
+    /// its value, or to `default` if no case matches. The case values must be
+    /// integer constant expressions.
+    pub fn switch(
+        &mut self,
+        value: &Expr<'_>,
+        cases: &[(Expr<'_>, String)],
+        default: &str,
+    ) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "switch ({}) {{", value)?;
+        for (case, label) in cases {
+            self.indent()?;
+            writeln!(self.body, "    case {}: goto {};", case, label)?;
+        }
+        self.indent()?;
+        writeln!(self.body, "    default: goto {};", default)?;
+        self.indent()?;
+        writeln!(self.body, "}}")
+    }
+
+    /// Starts a loop that runs the statements up to the matching `end_loop`
+    /// once for every value of `index` in `0..len`.
+    pub fn begin_loop(&mut self, index: Variable<'a>, len: &Expr<'_>) -> io::Result<()> {
+        self.indent()?;
+        writeln!(
+            self.body,
+            "for ({0} = 0; {0} < {1}; {0}++) {{",
+            index.name, len
+        )?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Closes the innermost loop started by `begin_loop`.
+    pub fn end_loop(&mut self) -> io::Result<()> {
+        self.depth -= 1;
+        self.indent()?;
+        writeln!(self.body, "}}")
+    }
+
+    /// Returns from the function, optionally returning the value of `value`.
+    pub fn ret(&mut self, value: Option<&Expr<'_>>) -> io::Result<()> {
+        self.indent()?;
+        match value {
+            Some(value) => writeln!(self.body, "return {};", value),
+            None => writeln!(self.body, "return;"),
+        }
+    }
+
+    /// Aborts the process by calling the C library's `abort` function.
+    pub fn abort(&mut self) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "abort();")
+    }
+
+    /// Aborts the process with a trap instruction (`__builtin_trap`).
+    pub fn trap(&mut self) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "__builtin_trap();")
+    }
+
+    /// Enters an infinite loop.
+    ///
+    /// This is the only portable way to stop a freestanding program.
+    pub fn hang(&mut self) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "for (;;) {{}}")
+    }
+
+    /// Marks the current position as unreachable.
+    pub fn unreachable(&mut self) -> io::Result<()> {
+        self.indent()?;
+        writeln!(self.body, "__builtin_unreachable();")
+    }
 }
 
 impl<'a, W: WriteStr> Drop for FunctionBuilder<'a, W> {
     fn drop(&mut self) {
-        if !self.finished {
+        // Don't turn a fatal error reported while building the function into
+        // a double panic
+        if !self.finished && !thread::panicking() {
             panic!("FunctionBuilder dropped without calling `finish`");
         }
     }
@@ -123,9 +452,257 @@ mod tests {
             let sig = tu.fn_sig(None, &[]);
             let double = tu.double();
             let fnptr = tu.fn_ptr(sig);
-            let mut f = tu.define_function(&a, Name::test("declare_locals"), sig)?;
+            let u32 = tu.u32();
+            let arr = tu.array_of(u32, 3);
+            let arrptr = tu.ptr_to(arr);
+            let mut f =
+                tu.define_function(&a, Name::test("declare_locals"), sig, FnAttrs::default())?;
             f.declare_variable("dbl", double, None)?;
             f.declare_variable("f", fnptr, Some("i'm a function pointer with a comment"))?;
+            f.declare_variable("arr", arrptr, None)?;
+            // Type names can contain `*/`, which mustn't end the comment
+            f.declare_variable("c", u32, Some("[closure@a*/b.rs:1:1: 1:9]"))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn statements() {
+        compile_test("statements", |tu| {
+            let a = Arena::new();
+            let i = tu.i32();
+            let pi = tu.ptr_to(i);
+            let callee_sig = tu.fn_sig(Some(i), &[pi]);
+            let callee =
+                tu.fwd_declare_function(Name::test("callee"), callee_sig, FnAttrs::default())?;
+
+            let sig = tu.fn_sig(Some(i), &[i]);
+            let mut f =
+                tu.define_function(&a, Name::test("statements"), sig, FnAttrs::default())?;
+            let arg = f.args[0];
+            let local = f.declare_variable("_2", i, None)?;
+            let call = Expr::from(callee).call(vec![Expr::from(arg).addr_of()]);
+            f.assign(&Expr::from(local), &call)?;
+            f.eval(&call)?;
+            // collides with the argument and the local declared above
+            let dup = f.declare_variable("_1", i, None)?;
+            let dup2 = f.declare_variable("_1", i, None)?;
+            f.memcpy(&Expr::from(dup).addr_of(), &Expr::from(dup2).addr_of(), 4)?;
+            f.ret(Some(&Expr::from(local)))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn inline_asm() {
+        compile_test("inline_asm", |tu| {
+            let a = Arena::new();
+            let i = tu.i32();
+            let sig = tu.fn_sig(Some(i), &[i]);
+            let mut f =
+                tu.define_function(&a, Name::test("inline_asm"), sig, FnAttrs::default())?;
+            let arg = Expr::from(f.args[0]);
+            let out = Expr::from(f.declare_variable("out", i, None)?);
+            f.inline_asm("", false, &[], &[], &[])?;
+            f.inline_asm(
+                "/* \"%0\" %1 */",
+                true,
+                &[AsmOperand {
+                    constraint: "=r".to_string(),
+                    expr: out.clone(),
+                }],
+                &[AsmOperand {
+                    constraint: "0".to_string(),
+                    expr: arg,
+                }],
+                &["memory".to_string()],
+            )?;
+            f.ret(Some(&out))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn line_directives() {
+        compile_test("line_directives", |tu| {
+            let a = Arena::new();
+            let sig = tu.fn_sig(None, &[]);
+            let mut f =
+                tu.define_function(&a, Name::test("line_directives"), sig, FnAttrs::default())?;
+            f.line_directive(12, "src/main.rs")?;
+            f.goto("bb1")?;
+            f.label("bb1")?;
+            f.line_directive(3, "C:\\src\\\"lib\".rs")?;
+            f.ret(None)?;
+            f.finish()?;
+            // Attributes the rest of the TU to the C file again
+            tu.line_directive("line_directives.c")?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn arg_names() {
+        compile_test("arg_names", |tu| {
+            let a = Arena::new();
+            let i = tu.i32();
+            let sig = tu.fn_sig(Some(i), &[i, i, i]);
+            let names = [Some("x_1".to_string()), None];
+            let mut f = FunctionBuilder::create(
+                tu.writer(),
+                &a,
+                Dialect::default(),
+                Name::test("arg_names"),
+                sig,
+                FnAttrs::default(),
+                &names,
+            )?;
+            // Clashes with the name of the second argument
+            let tmp = f.declare_variable("_2", i, "i32")?;
+            f.assign(&Expr::from(tmp), &Expr::from(f.args[0]))?;
+            f.ret(Some(&Expr::from(tmp)))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn duplicate_arg_names() {
+        compile_test("duplicate_arg_names", |tu| {
+            let a = Arena::new();
+            let i = tu.i32();
+            let sig = tu.fn_sig(Some(i), &[i, i, i]);
+            // The parts of a pair get the default names, which the name of the
+            // following argument clashes with
+            let names = [None, None, Some("_2".to_string())];
+            let mut f = FunctionBuilder::create(
+                tu.writer(),
+                &a,
+                Dialect::default(),
+                Name::test("duplicate_arg_names"),
+                sig,
+                FnAttrs::default(),
+                &names,
+            )?;
+            let sum = Expr::from(f.args[1]).binary("+", Expr::from(f.args[2]));
+            f.ret(Some(&sum))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn loops() {
+        compile_test("loops", |tu| {
+            let a = Arena::new();
+            let uintptr = tu.uintptr();
+            let sig = tu.fn_sig(None, &[uintptr]);
+            let callee_sig = tu.fn_sig(None, &[uintptr]);
+            let callee =
+                tu.fwd_declare_function(Name::test("callee"), callee_sig, FnAttrs::default())?;
+            let mut f = tu.define_function(&a, Name::test("loops"), sig, FnAttrs::default())?;
+            let len = Expr::from(f.args[0]);
+            let i = f.declare_variable("i", uintptr, None)?;
+            let j = f.declare_variable("j", uintptr, None)?;
+            f.begin_loop(i, &len)?;
+            f.begin_loop(j, &Expr::Uint(4))?;
+            f.eval(&Expr::from(callee).call(vec![Expr::from(j)]))?;
+            f.end_loop()?;
+            f.end_loop()?;
+            f.ret(None)?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn control_flow() {
+        compile_test("control_flow", |tu| {
+            let a = Arena::new();
+            let sig = tu.fn_sig(None, &[]);
+            let attrs = FnAttrs {
+                noreturn: true,
+                ..FnAttrs::default()
+            };
+            let u8 = tu.u8();
+            let mut f = tu.define_function(&a, Name::test("control_flow"), sig, attrs)?;
+            let cases = [
+                (Expr::Uint(0), "bb1".to_string()),
+                (Expr::Uint(1), "bb3".to_string()),
+                (Expr::Uint(255), "bb2".to_string()),
+            ];
+            f.switch(&Expr::Uint(1).cast(u8), &cases, "bb4")?;
+            f.label("bb1")?;
+            f.abort()?;
+            f.label("bb2")?;
+            f.unreachable()?;
+            f.label("bb3")?;
+            f.trap()?;
+            f.label("bb4")?;
+            f.hang()?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn shuffle() {
+        compile_test("shuffle", |tu| {
+            let a = Arena::new();
+            let float = tu.float();
+            let u32 = tu.u32();
+            let f32x4 = tu.define_vector(Name::test("f32x4"), float, 4)?;
+            let u32x4 = tu.define_vector(Name::test("u32x4"), u32, 4)?;
+            tu.static_assert("sizeof(f32x4) == 16", "f32x4 has the wrong size")?;
+            let sig = tu.fn_sig(Some(f32x4), &[f32x4, f32x4]);
+            let mut f = tu.define_function(&a, Name::test("shuffle"), sig, FnAttrs::default())?;
+            let lhs = Expr::from(f.args[0]);
+            let rhs = Expr::from(f.args[1]);
+            let dest = f.declare_variable("dest", f32x4, None)?;
+            f.shuffle(&Expr::from(dest), &lhs, &rhs, &[0, 4, 1, 5], u32x4)?;
+            f.ret(Some(&Expr::from(dest)))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn int128() {
+        compile_test("int128", |tu| {
+            let a = Arena::new();
+            let u128 = tu.u128();
+            let i128 = tu.i128();
+            let sig = tu.fn_sig(Some(i128), &[u128]);
+            let mut f = tu.define_function(&a, Name::test("int128"), sig, FnAttrs::default())?;
+            let arg = Expr::from(f.args[0]);
+            let cases = [(Expr::Uint(u128::max_value()), "bb1".to_string())];
+            f.switch(&arg, &cases, "bb2")?;
+            f.label("bb1")?;
+            f.ret(Some(&Expr::Uint(1 << 64).cast(i128)))?;
+            f.label("bb2")?;
+            f.ret(Some(&Expr::Uint(0).cast(i128)))?;
+            f.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn overflow() {
+        compile_test("overflow", |tu| {
+            let a = Arena::new();
+            let i32 = tu.i32();
+            let bool = tu.bool();
+            let sig = tu.fn_sig(Some(bool), &[i32, i32]);
+            let mut f = tu.define_function(&a, Name::test("overflow"), sig, FnAttrs::default())?;
+            let lhs = Expr::from(f.args[0]);
+            let rhs = Expr::from(f.args[1]);
+            let result = Expr::from(f.declare_variable("result", i32, None)?);
+            let overflowed = Expr::from(f.declare_variable("overflowed", bool, None)?);
+            f.overflow_op("mul", &result, &overflowed, &lhs, &rhs)?;
+            f.ret(Some(&overflowed))?;
             f.finish()?;
             Ok(())
         });
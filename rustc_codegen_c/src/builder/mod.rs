@@ -7,47 +7,240 @@
 //! * Keep all global identifiers as-is (should work due to mangling) and rename
 //!   colliding locals
 
+pub mod attrs;
+pub mod dialect;
+pub mod expr;
 pub mod function;
 pub mod test;
 pub mod types;
 
-use self::function::FunctionBuilder;
-use self::types::{AsType, FnSig, IncompleteTypeRef, Type, TypeRef};
-use utils::WriteStr;
+use self::attrs::{FnAttrs, GlobalAttrs};
+use self::dialect::{Dialect, Environment};
+use self::expr::Expr;
+use self::function::{write_c_string, FunctionBuilder};
+use self::types::{AsType, FnSig, IncompleteTypeRef, PtrMeta, Type, TypeRef};
+use self::types::{VTABLE, VTABLE_ALIGN, VTABLE_DROP_IN_PLACE, VTABLE_SIZE};
+use utils::{LineCounter, WriteStr};
 
-use hashbrown::hash_map::{HashMap, RawEntryMut};
 use hashbrown::HashSet;
 use toolshed::Arena;
 
-use bitflags::_core::fmt::{Error, Formatter};
-use rustc::ty::{Instance, SymbolName, TyCtxt};
-use rustc_index::{newtype_index, vec::IndexVec};
+use rustc::ty::{Instance, Ty, TyCtxt};
 use std::borrow::Cow;
-use std::fmt;
-use std::io::{self, Write};
-use std::rc::Rc;
+use std::io;
 use syntax::symbol::{Symbol, SymbolStr};
 
+/// Escapes `text` for a C comment, which would end at a `*/` in it.
+fn escape_comment(text: &str) -> Cow<'_, str> {
+    if text.contains("*/") {
+        text.replace("*/", "*-/").into()
+    } else {
+        text.into()
+    }
+}
+
+/// Identifiers the generated code can't declare: keywords, and the names the
+/// preamble defines or includes in any dialect (eg. `bool` and `int8_t` are
+/// typedefs, and `true` a macro, in C89).
+const RESERVED_IDENTS: &[&str] = &[
+    // C89 to C11
+    "auto",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "unsigned",
+    "void",
+    "volatile",
+    "while",
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_Bool",
+    "_Complex",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+    // GNU C
+    "asm",
+    "typeof",
+    "__asm__",
+    "__attribute__",
+    "__extension__",
+    "__inline__",
+    "__int128",
+    "__restrict__",
+    "__thread",
+    "__typeof__",
+    // The preamble
+    "bool",
+    "true",
+    "false",
+    "int8_t",
+    "uint8_t",
+    "int16_t",
+    "uint16_t",
+    "int32_t",
+    "uint32_t",
+    "int64_t",
+    "uint64_t",
+    "intptr_t",
+    "uintptr_t",
+    "size_t",
+    "ptrdiff_t",
+    "NULL",
+    "abort",
+    "memcpy",
+    "memmove",
+    "memset",
+];
+
+/// Returns `ident`, with underscores appended if it's reserved (see
+/// `RESERVED_IDENTS`).
+pub fn unreserved_ident(mut ident: String) -> String {
+    while RESERVED_IDENTS.contains(&&*ident) {
+        ident.push('_');
+    }
+    ident
+}
+
 /// A Rust/C function or type name.
 pub struct Name<'a> {
     rust: Cow<'a, str>,
     mangled: Symbol,
+    /// The symbol name seen by the assembler and linker, if it differs from
+    /// the C identifier.
+    link_name: Option<Symbol>,
 }
 
 impl<'a> Name<'a> {
     /// Create a `Symbol` for a monomorphized `Instance`.
+    ///
+    /// The instance is linked under the same symbol name the LLVM backend
+    /// would use, so that it can be linked against LLVM-compiled crates.
     pub fn for_instance<'tcx>(inst: &Instance<'tcx>, tcx: TyCtxt<'tcx>) -> Self {
+        // Mach-O prefixes all C symbols with an underscore, and so does the
+        // LLVM backend.
+        // FIXME: 32-bit Windows does the same for `cdecl` functions
+        let prefix = if tcx.sess.target.target.options.is_like_osx {
+            "_"
+        } else {
+            ""
+        };
+        Self::for_symbol(
+            inst.to_string(),
+            &tcx.symbol_name(*inst).name.as_str(),
+            prefix,
+        )
+    }
+
+    /// Create a `Name` for an item that must be linked as `symbol`.
+    ///
+    /// Legacy Rust symbol names contain `.` and `$`, which aren't valid in C
+    /// identifiers, and foreign functions can be named like the C library
+    /// functions of the preamble (eg. `memcpy`). Such symbols are given a
+    /// sanitized C identifier, and the real symbol name (prefixed with
+    /// `asm_prefix`) is attached to their declaration as an assembler label.
+    ///
+    /// The sanitized identifier starts with the backend's `_R2C2S` prefix,
+    /// and escapes `_` as `__` and other characters as `_<hex code>_`, so
+    /// distinct symbols never get the same identifier.
+    pub fn for_symbol(rust: impl Into<Cow<'a, str>>, symbol: &str, asm_prefix: &str) -> Self {
+        let is_c_ident = symbol
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            && !RESERVED_IDENTS.contains(&symbol);
+        if is_c_ident {
+            return Self {
+                rust: rust.into(),
+                mangled: Symbol::intern(symbol),
+                link_name: None,
+            };
+        }
+
+        let mut c_ident = String::from("_R2C2S");
+        for c in symbol.chars() {
+            if c.is_ascii_alphanumeric() {
+                c_ident.push(c);
+            } else if c == '_' {
+                c_ident.push_str("__");
+            } else {
+                c_ident.push_str(&format!("_{:02X}_", c as u32));
+            }
+        }
+        Self {
+            rust: rust.into(),
+            mangled: Symbol::intern(&c_ident),
+            link_name: Some(Symbol::intern(&format!("{}{}", asm_prefix, symbol))),
+        }
+    }
+
+    /// Create a `Name` for a monomorphic Rust type.
+    ///
+    /// Types don't have symbol names, so the C name is derived from the
+    /// type's stable hash, which is the same in every codegen unit and crate.
+    pub fn for_type<'tcx>(ty: Ty<'tcx>, tcx: TyCtxt<'tcx>) -> Self {
         Self {
-            rust: inst.to_string().into(),
-            mangled: tcx.symbol_name(*inst).name,
+            rust: ty.to_string().into(),
+            mangled: Symbol::intern(&format!("_R2C2T{:016x}", tcx.type_id_hash(ty))),
+            link_name: None,
+        }
+    }
+
+    /// Create a `Name` for a part of the C representation of a monomorphic
+    /// Rust type (eg. the `struct` of one generator state).
+    pub fn for_type_part<'tcx>(ty: Ty<'tcx>, part: &str, tcx: TyCtxt<'tcx>) -> Self {
+        Self {
+            rust: format!("{} ({})", ty, part).into(),
+            mangled: Symbol::intern(&format!("_R2C2T{:016x}_{}", tcx.type_id_hash(ty), part)),
+            link_name: None,
+        }
+    }
+
+    /// Create a `Name` for an item that is private to the translation unit and
+    /// has no Rust symbol name.
+    pub fn internal(rust: impl Into<Cow<'a, str>>, c_name: &str) -> Self {
+        Self {
+            rust: rust.into(),
+            mangled: Symbol::intern(c_name),
+            link_name: None,
         }
     }
 
     /// A test symbol that doesn't have an associated Rust name (used only by unit tests).
+    #[cfg(test)]
     pub fn test(c_name: &'a str) -> Self {
         Self {
             rust: "(test-symbol: no associated Rust name)".into(),
             mangled: Symbol::intern(c_name),
+            link_name: None,
         }
     }
 
@@ -60,6 +253,32 @@ impl<'a> Name<'a> {
     pub fn mangled(&self) -> SymbolStr {
         self.mangled.as_str()
     }
+
+    /// Returns the symbol name to use in the object file, if it differs from
+    /// the C name.
+    pub fn link_name(&self) -> Option<SymbolStr> {
+        self.link_name.map(|sym| sym.as_str())
+    }
+
+    /// Drops the link name, so the item's symbol is its C identifier.
+    ///
+    /// This is only correct for items internal to the TU, whose symbol isn't
+    /// linked against.
+    pub fn without_link_name(self) -> Self {
+        Self {
+            link_name: None,
+            ..self
+        }
+    }
+
+    /// Writes the assembler label (`__asm__("symbol")`) that makes a
+    /// declaration use `link_name` as its symbol, if needed.
+    fn write_asm_label(&self, w: &mut impl WriteStr) -> io::Result<()> {
+        match self.link_name() {
+            Some(link_name) => write!(w, " __asm__(\"{}\")", link_name),
+            None => Ok(()),
+        }
+    }
 }
 
 /// A builder for C translation units.
@@ -67,26 +286,47 @@ impl<'a> Name<'a> {
 /// A "translation unit" is a single `.c` file and corresponds directly to a
 /// codegen unit in Rust.
 pub struct TranslationUnitBuilder<'a, W: WriteStr> {
-    /// Output file writer.
-    writer: W,
+    /// Output file writer, counting the lines of the TU for `line_directive`.
+    writer: LineCounter<W>,
 
     /// Arena storing TU-wide data (types, type names, etc.).
     arena: &'a Arena,
 
     /// `struct`s and `union`s that have been defined (not declared) so far.
     defd_types: HashSet<&'a str>,
+
+    /// The C dialect to write.
+    dialect: Dialect,
+
+    /// The C implementation the TU is compiled by.
+    environment: Environment,
+
+    /// The number of emulated static assertions emitted so far, used to name
+    /// their typedefs.
+    static_asserts: usize,
 }
 
 impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
-    /// Creates a new C file builder that writes its output to `writer`.
+    /// Creates a new C file builder that writes its output to `writer`, in
+    /// the C dialect `dialect`, for compilation in `environment`.
     ///
     /// This will also write the preamble to the writer, which includes standard
     /// files that are assumed to be present by the rest of the code generator.
-    pub fn create(arena: &'a Arena, writer: W) -> io::Result<Self> {
+    /// Freestanding TUs only include headers that freestanding implementations
+    /// provide.
+    pub fn create(
+        arena: &'a Arena,
+        writer: W,
+        dialect: Dialect,
+        environment: Environment,
+    ) -> io::Result<Self> {
         let mut this = Self {
-            writer,
+            writer: LineCounter::new(writer),
             arena,
             defd_types: HashSet::new(),
+            dialect,
+            environment,
+            static_asserts: 0,
         };
 
         // Include a few standard headers the code generator relies on
@@ -94,33 +334,236 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
             "generated by r2c2 version {}",
             env!("CARGO_PKG_VERSION")
         ))?;
-        this.include("stdint.h")?;
-        this.include("stdbool.h")?;
+        match environment {
+            Environment::Hosted if dialect.is_c99() => {
+                this.include("stddef.h")?;
+                this.include("stdint.h")?;
+                this.include("stdbool.h")?;
+            }
+            _ => this.define_c99_types()?,
+        }
+        // The C library functions are declared rather than included, since
+        // `<string.h>` and `<stdlib.h>` declare functions that Rust crates
+        // declare with other types (eg. `core`'s `memcmp`)
+        match environment {
+            Environment::Hosted => {
+                this.define_mem_fns(false, false, false, false)?;
+                writeln!(this.writer, "void abort(void);")?;
+            }
+            Environment::Freestanding {
+                memcpy_fallback,
+                memmove_fallback,
+                memset_fallback,
+                weak_fallbacks,
+            } => this.define_mem_fns(
+                memcpy_fallback,
+                memmove_fallback,
+                memset_fallback,
+                weak_fallbacks,
+            )?,
+        }
+        writeln!(this.writer)?;
+
+        // Fat pointers to slices/`str` and trait objects. Vtables start with
+        // the drop glue, size and alignment of the type, followed by the
+        // trait's methods (see `vtable`).
+        writeln!(this.writer, "struct r2c2_slice_ptr {{")?;
+        writeln!(this.writer, "    void * data;")?;
+        writeln!(this.writer, "    uintptr_t len;")?;
+        writeln!(this.writer, "}};")?;
+        writeln!(this.writer, "struct {} {{", VTABLE)?;
+        writeln!(this.writer, "    void (*{})(void *);", VTABLE_DROP_IN_PLACE)?;
+        writeln!(this.writer, "    uintptr_t {};", VTABLE_SIZE)?;
+        writeln!(this.writer, "    uintptr_t {};", VTABLE_ALIGN)?;
+        writeln!(this.writer, "}};")?;
+        writeln!(this.writer, "struct r2c2_dyn_ptr {{")?;
+        writeln!(this.writer, "    void * data;")?;
+        writeln!(this.writer, "    const struct {} * vtable;", VTABLE)?;
+        writeln!(this.writer, "}};")?;
         this.separator("END PREAMBLE")?;
 
         Ok(this)
     }
 
     /// Returns the output file writer associated with this TU builder.
-    pub fn writer(&mut self) -> &mut W {
+    pub fn writer(&mut self) -> &mut LineCounter<W> {
         &mut self.writer
     }
 
     pub fn into_writer(self) -> W {
-        self.writer
+        self.writer.into_inner()
+    }
+
+    /// Returns the C dialect this TU is written in.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Returns the C implementation this TU is compiled by.
+    pub fn environment(&self) -> Environment {
+        self.environment
     }
 
     fn include(&mut self, sys: &str) -> io::Result<()> {
         writeln!(self.writer, "#include <{}>", sys)
     }
 
+    /// Defines the types of `<stdint.h>` and `<stdbool.h>` that the generated
+    /// code uses, for C89 dialects, which have neither, and freestanding TUs.
+    ///
+    /// Static assertions make sure the types have the expected sizes.
+    fn define_c99_types(&mut self) -> io::Result<()> {
+        self.include("limits.h")?;
+        self.include("stddef.h")?;
+        writeln!(self.writer, "typedef signed char int8_t;")?;
+        writeln!(self.writer, "typedef unsigned char uint8_t;")?;
+        writeln!(self.writer, "typedef short int16_t;")?;
+        writeln!(self.writer, "typedef unsigned short uint16_t;")?;
+        writeln!(self.writer, "#if UINT_MAX == 0xffffffff")?;
+        writeln!(self.writer, "typedef int int32_t;")?;
+        writeln!(self.writer, "typedef unsigned int uint32_t;")?;
+        writeln!(self.writer, "#else")?;
+        writeln!(self.writer, "typedef long int32_t;")?;
+        writeln!(self.writer, "typedef unsigned long uint32_t;")?;
+        writeln!(self.writer, "#endif")?;
+        // Shifted twice, since the preprocessor might only have 32-bit
+        // arithmetic
+        writeln!(self.writer, "#if ULONG_MAX >> 31 >> 31 == 3")?;
+        writeln!(self.writer, "typedef long int64_t;")?;
+        writeln!(self.writer, "typedef unsigned long uint64_t;")?;
+        writeln!(self.writer, "#else")?;
+        writeln!(self.writer, "typedef long long int64_t;")?;
+        writeln!(self.writer, "typedef unsigned long long uint64_t;")?;
+        writeln!(self.writer, "#endif")?;
+        // `size_t` is pointer-sized on all targets Rust supports
+        writeln!(self.writer, "typedef ptrdiff_t intptr_t;")?;
+        writeln!(self.writer, "typedef size_t uintptr_t;")?;
+        if self.dialect.is_c99() {
+            writeln!(self.writer, "typedef _Bool bool;")?;
+        } else {
+            writeln!(self.writer, "typedef unsigned char bool;")?;
+        }
+        writeln!(self.writer, "#define true 1")?;
+        writeln!(self.writer, "#define false 0")?;
+
+        for &bits in &[8, 16, 32, 64] {
+            for &prefix in &["int", "uint"] {
+                self.static_assert(
+                    &format!("sizeof({}{}_t) == {}", prefix, bits, bits / 8),
+                    &format!("{}{}_t has the wrong size", prefix, bits),
+                )?;
+            }
+        }
+        for &ty in &["intptr_t", "uintptr_t"] {
+            self.static_assert(
+                &format!("sizeof({}) == sizeof(void *)", ty),
+                &format!("{} has the wrong size", ty),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Declares the C library's `memcpy`, `memmove` and `memset`, which the
+    /// generated code and the C compiler rely on even in freestanding TUs.
+    ///
+    /// If nothing else provides them, as indicated by `memcpy_fallback`,
+    /// `memmove_fallback` and `memset_fallback`, the TU defines its own
+    /// versions instead, which are weak if `weak` is set.
+    fn define_mem_fns(
+        &mut self,
+        memcpy_fallback: bool,
+        memmove_fallback: bool,
+        memset_fallback: bool,
+        weak: bool,
+    ) -> io::Result<()> {
+        // The `volatile` accesses keep the C compiler from turning the loops
+        // into calls to the functions themselves
+        if memcpy_fallback {
+            self.mem_fn_linkage(weak)?;
+            writeln!(
+                self.writer,
+                "void * memcpy(void * dst, const void * src, size_t n)"
+            )?;
+            writeln!(self.writer, "{{")?;
+            writeln!(self.writer, "    volatile unsigned char * d = dst;")?;
+            writeln!(self.writer, "    const unsigned char * s = src;")?;
+            writeln!(self.writer, "    while (n--) *d++ = *s++;")?;
+            writeln!(self.writer, "    return dst;")?;
+            writeln!(self.writer, "}}")?;
+        } else {
+            writeln!(
+                self.writer,
+                "void * memcpy(void * dst, const void * src, size_t n);"
+            )?;
+        }
+        if memmove_fallback {
+            self.mem_fn_linkage(weak)?;
+            writeln!(
+                self.writer,
+                "void * memmove(void * dst, const void * src, size_t n)"
+            )?;
+            writeln!(self.writer, "{{")?;
+            writeln!(self.writer, "    volatile unsigned char * d = dst;")?;
+            writeln!(self.writer, "    const unsigned char * s = src;")?;
+            writeln!(self.writer, "    if (d < s) while (n--) *d++ = *s++;")?;
+            writeln!(self.writer, "    else while (n--) d[n] = s[n];")?;
+            writeln!(self.writer, "    return dst;")?;
+            writeln!(self.writer, "}}")?;
+        } else {
+            writeln!(
+                self.writer,
+                "void * memmove(void * dst, const void * src, size_t n);"
+            )?;
+        }
+        if memset_fallback {
+            self.mem_fn_linkage(weak)?;
+            writeln!(self.writer, "void * memset(void * dst, int c, size_t n)")?;
+            writeln!(self.writer, "{{")?;
+            writeln!(self.writer, "    volatile unsigned char * d = dst;")?;
+            writeln!(self.writer, "    while (n--) *d++ = (unsigned char) c;")?;
+            writeln!(self.writer, "    return dst;")?;
+            writeln!(self.writer, "}}")
+        } else {
+            writeln!(self.writer, "void * memset(void * dst, int c, size_t n);")
+        }
+    }
+
+    /// Writes the linkage of a fallback definition of `memcpy`, `memmove` or
+    /// `memset`.
+    ///
+    /// GCC treats them as builtins, and drops internal definitions in favor
+    /// of calls to the external functions. The calls the compiler emits for
+    /// copies need the external functions anyway, so compilers with GNU
+    /// attributes get weak definitions, of which every TU can have one.
+    fn mem_fn_linkage(&mut self, weak: bool) -> io::Result<()> {
+        if weak {
+            writeln!(self.writer, "__attribute__((weak))")
+        } else {
+            writeln!(self.writer, "static")
+        }
+    }
+
     fn comment(&mut self, comment: &str) -> io::Result<()> {
         for line in comment.lines() {
-            writeln!(self.writer, "/* {} */", line.replace("*/", "*-/"))?;
+            writeln!(self.writer, "/* {} */", escape_comment(line))?;
         }
         Ok(())
     }
 
+    /// Emits a `#line` directive attributing the following lines to the TU
+    /// itself, which is written to the C file `file`.
+    ///
+    /// Functions attributed to Rust source with `FunctionBuilder::line_directive`
+    /// have to be followed by this, or the rest of the TU would be attributed
+    /// to the last Rust line.
+    pub fn line_directive(&mut self, file: &str) -> io::Result<()> {
+        // The directive is on the next line, and numbers the one after it
+        let line = self.writer.lines() + 2;
+        write!(self.writer, "#line {} ", line)?;
+        write_c_string(file, &mut self.writer)?;
+        writeln!(self.writer)
+    }
+
     /// Emits 3 comment lines to separate different pieces of output.
     pub fn separator(&mut self, name: &str) -> io::Result<()> {
         writeln!(self.writer)?;
@@ -143,7 +586,7 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
 
 macro_rules! prim {
     ($fnname:ident $tname:ident) => {
-        fn $fnname(&self) -> TypeRef<'a> {
+        pub fn $fnname(&self) -> TypeRef<'a> {
             TypeRef(&Type::$tname)
         }
     };
@@ -156,9 +599,11 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
     prim!(u16 U16);
     prim!(i16 I16);
     prim!(u32 U32);
-    prim!(i32 U32);
+    prim!(i32 I32);
     prim!(u64 U64);
     prim!(i64 I64);
+    prim!(u128 U128);
+    prim!(i128 I128);
     prim!(float Float);
     prim!(double Double);
     prim!(intptr IntPtr);
@@ -173,6 +618,66 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
         TypeRef(self.arena.alloc(Type::Pointer(pointee.as_type())))
     }
 
+    /// Builds an array type containing `len` elements of type `elem`.
+    pub fn array_of(&mut self, elem: TypeRef<'a>, len: usize) -> TypeRef<'a> {
+        TypeRef(self.arena.alloc(Type::Array { ty: elem.0, len }))
+    }
+
+    /// Builds a `const`-qualified version of `ty`.
+    pub fn const_of<Ty: AsType<'a>>(&mut self, ty: Ty) -> TypeRef<'a> {
+        TypeRef(self.arena.alloc(Type::Const(ty.as_type())))
+    }
+
+    /// Returns the type of fat pointers to slices with element type `elem`.
+    ///
+    /// `&str` is represented as a fat pointer to `uint8_t`.
+    pub fn slice_ptr<Ty: AsType<'a>>(&mut self, elem: Ty) -> TypeRef<'a> {
+        TypeRef(self.arena.alloc(Type::FatPointer {
+            pointee: elem.as_type(),
+            meta: PtrMeta::Len,
+        }))
+    }
+
+    /// Returns the type of the start of vtables, which every vtable shares.
+    ///
+    /// Vtables of traits with methods are structs with this type as their
+    /// first field, followed by an array of the (type-erased) methods, so
+    /// that they have the same layout as the LLVM backend's vtables.
+    pub fn vtable(&self) -> TypeRef<'a> {
+        TypeRef(&Type::Struct { name: VTABLE })
+    }
+
+    /// Returns the type of fat pointers to trait objects.
+    pub fn dyn_ptr(&self) -> TypeRef<'a> {
+        TypeRef(&Type::FatPointer {
+            pointee: &Type::Void,
+            meta: PtrMeta::Vtable,
+        })
+    }
+
+    /// Splits a fat pointer type into the types of its data pointer and its
+    /// metadata.
+    ///
+    /// Fat pointers are passed to and returned from functions as 2 separate
+    /// scalars, the same way the Rust ABI passes them.
+    pub fn fat_ptr_parts(&mut self, ty: TypeRef<'a>) -> Option<(TypeRef<'a>, TypeRef<'a>)> {
+        let (pointee, meta) = match ty.0 {
+            Type::FatPointer { pointee, meta } => (pointee, *meta),
+            _ => return None,
+        };
+
+        let data = TypeRef(self.arena.alloc(Type::Pointer(pointee)));
+        let meta = match meta {
+            PtrMeta::Len => self.uintptr(),
+            PtrMeta::Vtable => {
+                let vtable = self.vtable();
+                let vtable = self.const_of(vtable);
+                self.ptr_to(vtable)
+            }
+        };
+        Some((data, meta))
+    }
+
     pub fn fn_sig(&mut self, ret: Option<TypeRef<'a>>, args: &[TypeRef<'a>]) -> FnSig<'a> {
         let ret = ret.unwrap_or(TypeRef(&Type::Void));
         FnSig {
@@ -191,7 +696,7 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
             self.writer,
             "struct {};  /* {} */",
             sym.mangled(),
-            sym.rust()
+            escape_comment(sym.rust())
         )?;
         let name = self.arena.alloc_str(&sym.mangled());
         Ok(IncompleteTypeRef(
@@ -205,7 +710,7 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
             self.writer,
             "union {};  /* {} */",
             sym.mangled(),
-            sym.rust()
+            escape_comment(sym.rust())
         )?;
         let name = self.arena.alloc_str(&sym.mangled());
         Ok(IncompleteTypeRef(self.arena.alloc(Type::FwdUnion { name })))
@@ -215,22 +720,27 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
         &mut self,
         name: Name<'_>,
         proto: FnSig<'a>,
+        attrs: FnAttrs,
     ) -> io::Result<Function<'a>> {
+        attrs.write_prefix(self.dialect, &mut self.writer)?;
         proto.declare(&*name.mangled(), &mut self.writer)?;
-        let fnty = self.fn_ptr(proto);
-        writeln!(self.writer, "; /* {} */", name.rust())?;
+        name.write_asm_label(&mut self.writer)?;
+        writeln!(self.writer, "; /* {} */", escape_comment(name.rust()))?;
         Ok(Function {
             sig: proto,
-            ptr_ty: fnty,
             name: self.arena.alloc_str(&name.mangled()),
+            attrs,
         })
     }
 
+    /// Defines a `struct` or `union` (as given by `kind`), with the GCC type
+    /// attributes `attrs` if they aren't empty.
     fn define_named_type<'f, F>(
         &mut self,
         kind: &str,
         sym: Name<'_>,
         fields: F,
+        attrs: &str,
     ) -> io::Result<TypeRef<'a>>
     where
         F: IntoIterator<Item = (&'f str, TypeRef<'a>)>,
@@ -247,18 +757,27 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
             "{} {} {{  /* {} */",
             kind,
             sym.mangled(),
-            sym.rust()
+            escape_comment(sym.rust())
         )?;
         for (field, ty) in fields {
             write!(self.writer, "    ")?;
             ty.declare_variable(field, &mut self.writer)?;
             writeln!(self.writer, ";")?;
         }
-        writeln!(self.writer, "}};")?;
+        if attrs.is_empty() {
+            writeln!(self.writer, "}};")?;
+        } else {
+            writeln!(self.writer, "}} __attribute__(({}));", attrs)?;
+        }
 
         let name = self.arena.alloc_str(&sym.mangled());
         self.defd_types.insert(name);
-        Ok(TypeRef(self.arena.alloc(Type::Struct { name })))
+        let ty = if kind == "union" {
+            Type::Union { name }
+        } else {
+            Type::Struct { name }
+        };
+        Ok(TypeRef(self.arena.alloc(ty)))
     }
 
     /// Defines a `struct` with field names and types.
@@ -267,7 +786,7 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
     where
         F: IntoIterator<Item = (&'f str, TypeRef<'a>)>,
     {
-        self.define_named_type("struct", sym, fields)
+        self.define_named_type("struct", sym, fields, "")
     }
 
     /// Defines a `union` with field names and types.
@@ -275,41 +794,213 @@ impl<'a, W: WriteStr> TranslationUnitBuilder<'a, W> {
     where
         F: IntoIterator<Item = (&'f str, TypeRef<'a>)>,
     {
-        self.define_named_type("union", sym, fields)
+        self.define_named_type("union", sym, fields, "")
+    }
+
+    /// Defines a `struct` (or a `union` if `union` is set) with GCC's `packed`
+    /// attribute, so that the C compiler inserts no padding, and the
+    /// alignment `align`.
+    pub fn define_packed<'f, F>(
+        &mut self,
+        union: bool,
+        sym: Name<'_>,
+        fields: F,
+        align: u64,
+    ) -> io::Result<TypeRef<'a>>
+    where
+        F: IntoIterator<Item = (&'f str, TypeRef<'a>)>,
+    {
+        let kind = if union { "union" } else { "struct" };
+        let attrs = format!("packed, aligned({})", align);
+        self.define_named_type(kind, sym, fields, &attrs)
+    }
+
+    /// Defines a `struct` (or a `union` if `union` is set) with GCC's
+    /// `aligned` attribute, which raises its alignment to `align`.
+    pub fn define_aligned<'f, F>(
+        &mut self,
+        union: bool,
+        sym: Name<'_>,
+        fields: F,
+        align: u64,
+    ) -> io::Result<TypeRef<'a>>
+    where
+        F: IntoIterator<Item = (&'f str, TypeRef<'a>)>,
+    {
+        let kind = if union { "union" } else { "struct" };
+        let attrs = format!("aligned({})", align);
+        self.define_named_type(kind, sym, fields, &attrs)
+    }
+
+    /// Defines a vector type of `count` elements of the scalar type `elem`,
+    /// using GCC's vector extensions.
+    ///
+    /// `count` must be a power of 2.
+    pub fn define_vector(
+        &mut self,
+        sym: Name<'_>,
+        elem: TypeRef<'a>,
+        count: usize,
+    ) -> io::Result<TypeRef<'a>> {
+        write!(self.writer, "typedef ")?;
+        elem.declare_variable(&*sym.mangled(), &mut self.writer)?;
+        writeln!(
+            self.writer,
+            " __attribute__((vector_size({} * sizeof({})))); /* {} */",
+            count,
+            elem.name(),
+            escape_comment(sym.rust())
+        )?;
+        let name = self.arena.alloc_str(&sym.mangled());
+        self.defd_types.insert(name);
+        Ok(TypeRef(self.arena.alloc(Type::Vector {
+            name,
+            elem: elem.0,
+            count,
+        })))
+    }
+
+    /// Declares `sym` as an alias of the (possibly incomplete) type `ty`,
+    /// returning the alias.
+    pub fn typedef<Ty: AsType<'a>>(&mut self, sym: Name<'_>, ty: Ty) -> io::Result<Ty> {
+        write!(self.writer, "typedef ")?;
+        let name = self.arena.alloc_str(&sym.mangled());
+        TypeRef(ty.as_type()).declare_variable(&*name, &mut self.writer)?;
+        writeln!(self.writer, "; /* {} */", escape_comment(sym.rust()))?;
+        Ok(ty.with_type(self.arena.alloc(Type::Typedef {
+            name,
+            ty: ty.as_type(),
+        })))
+    }
+
+    /// Declares a global variable defined in another TU.
+    pub fn fwd_declare_global(
+        &mut self,
+        sym: Name<'_>,
+        ty: TypeRef<'a>,
+        attrs: GlobalAttrs,
+    ) -> io::Result<Global<'a>> {
+        assert!(!attrs.internal, "declaring internal global {}", sym.rust());
+        attrs.write_prefix(self.dialect, true, &mut self.writer)?;
+        ty.declare_variable(&*sym.mangled(), &mut self.writer)?;
+        sym.write_asm_label(&mut self.writer)?;
+        writeln!(self.writer, "; /* {} */", escape_comment(sym.rust()))?;
+        Ok(Global {
+            ty,
+            name: self.arena.alloc_str(&sym.mangled()),
+        })
+    }
+
+    /// Defines a global variable, optionally initialized to `init`.
+    pub fn define_global(
+        &mut self,
+        sym: Name<'_>,
+        ty: TypeRef<'a>,
+        attrs: GlobalAttrs,
+        init: Option<&Expr<'_>>,
+    ) -> io::Result<Global<'a>> {
+        attrs.write_prefix(self.dialect, false, &mut self.writer)?;
+        ty.declare_variable(&*sym.mangled(), &mut self.writer)?;
+        sym.write_asm_label(&mut self.writer)?;
+        if let Some(init) = init {
+            write!(self.writer, " = {}", init)?;
+        }
+        writeln!(self.writer, "; /* {} */", escape_comment(sym.rust()))?;
+        Ok(Global {
+            ty,
+            name: self.arena.alloc_str(&sym.mangled()),
+        })
+    }
+
+    /// Emits a static assertion that `cond` holds, which makes the C compiler
+    /// reject the TU with `msg` otherwise.
+    ///
+    /// Before C11, this declares an array type that has a negative size if
+    /// `cond` doesn't hold, and `msg` is only left in a comment.
+    pub fn static_assert(&mut self, cond: &str, msg: &str) -> io::Result<()> {
+        if self.dialect.has_static_assert() {
+            return writeln!(self.writer, "_Static_assert({}, \"{}\");", cond, msg);
+        }
+        self.static_asserts += 1;
+        writeln!(
+            self.writer,
+            "typedef char r2c2_static_assert_{}[({}) ? 1 : -1]; /* {} */",
+            self.static_asserts,
+            cond,
+            escape_comment(msg)
+        )
     }
 
     /// Starts defining a function, returning a `FunctionBuilder`.
+    ///
+    /// Assembler labels can't be attached to function definitions, so if
+    /// `name` has a separate link name, the function must have been declared
+    /// with `fwd_declare_function` first.
     pub fn define_function<'b>(
         &'b mut self,
         arena: &'b Arena,
         name: Name<'_>,
         proto: FnSig<'a>,
-    ) -> io::Result<FunctionBuilder<'b, W>> {
+        attrs: FnAttrs,
+    ) -> io::Result<FunctionBuilder<'b, LineCounter<W>>> {
         Ok(FunctionBuilder::create(
             &mut self.writer,
             arena,
+            self.dialect,
             name,
             proto,
+            attrs,
+            &[],
         )?)
     }
 }
 
 /// A declared function.
+#[derive(Copy, Clone)]
 pub struct Function<'a> {
     sig: FnSig<'a>,
-    ptr_ty: TypeRef<'a>,
+    name: &'a str,
+    attrs: FnAttrs,
+}
+
+impl<'a> Function<'a> {
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn sig(&self) -> FnSig<'a> {
+        self.sig
+    }
+
+    /// Returns the attributes the function was declared with, which its
+    /// definition has to use as well.
+    pub fn attrs(&self) -> FnAttrs {
+        self.attrs
+    }
+}
+
+/// A declared or defined global variable.
+#[derive(Copy, Clone)]
+pub struct Global<'a> {
+    ty: TypeRef<'a>,
     name: &'a str,
 }
 
+impl<'a> Global<'a> {
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn ty(&self) -> TypeRef<'a> {
+        self.ty
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::test::compile_test;
+    use super::attrs::{InlineHint, TlsModel, Visibility};
+    use super::test::{compile_test, compile_test_in};
     use super::*;
-    use utils::StringWriter;
-
-    use cc::Build;
-    use std::error::Error;
-    use tempfile::{self, TempDir};
 
     #[test]
     fn fwd_declare() {
@@ -366,6 +1057,37 @@ mod tests {
         });
     }
 
+    #[test]
+    fn define_packed() {
+        compile_test("define_packed", |f| {
+            let u8 = f.u8();
+            let u32 = f.u32();
+            f.define_packed(false, Name::test("packed"), vec![("a", u8), ("b", u32)], 1)?;
+            f.define_packed(true, Name::test("packed2"), vec![("a", u8), ("b", u32)], 2)?;
+            f.static_assert("sizeof(struct packed) == 5", "packed struct has padding")?;
+            f.static_assert("sizeof(union packed2) == 4", "packed union has padding")?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn define_aligned() {
+        compile_test("define_aligned", |f| {
+            let u8 = f.u8();
+            let u32 = f.u32();
+            f.define_aligned(
+                false,
+                Name::test("aligned"),
+                vec![("a", u8), ("b", u32)],
+                16,
+            )?;
+            f.define_aligned(true, Name::test("aligned2"), vec![("a", u8)], 8)?;
+            f.static_assert("sizeof(struct aligned) == 16", "struct isn't padded")?;
+            f.static_assert("sizeof(union aligned2) == 8", "union isn't padded")?;
+            Ok(())
+        });
+    }
+
     /// Defines a struct containing function pointer fields.
     #[test]
     fn define_struct_fn_ptr() {
@@ -373,7 +1095,7 @@ mod tests {
             let i = f.i32();
             let pi = f.ptr_to(i);
             let v = f.void();
-            let pv = f.ptr_to(v);
+            let _pv = f.ptr_to(v);
             let b = f.bool();
             let fn_sig = f.fn_sig(Some(pi), &[i, i, b]);
             let fn_ptr = f.fn_ptr(fn_sig);
@@ -398,9 +1120,9 @@ mod tests {
             let b = f.bool();
 
             let sig = f.fn_sig(Some(pi), &[i, i, b]);
-            f.fwd_declare_function(Name::test("myfn"), sig)?;
+            f.fwd_declare_function(Name::test("myfn"), sig, FnAttrs::default())?;
             let sig = f.fn_sig(None, &[]);
-            f.fwd_declare_function(Name::test("empty"), sig)?;
+            f.fwd_declare_function(Name::test("empty"), sig, FnAttrs::default())?;
             Ok(())
         });
     }
@@ -415,7 +1137,270 @@ mod tests {
             // TODO: Test more way to declare fns (returning fn ptr, nothing, taking no args, ...)
             let sig = f.fn_sig(Some(pi), &[i, i, b]);
             let arena = Arena::new();
-            let b = f.define_function(&arena, Name::test("myfn"), sig)?;
+            let b = f.define_function(&arena, Name::test("myfn"), sig, FnAttrs::default())?;
+            b.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn define_vtable() {
+        // Vtables are constant expressions in ISO C
+        compile_test_in("define_vtable", Dialect::C99, Environment::Hosted, |f| {
+            let v = f.void();
+            let pv = f.ptr_to(v);
+            let drop_sig = f.fn_sig(None, &[pv]);
+            let drop_ptr = f.fn_ptr(drop_sig);
+            let drop = f.fwd_declare_function(Name::test("drop"), drop_sig, FnAttrs::default())?;
+            let u32 = f.u32();
+            let method_sig = f.fn_sig(Some(u32), &[pv]);
+            let method =
+                f.fwd_declare_function(Name::test("method"), method_sig, FnAttrs::default())?;
+
+            let header = f.vtable();
+            let erased_sig = f.fn_sig(None, &[]);
+            let erased = f.fn_ptr(erased_sig);
+            let methods = f.array_of(erased, 2);
+            let vtable_ty = f.define_struct(
+                Name::test("vtable_2"),
+                vec![("header", header), ("methods", methods)],
+            )?;
+            let init = Expr::InitList(vec![
+                Expr::InitList(vec![
+                    Expr::from(drop).cast(drop_ptr),
+                    Expr::Uint(16),
+                    Expr::Uint(8),
+                ]),
+                Expr::InitList(vec![Expr::from(method).cast(erased), Expr::Uint(0)]),
+            ]);
+            let attrs = GlobalAttrs {
+                internal: true,
+                constant: true,
+                ..GlobalAttrs::default()
+            };
+            let vtable = f.define_global(Name::test("vtable"), vtable_ty, attrs, Some(&init))?;
+
+            let dyn_ptr = f.dyn_ptr();
+            let init = Expr::InitList(vec![
+                Expr::Uint(0),
+                Expr::from(vtable).field("header").addr_of(),
+            ]);
+            f.define_global(Name::test("obj"), dyn_ptr, attrs, Some(&init))?;
+            f.define_struct(Name::test("has_dyn"), vec![("obj", dyn_ptr)])?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn fat_ptr_parts() {
+        compile_test("fat_ptr_parts", |f| {
+            let u8 = f.u8();
+            let str_ptr = f.slice_ptr(u8);
+            let dyn_ptr = f.dyn_ptr();
+
+            let mut args = Vec::new();
+            for &ty in &[str_ptr, dyn_ptr] {
+                let (data, meta) = f.fat_ptr_parts(ty).unwrap();
+                args.push(data);
+                args.push(meta);
+            }
+            let sig = f.fn_sig(Some(str_ptr), &args);
+            f.fwd_declare_function(Name::test("split"), sig, FnAttrs::default())?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn fwd_declare_noreturn() {
+        compile_test("fwd_declare_noreturn", |f| {
+            let sig = f.fn_sig(None, &[]);
+            let attrs = FnAttrs {
+                noreturn: true,
+                ..FnAttrs::default()
+            };
+            f.fwd_declare_function(Name::test("diverge"), sig, attrs)?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn static_assert() {
+        compile_test("static_assert", |f| {
+            f.static_assert("sizeof(uint32_t) == 4", "uint32_t has the wrong size")?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn link_name() {
+        compile_test("link_name", |f| {
+            let sig = f.fn_sig(None, &[]);
+            let symbol = "_ZN39_$LT$test..Foo$u20$as$u20$test..Bar$GT$3bar17h0123456789abcdefE";
+            let name = Name::for_symbol("<test::Foo as test::Bar>::bar", symbol, "");
+            f.fwd_declare_function(name, sig, FnAttrs::default())?;
+            let name = Name::for_symbol("<test::Foo as test::Bar>::bar", symbol, "");
+            let arena = Arena::new();
+            f.define_function(&arena, name, sig, FnAttrs::default())?
+                .finish()?;
+
+            let i = f.i32();
+            let symbol = "_ZN4test3foo28_$u7b$$u7b$closure$u7d$$u7d$6STATIC17h0123456789abcdefE";
+            let name = Name::for_symbol("test::foo::{{closure}}::STATIC", symbol, "");
+            f.define_global(name, i, GlobalAttrs::default(), None)?;
+
+            let name = Name::for_symbol("test::memcpy", "memcpy", "");
+            f.fwd_declare_function(name, sig, FnAttrs::default())?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn thread_local() {
+        compile_test("thread_local", |f| {
+            let i = f.i32();
+            let attrs = GlobalAttrs {
+                thread_local: true,
+                ..GlobalAttrs::default()
+            };
+            f.fwd_declare_global(Name::test("extern_tls"), i, attrs)?;
+            let attrs = GlobalAttrs {
+                internal: true,
+                thread_local: true,
+                tls_model: Some(TlsModel::InitialExec),
+                ..GlobalAttrs::default()
+            };
+            f.define_global(Name::test("tls"), i, attrs, Some(&Expr::Uint(1)))?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn typedef() {
+        compile_test("typedef", |f| {
+            let fwd = f.fwd_declare_struct(Name::test("list"))?;
+            let list = f.typedef(Name::test("List"), fwd)?;
+            let list_ptr = f.ptr_to(list);
+            let i32 = f.i32();
+            f.define_struct(Name::test("list"), vec![("next", list_ptr), ("value", i32)])?;
+            // The alias is complete once the struct is
+            let list = TypeRef(list.0);
+            f.define_global(Name::test("head"), list, GlobalAttrs::default(), None)?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn linkage_attrs() {
+        compile_test("linkage_attrs", |f| {
+            let sig = f.fn_sig(None, &[]);
+            let attrs = FnAttrs {
+                weak: true,
+                visibility: Visibility::Hidden,
+                section: Some(Symbol::intern(".text.r2c2")),
+                cold: true,
+                inline: InlineHint::Never,
+                ..FnAttrs::default()
+            };
+            f.fwd_declare_function(Name::test("weak_fn"), sig, attrs)?;
+
+            let attrs = FnAttrs {
+                internal: true,
+                inline: InlineHint::Always,
+                ..FnAttrs::default()
+            };
+            f.fwd_declare_function(Name::test("internal_fn"), sig, attrs)?;
+            let arena = Arena::new();
+            f.define_function(&arena, Name::test("internal_fn"), sig, attrs)?
+                .finish()?;
+
+            let i = f.i32();
+            let attrs = GlobalAttrs {
+                weak: true,
+                visibility: Visibility::Protected,
+                section: Some(Symbol::intern(".data.r2c2")),
+                ..GlobalAttrs::default()
+            };
+            f.define_global(Name::test("weak_global"), i, attrs, None)?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn target_features() {
+        compile_test("target_features", |f| {
+            let sig = f.fn_sig(None, &[]);
+            let attrs = FnAttrs {
+                target_features: Some(Symbol::intern("avx2,popcnt")),
+                ..FnAttrs::default()
+            };
+            let arena = Arena::new();
+            f.define_function(&arena, Name::test("avx2_fn"), sig, attrs)?
+                .finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn c89() {
+        compile_test_in("c89", Dialect::C89, Environment::Hosted, |f| {
+            f.static_assert("sizeof(uint64_t) == 8", "uint64_t has the wrong size")?;
+            let sig = f.fn_sig(None, &[]);
+            let attrs = FnAttrs {
+                internal: true,
+                inline: InlineHint::Hint,
+                ..FnAttrs::default()
+            };
+            let bool = f.bool();
+            let intptr = f.intptr();
+            let arena = Arena::new();
+            let mut b = f.define_function(&arena, Name::test("c89_fn"), sig, attrs)?;
+            let flag = b.declare_variable("flag", bool, None)?;
+            b.assign(&Expr::from(flag), &Expr::Ident("true".into()))?;
+            let n = b.declare_variable("n", intptr, Some("declared after a statement"))?;
+            b.assign(&Expr::from(n), &Expr::Uint(0))?;
+            b.finish()?;
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reserved_idents() {
+        compile_test_in("reserved_idents", Dialect::C89, Environment::Hosted, |f| {
+            let i32 = f.i32();
+            let names = RESERVED_IDENTS
+                .iter()
+                .map(|&ident| unreserved_ident(ident.to_string()))
+                .collect::<Vec<_>>();
+            f.define_struct(
+                Name::test("reserved"),
+                names.iter().map(|name| (&**name, i32)),
+            )?;
+            for name in &names {
+                f.typedef(Name::test(name), i32)?;
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn freestanding() {
+        let environment = Environment::Freestanding {
+            memcpy_fallback: true,
+            memmove_fallback: true,
+            memset_fallback: false,
+            weak_fallbacks: true,
+        };
+        compile_test_in("freestanding", Dialect::C11, environment, |f| {
+            let sig = f.fn_sig(None, &[]);
+            let i64 = f.i64();
+            let arena = Arena::new();
+            let mut b = f.define_function(&arena, Name::test("copy"), sig, FnAttrs::default())?;
+            let src = b.declare_variable("src", i64, None)?;
+            let dst = b.declare_variable("dst", i64, None)?;
+            b.assign(&Expr::from(src), &Expr::Uint(1))?;
+            b.memcpy(&Expr::from(dst).addr_of(), &Expr::from(src).addr_of(), 8)?;
+            b.trap()?;
             b.finish()?;
             Ok(())
         });
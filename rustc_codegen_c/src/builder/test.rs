@@ -2,9 +2,9 @@
 
 #![cfg(test)]
 
-use super::function::FunctionBuilder;
-use super::{FnSig, Symbol, TranslationUnitBuilder, TypeRef};
-use utils::{StringWriter, WriteStr};
+use super::dialect::{Dialect, Environment};
+use super::TranslationUnitBuilder;
+use utils::StringWriter;
 
 use cc::Build;
 use std::error::Error;
@@ -15,20 +15,30 @@ use tempfile::{self, TempDir};
 use toolshed::Arena;
 
 /// Result to return from unit tests (instead of panicking).
-pub type TestResult = Result<(), Box<Error>>;
+pub type TestResult = Result<(), Box<dyn Error>>;
 
 /// Calls the test function `f` with a `TranslationUnitBuilder` and tests
 /// that the produced output can be compiled by the system's C compiler.
 ///
 /// The output is also snapshot-tested using `insta`.
 pub fn compile_test<F>(name: &str, f: F)
+where
+    F: FnOnce(&mut TranslationUnitBuilder<'_, StringWriter>) -> TestResult,
+{
+    compile_test_in(name, Dialect::default(), Environment::default(), f)
+}
+
+/// Like `compile_test`, but writes and compiles the output in the C dialect
+/// `dialect`, for the C implementation `environment`.
+pub fn compile_test_in<F>(name: &str, dialect: Dialect, environment: Environment, f: F)
 where
     F: FnOnce(&mut TranslationUnitBuilder<'_, StringWriter>) -> TestResult,
 {
     GLOBALS.set(&Globals::new(DEFAULT_EDITION), || {
         let arena = Arena::new();
-        let mut writer = StringWriter(String::new());
-        let mut builder = TranslationUnitBuilder::create(&arena, writer).unwrap();
+        let writer = StringWriter(String::new());
+        let mut builder =
+            TranslationUnitBuilder::create(&arena, writer, dialect, environment).unwrap();
 
         f(&mut builder).unwrap();
 
@@ -36,13 +46,18 @@ where
 
         let mut f = tempfile::Builder::new().suffix(".c").tempfile().unwrap();
         f.write_all(&builder.writer().0.as_bytes()).unwrap();
-        Build::new()
+        let mut build = Build::new();
+        if let Environment::Freestanding { .. } = environment {
+            build.flag("-ffreestanding");
+        }
+        build
             .file(f.path())
             .cargo_metadata(false)
             .out_dir(&dir)
             .target(::utils::TARGET)
             .host(::utils::HOST)
             .opt_level(0)
+            .flag(&format!("-std={}", dialect.name()))
             .compile("foo");
         let output = builder.into_writer().0;
         assert_snapshot_matches!(name, output);
@@ -5,7 +5,46 @@
 use utils::{StringWriter, WriteStr};
 
 use std::fmt;
-use std::io::{self, Write};
+use std::io;
+
+/// The metadata stored next to the data pointer in a fat pointer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PtrMeta {
+    /// The number of elements of a slice or bytes of a `str`.
+    Len,
+    /// A pointer to the vtable of a trait object.
+    Vtable,
+}
+
+impl PtrMeta {
+    /// Returns the name of the fat pointer struct field storing the metadata.
+    pub fn field(self) -> &'static str {
+        match self {
+            PtrMeta::Len => "len",
+            PtrMeta::Vtable => "vtable",
+        }
+    }
+
+    /// Returns the name of the fat pointer struct defined in the preamble.
+    fn struct_name(self) -> &'static str {
+        match self {
+            PtrMeta::Len => "r2c2_slice_ptr",
+            PtrMeta::Vtable => "r2c2_dyn_ptr",
+        }
+    }
+}
+
+/// Name of the fat pointer struct field storing the data pointer.
+pub const FAT_PTR_DATA: &str = "data";
+
+/// Name of the struct defined in the preamble that all vtables start with.
+pub const VTABLE: &str = "r2c2_vtable";
+
+/// Names of the fields of the vtable struct storing the drop glue, size and
+/// alignment of the type.
+pub const VTABLE_DROP_IN_PLACE: &str = "drop_in_place";
+pub const VTABLE_SIZE: &str = "size";
+pub const VTABLE_ALIGN: &str = "align";
 
 /// A function signature.
 #[derive(Copy, Clone)]
@@ -20,15 +59,29 @@ impl FnSig<'_> {
     /// The output will not contain a trailing `;`. This makes this function
     /// also useful for declaring function pointer variables/static and casts.
     pub fn declare(&self, name: impl Into<String>, w: &mut impl WriteStr) -> io::Result<()> {
+        let arg_names = (1..=self.args.len())
+            .map(|i| format!("_{}", i))
+            .collect::<Vec<_>>();
+        self.declare_with_arg_names(name, &arg_names, w)
+    }
+
+    /// Like `declare`, but names the arguments `arg_names` instead of `_1`,
+    /// `_2`, ...
+    pub fn declare_with_arg_names(
+        &self,
+        name: impl Into<String>,
+        arg_names: &[String],
+        w: &mut impl WriteStr,
+    ) -> io::Result<()> {
         // this is perverse
         let mut buf = StringWriter(name.into());
         buf.push('(');
-        for (i, arg) in self.args.iter().enumerate() {
+        for (i, (arg, arg_name)) in self.args.iter().zip(arg_names).enumerate() {
             if i != 0 {
                 buf.push_str(", ");
             }
 
-            arg.declare_variable(format!("_{}", i + 1), &mut buf)?;
+            arg.declare_variable(arg_name, &mut buf)?;
         }
         if self.args.is_empty() {
             // `void` ensures the compiler doesn't let us call the function with arguments
@@ -51,6 +104,10 @@ pub enum Type<'a> {
     I32,
     U64,
     I64,
+    /// `unsigned __int128`, a GCC extension.
+    U128,
+    /// `__int128`, a GCC extension.
+    I128,
     Float,
     Double,
     Pointer(&'a Type<'a>),
@@ -63,9 +120,36 @@ pub enum Type<'a> {
         len: usize,
     },
     FunctionPointer(FnSig<'a>),
+    /// A pointer to an unsized value, consisting of an untyped data pointer
+    /// and metadata (`struct r2c2_slice_ptr` or `struct r2c2_dyn_ptr`).
+    FatPointer {
+        /// The type the data pointer points to (the element type for slices
+        /// and `void` for trait objects).
+        pointee: &'a Type<'a>,
+        meta: PtrMeta,
+    },
+    /// A `const`-qualified type.
+    Const(&'a Type<'a>),
+    /// A vector of `count` scalars using GCC's vector extensions, declared by
+    /// a `typedef` named `name`.
+    Vector {
+        name: &'a str,
+        elem: &'a Type<'a>,
+        count: usize,
+    },
     Struct {
         name: &'a str,
     },
+    Union {
+        name: &'a str,
+    },
+    /// An alias of `ty` declared by a `typedef`.
+    ///
+    /// Whether the alias is complete depends on `ty`.
+    Typedef {
+        name: &'a str,
+        ty: &'a Type<'a>,
+    },
 
     // Incomplete types
     Void,
@@ -91,11 +175,20 @@ impl Type<'_> {
             Type::I32 => "int32_t",
             Type::U64 => "uint64_t",
             Type::I64 => "int64_t",
+            Type::U128 => "unsigned __int128",
+            Type::I128 => "__int128",
             Type::Float => "float",
             Type::Double => "double",
             Type::IntPtr => "intptr_t",
             Type::UintPtr => "uintptr_t",
-            Type::Pointer(pointee) => return pointee.declare_variable(&format!("* {}", name), w),
+            Type::Pointer(pointee) => {
+                // `[]` binds tighter than `*`, so pointers to arrays need parentheses
+                let name = match pointee {
+                    Type::Array { .. } => format!("(* {})", name),
+                    _ => format!("* {}", name),
+                };
+                return pointee.declare_variable(&name, w);
+            }
             Type::Array { ty, len } => {
                 return ty.declare_variable(&format!("{}[{}]", name, len), w)
             }
@@ -107,7 +200,19 @@ impl Type<'_> {
             Type::FwdStruct { name: ty } | Type::Struct { name: ty } => {
                 return write!(w, "struct {} {}", ty, name)
             }
-            Type::FwdUnion { name: ty } => return write!(w, "union {} {}", ty, name),
+            Type::FwdUnion { name: ty } | Type::Union { name: ty } => {
+                return write!(w, "union {} {}", ty, name)
+            }
+            Type::FatPointer { meta, .. } => {
+                return write!(w, "struct {} {}", meta.struct_name(), name)
+            }
+            Type::Const(ty) => {
+                write!(w, "const ")?;
+                return ty.declare_variable(name, w);
+            }
+            Type::Vector { name: ty, .. } | Type::Typedef { name: ty, .. } => {
+                return write!(w, "{} {}", ty, name)
+            }
             // the void case is needed to declare functions and fn pointers
             Type::Void => return write!(w, "void {}", name),
         };
@@ -128,6 +233,9 @@ impl fmt::Debug for Type<'_> {
 /// that can work with either.
 pub trait AsType<'a> {
     fn as_type(&self) -> &'a Type<'a>;
+
+    /// Wraps a type that is as complete as `self`.
+    fn with_type(&self, ty: &'a Type<'a>) -> Self;
 }
 
 /// Reference to a complete C type.
@@ -138,9 +246,13 @@ impl<'a> AsType<'a> for TypeRef<'a> {
     fn as_type(&self) -> &'a Type<'a> {
         self.0
     }
+
+    fn with_type(&self, ty: &'a Type<'a>) -> Self {
+        TypeRef(ty)
+    }
 }
 
-impl TypeRef<'_> {
+impl<'a> TypeRef<'a> {
     /// Declares a variable of this type and prints the declaration to `f`.
     ///
     /// Output is normally of the form `type name`. Note that no trailing `;` is
@@ -148,13 +260,32 @@ impl TypeRef<'_> {
     ///
     /// This can be used to declare local and global variables as well as struct
     /// fields.
-    pub fn declare_variable<W: WriteStr>(
-        &self,
-        name: impl AsRef<str>,
-        w: &mut W,
-    ) -> io::Result<()> {
+    pub fn declare_variable<W: WriteStr>(self, name: impl AsRef<str>, w: &mut W) -> io::Result<()> {
         self.0.declare_variable(name.as_ref(), w)
     }
+
+    /// Returns the kind of metadata if this is a fat pointer type.
+    pub fn fat_ptr_meta(self) -> Option<PtrMeta> {
+        match self.0 {
+            Type::FatPointer { meta, .. } => Some(*meta),
+            _ => None,
+        }
+    }
+
+    /// Returns the element type and lane count if this is a vector type.
+    pub fn vector_lanes(self) -> Option<(TypeRef<'a>, usize)> {
+        match self.0 {
+            Type::Vector { elem, count, .. } => Some((TypeRef(elem), *count)),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of this type, as used in casts.
+    pub fn name(self) -> String {
+        let mut buf = StringWriter(String::new());
+        self.0.declare_variable("", &mut buf).unwrap();
+        buf.0.trim_end().to_string()
+    }
 }
 
 /// Reference to an incomplete C type.
@@ -165,4 +296,8 @@ impl<'a> AsType<'a> for IncompleteTypeRef<'a> {
     fn as_type(&self) -> &'a Type<'a> {
         self.0
     }
+
+    fn with_type(&self, ty: &'a Type<'a>) -> Self {
+        IncompleteTypeRef(ty)
+    }
 }
@@ -0,0 +1,325 @@
+//! Lowering of rustc's `FnAbi` to C function signatures.
+//!
+//! Functions using a C-compatible ABI (`extern "C"`, `extern "system"`, ...)
+//! take and return all values by value, and the C compiler applies the
+//! platform's calling convention, just like it does for C code.
+//!
+//! In both cases, aggregates with a `Scalar` layout (eg. `#[repr(transparent)]`
+//! structs, fieldless `#[repr(C)]` enums and `Option<&T>`) are passed as their
+//! scalar, like LLVM passes them. Passing them as a C struct wouldn't match,
+//! since some calling conventions (eg. i386 SysV) return all structs through
+//! a hidden pointer.
+//!
+//! Functions using the Rust ABI are declared so that they follow the same
+//! conventions as the LLVM backend's `FnAbi` lowering: zero-sized arguments
+//! are dropped, scalar pairs are passed as 2 arguments, small aggregates are
+//! passed as integers, and large ones by pointer. This makes them callable
+//! from (and able to call) code compiled by the LLVM backend.
+//!
+//! Scalar pairs are returned as an LLVM `{ a, b }` struct, which LLVM returns
+//! in 2 registers even if both scalars would fit in one. C compilers pack
+//! small structs into as few registers as possible, so the pair is returned
+//! as a C struct that gives each scalar a register of its own under the
+//! target's C calling convention (see `pair_return_type`).
+
+use super::context::CodegenCx;
+use builder::types::{FnSig, TypeRef};
+use builder::Name;
+use utils::WriteStr;
+
+use rustc::ty::layout::{self, FnAbiExt, TyLayout};
+use rustc::ty::{self, Instance, Ty, TyCtxt};
+use rustc_target::abi::call::{ArgAbi, FnAbi, PassMode};
+use rustc_target::spec::abi::Abi;
+use std::io;
+use syntax_pos::{Span, DUMMY_SP};
+
+/// How a value is passed to or returned from a C function.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CPassMode {
+    /// Not passed at all.
+    Ignore,
+    /// Passed as a single value of the value's C type.
+    ByValue,
+    /// Passed as the 2 scalars making up a `ScalarPair` layout.
+    ///
+    /// Return values are returned as a single struct instead, with the
+    /// fields `PAIR_RETURN_FIELDS`.
+    Pair,
+    /// Passed as the C type of the scalar of a `Scalar` layout, or as an
+    /// unsigned integer type at least as large as the value (see
+    /// `cast_target`).
+    Cast,
+    /// Passed as a pointer to the value.
+    ///
+    /// For return values, the pointer is passed as the first argument, and
+    /// the callee writes the return value to it.
+    Indirect,
+}
+
+/// The fields of the struct scalar pairs are returned as, which hold the
+/// first and second scalar.
+pub const PAIR_RETURN_FIELDS: [&str; 2] = ["a", "b"];
+
+/// A function's ABI, lowered to a C signature.
+pub struct CFnAbi<'a, 'tcx> {
+    /// The C signature of the function.
+    pub sig: FnSig<'a>,
+    /// How the return value is passed.
+    pub ret: (CPassMode, TyLayout<'tcx>),
+    /// How each Rust argument is passed, in order.
+    pub args: Vec<(CPassMode, TyLayout<'tcx>)>,
+}
+
+/// Returns whether functions with the `abi` ABI use rustc's unstable Rust
+/// calling convention.
+fn is_rust_abi(abi: Abi) -> bool {
+    match abi {
+        Abi::Rust | Abi::RustCall | Abi::RustIntrinsic | Abi::PlatformIntrinsic => true,
+        _ => false,
+    }
+}
+
+/// Returns whether `ty` is lowered to a C scalar type, which is passed like
+/// the scalar of its layout.
+pub fn is_c_scalar(ty: Ty<'_>) -> bool {
+    match ty.kind {
+        ty::Bool
+        | ty::Char
+        | ty::Int(_)
+        | ty::Uint(_)
+        | ty::Float(_)
+        | ty::Ref(..)
+        | ty::RawPtr(_)
+        | ty::FnPtr(_) => true,
+        ty::Adt(def, _) => def.is_box(),
+        _ => false,
+    }
+}
+
+/// Returns the ABI `instance` is defined with.
+fn instance_abi<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> Abi {
+    let ty = instance.ty(tcx);
+    match ty.kind {
+        // Closures take their arguments as a tuple
+        ty::Closure(..) => Abi::RustCall,
+        ty::Generator(..) => Abi::Rust,
+        _ => ty.fn_sig(tcx).abi(),
+    }
+}
+
+impl<'a, 'tcx, W: WriteStr> CodegenCx<'a, 'tcx, W> {
+    /// Lowers the ABI of a function instance.
+    pub fn fn_abi_of_instance(&mut self, instance: Instance<'tcx>) -> io::Result<CFnAbi<'a, 'tcx>> {
+        let fn_abi = FnAbi::of_instance(self, instance, &[]);
+        let span = self.tcx.def_span(instance.def_id());
+        self.lower_fn_abi(&fn_abi, instance_abi(self.tcx, instance), span)
+    }
+
+    /// Lowers the ABI of calls through a function pointer of type `sig`.
+    ///
+    /// Function pointer types have no span, so unsupported ABIs are reported
+    /// without one.
+    pub fn fn_abi_of_fn_ptr(&mut self, sig: ty::PolyFnSig<'tcx>) -> io::Result<CFnAbi<'a, 'tcx>> {
+        let fn_abi = FnAbi::of_fn_ptr(self, sig, &[]);
+        self.lower_fn_abi(&fn_abi, sig.abi(), DUMMY_SP)
+    }
+
+    /// Lowers `fn_abi`, reporting anything that can't be lowered at `span`.
+    fn lower_fn_abi(
+        &mut self,
+        fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+        abi: Abi,
+        span: Span,
+    ) -> io::Result<CFnAbi<'a, 'tcx>> {
+        if fn_abi.c_variadic {
+            self.tcx.sess.span_fatal(
+                span,
+                "C-variadic functions aren't supported by the C codegen backend",
+            );
+        }
+
+        let rust_abi = is_rust_abi(abi);
+        let mut c_args = Vec::new();
+
+        let ret_mode = self.pass_mode(&fn_abi.ret, rust_abi, true, span);
+        let ret = match ret_mode {
+            CPassMode::Ignore => None,
+            CPassMode::ByValue => self.c_type(fn_abi.ret.layout.ty)?,
+            CPassMode::Pair => Some(self.pair_return_type(fn_abi.ret.layout)?),
+            CPassMode::Cast => Some(self.cast_target(fn_abi.ret.layout)),
+            CPassMode::Indirect => {
+                let ret = self.c_type(fn_abi.ret.layout.ty)?.unwrap();
+                c_args.push(self.tu.ptr_to(ret));
+                None
+            }
+        };
+
+        let mut args = Vec::new();
+        for arg in &fn_abi.args {
+            let mode = self.pass_mode(arg, rust_abi, false, span);
+            match mode {
+                CPassMode::Ignore => {}
+                CPassMode::ByValue => c_args.push(self.by_value_type(arg.layout)?),
+                CPassMode::Pair => {
+                    let (a, b) = self.pair_types(arg.layout)?;
+                    c_args.push(a);
+                    c_args.push(b);
+                }
+                CPassMode::Cast => c_args.push(self.cast_target(arg.layout)),
+                CPassMode::Indirect => {
+                    let pointee = self.c_type(arg.layout.ty)?.unwrap();
+                    c_args.push(self.tu.ptr_to(pointee));
+                }
+            }
+            args.push((mode, arg.layout));
+        }
+
+        Ok(CFnAbi {
+            sig: self.tu.fn_sig(ret, &c_args),
+            ret: (ret_mode, fn_abi.ret.layout),
+            args,
+        })
+    }
+
+    /// Determines how an argument or return value is passed in C.
+    fn pass_mode(
+        &self,
+        arg: &ArgAbi<'tcx, Ty<'tcx>>,
+        rust_abi: bool,
+        is_ret: bool,
+        span: Span,
+    ) -> CPassMode {
+        match arg.mode {
+            PassMode::Ignore => CPassMode::Ignore,
+            // Aggregates with a scalar layout are passed as the scalar
+            PassMode::Direct(_) if !is_c_scalar(arg.layout.ty) => match arg.layout.abi {
+                layout::Abi::Scalar(_) => CPassMode::Cast,
+                // Scalar pairs are returned directly, as an LLVM `{ a, b }`
+                // struct
+                layout::Abi::ScalarPair(..) if rust_abi && is_ret => CPassMode::Pair,
+                _ => CPassMode::ByValue,
+            },
+            // The C compiler does the same lowering for C ABIs
+            _ if !rust_abi => CPassMode::ByValue,
+            PassMode::Direct(_) => CPassMode::ByValue,
+            PassMode::Pair(..) => CPassMode::Pair,
+            PassMode::Cast(_) if arg.layout.size.bytes() > 16 => self.tcx.sess.span_fatal(
+                span,
+                &format!(
+                    "the C codegen backend can't pass the {}-byte value `{}` as an integer",
+                    arg.layout.size.bytes(),
+                    arg.layout.ty
+                ),
+            ),
+            PassMode::Cast(_) => CPassMode::Cast,
+            PassMode::Indirect(_, None) => CPassMode::Indirect,
+            PassMode::Indirect(_, Some(_)) => self.tcx.sess.span_fatal(
+                span,
+                &format!(
+                    "unsized arguments (`{}`) aren't supported by the C codegen backend",
+                    arg.layout.ty
+                ),
+            ),
+        }
+    }
+
+    /// Returns the C type a value of layout `layout` is passed as when
+    /// passed as `CPassMode::ByValue`.
+    ///
+    /// The receiver of a virtual call keeps the type of the pointer to the
+    /// trait object, but has the layout of a thin pointer, since only the
+    /// data pointer is passed. It is passed as the scalar of its layout.
+    fn by_value_type(&mut self, layout: TyLayout<'tcx>) -> io::Result<TypeRef<'a>> {
+        let c_ty = self.c_type(layout.ty)?.unwrap();
+        match layout.abi {
+            layout::Abi::Scalar(ref scalar) if self.tu.fat_ptr_parts(c_ty).is_some() => {
+                Ok(self.scalar_type(scalar.value))
+            }
+            _ => Ok(c_ty),
+        }
+    }
+
+    /// Returns the C types of the 2 halves of a `ScalarPair` layout.
+    pub fn pair_types(&mut self, layout: TyLayout<'tcx>) -> io::Result<(TypeRef<'a>, TypeRef<'a>)> {
+        let c_ty = self.c_type(layout.ty)?.unwrap();
+        if let Some(parts) = self.tu.fat_ptr_parts(c_ty) {
+            return Ok(parts);
+        }
+
+        match layout.abi {
+            layout::Abi::ScalarPair(ref a, ref b) => {
+                Ok((self.scalar_type(a.value), self.scalar_type(b.value)))
+            }
+            _ => bug!("`PassMode::Pair` for non-pair layout {:?}", layout),
+        }
+    }
+
+    /// Returns the struct a scalar pair of layout `layout` is returned as.
+    ///
+    /// On x86-64 (except on Windows), the C calling convention returns
+    /// structs of up to 16 bytes in 2 registers, one for each 8-byte half,
+    /// which is where LLVM returns the 2 scalars of a pair. The first scalar
+    /// is thus padded to 8 bytes, with padding of its own type so that the
+    /// half is still returned in an integer or SSE register like the scalar.
+    /// Structs with a scalar larger than 8 bytes are returned through a
+    /// hidden pointer in both cases.
+    ///
+    /// Other calling conventions aren't supported yet.
+    pub fn pair_return_type(&mut self, layout: TyLayout<'tcx>) -> io::Result<TypeRef<'a>> {
+        if let Some(&ty) = self.pair_returns.get(&layout.ty) {
+            return Ok(ty);
+        }
+
+        let target = &self.tcx.sess.target.target;
+        if target.arch != "x86_64" || target.options.is_like_windows {
+            self.tcx.sess.fatal(&format!(
+                "the C codegen backend can't return `{}` from Rust functions on {}",
+                layout.ty, target.llvm_target
+            ));
+        }
+
+        let (a, b) = self.pair_types(layout)?;
+        let a_size = match layout.abi {
+            layout::Abi::ScalarPair(ref a, _) => a.value.size(&*self).bytes(),
+            // Fat pointers
+            _ => self.tcx.data_layout.pointer_size.bytes(),
+        };
+        let mut fields = vec![(PAIR_RETURN_FIELDS[0], a)];
+        if a_size < 8 {
+            let padding = self.tu.array_of(a, (8 / a_size - 1) as usize);
+            fields.push(("padding", padding));
+        }
+        fields.push((PAIR_RETURN_FIELDS[1], b));
+
+        let name = Name::for_type_part(layout.ty, "ret", self.tcx);
+        let ty = self.tu.define_struct(name, fields)?;
+        self.pair_returns.insert(layout.ty, ty);
+        Ok(ty)
+    }
+
+    /// Returns the C type a value of layout `layout` is passed as when
+    /// passed as `CPassMode::Cast`.
+    pub fn cast_target(&mut self, layout: TyLayout<'tcx>) -> TypeRef<'a> {
+        match layout.abi {
+            layout::Abi::Scalar(ref scalar) => self.scalar_type(scalar.value),
+            _ => self.cast_type(layout),
+        }
+    }
+
+    /// Returns the smallest unsigned integer type at least as large as a
+    /// value of layout `layout`.
+    ///
+    /// Values of up to 16 bytes are supported, which `pass_mode` checks for
+    /// arguments.
+    pub fn cast_type(&mut self, layout: TyLayout<'tcx>) -> TypeRef<'a> {
+        match layout.size.bytes() {
+            1 => self.tu.u8(),
+            2 => self.tu.u16(),
+            3..=4 => self.tu.u32(),
+            5..=8 => self.tu.u64(),
+            9..=16 => self.scalar_type(layout::Primitive::Int(layout::Integer::I128, false)),
+            size => bug!("{}-byte value {} cast to an integer", size, layout.ty),
+        }
+    }
+}
@@ -1 +1,109 @@
-// allocator shim codegen
+//! The allocator shim, which forwards the `__rust_*` allocation functions to
+//! the `#[global_allocator]` or to the default allocator of `std`.
+//!
+//! This is the counterpart of `librustc_codegen_llvm/allocator.rs`.
+
+use super::{attributes, environment, Module};
+use builder::attrs::{FnAttrs, Visibility};
+use builder::expr::Expr;
+use builder::{Name, TranslationUnitBuilder};
+use compiler::Compiler;
+use utils::StringWriter;
+
+use toolshed::Arena;
+
+use rustc::ty::TyCtxt;
+use std::io;
+use syntax::expand::allocator::{AllocatorKind, AllocatorTy, ALLOCATOR_METHODS};
+use syntax_pos::DUMMY_SP;
+
+/// Defines the allocator shim for `kind` in the allocator module `module`.
+pub fn codegen(tcx: TyCtxt<'_>, compiler: &Compiler, module: &mut Module, kind: AllocatorKind) {
+    // The shim is only exported from dylibs if the target exports hidden
+    // symbols by default
+    let visibility = if tcx.sess.target.target.options.default_hidden_visibility {
+        Visibility::Hidden
+    } else {
+        Visibility::Default
+    };
+    let fn_attrs = |visibility| {
+        let attrs = FnAttrs {
+            visibility,
+            ..FnAttrs::default()
+        };
+        attributes::supported_fn_attrs(tcx.sess, compiler.capabilities(), DUMMY_SP, attrs)
+    };
+
+    let arena = Arena::new();
+    let source = (|| {
+        let mut tu = TranslationUnitBuilder::create(
+            &arena,
+            StringWriter(String::new()),
+            compiler.dialect,
+            environment(tcx, compiler.capabilities()),
+        )?;
+        let usize = tu.uintptr();
+        let u8 = tu.u8();
+        let u8_ptr = tu.ptr_to(u8);
+
+        for method in ALLOCATOR_METHODS {
+            let mut args = Vec::new();
+            for ty in method.inputs {
+                match *ty {
+                    // Layouts are passed as their size and alignment
+                    AllocatorTy::Layout => {
+                        args.push(usize);
+                        args.push(usize);
+                    }
+                    AllocatorTy::Ptr => args.push(u8_ptr),
+                    AllocatorTy::Usize => args.push(usize),
+                    AllocatorTy::ResultPtr | AllocatorTy::Unit => {
+                        bug!("invalid allocator argument {}", method.name)
+                    }
+                }
+            }
+            let output = match method.output {
+                AllocatorTy::ResultPtr => Some(u8_ptr),
+                AllocatorTy::Unit => None,
+                AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
+                    bug!("invalid allocator output {}", method.name)
+                }
+            };
+            let sig = tu.fn_sig(output, &args);
+
+            let callee_name = kind.fn_name(method.name);
+            let callee = tu.fwd_declare_function(
+                Name::for_symbol(&*callee_name, &callee_name, ""),
+                sig,
+                fn_attrs(Visibility::Hidden),
+            )?;
+
+            let name = format!("__rust_{}", method.name);
+            let fn_arena = Arena::new();
+            let mut bx = tu.define_function(
+                &fn_arena,
+                Name::for_symbol(&*name, &name, ""),
+                sig,
+                fn_attrs(visibility),
+            )?;
+            let args = bx.args.iter().map(|&arg| Expr::from(arg)).collect();
+            let call = Expr::from(callee).call(args);
+            if output.is_some() {
+                bx.ret(Some(&call))?;
+            } else {
+                bx.eval(&call)?;
+                bx.ret(None)?;
+            }
+            bx.finish()?;
+        }
+        Ok(tu.into_writer().0)
+    })()
+    .unwrap_or_else(|e: io::Error| {
+        tcx.sess.fatal(&format!(
+            "couldn't write the C code of the allocator shim: {}",
+            e
+        ))
+    });
+
+    module.source = source;
+}
@@ -0,0 +1,214 @@
+//! Translation of LLVM-style inline assembly to GNU C extended `asm`.
+//!
+//! `asm!` passes its template and constraints to LLVM unchanged, so they
+//! have to be rewritten into GCC's syntax:
+//!
+//! * Operands are referenced as `$0`/`${0:k}` instead of `%0`/`%k0`, and
+//!   `$$` is a literal `$` (while GCC needs `%%` for a literal `%`).
+//! * Constraints mostly use the same letters, but registers are named
+//!   explicitly (`{eax}`), and indirect operands are marked with `*` instead
+//!   of being passed as lvalues.
+//! * Clobbers are register names or `memory`/`cc`, like in GCC.
+//!
+//! Anything that can't be translated is reported as an error, since silently
+//! miscompiling assembly would be much worse.
+
+use syntax::ast::AsmDialect;
+
+/// Translates the assembly template `asm` of a statement with
+/// `operand_count` operands.
+pub fn template(asm: &str, dialect: AsmDialect, operand_count: usize) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = asm.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => out.push_str("%%"),
+            '$' => match chars.next() {
+                Some('$') => out.push('$'),
+                Some('{') => {
+                    let operand = chars.by_ref().take_while(|&c| c != '}').collect::<String>();
+                    let mut parts = operand.splitn(2, ':');
+                    let index = parts.next().unwrap();
+                    let modifier = parts.next().unwrap_or("");
+                    check_operand(index, operand_count)?;
+                    out.push('%');
+                    out.push_str(modifier);
+                    out.push_str(index);
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    let mut index = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if !c.is_ascii_digit() {
+                            break;
+                        }
+                        index.push(c);
+                        chars.next();
+                    }
+                    check_operand(&index, operand_count)?;
+                    out.push('%');
+                    out.push_str(&index);
+                }
+                _ => return Err("stray `$` in inline assembly (use `$$` for a literal `$`)".into()),
+            },
+            c => out.push(c),
+        }
+    }
+
+    match dialect {
+        AsmDialect::Att => Ok(out),
+        // GCC substitutes operands in AT&T syntax unless the whole TU is
+        // compiled with `-masm=intel`
+        AsmDialect::Intel if operand_count > 0 => Err(
+            "Intel-syntax inline assembly with operands can't be translated to GCC's syntax \
+             (use AT&T syntax instead)"
+                .into(),
+        ),
+        AsmDialect::Intel => Ok(format!(
+            ".intel_syntax noprefix\n{}\n.att_syntax prefix",
+            out
+        )),
+    }
+}
+
+fn check_operand(index: &str, operand_count: usize) -> Result<(), String> {
+    match index.parse::<usize>() {
+        Ok(index) if index < operand_count => Ok(()),
+        _ => Err(format!("invalid operand `{}` in inline assembly", index)),
+    }
+}
+
+/// Translates the constraint of an output operand.
+///
+/// Read-write outputs (`+r`) have their `+` replaced by `=` by the parser,
+/// and it is restored here.
+pub fn output_constraint(constraint: &str, is_rw: bool, arch: &str) -> Result<String, String> {
+    let constraint = operand_constraint(constraint.trim_start_matches('='), arch)?;
+    Ok(format!("{}{}", if is_rw { '+' } else { '=' }, constraint))
+}
+
+/// Translates the constraint of an input operand.
+pub fn input_constraint(constraint: &str, arch: &str) -> Result<String, String> {
+    operand_constraint(constraint, arch)
+}
+
+fn operand_constraint(constraint: &str, arch: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = constraint.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // Indirect operands are passed as the lvalue they point to
+            '*' => {}
+            '{' => {
+                let reg = chars.by_ref().take_while(|&c| c != '}').collect::<String>();
+                out.push_str(register_constraint(&reg, arch)?);
+            }
+            '=' | '+' | '~' | '|' | '!' => {
+                return Err(format!(
+                    "inline assembly constraint `{}` has no GCC equivalent",
+                    constraint
+                ))
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Returns the GCC constraint letter selecting the register `reg`.
+///
+/// GCC can only name a few x86 registers in constraints.
+fn register_constraint(reg: &str, arch: &str) -> Result<&'static str, String> {
+    let letter = match arch {
+        "x86" | "x86_64" => match reg {
+            "al" | "ax" | "eax" | "rax" => Some("a"),
+            "bl" | "bx" | "ebx" | "rbx" => Some("b"),
+            "cl" | "cx" | "ecx" | "rcx" => Some("c"),
+            "dl" | "dx" | "edx" | "rdx" => Some("d"),
+            "si" | "esi" | "rsi" => Some("S"),
+            "di" | "edi" | "rdi" => Some("D"),
+            _ => None,
+        },
+        _ => None,
+    };
+    letter.ok_or_else(|| {
+        format!(
+            "inline assembly constraint `{{{}}}` has no GCC equivalent (GCC can't select \
+             this register in a constraint)",
+            reg
+        )
+    })
+}
+
+/// Translates a clobber, returning `None` if GCC always assumes it.
+pub fn clobber(clobber: &str, arch: &str) -> Option<String> {
+    let clobber = clobber.trim_start_matches("~{").trim_end_matches('}');
+    match (arch, clobber) {
+        // rustc adds these to all x86 assembly. GCC always assumes that the
+        // flags are clobbered, and has no clobbers for the direction flag and
+        // FPU status word (which must be restored per the ABI anyway).
+        ("x86", "flags") | ("x86_64", "flags") => Some("cc".to_string()),
+        ("x86", "dirflag") | ("x86_64", "dirflag") | ("x86", "fpsr") | ("x86_64", "fpsr") => None,
+        _ => Some(clobber.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_operands() {
+        assert_eq!(
+            template("mov $1, $0", AsmDialect::Att, 2),
+            Ok("mov %1, %0".to_string())
+        );
+        assert_eq!(
+            template("movb ${1:h}, ${0:k}", AsmDialect::Att, 2),
+            Ok("movb %h1, %k0".to_string())
+        );
+        assert_eq!(template("$10", AsmDialect::Att, 11), Ok("%10".to_string()));
+    }
+
+    #[test]
+    fn template_escapes() {
+        assert_eq!(
+            template("movl $$1, %eax", AsmDialect::Att, 0),
+            Ok("movl $1, %%eax".to_string())
+        );
+        assert!(template("movl $", AsmDialect::Att, 0).is_err());
+    }
+
+    #[test]
+    fn template_out_of_range() {
+        assert!(template("mov $1, $0", AsmDialect::Att, 1).is_err());
+        assert!(template("${2:k}", AsmDialect::Att, 2).is_err());
+        assert!(template("${x}", AsmDialect::Att, 1).is_err());
+    }
+
+    #[test]
+    fn template_intel() {
+        assert_eq!(
+            template("nop", AsmDialect::Intel, 0),
+            Ok(".intel_syntax noprefix\nnop\n.att_syntax prefix".to_string())
+        );
+        assert!(template("mov $0, 1", AsmDialect::Intel, 1).is_err());
+    }
+
+    #[test]
+    fn register_constraints() {
+        assert_eq!(input_constraint("{ecx}", "x86_64"), Ok("c".to_string()));
+        assert_eq!(
+            output_constraint("={rax}", false, "x86_64"),
+            Ok("=a".to_string())
+        );
+        assert_eq!(
+            output_constraint("=*m", true, "x86_64"),
+            Ok("+m".to_string())
+        );
+        // GCC has no constraints for most registers
+        assert!(input_constraint("{r8}", "x86_64").is_err());
+        assert!(input_constraint("{xmm0}", "x86_64").is_err());
+        assert!(input_constraint("{x0}", "aarch64").is_err());
+        assert!(input_constraint("~r", "x86_64").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Computes the C attributes of codegen'd functions and statics.
+//!
+//! This is the counterpart of `librustc_codegen_llvm/attributes.rs`.
+
+use builder::attrs::{FnAttrs, GlobalAttrs, InlineHint, TlsModel, Visibility};
+use compiler::Capabilities;
+use target_features;
+
+use rustc::hir::def_id::{DefId, LOCAL_CRATE};
+use rustc::hir::CodegenFnAttrFlags;
+use rustc::mir::mono::{self, Linkage};
+use rustc::session::Session;
+use rustc::ty::{self, Instance, TyCtxt};
+use syntax::attr::InlineAttr;
+use syntax::symbol::Symbol;
+use syntax_pos::{Span, DUMMY_SP};
+
+/// Determines the `FnAttrs` to declare `instance` with, if it isn't defined
+/// in the current TU.
+pub fn fn_attrs<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> FnAttrs {
+    let codegen_fn_attrs = tcx.codegen_fn_attrs(instance.def_id());
+
+    let inline = match codegen_fn_attrs.inline {
+        InlineAttr::Always => InlineHint::Always,
+        InlineAttr::Never => InlineHint::Never,
+        InlineAttr::Hint => InlineHint::Hint,
+        // Shims are instantiated in every CGU using them, like `#[inline]`
+        // functions
+        InlineAttr::None if instance.def.requires_local(tcx) => InlineHint::Hint,
+        InlineAttr::None => InlineHint::None,
+    };
+
+    FnAttrs {
+        noreturn: diverges(tcx, instance),
+        section: codegen_fn_attrs.link_section,
+        cold: codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::COLD),
+        inline,
+        naked: codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::NAKED),
+        target_features: target_features_attr(tcx.sess, &codegen_fn_attrs.target_features),
+        ..FnAttrs::default()
+    }
+}
+
+/// Returns whether `instance` returns `!`, so it can never return to its
+/// caller.
+fn diverges<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> bool {
+    let ty = instance.ty(tcx);
+    let sig = match ty.kind {
+        ty::Closure(def_id, substs) => substs.as_closure().sig(def_id, tcx),
+        // Generators are resumed, and return `GeneratorState`
+        ty::Generator(..) => return false,
+        _ => ty.fn_sig(tcx),
+    };
+    tcx.normalize_erasing_late_bound_regions(ty::ParamEnv::reveal_all(), &sig)
+        .output()
+        .is_never()
+}
+
+/// Returns the value of GCC's `target` attribute enabling the features of
+/// `#[target_feature]`, if any are enabled.
+fn target_features_attr(sess: &Session, features: &[Symbol]) -> Option<Symbol> {
+    if features.is_empty() {
+        return None;
+    }
+    let arch = &sess.target.target.arch;
+    let names = features
+        .iter()
+        .map(
+            |feature| match target_features::find(arch, &feature.as_str()) {
+                Some(feature) => feature.gcc_name(),
+                // rustc checks the features against the whitelist
+                None => bug!("unknown target feature `{}`", feature),
+            },
+        )
+        .collect::<Vec<_>>();
+    Some(Symbol::intern(&names.join(",")))
+}
+
+/// Determines the `FnAttrs` to declare and define `instance` with, when it
+/// is defined in the current TU with `linkage` and `visibility`.
+pub fn defined_fn_attrs<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    instance: Instance<'tcx>,
+    linkage: Linkage,
+    visibility: mono::Visibility,
+) -> FnAttrs {
+    let mut attrs = fn_attrs(tcx, instance);
+    let (internal, weak, visibility) = linkage_attrs(tcx, linkage, visibility, instance.def_id());
+    attrs.internal = internal;
+    attrs.weak = weak;
+    attrs.visibility = visibility;
+    attrs
+}
+
+/// Translates the `linkage` and `visibility` the item `def_id` is defined
+/// with to whether it's internal, whether it's weak, and its C visibility.
+///
+/// The other linkages (from `#[linkage]`) are reported as unsupported.
+fn linkage_attrs(
+    tcx: TyCtxt<'_>,
+    linkage: Linkage,
+    visibility: mono::Visibility,
+    def_id: DefId,
+) -> (bool, bool, Visibility) {
+    let (internal, weak) = match linkage {
+        Linkage::External => (false, false),
+        Linkage::Internal | Linkage::Private => (true, false),
+        // Generic instantiations and inline functions are emitted into every
+        // CGU using them, and the linker has to pick one of the copies
+        Linkage::LinkOnceAny | Linkage::LinkOnceODR | Linkage::WeakAny | Linkage::WeakODR => {
+            (false, true)
+        }
+        Linkage::AvailableExternally => unsupported_linkage(tcx, def_id, "available_externally"),
+        Linkage::Appending => unsupported_linkage(tcx, def_id, "appending"),
+        Linkage::ExternalWeak => unsupported_linkage(tcx, def_id, "extern_weak"),
+        Linkage::Common => unsupported_linkage(tcx, def_id, "common"),
+    };
+
+    let visibility = if internal {
+        Visibility::default()
+    } else if tcx.is_compiler_builtins(LOCAL_CRATE) {
+        // Like the LLVM backend, don't export `compiler_builtins`' symbols
+        // from dylibs, so they don't conflict with the system's
+        Visibility::Hidden
+    } else {
+        match visibility {
+            mono::Visibility::Default => Visibility::Default,
+            mono::Visibility::Hidden => Visibility::Hidden,
+            mono::Visibility::Protected => Visibility::Protected,
+        }
+    };
+    (internal, weak, visibility)
+}
+
+/// Reports that the item `def_id` has the `#[linkage]` `name`, which can't be
+/// expressed in C.
+fn unsupported_linkage(tcx: TyCtxt<'_>, def_id: DefId, name: &str) -> ! {
+    tcx.sess.span_fatal(
+        tcx.def_span(def_id),
+        &format!(
+            "`#[linkage = \"{}\"]` isn't supported by the C codegen backend",
+            name
+        ),
+    )
+}
+
+/// Determines the `GlobalAttrs` to declare and define the static `def_id`
+/// with.
+pub fn static_attrs(tcx: TyCtxt<'_>, def_id: DefId) -> GlobalAttrs {
+    let ty = tcx.type_of(def_id);
+    let codegen_fn_attrs = tcx.codegen_fn_attrs(def_id);
+    let thread_local = codegen_fn_attrs
+        .flags
+        .contains(CodegenFnAttrFlags::THREAD_LOCAL);
+
+    GlobalAttrs {
+        // Statics with interior mutability are written through shared
+        // references
+        constant: !tcx.is_mutable_static(def_id)
+            && ty.is_freeze(tcx, ty::ParamEnv::reveal_all(), DUMMY_SP),
+        thread_local,
+        tls_model: if thread_local {
+            tls_model(tcx.sess)
+        } else {
+            None
+        },
+        ..GlobalAttrs::default()
+    }
+}
+
+/// Determines the `GlobalAttrs` to declare and define the static `def_id`
+/// with, when it is defined in the current TU with `linkage` and
+/// `visibility`.
+pub fn defined_static_attrs(
+    tcx: TyCtxt<'_>,
+    def_id: DefId,
+    linkage: Linkage,
+    visibility: mono::Visibility,
+) -> GlobalAttrs {
+    let mut attrs = static_attrs(tcx, def_id);
+    let (internal, weak, visibility) = linkage_attrs(tcx, linkage, visibility, def_id);
+    attrs.internal = internal;
+    attrs.weak = weak;
+    attrs.visibility = visibility;
+    attrs.section = tcx.codegen_fn_attrs(def_id).link_section;
+    attrs
+}
+
+/// Returns the TLS model selected with `-Z tls-model`, if any.
+pub fn tls_model(sess: &Session) -> Option<TlsModel> {
+    sess.opts.debugging_opts.tls_model.as_ref().map(|name| {
+        TlsModel::from_name(name).unwrap_or_else(|| {
+            sess.fatal(&format!(
+                "invalid TLS model `{}` (see `--print tls-models`)",
+                name
+            ))
+        })
+    })
+}
+
+/// Adapts the attributes of the function at `span` to a C compiler with
+/// `capabilities`.
+///
+/// Compilers without GNU attributes get no hints (`noreturn`, `cold`,
+/// inlining and visibility), and attributes the generated code relies on
+/// are reported as errors.
+pub fn supported_fn_attrs(
+    sess: &Session,
+    capabilities: Capabilities,
+    span: Span,
+    attrs: FnAttrs,
+) -> FnAttrs {
+    if capabilities.attributes {
+        return attrs;
+    }
+    report_required_attrs(
+        sess,
+        span,
+        &[
+            (attrs.weak, "weak linkage"),
+            (attrs.naked, "`#[naked]` functions"),
+            (attrs.target_features.is_some(), "`#[target_feature]`"),
+        ],
+    );
+    FnAttrs {
+        noreturn: false,
+        visibility: Visibility::Default,
+        cold: false,
+        // `inline` is a keyword, the rest are attributes
+        inline: match attrs.inline {
+            InlineHint::Always => InlineHint::Hint,
+            InlineHint::Never => InlineHint::None,
+            hint => hint,
+        },
+        ..attrs
+    }
+}
+
+/// Adapts the attributes of the static at `span` to a C compiler with
+/// `capabilities`, like `supported_fn_attrs`.
+pub fn supported_static_attrs(
+    sess: &Session,
+    capabilities: Capabilities,
+    span: Span,
+    attrs: GlobalAttrs,
+) -> GlobalAttrs {
+    if capabilities.attributes {
+        return attrs;
+    }
+    report_required_attrs(sess, span, &[(attrs.weak, "weak linkage")]);
+    GlobalAttrs {
+        visibility: Visibility::Default,
+        tls_model: None,
+        ..attrs
+    }
+}
+
+/// Reports the first of the `required` attributes that is present, as not
+/// supported by the C compiler.
+fn report_required_attrs(sess: &Session, span: Span, required: &[(bool, &str)]) {
+    if let Some(&(_, what)) = required.iter().find(|&&(present, _)| present) {
+        sess.span_fatal(
+            span,
+            &format!("{} requires a C compiler supporting GNU attributes", what),
+        );
+    }
+}
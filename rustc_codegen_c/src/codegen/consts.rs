@@ -0,0 +1,530 @@
+//! Constant memory: the definitions of statics, and the allocations
+//! constants refer to (eg. the bytes of string literals).
+//!
+//! This is the counterpart of `librustc_codegen_llvm/consts.rs`. rustc
+//! evaluates the initializers to allocations, ie. bytes with relocations
+//! where pointers to other allocations, statics or functions are stored. An
+//! allocation is lowered to a `struct` with a `uint8_t` array per run of
+//! bytes and a `void *` per pointer, which is initialized with the pointer's
+//! target. To give the `struct` the alignment of the allocation, it's wrapped
+//! in a `union` with a member of the value's type (or of a scalar type with
+//! that alignment).
+//!
+//! The global holding an allocation has that `union` type, so the value in it
+//! is accessed through a pointer cast to the value's C type. Allocations with
+//! pointers at unaligned offsets (in packed structs) and over-aligned
+//! allocations can't be laid out this way, so their `struct` is packed and
+//! aligned with GCC's attributes instead, which not all C compilers
+//! support.
+//!
+//! Slice constants (`&str` and `&[u8]` literals) are lowered to internal
+//! `struct r2c2_slice_ptr` globals pointing into an allocation. Other
+//! constants that aren't scalars, and promoted constants, are read from the
+//! allocation they evaluate to.
+
+use super::attributes;
+use super::context::CodegenCx;
+use super::ty::field_name;
+use builder::attrs::GlobalAttrs;
+use builder::expr::Expr;
+use builder::types::TypeRef;
+use builder::{Global, Name};
+use utils::WriteStr;
+
+use rustc::hir::def_id::DefId;
+use rustc::mir::interpret::{
+    read_target_uint, write_target_uint, AllocId, Allocation, ConstValue, GlobalAlloc, GlobalId,
+};
+use rustc::mir::mono::{Linkage, Visibility};
+use rustc::mir::Promoted;
+use rustc::ty::layout::{Align, LayoutOf};
+use rustc::ty::{self, Instance, Ty};
+use std::io;
+use syntax::ast::{FloatTy, Mutability};
+use syntax_pos::{Span, DUMMY_SP};
+
+/// The member of the `union` holding an allocation that its bytes are
+/// initialized through.
+const ALLOC_BYTES: &str = "bytes";
+
+/// The member of the `union` holding an allocation that aligns it.
+const ALLOC_VALUE: &str = "value";
+
+/// A part of the `struct` laying out an allocation.
+enum Chunk {
+    /// A run of `len` bytes without pointers.
+    Bytes { offset: usize, len: usize },
+    /// A pointer (of the target's pointer size), pointing to `offset` bytes
+    /// into `alloc_id`.
+    Ptr { alloc_id: AllocId, offset: u64 },
+}
+
+impl<'a, 'tcx, W: WriteStr> CodegenCx<'a, 'tcx, W> {
+    /// Declares the static `def_id`, which is defined in this TU with
+    /// `linkage` and `visibility`.
+    ///
+    /// The static's type depends on its initializer, so this also evaluates
+    /// it and defines the type of the allocation it evaluates to. Statics
+    /// whose initializer can't be evaluated are skipped, since rustc has
+    /// already reported the error.
+    pub fn predefine_static(
+        &mut self,
+        def_id: DefId,
+        linkage: Linkage,
+        visibility: Visibility,
+    ) -> io::Result<()> {
+        let instance = Instance::mono(self.tcx, def_id);
+        let ty = instance.ty(self.tcx);
+        if self.layout_of(ty).is_zst() {
+            return Ok(());
+        }
+        let alloc = match self.static_alloc(def_id) {
+            Some(alloc) => alloc,
+            None => return Ok(()),
+        };
+
+        let span = self.tcx.def_span(def_id);
+        let attrs = attributes::defined_static_attrs(self.tcx, def_id, linkage, visibility);
+        let attrs = self.check_static_attrs(def_id, attrs);
+        let name = self.instance_name(&instance, attrs.internal);
+        let value_ty = self.c_type(ty)?;
+        let alloc_ty = self.define_alloc_type(alloc, &name.mangled(), value_ty, span)?;
+
+        // Internal statics are declared by a tentative definition, which the
+        // definition completes
+        let global = if attrs.internal {
+            self.tu.define_global(name, alloc_ty, attrs, None)?
+        } else {
+            self.tu.fwd_declare_global(name, alloc_ty, attrs)?
+        };
+        self.statics.insert(def_id, global);
+        Ok(())
+    }
+
+    /// Defines the static `def_id`, which has been declared by
+    /// `predefine_static`.
+    pub fn codegen_static(
+        &mut self,
+        def_id: DefId,
+        linkage: Linkage,
+        visibility: Visibility,
+    ) -> io::Result<()> {
+        let global = match self.statics.get(&def_id) {
+            Some(&global) => global,
+            // Zero-sized, or its initializer couldn't be evaluated
+            None => return Ok(()),
+        };
+        let alloc = self.static_alloc(def_id).unwrap();
+        let init = self.alloc_init(alloc)?;
+
+        let instance = Instance::mono(self.tcx, def_id);
+        let attrs = attributes::defined_static_attrs(self.tcx, def_id, linkage, visibility);
+        let attrs = self.check_static_attrs(def_id, attrs);
+        let name = self.instance_name(&instance, attrs.internal);
+        self.tu
+            .define_global(name, global.ty(), attrs, Some(&init))?;
+        Ok(())
+    }
+
+    /// Returns the allocation the initializer of the static `def_id`
+    /// evaluates to, or `None` if it can't be evaluated.
+    fn static_alloc(&self, def_id: DefId) -> Option<&'tcx Allocation> {
+        let cid = GlobalId {
+            instance: Instance::mono(self.tcx, def_id),
+            promoted: None,
+        };
+        match self.tcx.const_eval(ty::ParamEnv::reveal_all().and(cid)) {
+            Ok(&ty::Const {
+                val: ty::ConstKind::Value(ConstValue::ByRef { alloc, offset }),
+                ..
+            }) if offset.bytes() == 0 => Some(alloc),
+            Ok(value) => bug!("static {:?} evaluated to {:?}", def_id, value),
+            Err(_) => None,
+        }
+    }
+
+    /// Reports thread-local statics the C dialect or compiler can't express,
+    /// and `#[link_section]` if the compiler doesn't support it, and returns
+    /// the attributes the compiler supports (see
+    /// `attributes::supported_static_attrs`).
+    pub fn check_static_attrs(&self, def_id: DefId, attrs: GlobalAttrs) -> GlobalAttrs {
+        let span = self.tcx.def_span(def_id);
+        if attrs.thread_local && self.tu.dialect().thread_local_keyword().is_none() {
+            self.tcx.sess.span_fatal(
+                span,
+                &format!(
+                    "thread-local statics aren't supported in the `{}` C dialect",
+                    self.tu.dialect().name()
+                ),
+            );
+        }
+        if attrs.thread_local && !self.capabilities.thread_local {
+            self.tcx.sess.span_fatal(
+                span,
+                "thread-local statics aren't supported by the C compiler",
+            );
+        }
+        if attrs.section.is_some() && !self.capabilities.section_attributes {
+            self.tcx.sess.span_fatal(
+                span,
+                "`#[link_section]` isn't supported by the selected C compiler",
+            );
+        }
+        attributes::supported_static_attrs(self.tcx.sess, self.capabilities, span, attrs)
+    }
+
+    /// Returns the internal global holding the constant allocation `alloc`,
+    /// defining it on first use.
+    pub fn get_alloc(&mut self, alloc: &'tcx Allocation) -> io::Result<Global<'a>> {
+        if let Some(&global) = self.allocs.get(alloc) {
+            return Ok(global);
+        }
+
+        // The allocations this one points to are defined first, so they are
+        // numbered first
+        let init = self.alloc_init(alloc)?;
+        let c_name = format!("_R2C2alloc{}", self.allocs.len());
+        let alloc_ty = self.define_alloc_type(alloc, &c_name, None, DUMMY_SP)?;
+        let attrs = GlobalAttrs {
+            internal: true,
+            constant: alloc.mutability == Mutability::Immutable,
+            ..GlobalAttrs::default()
+        };
+        let global = self.tu.define_global(
+            Name::internal("constant allocation", &c_name),
+            alloc_ty,
+            attrs,
+            Some(&init),
+        )?;
+        self.allocs.insert(alloc, global);
+        Ok(global)
+    }
+
+    /// Returns the internal global holding the slice constant of C type
+    /// `c_ty` (a `struct r2c2_slice_ptr`) pointing to the bytes `start..end`
+    /// of `alloc`, defining it on first use.
+    pub fn get_slice(
+        &mut self,
+        alloc: &'tcx Allocation,
+        start: usize,
+        end: usize,
+        c_ty: TypeRef<'a>,
+    ) -> io::Result<Global<'a>> {
+        if let Some(&global) = self.slices.get(&(alloc, start, end)) {
+            return Ok(global);
+        }
+
+        let data = self.alloc_base(alloc)?;
+        let init = Expr::InitList(vec![
+            self.offset_ptr(data, start as u64),
+            Expr::Uint((end - start) as u128),
+        ]);
+        let attrs = GlobalAttrs {
+            internal: true,
+            constant: true,
+            ..GlobalAttrs::default()
+        };
+        let c_name = format!("_R2C2slice{}", self.slices.len());
+        let global = self.tu.define_global(
+            Name::internal("slice constant", &c_name),
+            c_ty,
+            attrs,
+            Some(&init),
+        )?;
+        self.slices.insert((alloc, start, end), global);
+        Ok(global)
+    }
+
+    /// Returns an lvalue for the value of the (non-zero-sized) static
+    /// `def_id`, whose C type is `c_ty`.
+    pub fn static_value(&mut self, def_id: DefId, c_ty: TypeRef<'a>) -> io::Result<Expr<'a>> {
+        let global = self.get_static(def_id)?;
+        Ok(self.alloc_value(global, 0, c_ty))
+    }
+
+    /// Returns an lvalue for the value of C type `c_ty` stored `offset`
+    /// bytes into `global`, which holds an allocation.
+    pub fn alloc_value(&mut self, global: Global<'a>, offset: u64, c_ty: TypeRef<'a>) -> Expr<'a> {
+        let ptr_ty = self.tu.ptr_to(c_ty);
+        let ptr = Expr::from(global).addr_of();
+        self.offset_ptr(ptr, offset).cast(ptr_ty).deref()
+    }
+
+    /// Returns an lvalue for the constant of C type `c_ty` stored `offset`
+    /// bytes into the allocation `alloc`.
+    pub fn const_value(
+        &mut self,
+        alloc: &'tcx Allocation,
+        offset: u64,
+        c_ty: TypeRef<'a>,
+    ) -> io::Result<Expr<'a>> {
+        let global = self.get_alloc(alloc)?;
+        Ok(self.alloc_value(global, offset, c_ty))
+    }
+
+    /// Returns an lvalue for the (non-zero-sized) promoted constant
+    /// `promoted` of `instance`, whose C type is `c_ty`.
+    ///
+    /// `span` is where an erroneous constant is reported.
+    pub fn promoted_value(
+        &mut self,
+        instance: Instance<'tcx>,
+        promoted: Promoted,
+        c_ty: TypeRef<'a>,
+        span: Span,
+    ) -> io::Result<Expr<'a>> {
+        let cid = GlobalId {
+            instance,
+            promoted: Some(promoted),
+        };
+        match self.tcx.const_eval(ty::ParamEnv::reveal_all().and(cid)) {
+            Ok(&ty::Const {
+                val: ty::ConstKind::Value(ConstValue::ByRef { alloc, offset }),
+                ..
+            }) => self.const_value(alloc, offset.bytes(), c_ty),
+            Ok(value) => bug!("promoted {:?} evaluated to {:?}", cid, value),
+            Err(_) => self
+                .tcx
+                .sess
+                .span_fatal(span, "erroneous constant encountered"),
+        }
+    }
+
+    /// Returns the internal global holding a `&str` (of C type `c_ty`)
+    /// pointing to a copy of `s`.
+    pub fn const_str(&mut self, s: &str, c_ty: TypeRef<'a>) -> io::Result<Global<'a>> {
+        let alloc = Allocation::from_byte_aligned_bytes(s.as_bytes());
+        let alloc = self.tcx.intern_const_alloc(alloc);
+        self.get_slice(alloc, 0, s.len(), c_ty)
+    }
+
+    /// Returns a constant of the float type `ty` (of C type `c_ty`) with the
+    /// bit pattern `bits`.
+    ///
+    /// Infinities and NaNs have no C literal, so they are read from an
+    /// allocation holding their bits.
+    pub fn float_const(
+        &mut self,
+        bits: u128,
+        ty: Ty<'tcx>,
+        c_ty: TypeRef<'a>,
+    ) -> io::Result<Expr<'a>> {
+        let size = match ty.kind {
+            ty::Float(FloatTy::F32) => {
+                let value = f32::from_bits(bits as u32);
+                if value.is_finite() {
+                    return Ok(Expr::Float(value));
+                }
+                4
+            }
+            ty::Float(FloatTy::F64) => {
+                let value = f64::from_bits(bits as u64);
+                if value.is_finite() {
+                    return Ok(Expr::Double(value));
+                }
+                8
+            }
+            _ => bug!("float constant of type {}", ty),
+        };
+        let mut bytes = vec![0; size];
+        write_target_uint(self.tcx.data_layout.endian, &mut bytes, bits)?;
+        let align = self.layout_of(ty).align.abi;
+        let alloc = self
+            .tcx
+            .intern_const_alloc(Allocation::from_bytes(&bytes[..], align));
+        self.const_value(alloc, 0, c_ty)
+    }
+
+    /// Splits `alloc` into runs of bytes and pointers.
+    fn chunks(&self, alloc: &'tcx Allocation) -> Vec<Chunk> {
+        let dl = &self.tcx.data_layout;
+        let ptr_size = dl.pointer_size.bytes() as usize;
+        let mut chunks = Vec::new();
+        let mut next = 0;
+        for &(offset, ((), alloc_id)) in alloc.relocations().iter() {
+            let offset = offset.bytes() as usize;
+            if offset > next {
+                chunks.push(Chunk::Bytes {
+                    offset: next,
+                    len: offset - next,
+                });
+            }
+            let bytes =
+                alloc.inspect_with_undef_and_ptr_outside_interpreter(offset..offset + ptr_size);
+            let ptr_offset =
+                read_target_uint(dl.endian, bytes).expect("reading pointer offset") as u64;
+            chunks.push(Chunk::Ptr {
+                alloc_id,
+                offset: ptr_offset,
+            });
+            next = offset + ptr_size;
+        }
+        if alloc.len() > next {
+            chunks.push(Chunk::Bytes {
+                offset: next,
+                len: alloc.len() - next,
+            });
+        }
+        chunks
+    }
+
+    /// Defines the C type of a global holding the allocation `alloc`, whose
+    /// value has the C type `value_ty` (if it's known).
+    ///
+    /// The types are named after `c_name`, and `span` is where an allocation
+    /// that can't be lowered is reported.
+    fn define_alloc_type(
+        &mut self,
+        alloc: &'tcx Allocation,
+        c_name: &str,
+        value_ty: Option<TypeRef<'a>>,
+        span: Span,
+    ) -> io::Result<TypeRef<'a>> {
+        let dl = &self.tcx.data_layout;
+        let ptr_size = dl.pointer_size.bytes() as usize;
+        let ptr_align = dl.pointer_align.abi;
+
+        let u8 = self.tu.u8();
+        let void = self.tu.void();
+        let void_ptr = self.tu.ptr_to(void);
+        let chunks = self.chunks(alloc);
+        let mut fields = Vec::with_capacity(chunks.len());
+        let mut offset = 0;
+        for (index, chunk) in chunks.iter().enumerate() {
+            let ty = match *chunk {
+                Chunk::Bytes { len, .. } => {
+                    offset += len;
+                    self.tu.array_of(u8, len)
+                }
+                Chunk::Ptr { .. } => {
+                    offset += ptr_size;
+                    void_ptr
+                }
+            };
+            fields.push((field_name(index), ty));
+        }
+        debug_assert_eq!(offset, alloc.len());
+
+        // A plain `struct` has the allocation's layout if its pointers are
+        // aligned and it needs no tail padding
+        let relocations = alloc.relocations();
+        let natural = relocations.is_empty()
+            || (alloc.align >= ptr_align
+                && alloc.len() as u64 % ptr_align.bytes() == 0
+                && relocations
+                    .iter()
+                    .all(|&(offset, _)| offset.bytes() % ptr_align.bytes() == 0));
+        let align_ty = value_ty.or_else(|| self.aligned_scalar(alloc.align));
+
+        let bytes_name = Name::internal("allocation bytes", &format!("{}_bytes", c_name));
+        let fields = fields.iter().map(|(name, ty)| (&**name, *ty));
+        let mut members = Vec::new();
+        if natural && (alloc.align.bytes() == 1 || align_ty.is_some()) {
+            members.push((ALLOC_BYTES, self.tu.define_struct(bytes_name, fields)?));
+            members.extend(align_ty.map(|ty| (ALLOC_VALUE, ty)));
+        } else if self.capabilities.packed_attributes {
+            let bytes = self
+                .tu
+                .define_packed(false, bytes_name, fields, alloc.align.bytes())?;
+            members.push((ALLOC_BYTES, bytes));
+        } else {
+            self.tcx.sess.span_fatal(
+                span,
+                "laying out this constant requires a C compiler supporting packed structs",
+            )
+        }
+        self.tu
+            .define_union(Name::internal("allocation", c_name), members)
+    }
+
+    /// Returns a scalar C type whose alignment is `align`, if there is one.
+    fn aligned_scalar(&mut self, align: Align) -> Option<TypeRef<'a>> {
+        let dl = &self.tcx.data_layout;
+        let candidates = [
+            (dl.i16_align.abi, self.tu.u16()),
+            (dl.i32_align.abi, self.tu.u32()),
+            (dl.i64_align.abi, self.tu.u64()),
+            (dl.f64_align.abi, self.tu.double()),
+        ];
+        candidates
+            .iter()
+            .find(|&&(candidate, _)| candidate == align)
+            .map(|&(_, ty)| ty)
+    }
+
+    /// Returns the initializer of a global holding `alloc`, whose type
+    /// `define_alloc_type` defined.
+    fn alloc_init(&mut self, alloc: &'tcx Allocation) -> io::Result<Expr<'a>> {
+        let mut fields = Vec::new();
+        for chunk in self.chunks(alloc) {
+            fields.push(match chunk {
+                Chunk::Bytes { offset, len } => Expr::InitList(
+                    alloc
+                        .inspect_with_undef_and_ptr_outside_interpreter(offset..offset + len)
+                        .iter()
+                        .map(|&byte| Expr::Uint(byte.into()))
+                        .collect(),
+                ),
+                Chunk::Ptr { alloc_id, offset } => self.alloc_ptr(alloc_id, offset)?,
+            });
+        }
+        // The `union` is initialized through its first member
+        Ok(Expr::InitList(vec![Expr::InitList(fields)]))
+    }
+
+    /// Returns a constant `void *` pointing `offset` bytes into the
+    /// allocation `alloc_id`.
+    pub fn alloc_ptr(&mut self, alloc_id: AllocId, offset: u64) -> io::Result<Expr<'a>> {
+        let void = self.tu.void();
+        let void_ptr = self.tu.ptr_to(void);
+        let global_alloc = self.tcx.alloc_map.lock().get(alloc_id);
+        let base = match global_alloc {
+            Some(GlobalAlloc::Function(instance)) => {
+                return Ok(Expr::from(self.get_fn(instance)?).cast(void_ptr));
+            }
+            Some(GlobalAlloc::Static(def_id)) => {
+                let ty = Instance::mono(self.tcx, def_id).ty(self.tcx);
+                let layout = self.layout_of(ty);
+                if layout.is_zst() {
+                    // Zero-sized statics have no storage, so any aligned
+                    // address will do
+                    let addr = layout.align.abi.bytes() + offset;
+                    return Ok(Expr::Uint(addr.into()).cast(void_ptr));
+                }
+                Expr::from(self.get_static(def_id)?).addr_of()
+            }
+            Some(GlobalAlloc::Memory(alloc)) => self.alloc_base(alloc)?,
+            None => bug!("dangling pointer to {:?} in a constant", alloc_id),
+        };
+        Ok(self.offset_ptr(base, offset))
+    }
+
+    /// Returns a constant pointer to the start of the allocation `alloc`.
+    ///
+    /// Empty allocations (eg. of `""`) have no storage, since C has no
+    /// zero-sized objects, so any aligned address will do.
+    fn alloc_base(&mut self, alloc: &'tcx Allocation) -> io::Result<Expr<'a>> {
+        if alloc.len() == 0 {
+            let void = self.tu.void();
+            let void_ptr = self.tu.ptr_to(void);
+            return Ok(Expr::Uint(alloc.align.bytes().into()).cast(void_ptr));
+        }
+        Ok(Expr::from(self.get_alloc(alloc)?).addr_of())
+    }
+
+    /// Returns a constant `void *` pointing `offset` bytes past the pointer
+    /// `base`.
+    fn offset_ptr(&mut self, base: Expr<'a>, offset: u64) -> Expr<'a> {
+        let void = self.tu.void();
+        let void_ptr = self.tu.ptr_to(void);
+        if offset == 0 {
+            base.cast(void_ptr)
+        } else {
+            let u8 = self.tu.u8();
+            let u8_ptr = self.tu.ptr_to(u8);
+            base.cast(u8_ptr)
+                .binary("+", Expr::Uint(offset.into()))
+                .cast(void_ptr)
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! The per-codegen-unit codegen context.
+
+use super::attributes;
+use builder::attrs::FnAttrs;
+use builder::expr::Expr;
+use builder::types::{IncompleteTypeRef, TypeRef};
+use builder::{Function, Global, Name, TranslationUnitBuilder};
+use compiler::Capabilities;
+use utils::WriteStr;
+
+use rustc::hir::def_id::DefId;
+use rustc::mir::interpret::Allocation;
+use rustc::mir::mono::{Linkage, Visibility};
+use rustc::session::config::DebugInfo;
+use rustc::ty::layout::{
+    Endian, HasDataLayout, HasParamEnv, HasTyCtxt, LayoutError, LayoutOf, Primitive,
+    TargetDataLayout, TyLayout,
+};
+use rustc::ty::{self, Instance, Ty, TyCtxt};
+use rustc::util::nodemap::{FxHashMap, FxHashSet};
+use rustc_target::spec::{HasTargetSpec, Target};
+use std::io;
+
+/// State shared by everything that is lowered into a single C translation
+/// unit.
+pub struct CodegenCx<'a, 'tcx, W: WriteStr> {
+    pub tcx: TyCtxt<'tcx>,
+
+    /// The translation unit that the codegen unit is lowered into.
+    pub tu: TranslationUnitBuilder<'a, W>,
+
+    /// The path of the C file the TU is written to, if functions are
+    /// attributed to their Rust source with `#line` directives (`-C
+    /// debuginfo`). The code following a function is attributed back to it.
+    pub c_file: Option<String>,
+
+    /// C types of already lowered Rust types.
+    pub types: FxHashMap<Ty<'tcx>, TypeRef<'a>>,
+
+    /// Forward-declared `struct`s and `union`s, used to lower pointers to
+    /// (possibly recursive) aggregates.
+    pub fwd_types: FxHashMap<Ty<'tcx>, IncompleteTypeRef<'a>>,
+
+    /// Functions declared in this TU.
+    pub functions: FxHashMap<Instance<'tcx>, Function<'a>>,
+
+    /// Pointers to the vtables emitted into this TU, keyed by implementing
+    /// type and trait.
+    pub vtables: FxHashMap<(Ty<'tcx>, Option<ty::PolyExistentialTraitRef<'tcx>>), Expr<'a>>,
+
+    /// The types of vtables defined in this TU, keyed by number of methods.
+    pub vtable_types: FxHashMap<usize, TypeRef<'a>>,
+
+    /// Statics declared in this TU. Those defined in this TU are declared
+    /// with the type of their allocation (see `consts`), the others with
+    /// their C type.
+    pub statics: FxHashMap<DefId, Global<'a>>,
+
+    /// Internal globals holding constant allocations (see `consts`).
+    pub allocs: FxHashMap<&'tcx Allocation, Global<'a>>,
+
+    /// Internal globals holding slice constants, keyed by the allocation
+    /// and the range of bytes in it they point to.
+    pub slices: FxHashMap<(&'tcx Allocation, usize, usize), Global<'a>>,
+
+    /// SIMD vector types defined in this TU, keyed by lane type and count.
+    pub vectors: FxHashMap<(Primitive, u64), TypeRef<'a>>,
+
+    /// The structs scalar pairs are returned as (see `pair_return_type`),
+    /// keyed by the pair's type.
+    pub pair_returns: FxHashMap<Ty<'tcx>, TypeRef<'a>>,
+
+    /// Readable `typedef` aliases of the C aggregates Rust types are lowered
+    /// to, if `readable_names` is set.
+    pub aliases: FxHashMap<Ty<'tcx>, TypeRef<'a>>,
+
+    /// The names of all aliases in `aliases`.
+    pub alias_names: FxHashSet<String>,
+
+    /// Whether to name C types and fields after the Rust types and fields,
+    /// so that they show up in debuggers (`-C debuginfo=2`).
+    pub readable_names: bool,
+
+    /// The extensions supported by the C compiler the TU is compiled with.
+    ///
+    /// Without vector extensions, SIMD types are lowered to structs wrapping
+    /// an array of lanes, and operations on them to loops over the lanes.
+    pub capabilities: Capabilities,
+}
+
+impl<'a, 'tcx, W: WriteStr> CodegenCx<'a, 'tcx, W> {
+    pub fn new(
+        tcx: TyCtxt<'tcx>,
+        capabilities: Capabilities,
+        tu: TranslationUnitBuilder<'a, W>,
+        c_file: Option<String>,
+    ) -> io::Result<Self> {
+        let mut cx = Self {
+            tcx,
+            tu,
+            c_file,
+            types: FxHashMap::default(),
+            fwd_types: FxHashMap::default(),
+            functions: FxHashMap::default(),
+            vtables: FxHashMap::default(),
+            vtable_types: FxHashMap::default(),
+            statics: FxHashMap::default(),
+            allocs: FxHashMap::default(),
+            slices: FxHashMap::default(),
+            vectors: FxHashMap::default(),
+            pair_returns: FxHashMap::default(),
+            aliases: FxHashMap::default(),
+            alias_names: FxHashSet::default(),
+            readable_names: tcx.sess.opts.debuginfo == DebugInfo::Full,
+            capabilities,
+        };
+
+        // Make the C compiler reject the TU if its data layout differs from
+        // the one rustc (and the LLVM-compiled crates we link with) assume
+        let dl = &tcx.data_layout;
+        let ptr_size = dl.pointer_size.bytes();
+        cx.tu.static_assert(
+            &format!("sizeof(void *) == {}", ptr_size),
+            "pointer size doesn't match the Rust target",
+        )?;
+        cx.tu.static_assert(
+            &format!("sizeof(uintptr_t) == {}", ptr_size),
+            "usize doesn't match the Rust target",
+        )?;
+        // The C compiler lays out aggregates, so it must align their fields
+        // like rustc does
+        let aligns = [
+            ("int16_t", dl.i16_align),
+            ("int32_t", dl.i32_align),
+            ("int64_t", dl.i64_align),
+            ("float", dl.f32_align),
+            ("double", dl.f64_align),
+            ("void *", dl.pointer_align),
+        ];
+        for &(c_ty, align) in &aligns {
+            cx.tu.static_assert(
+                &format!(
+                    "sizeof(struct {{ char c; {} x; }}) - sizeof({}) == {}",
+                    c_ty,
+                    c_ty,
+                    align.abi.bytes()
+                ),
+                &format!("alignment of {} doesn't match the Rust target", c_ty),
+            )?;
+        }
+        // The byte order can only be checked if the compiler predefines it
+        if cx.capabilities.byte_order_macros {
+            let endian = match cx.tcx.data_layout.endian {
+                Endian::Little => "__ORDER_LITTLE_ENDIAN__",
+                Endian::Big => "__ORDER_BIG_ENDIAN__",
+            };
+            cx.tu.static_assert(
+                &format!("__BYTE_ORDER__ == {}", endian),
+                "byte order doesn't match the Rust target",
+            )?;
+        }
+        Ok(cx)
+    }
+
+    /// Returns the C function for `instance`, forward-declaring it on first
+    /// use.
+    ///
+    /// Functions defined in this TU must have been declared by
+    /// `predefine_fn` before, so they get the right linkage.
+    pub fn get_fn(&mut self, instance: Instance<'tcx>) -> io::Result<Function<'a>> {
+        if let Some(&f) = self.functions.get(&instance) {
+            return Ok(f);
+        }
+
+        let attrs = attributes::fn_attrs(self.tcx, instance);
+        self.declare_fn(instance, attrs)
+    }
+
+    /// Declares `instance`, which is defined in this TU with `linkage` and
+    /// `visibility`.
+    pub fn predefine_fn(
+        &mut self,
+        instance: Instance<'tcx>,
+        linkage: Linkage,
+        visibility: Visibility,
+    ) -> io::Result<Function<'a>> {
+        let attrs = attributes::defined_fn_attrs(self.tcx, instance, linkage, visibility);
+        self.declare_fn(instance, attrs)
+    }
+
+    fn declare_fn(&mut self, instance: Instance<'tcx>, attrs: FnAttrs) -> io::Result<Function<'a>> {
+        let span = self.tcx.def_span(instance.def_id());
+        if attrs.section.is_some() && !self.capabilities.section_attributes {
+            self.tcx.sess.span_fatal(
+                span,
+                "`#[link_section]` isn't supported by the selected C compiler",
+            );
+        }
+        let attrs = attributes::supported_fn_attrs(self.tcx.sess, self.capabilities, span, attrs);
+        let name = self.instance_name(&instance, attrs.internal);
+        let abi = self.fn_abi_of_instance(instance)?;
+        let f = self.tu.fwd_declare_function(name, abi.sig, attrs)?;
+        self.functions.insert(instance, f);
+        Ok(f)
+    }
+
+    /// Returns the C global for the (non-zero-sized) static `def_id`,
+    /// declaring it on first use.
+    ///
+    /// Statics defined in this TU have the type of their allocation, so the
+    /// global must be accessed through `static_value`.
+    pub fn get_static(&mut self, def_id: DefId) -> io::Result<Global<'a>> {
+        if let Some(&global) = self.statics.get(&def_id) {
+            return Ok(global);
+        }
+
+        let instance = Instance::mono(self.tcx, def_id);
+        let ty = instance.ty(self.tcx);
+        let c_ty = match self.c_type(ty)? {
+            Some(c_ty) => c_ty,
+            None => bug!("declaring zero-sized static {}", instance),
+        };
+        let attrs = attributes::static_attrs(self.tcx, def_id);
+        let attrs = self.check_static_attrs(def_id, attrs);
+        let name = self.instance_name(&instance, false);
+        let global = self.tu.fwd_declare_global(name, c_ty, attrs)?;
+        self.statics.insert(def_id, global);
+        Ok(global)
+    }
+
+    /// Returns the name of the function or static `instance`, which is
+    /// `internal` to the TU if it's defined with internal linkage.
+    ///
+    /// Symbols that aren't C identifiers need an assembler label. Without
+    /// assembler labels, internal items keep their sanitized C identifier,
+    /// since nothing links against them, and other items are reported.
+    pub fn instance_name(&self, instance: &Instance<'tcx>, internal: bool) -> Name<'static> {
+        let name = Name::for_instance(instance, self.tcx);
+        let link_name = match name.link_name() {
+            Some(link_name) if !self.capabilities.asm_labels => link_name,
+            _ => return name,
+        };
+        if !internal {
+            self.tcx.sess.span_fatal(
+                self.tcx.def_span(instance.def_id()),
+                &format!(
+                    "the symbol `{}` isn't a C identifier, which requires a C compiler \
+                     supporting assembler labels",
+                    link_name
+                ),
+            );
+        }
+        name.without_link_name()
+    }
+}
+
+impl<W: WriteStr> HasDataLayout for CodegenCx<'_, '_, W> {
+    fn data_layout(&self) -> &TargetDataLayout {
+        &self.tcx.data_layout
+    }
+}
+
+impl<'tcx, W: WriteStr> HasTyCtxt<'tcx> for CodegenCx<'_, 'tcx, W> {
+    fn tcx(&self) -> TyCtxt<'tcx> {
+        self.tcx
+    }
+}
+
+impl<W: WriteStr> HasTargetSpec for CodegenCx<'_, '_, W> {
+    fn target_spec(&self) -> &Target {
+        &self.tcx.sess.target.target
+    }
+}
+
+impl<'tcx, W: WriteStr> HasParamEnv<'tcx> for CodegenCx<'_, 'tcx, W> {
+    fn param_env(&self) -> ty::ParamEnv<'tcx> {
+        ty::ParamEnv::reveal_all()
+    }
+}
+
+impl<'tcx, W: WriteStr> LayoutOf for CodegenCx<'_, 'tcx, W> {
+    type Ty = Ty<'tcx>;
+    type TyLayout = TyLayout<'tcx>;
+
+    fn layout_of(&self, ty: Ty<'tcx>) -> Self::TyLayout {
+        self.tcx
+            .layout_of(ty::ParamEnv::reveal_all().and(ty))
+            .unwrap_or_else(|e| {
+                if let LayoutError::SizeOverflow(_) = e {
+                    self.tcx.sess.fatal(&e.to_string())
+                } else {
+                    bug!("failed to get layout for `{}`: {}", ty, e)
+                }
+            })
+    }
+}
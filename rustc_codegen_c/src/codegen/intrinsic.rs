@@ -0,0 +1,48 @@
+//! Classification of the intrinsics that aren't SIMD intrinsics.
+//!
+//! The intrinsics `core` and `alloc` use in generic code (and therefore in
+//! every crate instantiating it) are lowered in
+//! `FunctionCx::codegen_intrinsic`. Most map to a C operator, a C library
+//! function or a compiler builtin.
+
+use rustc::mir::BinOp;
+
+/// Returns the binary operation the arithmetic intrinsic `name` performs.
+///
+/// Overflow is either impossible (`exact_div`), undefined behavior
+/// (`unchecked_*`) or wraps around (`wrapping_*`), which is what
+/// `FunctionCx::codegen_binop` does in any case.
+pub fn binary_op(name: &str) -> Option<BinOp> {
+    Some(match name {
+        "wrapping_add" | "unchecked_add" | "fadd_fast" => BinOp::Add,
+        "wrapping_sub" | "unchecked_sub" | "fsub_fast" => BinOp::Sub,
+        "wrapping_mul" | "unchecked_mul" | "fmul_fast" => BinOp::Mul,
+        "unchecked_div" | "exact_div" | "fdiv_fast" => BinOp::Div,
+        "unchecked_rem" | "frem_fast" => BinOp::Rem,
+        "unchecked_shl" => BinOp::Shl,
+        "unchecked_shr" => BinOp::Shr,
+        _ => return None,
+    })
+}
+
+/// Returns the name of the compiler builtin implementing the float intrinsic
+/// `name` (eg. `sqrtf32`), whose `f64` version is the C library function of
+/// the same name.
+pub fn float_builtin(name: &str) -> Option<String> {
+    let (base, f32_suffix) = if name.ends_with("f32") {
+        (&name[..name.len() - 3], "f")
+    } else if name.ends_with("f64") {
+        (&name[..name.len() - 3], "")
+    } else {
+        return None;
+    };
+    let function = match base {
+        "sqrt" | "sin" | "cos" | "pow" | "exp" | "exp2" | "log" | "log10" | "log2" | "fma"
+        | "fabs" | "copysign" | "floor" | "ceil" | "trunc" | "rint" | "nearbyint" | "round" => base,
+        "powi" => return Some(format!("__builtin_powi{}", f32_suffix)),
+        "minnum" => "fmin",
+        "maxnum" => "fmax",
+        _ => return None,
+    };
+    Some(format!("__builtin_{}{}", function, f32_suffix))
+}
@@ -0,0 +1,191 @@
+//! Vtables and dynamic dispatch.
+//!
+//! This mirrors `rustc_codegen_ssa::meth`. Vtables have the same layout as
+//! the LLVM backend's vtables: the drop glue, the size and alignment of the
+//! type (`struct r2c2_vtable`, which trait objects point to), followed by one
+//! function pointer per trait method. The methods are stored as
+//! `void (*)(void)` and cast to their signature when they're called, so that
+//! vtables are constant expressions even in ISO C.
+
+use super::context::CodegenCx;
+use builder::attrs::GlobalAttrs;
+use builder::expr::Expr;
+use builder::types::{TypeRef, VTABLE_ALIGN, VTABLE_DROP_IN_PLACE, VTABLE_SIZE};
+use builder::Name;
+use utils::WriteStr;
+
+use rustc::ty::layout::LayoutOf;
+use rustc::ty::{self, Instance, Ty};
+use std::io;
+
+/// Name of the field of vtables with methods that stores the shared start of
+/// the vtable.
+const HEADER: &str = "header";
+
+/// Name of the field of vtables with methods that stores the methods.
+const METHODS: &str = "methods";
+
+/// A field of `struct r2c2_vtable`, which every vtable starts with.
+#[derive(Copy, Clone, Debug)]
+pub struct VtableField(&'static str);
+
+pub const DESTRUCTOR: VtableField = VtableField(VTABLE_DROP_IN_PLACE);
+pub const SIZE: VtableField = VtableField(VTABLE_SIZE);
+pub const ALIGN: VtableField = VtableField(VTABLE_ALIGN);
+
+impl VtableField {
+    /// Loads this field of the vtable `vtable` points to.
+    pub fn get(self, vtable: Expr<'_>) -> Expr<'_> {
+        vtable.deref().field(self.0)
+    }
+}
+
+/// The index of a method in a vtable.
+#[derive(Copy, Clone, Debug)]
+pub struct VirtualIndex(u64);
+
+impl VirtualIndex {
+    pub fn from_index(index: usize) -> Self {
+        VirtualIndex(index as u64)
+    }
+
+    /// Loads the method stored at this index of the vtable `vtable` points
+    /// to, which is a vtable of the trait object type `dyn_ty`.
+    ///
+    /// `fn_ptr_ty` is the function pointer type the method is cast to.
+    pub fn get_fn<'e, 'a: 'e, 'tcx, W: WriteStr>(
+        self,
+        cx: &mut CodegenCx<'a, 'tcx, W>,
+        vtable: Expr<'e>,
+        dyn_ty: Ty<'tcx>,
+        fn_ptr_ty: TypeRef<'e>,
+    ) -> io::Result<Expr<'e>> {
+        let principal = match dyn_ty.kind {
+            ty::Dynamic(ref data, _) => data.principal(),
+            _ => None,
+        };
+        let principal = principal.unwrap_or_else(|| bug!("method call on `{}`", dyn_ty));
+        let count = cx
+            .tcx
+            .vtable_methods(principal.with_self_ty(cx.tcx, dyn_ty))
+            .len();
+        let vtable_ty = vtable_type(cx, count)?;
+        let vtable_ty = cx.tu.const_of(vtable_ty);
+        let vtable_ptr_ty = cx.tu.ptr_to(vtable_ty);
+
+        // The vtable's start is its first field, so a pointer to it can be
+        // converted back to a pointer to the whole vtable
+        Ok(vtable
+            .cast(vtable_ptr_ty)
+            .deref()
+            .field(METHODS)
+            .index(Expr::Uint(self.0.into()))
+            .cast(fn_ptr_ty))
+    }
+}
+
+/// Returns the type of vtables with `count` methods, defining it in the
+/// translation unit first if necessary.
+fn vtable_type<'a, 'tcx, W: WriteStr>(
+    cx: &mut CodegenCx<'a, 'tcx, W>,
+    count: usize,
+) -> io::Result<TypeRef<'a>> {
+    if let Some(&ty) = cx.vtable_types.get(&count) {
+        return Ok(ty);
+    }
+
+    let header = cx.tu.vtable();
+    let method_sig = cx.tu.fn_sig(None, &[]);
+    let method_ty = cx.tu.fn_ptr(method_sig);
+    let methods = cx.tu.array_of(method_ty, count);
+    let name = Name::internal(
+        format!("vtable with {} methods", count),
+        &format!("r2c2_vtable_{}", count),
+    );
+    let ty = cx
+        .tu
+        .define_struct(name, vec![(HEADER, header), (METHODS, methods)])?;
+
+    cx.vtable_types.insert(count, ty);
+    Ok(ty)
+}
+
+/// Returns a pointer to the vtable of `ty` as an implementor of `trait_ref`,
+/// emitting the vtable into the translation unit first if necessary.
+///
+/// `trait_ref` is `None` for trait objects without a principal trait (eg.
+/// `dyn Send`), whose vtables only contain drop glue, size and alignment.
+pub fn get_vtable<'a, 'tcx, W: WriteStr>(
+    cx: &mut CodegenCx<'a, 'tcx, W>,
+    ty: Ty<'tcx>,
+    trait_ref: Option<ty::PolyExistentialTraitRef<'tcx>>,
+) -> io::Result<Expr<'a>> {
+    let tcx = cx.tcx;
+
+    if let Some(vtable) = cx.vtables.get(&(ty, trait_ref)) {
+        return Ok(vtable.clone());
+    }
+
+    let layout = cx.layout_of(ty);
+    let drop_in_place = cx.get_fn(Instance::resolve_drop_in_place(tcx, ty))?;
+    let void = cx.tu.void();
+    let void_ptr = cx.tu.ptr_to(void);
+    let drop_sig = cx.tu.fn_sig(None, &[void_ptr]);
+    let drop_ty = cx.tu.fn_ptr(drop_sig);
+    let header = Expr::InitList(vec![
+        Expr::from(drop_in_place).cast(drop_ty),
+        Expr::Uint(layout.size.bytes().into()),
+        Expr::Uint(layout.align.abi.bytes().into()),
+    ]);
+
+    let methods = match trait_ref {
+        Some(trait_ref) => tcx.vtable_methods(trait_ref.with_self_ty(tcx, ty)),
+        None => &[],
+    };
+    let method_sig = cx.tu.fn_sig(None, &[]);
+    let method_ty = cx.tu.fn_ptr(method_sig);
+    let mut entries = Vec::with_capacity(methods.len());
+    for method in methods {
+        let entry = match *method {
+            Some((def_id, substs)) => {
+                let instance =
+                    Instance::resolve_for_vtable(tcx, ty::ParamEnv::reveal_all(), def_id, substs)
+                        .unwrap();
+                Expr::from(cx.get_fn(instance)?).cast(method_ty)
+            }
+            // Methods that can't be called on the trait object are null
+            None => Expr::Uint(0),
+        };
+        entries.push(entry);
+    }
+
+    // C has no empty arrays, so vtables without methods are just the start
+    let (vtable_ty, init) = if entries.is_empty() {
+        (cx.tu.vtable(), header)
+    } else {
+        let vtable_ty = vtable_type(cx, entries.len())?;
+        (
+            vtable_ty,
+            Expr::InitList(vec![header, Expr::InitList(entries)]),
+        )
+    };
+    let rust_name = match trait_ref {
+        Some(trait_ref) => format!("vtable of {} as {}", ty, trait_ref.skip_binder()),
+        None => format!("vtable of {}", ty),
+    };
+    let name = Name::internal(rust_name, &format!("_R2C2vtable{}", cx.vtables.len()));
+    let attrs = GlobalAttrs {
+        internal: true,
+        constant: true,
+        ..GlobalAttrs::default()
+    };
+    let global = cx.tu.define_global(name, vtable_ty, attrs, Some(&init))?;
+
+    let vtable = if methods.is_empty() {
+        Expr::from(global).addr_of()
+    } else {
+        Expr::from(global).field(HEADER).addr_of()
+    };
+    cx.vtables.insert((ty, trait_ref), vtable.clone());
+    Ok(vtable)
+}
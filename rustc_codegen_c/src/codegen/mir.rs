@@ -0,0 +1,2908 @@
+//! Lowering of MIR bodies to C function bodies.
+
+use super::abi::{self, CFnAbi, CPassMode, PAIR_RETURN_FIELDS};
+use super::asm;
+use super::context::CodegenCx;
+use super::intrinsic;
+use super::meth::{self, VirtualIndex};
+use super::simd::{self, Reduction};
+use super::ty::{c_ident, field_name, generator_variant, GENERATOR_PREFIX, VECTOR_LANES};
+use builder::dialect::Environment;
+use builder::expr::Expr;
+use builder::function::{AsmOperand, FunctionBuilder};
+use builder::types::{PtrMeta, TypeRef};
+use utils::{StringWriter, WriteStr};
+
+use rustc::middle::lang_items::{
+    ExchangeMallocFnLangItem, LangItem, PanicBoundsCheckFnLangItem, PanicFnLangItem,
+};
+use rustc::mir::interpret::{ConstValue, GlobalId, PanicInfo, Scalar};
+use rustc::mir::{
+    self, BasicBlock, BasicBlockData, CastKind, Local, Operand, Place, PlaceBase, ProjectionElem,
+    Rvalue, SourceInfo, StatementKind, StaticKind, TerminatorKind,
+};
+use rustc::session::config::DebugInfo;
+use rustc::ty::adjustment::PointerCast;
+use rustc::ty::layout::{self, Integer, LayoutOf, Primitive, Size, TyLayout, VariantIdx};
+use rustc::ty::{self, Instance, Ty, TypeFoldable};
+use rustc_index::vec::{Idx, IndexVec};
+use rustc_target::spec::abi::Abi;
+use std::io;
+use syntax_pos::{Span, Symbol};
+use toolshed::Arena;
+
+/// Returns the number of C parameters an argument passed as `mode` takes up.
+fn param_count(mode: CPassMode) -> usize {
+    match mode {
+        CPassMode::Ignore => 0,
+        CPassMode::Pair => 2,
+        _ => 1,
+    }
+}
+
+/// Truncates `value` to an unsigned integer of size `size`.
+fn truncate(value: u128, size: Size) -> u128 {
+    match size.bits() {
+        0 => 0,
+        bits => value & (u128::max_value() >> (128 - bits)),
+    }
+}
+
+/// Returns the C label used for the basic block `bb`.
+fn block_label(bb: BasicBlock) -> String {
+    format!("bb{}", bb.index())
+}
+
+/// Returns the C variable names of the locals of `mir`.
+///
+/// Locals holding a user variable are named after it, turned into a valid C
+/// identifier and suffixed with the local's index (`x_3`). The suffix makes the
+/// names unique and keeps them from clashing with C keywords and the names of
+/// globals. Other locals are named `_N`, like in MIR dumps.
+fn local_names(mir: &mir::Body<'_>) -> IndexVec<Local, String> {
+    let mut names = mir
+        .local_decls
+        .indices()
+        .map(|local| format!("_{}", local.index()))
+        .collect::<IndexVec<Local, _>>();
+    for var in &mir.var_debug_info {
+        if let Place {
+            base: PlaceBase::Local(local),
+            ref projection,
+        } = var.place
+        {
+            if !projection.is_empty() {
+                continue;
+            }
+            names[local] = format!("{}_{}", c_ident(&var.name.as_str()), local.index());
+        }
+    }
+    names
+}
+
+/// Returns the names of the C parameters of a function with ABI `fn_abi`.
+///
+/// Parameters holding a whole argument are named after the argument's local,
+/// and the parts of split arguments after the local and the part's index
+/// (`s_1_0`, `s_1_1`). The return pointer gets the default name.
+fn param_names(
+    fn_abi: &CFnAbi<'_, '_>,
+    mir: &mir::Body<'_>,
+    local_names: &IndexVec<Local, String>,
+) -> Vec<Option<String>> {
+    let mut names = Vec::new();
+    if fn_abi.ret.0 == CPassMode::Indirect {
+        names.push(None);
+    }
+    for (local, &(mode, _)) in mir.args_iter().zip(&fn_abi.args) {
+        // The fields of a spread argument are passed separately
+        if mir.spread_arg == Some(local) {
+            break;
+        }
+        match mode {
+            CPassMode::ByValue | CPassMode::Indirect => {
+                names.push(Some(local_names[local].clone()))
+            }
+            _ => names.extend(
+                (0..param_count(mode)).map(|i| Some(format!("{}_{}", local_names[local], i))),
+            ),
+        }
+    }
+    names
+}
+
+/// A lowered MIR place.
+#[derive(Clone)]
+struct PlaceRef<'b> {
+    /// The place as an lvalue, or a pointer to the start of the place if it
+    /// is unsized.
+    expr: Expr<'b>,
+
+    /// The pointer metadata of an unsized place.
+    meta: Option<(PtrMeta, Expr<'b>)>,
+}
+
+/// Defines the C function for a monomorphized function instance.
+///
+/// The instance must have been declared with `CodegenCx::predefine_fn`.
+pub fn codegen_instance<'a, 'tcx, W: WriteStr>(
+    cx: &mut CodegenCx<'a, 'tcx, W>,
+    instance: Instance<'tcx>,
+) -> io::Result<()> {
+    let mir = cx.tcx.instance_mir(instance.def).body();
+    // The definition gets its link name and linkage from the declaration
+    let attrs = cx.get_fn(instance)?.attrs();
+    let fn_abi = cx.fn_abi_of_instance(instance)?;
+    let name = cx.instance_name(&instance, attrs.internal);
+
+    // The body is written into a separate buffer, since lowering it might
+    // need to emit type definitions, declarations and vtables into the TU,
+    // which have to precede the function.
+    let arena = Arena::new();
+    let mut body = StringWriter(String::new());
+    {
+        let param_names = param_names(&fn_abi, mir, &local_names(mir));
+        let mut bx = FunctionBuilder::create(
+            &mut body,
+            &arena,
+            cx.tu.dialect(),
+            name,
+            fn_abi.sig,
+            attrs,
+            &param_names,
+        )?;
+        let mut fx = FunctionCx::new(cx, instance, mir, fn_abi, &mut bx)?;
+        match instance.def {
+            // Drop glue is generated directly from the type instead of from
+            // the shim's elaborated MIR, except for generators, whose shim is
+            // the generator's own drop function
+            ty::InstanceDef::DropGlue(_, Some(ty)) if !ty.is_generator() => {
+                fx.codegen_drop_glue(ty)?
+            }
+            _ => fx.codegen_body()?,
+        }
+        bx.finish()?;
+    }
+
+    cx.tu.writer().write_str(&body)?;
+    match cx.c_file {
+        Some(ref c_file) => cx.tu.line_directive(c_file),
+        None => Ok(()),
+    }
+}
+
+/// State for lowering a single MIR body into a `FunctionBuilder`.
+///
+/// `'a` is the lifetime of the TU-wide data, while `'b` is the lifetime of
+/// data that belongs to the function being lowered.
+pub struct FunctionCx<'f, 'b, 'a, 'tcx, W: WriteStr> {
+    cx: &'f mut CodegenCx<'a, 'tcx, W>,
+    instance: Instance<'tcx>,
+    mir: &'tcx mir::Body<'tcx>,
+    fn_abi: CFnAbi<'a, 'tcx>,
+    bx: &'f mut FunctionBuilder<'b, StringWriter>,
+
+    /// The lvalue each MIR local lives in, or `None` for zero-sized locals.
+    ///
+    /// This is usually a C variable, but arguments passed indirectly are
+    /// accessed through their pointer.
+    locals: IndexVec<Local, Option<Expr<'b>>>,
+
+    /// The C variable names of the locals (see `local_names`).
+    local_names: IndexVec<Local, String>,
+
+    /// The span of the statement or terminator being lowered, which
+    /// unsupported constructs are reported at.
+    span: Span,
+
+    /// Whether to emit `#line` directives pointing at the Rust source.
+    debuginfo: bool,
+}
+
+impl<'f, 'b, 'a: 'b, 'tcx, W: WriteStr> FunctionCx<'f, 'b, 'a, 'tcx, W> {
+    /// Creates the lowering context and declares all locals of `mir`.
+    pub fn new(
+        cx: &'f mut CodegenCx<'a, 'tcx, W>,
+        instance: Instance<'tcx>,
+        mir: &'tcx mir::Body<'tcx>,
+        fn_abi: CFnAbi<'a, 'tcx>,
+        bx: &'f mut FunctionBuilder<'b, StringWriter>,
+    ) -> io::Result<Self> {
+        let debuginfo = cx.tcx.sess.opts.debuginfo != DebugInfo::None;
+        let mut fx = Self {
+            cx,
+            instance,
+            mir,
+            fn_abi,
+            bx,
+            locals: IndexVec::new(),
+            local_names: local_names(mir),
+            span: mir.span,
+            debuginfo,
+        };
+        fx.declare_locals()?;
+        Ok(fx)
+    }
+
+    /// Applies the substitutions of the instance being lowered to `value`.
+    fn monomorphize<T: TypeFoldable<'tcx>>(&self, value: &T) -> T {
+        self.cx.tcx.subst_and_normalize_erasing_regions(
+            self.instance.substs,
+            ty::ParamEnv::reveal_all(),
+            value,
+        )
+    }
+
+    fn declare_locals(&mut self) -> io::Result<()> {
+        let params = self.bx.args;
+        let mut params = params.iter().map(|&param| Expr::from(param));
+        let ret_ptr = match self.fn_abi.ret.0 {
+            CPassMode::Indirect => params.next(),
+            _ => None,
+        };
+        let arg_modes = self.fn_abi.args.iter().map(|arg| arg.0).collect::<Vec<_>>();
+        let mut arg_modes = arg_modes.into_iter();
+        // Arguments that are split up, cast or untupled are moved into their
+        // local once all locals are declared
+        let mut moves = Vec::<(_, _, _, Vec<_>)>::new();
+
+        for (local, decl) in self.mir.local_decls.iter_enumerated() {
+            let ty = self.monomorphize(&decl.ty);
+            let c_ty = self.cx.c_type(ty)?;
+
+            let is_arg = local.index() >= 1 && local.index() <= self.mir.arg_count;
+            if is_arg && self.mir.spread_arg == Some(local) {
+                // The "rust-call" ABI passes the fields of the last argument
+                // (a tuple) as separate arguments
+                let var = match c_ty {
+                    Some(c_ty) => Some(self.declare_local(local, ty, c_ty)?),
+                    None => None,
+                };
+                let layout = self.cx.layout_of(ty);
+                for index in 0..layout.fields.count() {
+                    let mode = arg_modes.next().unwrap();
+                    let parts = params.by_ref().take(param_count(mode)).collect();
+                    if let Some(ref var) = var {
+                        let field = var.clone().field(field_name(index));
+                        moves.push((field, layout.field(&*self.cx, index), mode, parts));
+                    }
+                }
+                self.locals.push(var);
+                continue;
+            }
+
+            // Only arguments and indirect return places are passed in
+            let mode = if is_arg {
+                arg_modes.next()
+            } else if local == mir::RETURN_PLACE && ret_ptr.is_some() {
+                Some(CPassMode::Indirect)
+            } else {
+                None
+            };
+
+            let expr = match (c_ty, mode) {
+                (None, _) | (_, Some(CPassMode::Ignore)) => None,
+                (Some(_), Some(CPassMode::ByValue)) => params.next(),
+                (Some(_), Some(CPassMode::Indirect)) if is_arg => {
+                    Some(params.next().unwrap().deref())
+                }
+                (Some(_), Some(CPassMode::Indirect)) => Some(ret_ptr.clone().unwrap().deref()),
+                (Some(c_ty), mode) => {
+                    let var = self.declare_local(local, ty, c_ty)?;
+                    if let Some(mode) = mode {
+                        let parts = params.by_ref().take(param_count(mode)).collect();
+                        moves.push((var.clone(), self.cx.layout_of(ty), mode, parts));
+                    }
+                    Some(var)
+                }
+            };
+            self.locals.push(expr);
+        }
+
+        for (dest, layout, mode, mut parts) in moves {
+            match mode {
+                CPassMode::Ignore => {}
+                CPassMode::ByValue => self.bx.assign(&dest, &parts.pop().unwrap())?,
+                CPassMode::Pair => {
+                    let b = parts.pop().unwrap();
+                    let a = parts.pop().unwrap();
+                    let dest_a = self.pair_field(dest.clone(), layout, 0)?;
+                    let dest_b = self.pair_field(dest, layout, 1)?;
+                    self.bx.assign(&dest_a, &a)?;
+                    self.bx.assign(&dest_b, &b)?;
+                }
+                CPassMode::Cast => {
+                    let param = parts.pop().unwrap();
+                    self.bx
+                        .memcpy(&dest.addr_of(), &param.addr_of(), layout.size.bytes())?;
+                }
+                CPassMode::Indirect => self.bx.assign(&dest, &parts.pop().unwrap().deref())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Declares the C variable holding `local`.
+    fn declare_local(
+        &mut self,
+        local: Local,
+        ty: Ty<'tcx>,
+        c_ty: TypeRef<'b>,
+    ) -> io::Result<Expr<'b>> {
+        let comment = ty.to_string();
+        let var = self
+            .bx
+            .declare_variable(&self.local_names[local], c_ty, &*comment)?;
+        Ok(Expr::from(var))
+    }
+
+    /// Accesses the first (`index == 0`) or second (`index == 1`) scalar of
+    /// the lvalue `value`, whose layout is a `ScalarPair`.
+    fn pair_field(
+        &mut self,
+        value: Expr<'b>,
+        layout: TyLayout<'tcx>,
+        index: usize,
+    ) -> io::Result<Expr<'b>> {
+        let c_ty = self.cx.c_type(layout.ty)?.unwrap();
+        if let Some(meta) = c_ty.fat_ptr_meta() {
+            return Ok(match index {
+                0 => value.fat_data(),
+                _ => value.fat_meta(meta),
+            });
+        }
+
+        let (a, b) = match layout.abi {
+            layout::Abi::ScalarPair(ref a, ref b) => (a.value, b.value),
+            _ => bug!("pair access of non-pair layout {:?}", layout),
+        };
+        let (a_ty, b_ty) = self.cx.pair_types(layout)?;
+        let (offset, field_ty) = match index {
+            0 => (Size::ZERO, a_ty),
+            _ => (a.size(&*self.cx).align_to(b.align(&*self.cx).abi), b_ty),
+        };
+
+        Ok(self.at_offset(value, offset, field_ty))
+    }
+
+    /// Accesses the value of type `ty` at byte offset `offset` in the lvalue
+    /// `value`.
+    ///
+    /// This goes through a pointer cast, which works for any C type `value`
+    /// might have (Rust has no type-based alias analysis, so C code is always
+    /// compiled with `-fno-strict-aliasing`).
+    fn at_offset(&mut self, value: Expr<'b>, offset: Size, ty: TypeRef<'a>) -> Expr<'b> {
+        let u8 = self.cx.tu.u8();
+        let u8_ptr = self.cx.tu.ptr_to(u8);
+        let ptr = self.cx.tu.ptr_to(ty);
+        value
+            .addr_of()
+            .cast(u8_ptr)
+            .index(Expr::Uint(offset.bytes().into()))
+            .addr_of()
+            .cast(ptr)
+            .deref()
+    }
+
+    /// Accesses field `index` of the lvalue `base`, which is of type
+    /// `base_ty`.
+    fn codegen_field(
+        &mut self,
+        base: Expr<'b>,
+        base_ty: mir::tcx::PlaceTy<'tcx>,
+        index: usize,
+        field_ty: Ty<'tcx>,
+    ) -> io::Result<Expr<'b>> {
+        match (&base_ty.ty.kind, base_ty.variant_index) {
+            (ty::Generator(..), None) => Ok(base.field(GENERATOR_PREFIX).field(field_name(index))),
+            (ty::Generator(..), Some(variant)) => Ok(base
+                .field(generator_variant(variant))
+                .field(field_name(index))),
+            // Enums are opaque, so their fields are accessed by offset
+            (_, Some(variant)) => {
+                let layout = self
+                    .cx
+                    .layout_of(base_ty.ty)
+                    .for_variant(&*self.cx, variant);
+                let field_ty = self.monomorphize(&field_ty);
+                match self.cx.c_type(field_ty)? {
+                    Some(c_ty) => Ok(self.at_offset(base, layout.fields.offset(index), c_ty)),
+                    // Never used, the place is zero-sized
+                    None => Ok(base),
+                }
+            }
+            // The fields of SIMD types are their lanes
+            (ty::Adt(def, _), None) if def.repr.simd() => {
+                self.lane(base, base_ty.ty, Expr::Uint(index as u128))
+            }
+            // Boxes are lowered to the pointer of their only field, the
+            // `Unique<T>`
+            (ty::Adt(def, _), None) if def.is_box() => {
+                let field_ty = self.monomorphize(&field_ty);
+                let c_ty = self.cx.c_type(field_ty)?.unwrap();
+                Ok(self.at_offset(base, Size::ZERO, c_ty))
+            }
+            (_, None) => {
+                // The base may only have been forward declared so far, if it's
+                // reached through a pointer
+                self.cx.c_type(base_ty.ty)?;
+                Ok(base.field(self.cx.c_field_name(base_ty.ty, index)))
+            }
+        }
+    }
+
+    /// Copies `value`, of the Rust type `ty`, to the lvalue `dest`.
+    ///
+    /// C arrays can't be assigned, so they're copied with `memcpy` instead.
+    fn copy(&mut self, dest: &Expr<'_>, value: &Expr<'_>, ty: Ty<'tcx>) -> io::Result<()> {
+        if let ty::Array(..) = ty.kind {
+            let size = self.cx.layout_of(ty).size.bytes();
+            self.bx
+                .memcpy(&dest.clone().addr_of(), &value.clone().addr_of(), size)
+        } else {
+            self.bx.assign(dest, value)
+        }
+    }
+
+    /// Stores `value` in a new temporary of type `ty` and returns the
+    /// temporary.
+    fn spill(&mut self, value: &Expr<'_>, ty: TypeRef<'b>) -> io::Result<Expr<'b>> {
+        let tmp = self.bx.declare_variable("tmp", ty, None)?;
+        self.bx.assign(&Expr::from(tmp), value)?;
+        Ok(Expr::from(tmp))
+    }
+
+    /// Reports that `what`, which the statement or terminator being lowered
+    /// uses, can't be lowered to C yet.
+    fn unsupported(&self, what: &str) -> ! {
+        self.cx.tcx.sess.span_fatal(
+            self.span,
+            &format!("{} isn't supported by the C codegen backend", what),
+        )
+    }
+
+    /// Lowers all basic blocks of the MIR body.
+    ///
+    /// Crates are compiled with `-C panic=abort` (see `check_llvm_compat`),
+    /// so nothing unwinds, and cleanup blocks are dead.
+    pub fn codegen_body(&mut self) -> io::Result<()> {
+        for (bb, data) in self.mir.basic_blocks().iter_enumerated() {
+            if data.is_cleanup {
+                continue;
+            }
+
+            self.codegen_block(bb, data)?;
+        }
+        Ok(())
+    }
+
+    fn codegen_block(
+        &mut self,
+        bb: BasicBlock,
+        data: &'tcx BasicBlockData<'tcx>,
+    ) -> io::Result<()> {
+        self.bx.label(&block_label(bb))?;
+
+        for stmt in &data.statements {
+            match stmt.kind {
+                // These have no runtime effect
+                StatementKind::StorageLive(_)
+                | StatementKind::StorageDead(_)
+                | StatementKind::FakeRead(..)
+                | StatementKind::Retag(..)
+                | StatementKind::AscribeUserType(..)
+                | StatementKind::Nop => continue,
+                _ => {}
+            }
+
+            self.span = stmt.source_info.span;
+            self.set_source_location(stmt.source_info)?;
+            match stmt.kind {
+                StatementKind::Assign(ref assign) => {
+                    let (ref place, ref rvalue) = **assign;
+                    let dest = self.codegen_lvalue(place)?;
+                    self.codegen_rvalue(dest, rvalue)?;
+                }
+                StatementKind::SetDiscriminant {
+                    ref place,
+                    variant_index,
+                } => self.codegen_set_discr(place, variant_index)?,
+                StatementKind::InlineAsm(ref asm) => {
+                    self.codegen_inline_asm(asm, stmt.source_info.span)?
+                }
+                _ => self.unsupported(&format!("the statement `{:?}`", stmt)),
+            }
+        }
+
+        let terminator = data.terminator();
+        self.span = terminator.source_info.span;
+        self.set_source_location(terminator.source_info)?;
+        self.codegen_terminator(bb, &terminator.kind)
+    }
+
+    /// Attributes the C code emitted next to the Rust source `source_info`
+    /// points to, if debuginfo is enabled (`-C debuginfo`).
+    ///
+    /// This emits a `#line` directive, so debuggers and C compiler
+    /// diagnostics refer to the Rust source instead of the generated C.
+    fn set_source_location(&mut self, source_info: SourceInfo) -> io::Result<()> {
+        if !self.debuginfo || source_info.span.is_dummy() {
+            return Ok(());
+        }
+
+        // Code expanded from macros is attributed to the macro invocation,
+        // like the LLVM backend does unless `-Z debug-macros` is passed
+        let sess = self.cx.tcx.sess;
+        let span = if sess.opts.debugging_opts.debug_macros {
+            source_info.span
+        } else {
+            source_info.span.source_callsite()
+        };
+        let loc = sess.source_map().lookup_char_pos(span.lo());
+        self.bx.line_directive(loc.line, &loc.file.name.to_string())
+    }
+
+    /// Lowers an `asm!` statement to GNU C extended inline assembly.
+    fn codegen_inline_asm(&mut self, asm: &mir::InlineAsm<'tcx>, span: Span) -> io::Result<()> {
+        let sess = self.cx.tcx.sess;
+        let arch = &*sess.target.target.arch;
+        let check = |result: Result<String, String>| {
+            result.unwrap_or_else(|msg| sess.span_fatal(span, &msg))
+        };
+        if !self.cx.capabilities.inline_asm {
+            sess.span_fatal(
+                span,
+                "inline assembly isn't supported by the selected C compiler",
+            );
+        }
+        let ia = &asm.asm;
+        // `alignstack` has no equivalent, but GCC keeps the stack aligned
+        // anyway
+
+        let operand_count = ia.outputs.len() + ia.inputs.len();
+        let template = check(asm::template(&ia.asm.as_str(), ia.dialect, operand_count));
+
+        let mut outputs = Vec::new();
+        for (output, place) in ia.outputs.iter().zip(asm.outputs.iter()) {
+            let constraint = check(asm::output_constraint(
+                &output.constraint.as_str(),
+                output.is_rw,
+                arch,
+            ));
+            let expr = match self.codegen_lvalue(place)? {
+                // Indirect outputs are pointers to the memory written to
+                Some(expr) if output.is_indirect => expr.deref(),
+                Some(expr) => expr,
+                None => {
+                    sess.span_fatal(span, "zero-sized inline assembly outputs are not supported")
+                }
+            };
+            outputs.push(AsmOperand { constraint, expr });
+        }
+
+        let mut inputs = Vec::new();
+        for (constraint, &(_, ref operand)) in ia.inputs.iter().zip(asm.inputs.iter()) {
+            let constraint = constraint.as_str();
+            let expr = match self.codegen_operand(operand)? {
+                Some(expr) if constraint.contains('*') => expr.deref(),
+                Some(expr) => expr,
+                None => {
+                    sess.span_fatal(span, "zero-sized inline assembly inputs are not supported")
+                }
+            };
+            inputs.push(AsmOperand {
+                constraint: check(asm::input_constraint(&constraint, arch)),
+                expr,
+            });
+        }
+
+        let clobbers = ia
+            .clobbers
+            .iter()
+            .filter_map(|clobber| asm::clobber(&clobber.as_str(), arch))
+            .collect::<Vec<_>>();
+        self.bx
+            .inline_asm(&template, ia.volatile, &outputs, &inputs, &clobbers)
+    }
+
+    /// Lowers a place.
+    ///
+    /// Returns `None` if the place is zero-sized.
+    fn codegen_place(&mut self, place: &Place<'tcx>) -> io::Result<Option<PlaceRef<'b>>> {
+        let (expr, base_ty) = match place.base {
+            PlaceBase::Local(local) => match self.locals[local] {
+                Some(ref expr) => (
+                    expr.clone(),
+                    self.monomorphize(&self.mir.local_decls[local].ty),
+                ),
+                None => return Ok(None),
+            },
+            PlaceBase::Static(ref static_) => match static_.kind {
+                StaticKind::Static => {
+                    let ty = self.monomorphize(&static_.ty);
+                    if self.cx.layout_of(ty).is_zst() {
+                        return Ok(None);
+                    }
+                    let c_ty = self.cx.c_type(ty)?.unwrap();
+                    (self.cx.static_value(static_.def_id, c_ty)?, ty)
+                }
+                StaticKind::Promoted(promoted, substs) => {
+                    let ty = self.monomorphize(&static_.ty);
+                    let c_ty = match self.cx.c_type(ty)? {
+                        Some(c_ty) => c_ty,
+                        None => return Ok(None),
+                    };
+                    let instance = Instance::new(static_.def_id, self.monomorphize(&substs));
+                    let value = self
+                        .cx
+                        .promoted_value(instance, promoted, c_ty, self.span)?;
+                    (value, ty)
+                }
+            },
+        };
+
+        let mut place_ref = PlaceRef { expr, meta: None };
+        let mut place_ty = mir::tcx::PlaceTy::from_ty(base_ty);
+        for elem in place.projection.iter() {
+            let expr = place_ref.expr;
+            place_ref = match *elem {
+                ProjectionElem::Deref => {
+                    let pointee = place_ty.ty.builtin_deref(true).unwrap().ty;
+                    if self.is_sized(pointee) {
+                        PlaceRef {
+                            expr: expr.deref(),
+                            meta: None,
+                        }
+                    } else {
+                        self.deref_fat_ptr(expr, pointee)?
+                    }
+                }
+                ProjectionElem::Field(field, field_ty) => match place_ref.meta {
+                    Some(meta) => {
+                        let layout = self.cx.layout_of(place_ty.ty);
+                        match self.unsized_field(expr, &meta, layout, field.index())? {
+                            Some(field) => field,
+                            // Projections of zero-sized places are zero-sized
+                            None => return Ok(None),
+                        }
+                    }
+                    None => PlaceRef {
+                        expr: self.codegen_field(expr, place_ty, field.index(), field_ty)?,
+                        meta: None,
+                    },
+                },
+                // Fields of the variant are accessed by the following `Field`
+                ProjectionElem::Downcast(..) => PlaceRef {
+                    expr,
+                    meta: place_ref.meta,
+                },
+                // Indexing works the same for arrays and the element pointers
+                // unsized slice places are represented as
+                ProjectionElem::Index(index) => {
+                    let index = match self.locals[index] {
+                        Some(ref index) => index.clone(),
+                        None => bug!("zero-sized index local"),
+                    };
+                    PlaceRef {
+                        expr: expr.index(index),
+                        meta: None,
+                    }
+                }
+                ProjectionElem::ConstantIndex {
+                    offset,
+                    from_end: false,
+                    ..
+                } => PlaceRef {
+                    expr: expr.index(Expr::Uint(offset.into())),
+                    meta: None,
+                },
+                _ => self.unsupported(&format!("the place projection `{:?}`", elem)),
+            };
+            // The types of fields are those of the generic MIR
+            place_ty = self.monomorphize(&place_ty.projection_ty(self.cx.tcx, elem));
+        }
+
+        if self.cx.layout_of(place_ty.ty).is_zst() {
+            Ok(None)
+        } else {
+            Ok(Some(place_ref))
+        }
+    }
+
+    /// Returns the address of the zero-sized `place` if it's a field (of a
+    /// field...) of a sized place that isn't zero-sized.
+    ///
+    /// The address of such fields is observable: `BTreeMap`'s nodes find
+    /// their keys from the address of a `[K; 0]` field, for example.
+    fn zst_field_addr(&mut self, place: &Place<'tcx>) -> io::Result<Option<Expr<'b>>> {
+        let tcx = self.cx.tcx;
+        let base_ty = match place.base {
+            PlaceBase::Local(local) => self.mir.local_decls[local].ty,
+            PlaceBase::Static(ref static_) => static_.ty,
+        };
+        let mut place_ty = mir::tcx::PlaceTy::from_ty(self.monomorphize(&base_ty));
+        // The projections up to `prefix_len` lead to the innermost place that
+        // isn't zero-sized, the others (only fields and downcasts) to the
+        // field at `offset` in it
+        let mut prefix_len = None;
+        let mut offset = Size::ZERO;
+        for (i, elem) in place.projection.iter().enumerate() {
+            if !self.cx.layout_of(place_ty.ty).is_zst() {
+                prefix_len = Some(i);
+                offset = Size::ZERO;
+            }
+            match *elem {
+                ProjectionElem::Field(field, _) => {
+                    let mut layout = self.cx.layout_of(place_ty.ty);
+                    if let Some(variant) = place_ty.variant_index {
+                        layout = layout.for_variant(&*self.cx, variant);
+                    }
+                    offset += layout.fields.offset(field.index());
+                }
+                ProjectionElem::Downcast(..) => {}
+                _ => prefix_len = None,
+            }
+            place_ty = self.monomorphize(&place_ty.projection_ty(tcx, elem));
+        }
+        let prefix = match prefix_len {
+            Some(prefix_len) => Place {
+                base: place.base.clone(),
+                projection: tcx.intern_place_elems(&place.projection[..prefix_len]),
+            },
+            None => return Ok(None),
+        };
+        match self.codegen_place(&prefix)? {
+            Some(PlaceRef { expr, meta: None }) => {
+                let u8 = self.cx.tu.u8();
+                Ok(Some(self.at_offset(expr, offset, u8).addr_of()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Lowers a sized place to an lvalue expression.
+    ///
+    /// Returns `None` if the place is zero-sized.
+    fn codegen_lvalue(&mut self, place: &Place<'tcx>) -> io::Result<Option<Expr<'b>>> {
+        Ok(self.codegen_place(place)?.map(|place| {
+            assert!(place.meta.is_none(), "unsized place used as a value");
+            place.expr
+        }))
+    }
+
+    /// Dereferences the fat pointer `ptr`, which points to a value of the
+    /// unsized type `pointee`.
+    fn deref_fat_ptr(&mut self, ptr: Expr<'b>, pointee: Ty<'tcx>) -> io::Result<PlaceRef<'b>> {
+        let tail = self
+            .cx
+            .tcx
+            .struct_tail_erasing_lifetimes(pointee, ty::ParamEnv::reveal_all());
+        let meta = match tail.kind {
+            ty::Slice(_) | ty::Str => PtrMeta::Len,
+            ty::Dynamic(..) => PtrMeta::Vtable,
+            _ => bug!("fat pointer to sized type {}", pointee),
+        };
+        let data = ptr.clone().fat_data();
+        self.unsized_place(data, (meta, ptr.fat_meta(meta)), pointee)
+    }
+
+    /// Returns the place of the unsized type `ty` that `data` points to,
+    /// where `meta` is the pointer metadata.
+    fn unsized_place(
+        &mut self,
+        data: Expr<'b>,
+        meta: (PtrMeta, Expr<'b>),
+        ty: Ty<'tcx>,
+    ) -> io::Result<PlaceRef<'b>> {
+        let u8 = self.cx.tu.u8();
+        let u8_ptr = self.cx.tu.ptr_to(u8);
+        let expr = match ty.kind {
+            ty::Slice(elem) => {
+                let elem_ptr = match self.cx.c_type(elem)? {
+                    Some(elem) => self.cx.tu.ptr_to(elem),
+                    None => {
+                        let void = self.cx.tu.void();
+                        self.cx.tu.ptr_to(void)
+                    }
+                };
+                data.cast(elem_ptr)
+            }
+            ty::Dynamic(..) => data,
+            // The fields of other unsized types are accessed by offset
+            _ => data.cast(u8_ptr),
+        };
+        Ok(PlaceRef {
+            expr,
+            meta: Some(meta),
+        })
+    }
+
+    /// Accesses field `index` of the unsized value with layout `layout` that
+    /// `data` points to, where `meta` is the pointer metadata.
+    ///
+    /// Returns `None` if the field is zero-sized.
+    fn unsized_field(
+        &mut self,
+        data: Expr<'b>,
+        meta: &(PtrMeta, Expr<'b>),
+        layout: TyLayout<'tcx>,
+        index: usize,
+    ) -> io::Result<Option<PlaceRef<'b>>> {
+        let field = layout.field(&*self.cx, index);
+        let offset = layout.fields.offset(index);
+        if field.is_zst() {
+            return Ok(None);
+        }
+        if !field.is_unsized() {
+            let c_ty = self.cx.c_type(field.ty)?.unwrap();
+            return Ok(Some(PlaceRef {
+                expr: self.at_offset(data.deref(), offset, c_ty),
+                meta: None,
+            }));
+        }
+
+        // Only the last field is unsized. The layout places it right after
+        // the other fields, which is where it goes unless it's a trait object
+        // with a larger alignment, which is only known at runtime.
+        let tail = self
+            .cx
+            .tcx
+            .struct_tail_erasing_lifetimes(field.ty, ty::ParamEnv::reveal_all());
+        let mut offset = Expr::Uint(offset.bytes().into());
+        if let ty::Dynamic(..) = tail.kind {
+            let align = meth::ALIGN.get(meta.1.clone());
+            let mask = align.clone().binary("-", Expr::Uint(1));
+            offset = offset
+                .binary("+", mask.clone())
+                .binary("&", Expr::Uint(0).binary("-", align));
+        }
+        let u8 = self.cx.tu.u8();
+        let u8_ptr = self.cx.tu.ptr_to(u8);
+        let void = self.cx.tu.void();
+        let void_ptr = self.cx.tu.ptr_to(void);
+        let field_data = data.cast(u8_ptr).index(offset).addr_of().cast(void_ptr);
+        self.unsized_place(field_data, meta.clone(), field.ty)
+            .map(Some)
+    }
+
+    fn is_sized(&self, ty: Ty<'tcx>) -> bool {
+        ty.is_sized(self.cx.tcx.at(self.mir.span), ty::ParamEnv::reveal_all())
+    }
+
+    /// Lowers an operand to an expression.
+    ///
+    /// Returns `None` if the operand is zero-sized.
+    fn codegen_operand(&mut self, operand: &Operand<'tcx>) -> io::Result<Option<Expr<'b>>> {
+        match *operand {
+            Operand::Copy(ref place) | Operand::Move(ref place) => self.codegen_lvalue(place),
+            Operand::Constant(ref constant) => {
+                let ty = self.monomorphize(&constant.literal.ty);
+                let c_ty = match self.cx.c_type(ty)? {
+                    Some(c_ty) => c_ty,
+                    None => return Ok(None),
+                };
+
+                let literal = self
+                    .monomorphize(&constant.literal)
+                    .eval(self.cx.tcx, ty::ParamEnv::reveal_all());
+                self.codegen_const(literal, ty, c_ty).map(Some)
+            }
+        }
+    }
+
+    /// Lowers the (non-zero-sized) constant `literal` of type `ty`, whose C
+    /// type is `c_ty`.
+    fn codegen_const(
+        &mut self,
+        literal: &'tcx ty::Const<'tcx>,
+        ty: Ty<'tcx>,
+        c_ty: TypeRef<'a>,
+    ) -> io::Result<Expr<'b>> {
+        let scalar = match literal.val {
+            ty::ConstKind::Value(ConstValue::Scalar(scalar)) => scalar,
+            ty::ConstKind::Value(ConstValue::Slice { data, start, end }) => {
+                return Ok(Expr::from(self.cx.get_slice(data, start, end, c_ty)?));
+            }
+            ty::ConstKind::Value(ConstValue::ByRef { alloc, offset }) => {
+                return self.cx.const_value(alloc, offset.bytes(), c_ty);
+            }
+            _ => self.unsupported(&format!("the constant `{:?}`", literal)),
+        };
+
+        let prim = self.scalar_primitive(ty);
+        let value = match scalar {
+            Scalar::Raw { data, .. } if prim.is_float() => {
+                let float_ty = match prim {
+                    Primitive::F32 => self.cx.tcx.types.f32,
+                    _ => self.cx.tcx.types.f64,
+                };
+                let scalar_ty = self.cx.scalar_type(prim);
+                self.cx.float_const(data, float_ty, scalar_ty)?
+            }
+            Scalar::Raw { data, .. } => self.cx.uint_literal(data),
+            Scalar::Ptr(ptr) => self.cx.alloc_ptr(ptr.alloc_id, ptr.offset.bytes())?,
+        };
+        if abi::is_c_scalar(ty) {
+            return Ok(value.cast(c_ty));
+        }
+
+        // Aggregates with a scalar layout (newtypes and fieldless enums) are
+        // built in a temporary
+        let scalar_ty = self.cx.scalar_type(prim);
+        let tmp = Expr::from(self.bx.declare_variable("tmp", c_ty, None)?);
+        let dest = self.at_offset(tmp.clone(), Size::ZERO, scalar_ty);
+        self.bx.assign(&dest, &value.cast(scalar_ty))?;
+        Ok(tmp)
+    }
+
+    /// Lowers an rvalue, assigning it to `dest` (if it isn't zero-sized).
+    fn codegen_rvalue(&mut self, dest: Option<Expr<'b>>, rvalue: &Rvalue<'tcx>) -> io::Result<()> {
+        match *rvalue {
+            Rvalue::Use(ref operand) => {
+                let value = self.codegen_operand(operand)?;
+                if let (Some(dest), Some(value)) = (dest, value) {
+                    let ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                    self.copy(&dest, &value, ty)?;
+                }
+                Ok(())
+            }
+            Rvalue::Ref(_, _, ref place) => {
+                let dest = dest.unwrap();
+                match self.codegen_place(place)? {
+                    Some(PlaceRef {
+                        expr,
+                        meta: Some((meta, meta_expr)),
+                    }) => {
+                        let void = self.cx.tu.void();
+                        let void_ptr = self.cx.tu.ptr_to(void);
+                        self.bx
+                            .assign(&dest.clone().fat_data(), &expr.cast(void_ptr))?;
+                        self.bx.assign(&dest.fat_meta(meta), &meta_expr)
+                    }
+                    Some(PlaceRef { expr, meta: None }) => self.bx.assign(&dest, &expr.addr_of()),
+                    None => {
+                        let ptr_ty = self
+                            .cx
+                            .c_type(self.monomorphize(&rvalue.ty(self.mir, self.cx.tcx)))?
+                            .unwrap();
+                        let addr = match self.zst_field_addr(place)? {
+                            Some(addr) => addr,
+                            // Any well-aligned pointer is a valid pointer to
+                            // a zero-sized value
+                            None => {
+                                let place_ty =
+                                    self.monomorphize(&place.ty(self.mir, self.cx.tcx).ty);
+                                let align = self.cx.layout_of(place_ty).align.abi.bytes();
+                                Expr::Uint(align.into())
+                            }
+                        };
+                        self.bx.assign(&dest, &addr.cast(ptr_ty))
+                    }
+                }
+            }
+            Rvalue::Len(ref place) => {
+                let len = match self.codegen_place(place)? {
+                    Some(PlaceRef {
+                        meta: Some((PtrMeta::Len, len)),
+                        ..
+                    }) => len,
+                    _ => {
+                        let place_ty = self.monomorphize(&place.ty(self.mir, self.cx.tcx).ty);
+                        match place_ty.kind {
+                            ty::Array(_, len) => Expr::Uint(
+                                len.eval_usize(self.cx.tcx, ty::ParamEnv::reveal_all())
+                                    .into(),
+                            ),
+                            _ => bug!("Rvalue::Len of {}", place_ty),
+                        }
+                    }
+                };
+                self.bx.assign(&dest.unwrap(), &len)
+            }
+            Rvalue::Cast(CastKind::Pointer(PointerCast::Unsize), ref operand, target_ty) => {
+                let source_ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                let target_ty = self.monomorphize(&target_ty);
+                let value = self.codegen_operand(operand)?.unwrap();
+                self.codegen_unsize(dest.unwrap(), value, source_ty, target_ty)
+            }
+            Rvalue::Cast(
+                CastKind::Pointer(PointerCast::ReifyFnPointer),
+                ref operand,
+                target_ty,
+            ) => {
+                let ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                let instance = match ty.kind {
+                    ty::FnDef(def_id, substs) => Instance::resolve_for_fn_ptr(
+                        self.cx.tcx,
+                        ty::ParamEnv::reveal_all(),
+                        def_id,
+                        substs,
+                    )
+                    .unwrap(),
+                    _ => bug!("reifying non-`FnDef` {}", ty),
+                };
+                self.codegen_fn_ptr(dest.unwrap(), instance, target_ty)
+            }
+            Rvalue::Cast(
+                CastKind::Pointer(PointerCast::ClosureFnPointer(_)),
+                ref operand,
+                target_ty,
+            ) => {
+                let ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                let instance = match ty.kind {
+                    ty::Closure(def_id, substs) => Instance::resolve_closure(
+                        self.cx.tcx,
+                        def_id,
+                        substs,
+                        ty::ClosureKind::FnOnce,
+                    ),
+                    _ => bug!("closure-to-fn-pointer cast of non-closure {}", ty),
+                };
+                self.codegen_fn_ptr(dest.unwrap(), instance, target_ty)
+            }
+            Rvalue::Cast(
+                CastKind::Pointer(PointerCast::MutToConstPointer),
+                ref operand,
+                target_ty,
+            )
+            | Rvalue::Cast(
+                CastKind::Pointer(PointerCast::UnsafeFnPointer),
+                ref operand,
+                target_ty,
+            )
+            | Rvalue::Cast(CastKind::Misc, ref operand, target_ty) => {
+                let source_ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                let target_ty = self.monomorphize(&target_ty);
+                let value = self.codegen_operand(operand)?;
+                let value = self.codegen_misc_cast(value, source_ty, target_ty)?;
+                self.bx.assign(&dest.unwrap(), &value)
+            }
+            Rvalue::Discriminant(ref place) => {
+                let dest_ty = self.monomorphize(&rvalue.ty(self.mir, self.cx.tcx));
+                let dest_ty = self.cx.c_type(dest_ty)?.unwrap();
+                let discr = self.codegen_get_discr(place)?;
+                self.bx.assign(&dest.unwrap(), &discr.cast(dest_ty))
+            }
+            Rvalue::BinaryOp(op, ref lhs, ref rhs) => {
+                let lhs_ty = self.monomorphize(&lhs.ty(self.mir, self.cx.tcx));
+                let rhs_ty = self.monomorphize(&rhs.ty(self.mir, self.cx.tcx));
+                let lhs = self.codegen_operand(lhs)?.unwrap();
+                let rhs = self.codegen_operand(rhs)?.unwrap();
+                let value = self.codegen_binop(op, lhs, rhs, lhs_ty, rhs_ty)?;
+                self.bx.assign(&dest.unwrap(), &value)
+            }
+            Rvalue::CheckedBinaryOp(op, ref lhs, ref rhs) => {
+                let dest_ty = self.monomorphize(&rvalue.ty(self.mir, self.cx.tcx));
+                let lhs_ty = self.monomorphize(&lhs.ty(self.mir, self.cx.tcx));
+                let rhs_ty = self.monomorphize(&rhs.ty(self.mir, self.cx.tcx));
+                let lhs = self.codegen_operand(lhs)?.unwrap();
+                let rhs = self.codegen_operand(rhs)?.unwrap();
+                let dest = dest.unwrap();
+                let result = dest.clone().field(self.cx.c_field_name(dest_ty, 0));
+                let overflowed = dest.field(self.cx.c_field_name(dest_ty, 1));
+                self.codegen_checked_binop(op, lhs, rhs, lhs_ty, rhs_ty, result, overflowed)
+            }
+            Rvalue::UnaryOp(op, ref operand) => {
+                let ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                let c_ty = self.cx.c_type(ty)?.unwrap();
+                let value = self.codegen_operand(operand)?.unwrap();
+                let value = match (op, self.scalar_primitive(ty)) {
+                    (mir::UnOp::Not, _) if ty.is_bool() => value.unary("!"),
+                    (mir::UnOp::Not, _) => value.unary("~"),
+                    (mir::UnOp::Neg, prim) if prim.is_float() => value.unary("-"),
+                    (mir::UnOp::Neg, _) => self.scalar_binop("-", Expr::Uint(0), value, ty)?,
+                };
+                self.bx.assign(&dest.unwrap(), &value.cast(c_ty))
+            }
+            Rvalue::NullaryOp(mir::NullOp::SizeOf, ty) => {
+                let size = self.cx.layout_of(self.monomorphize(&ty)).size.bytes();
+                let uintptr = self.cx.tu.uintptr();
+                self.bx
+                    .assign(&dest.unwrap(), &Expr::Uint(size.into()).cast(uintptr))
+            }
+            Rvalue::NullaryOp(mir::NullOp::Box, ty) => {
+                let box_ty = self.monomorphize(&rvalue.ty(self.mir, self.cx.tcx));
+                let layout = self.cx.layout_of(self.monomorphize(&ty));
+                self.codegen_box_alloc(dest.unwrap(), box_ty, layout)
+            }
+            Rvalue::Aggregate(ref kind, ref operands) => {
+                let dest = match dest {
+                    Some(dest) => dest,
+                    None => return Ok(()),
+                };
+                let dest_ty = self.monomorphize(&rvalue.ty(self.mir, self.cx.tcx));
+                self.codegen_aggregate(dest, dest_ty, kind, operands)
+            }
+            Rvalue::Repeat(ref operand, _) => {
+                let dest = match dest {
+                    Some(dest) => dest,
+                    None => return Ok(()),
+                };
+                let value = match self.codegen_operand(operand)? {
+                    Some(value) => value,
+                    None => return Ok(()),
+                };
+                let dest_ty = self.monomorphize(&rvalue.ty(self.mir, self.cx.tcx));
+                let elem_ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+                let count = self.cx.layout_of(dest_ty).fields.count();
+                let index = self.begin_lane_loop(count as u64)?;
+                self.copy(&dest.index(index), &value, elem_ty)?;
+                self.bx.end_loop()
+            }
+            _ => self.unsupported(&format!("the rvalue `{:?}`", rvalue)),
+        }
+    }
+
+    /// Lowers the binary operation `op` on `lhs` and `rhs`, which are of
+    /// type `lhs_ty` and `rhs_ty`.
+    ///
+    /// Arithmetic wraps around, and the shift amount is masked to the bit
+    /// width of `lhs`, like in LLVM's codegen (the checks are separate MIR
+    /// assertions).
+    fn codegen_binop(
+        &mut self,
+        op: mir::BinOp,
+        lhs: Expr<'b>,
+        rhs: Expr<'b>,
+        lhs_ty: Ty<'tcx>,
+        rhs_ty: Ty<'tcx>,
+    ) -> io::Result<Expr<'b>> {
+        use rustc::mir::BinOp;
+
+        let c_op = match op {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::BitXor => "^",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Ne => "!=",
+            BinOp::Ge => ">=",
+            BinOp::Gt => ">",
+            BinOp::Offset => {
+                // `lhs` is a thin pointer, and `rhs` a count of elements
+                let pointee = lhs_ty.builtin_deref(true).unwrap().ty;
+                let size = self.cx.layout_of(pointee).size.bytes();
+                let ptr_ty = self.cx.c_type(lhs_ty)?.unwrap();
+                let u8 = self.cx.tu.u8();
+                let u8_ptr = self.cx.tu.ptr_to(u8);
+                return Ok(lhs
+                    .cast(u8_ptr)
+                    .binary("+", rhs.binary("*", Expr::Uint(size.into())))
+                    .cast(ptr_ty));
+            }
+        };
+
+        let bool = self.cx.tu.bool();
+        let lhs_c_ty = self.cx.c_type(lhs_ty)?.unwrap();
+        if let Some(meta) = lhs_c_ty.fat_ptr_meta() {
+            let (combine, parts) = match op {
+                BinOp::Eq => ("&&", "=="),
+                BinOp::Ne => ("||", "!="),
+                _ => self.unsupported(&format!("`{:?}` on fat pointers", op)),
+            };
+            let data = lhs.clone().fat_data().binary(parts, rhs.clone().fat_data());
+            let meta = lhs.fat_meta(meta).binary(parts, rhs.fat_meta(meta));
+            return Ok(data.binary(combine, meta).cast(bool));
+        }
+
+        Ok(match op {
+            BinOp::Eq | BinOp::Lt | BinOp::Le | BinOp::Ne | BinOp::Ge | BinOp::Gt => {
+                lhs.binary(c_op, rhs).cast(bool)
+            }
+            BinOp::Shl | BinOp::Shr => {
+                let bits = self.cx.layout_of(lhs_ty).size.bits();
+                let rhs_uint = self.cx.unsigned_type(self.scalar_primitive(rhs_ty));
+                let amount = rhs
+                    .cast(rhs_uint)
+                    .binary("&", Expr::Uint((bits - 1).into()));
+                self.scalar_binop(c_op, lhs, amount, lhs_ty)?
+            }
+            // C has no `%` for floats
+            BinOp::Rem if lhs_ty.is_floating_point() => {
+                if !self.cx.capabilities.builtins {
+                    self.unsupported("the remainder of floats without `__builtin_fmod`");
+                }
+                let fmod = match self.scalar_primitive(lhs_ty) {
+                    Primitive::F32 => "__builtin_fmodf",
+                    _ => "__builtin_fmod",
+                };
+                Expr::Ident(fmod.into()).call(vec![lhs, rhs])
+            }
+            _ => self.scalar_binop(c_op, lhs, rhs, lhs_ty)?,
+        })
+    }
+
+    /// Lowers the overflow-checked binary operation `op` on `lhs` and `rhs`,
+    /// storing the result in `result` and whether it overflowed in
+    /// `overflowed`.
+    #[allow(clippy::too_many_arguments)]
+    fn codegen_checked_binop(
+        &mut self,
+        op: mir::BinOp,
+        lhs: Expr<'b>,
+        rhs: Expr<'b>,
+        lhs_ty: Ty<'tcx>,
+        rhs_ty: Ty<'tcx>,
+        result: Expr<'b>,
+        overflowed: Expr<'b>,
+    ) -> io::Result<()> {
+        use rustc::mir::BinOp;
+
+        // Checked operations in `#[rustc_inherit_overflow_checks]` code
+        // never overflow if overflow checks are disabled
+        if !self.cx.tcx.sess.overflow_checks() {
+            let bool = self.cx.tu.bool();
+            self.bx.assign(&overflowed, &Expr::Uint(0).cast(bool))?;
+            let value = self.codegen_binop(op, lhs, rhs, lhs_ty, rhs_ty)?;
+            return self.bx.assign(&result, &value);
+        }
+
+        let builtin = match op {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            // Shifts overflow if the shift amount is out of range
+            BinOp::Shl | BinOp::Shr => {
+                let bits = self.cx.layout_of(lhs_ty).size.bits();
+                let rhs_uint = self.cx.unsigned_type(self.scalar_primitive(rhs_ty));
+                let bool = self.cx.tu.bool();
+                let out_of_range = rhs
+                    .clone()
+                    .cast(rhs_uint)
+                    .binary(">=", Expr::Uint(bits.into()));
+                self.bx.assign(&overflowed, &out_of_range.cast(bool))?;
+                let value = self.codegen_binop(op, lhs, rhs, lhs_ty, rhs_ty)?;
+                return self.bx.assign(&result, &value);
+            }
+            _ => bug!("overflow-checked `{:?}`", op),
+        };
+        if !self.cx.capabilities.overflow_builtins {
+            self.unsupported("overflow-checked arithmetic without `__builtin_*_overflow`");
+        }
+        self.bx
+            .overflow_op(builtin, &result, &overflowed, &lhs, &rhs)
+    }
+
+    /// Lowers a numeric cast (`as`), or a pointer cast that doesn't change
+    /// the C type, of `value` from `source_ty` to `target_ty`.
+    ///
+    /// `value` is `None` if it is zero-sized.
+    fn codegen_misc_cast(
+        &mut self,
+        value: Option<Expr<'b>>,
+        source_ty: Ty<'tcx>,
+        target_ty: Ty<'tcx>,
+    ) -> io::Result<Expr<'b>> {
+        let target_c_ty = self.cx.c_type(target_ty)?.unwrap();
+        // Fieldless enums are cast through their discriminant
+        if source_ty.is_enum() {
+            return Ok(self.read_discr(value, source_ty)?.cast(target_c_ty));
+        }
+
+        let value = value.unwrap();
+        let source_c_ty = self.cx.c_type(source_ty)?.unwrap();
+        Ok(
+            match (source_c_ty.fat_ptr_meta(), target_c_ty.fat_ptr_meta()) {
+                // Fat pointers with the same metadata have the same C type
+                (Some(_), Some(_)) => value,
+                (Some(_), None) => value.fat_data().cast(target_c_ty),
+                // Go through `uintptr_t` between pointers and integers of
+                // different sizes, which C compilers warn about
+                _ if source_ty.is_unsafe_ptr()
+                    || source_ty.is_fn_ptr()
+                    || target_ty.is_unsafe_ptr() =>
+                {
+                    let uintptr = self.cx.tu.uintptr();
+                    value.cast(uintptr).cast(target_c_ty)
+                }
+                // C's conversions between integers and floats match `as`
+                // (out-of-range floats are undefined behavior for both)
+                _ => value.cast(target_c_ty),
+            },
+        )
+    }
+
+    /// Allocates the memory for `box` (of type `box_ty`) with the
+    /// `exchange_malloc` lang item, storing the pointer in `dest`.
+    fn codegen_box_alloc(
+        &mut self,
+        dest: Expr<'b>,
+        box_ty: Ty<'tcx>,
+        layout: TyLayout<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let def_id = tcx.require_lang_item(ExchangeMallocFnLangItem, Some(self.span));
+        let instance = Instance::mono(tcx, def_id);
+        let fn_abi = self.cx.fn_abi_of_instance(instance)?;
+        let mut args = Vec::new();
+        for (value, &(mode, arg_layout)) in [layout.size.bytes(), layout.align.abi.bytes()]
+            .iter()
+            .zip(&fn_abi.args)
+        {
+            let value = Expr::Uint((*value).into());
+            self.push_arg(&mut args, Some(value), mode, arg_layout)?;
+        }
+        let exchange_malloc = Expr::from(self.cx.get_fn(instance)?);
+        let box_c_ty = self.cx.c_type(box_ty)?.unwrap();
+        self.bx
+            .assign(&dest, &exchange_malloc.call(args).cast(box_c_ty))
+    }
+
+    /// Lowers an aggregate rvalue of type `ty` with the fields `operands`,
+    /// storing it in `dest`.
+    ///
+    /// The deaggregator pass only leaves arrays, but aggregates of every kind
+    /// are lowered field by field like it would.
+    fn codegen_aggregate(
+        &mut self,
+        dest: Expr<'b>,
+        ty: Ty<'tcx>,
+        kind: &mir::AggregateKind<'tcx>,
+        operands: &[Operand<'tcx>],
+    ) -> io::Result<()> {
+        let (variant, active_field) = match *kind {
+            mir::AggregateKind::Array(elem_ty) => {
+                let elem_ty = self.monomorphize(&elem_ty);
+                for (i, operand) in operands.iter().enumerate() {
+                    if let Some(value) = self.codegen_operand(operand)? {
+                        let elem = dest.clone().index(Expr::Uint(i as u128));
+                        self.copy(&elem, &value, elem_ty)?;
+                    }
+                }
+                return Ok(());
+            }
+            mir::AggregateKind::Adt(def, variant, _, _, active_field) if def.is_enum() => {
+                (Some(variant), active_field)
+            }
+            mir::AggregateKind::Adt(_, _, _, _, active_field) => (None, active_field),
+            _ => (None, None),
+        };
+
+        let place_ty = mir::tcx::PlaceTy {
+            ty,
+            variant_index: variant,
+        };
+        for (i, operand) in operands.iter().enumerate() {
+            let field_ty = self.monomorphize(&operand.ty(self.mir, self.cx.tcx));
+            if let Some(value) = self.codegen_operand(operand)? {
+                let index = active_field.unwrap_or(i);
+                let field = self.codegen_field(dest.clone(), place_ty, index, field_ty)?;
+                self.bx.assign(&field, &value)?;
+            }
+        }
+        match variant {
+            Some(variant) => self.write_discr(Some(dest), ty, variant),
+            None => Ok(()),
+        }
+    }
+
+    /// Lowers an unsizing coercion of the pointer `value` from `source_ty` to
+    /// `target_ty`, storing the resulting fat pointer in `dest`.
+    fn codegen_unsize(
+        &mut self,
+        dest: Expr<'b>,
+        value: Expr<'b>,
+        source_ty: Ty<'tcx>,
+        target_ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let source_pointee = source_ty.builtin_deref(true).unwrap().ty;
+        let target_pointee = target_ty.builtin_deref(true).unwrap().ty;
+        let (source_tail, target_tail) = tcx.struct_lockstep_tails_erasing_lifetimes(
+            source_pointee,
+            target_pointee,
+            ty::ParamEnv::reveal_all(),
+        );
+
+        let (value, meta) = match (&source_tail.kind, &target_tail.kind) {
+            (&ty::Array(_, len), &ty::Slice(_)) => (
+                value,
+                (
+                    PtrMeta::Len,
+                    Expr::Uint(len.eval_usize(tcx, ty::ParamEnv::reveal_all()).into()),
+                ),
+            ),
+            // Dropping auto traits (eg. `&dyn Any + Send` to `&dyn Any`)
+            // keeps the vtable
+            (&ty::Dynamic(..), &ty::Dynamic(..)) => (
+                value.clone().fat_data(),
+                (PtrMeta::Vtable, value.fat_vtable()),
+            ),
+            (_, &ty::Dynamic(ref data, _)) => {
+                let vtable = meth::get_vtable(self.cx, source_tail, data.principal())?;
+                (value, (PtrMeta::Vtable, vtable))
+            }
+            _ => self.unsupported(&format!("unsizing `{}` to `{}`", source_ty, target_ty)),
+        };
+
+        let void = self.cx.tu.void();
+        let void_ptr = self.cx.tu.ptr_to(void);
+        self.bx
+            .assign(&dest.clone().fat_data(), &value.cast(void_ptr))?;
+        self.bx.assign(&dest.fat_meta(meta.0), &meta.1)
+    }
+
+    /// Stores a pointer to the function `instance` in `dest`, which is of the
+    /// function pointer type `fn_ptr_ty`.
+    fn codegen_fn_ptr(
+        &mut self,
+        dest: Expr<'b>,
+        instance: Instance<'tcx>,
+        fn_ptr_ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let f = self.cx.get_fn(instance)?;
+        // The C signature of the function might differ from the pointer's
+        // (eg. closure shims take a zero-sized environment that's ignored)
+        let fn_ptr_ty = self.cx.c_type(self.monomorphize(&fn_ptr_ty))?.unwrap();
+        self.bx.assign(&dest, &Expr::from(f).cast(fn_ptr_ty))
+    }
+
+    /// Reads the discriminant of the enum or generator in `place`.
+    fn codegen_get_discr(&mut self, place: &Place<'tcx>) -> io::Result<Expr<'b>> {
+        let ty = self.monomorphize(&place.ty(self.mir, self.cx.tcx).ty);
+        let place = self.codegen_lvalue(place)?;
+        self.read_discr(place, ty)
+    }
+
+    /// Reads the discriminant of the enum or generator of type `ty` in the
+    /// lvalue `place` (`None` if it is zero-sized).
+    ///
+    /// The returned expression is of the type of the tag, or an unsigned
+    /// integer type of the same size for niche-encoded discriminants.
+    fn read_discr(&mut self, place: Option<Expr<'b>>, ty: Ty<'tcx>) -> io::Result<Expr<'b>> {
+        let tcx = self.cx.tcx;
+        let layout = self.cx.layout_of(ty);
+        match layout.variants {
+            layout::Variants::Single { index } => {
+                let discr = ty
+                    .discriminant_for_variant(tcx, index)
+                    .map_or(index.as_u32().into(), |discr| discr.val);
+                Ok(self.cx.uint_literal(discr))
+            }
+            layout::Variants::Multiple {
+                ref discr,
+                discr_kind: layout::DiscriminantKind::Tag,
+                discr_index,
+                ..
+            } => {
+                let tag_ty = self.cx.scalar_type(discr.value);
+                Ok(self.at_offset(place.unwrap(), layout.fields.offset(discr_index), tag_ty))
+            }
+            layout::Variants::Multiple {
+                ref discr,
+                discr_kind:
+                    layout::DiscriminantKind::Niche {
+                        dataful_variant,
+                        ref niche_variants,
+                        niche_start,
+                    },
+                discr_index,
+                ..
+            } => {
+                // The niche holds `niche_start + (variant - niche_variants.start)`
+                // for the niche variants, and any other value means the
+                // dataful variant. The subtraction wraps around, so the range
+                // check is a single unsigned comparison.
+                let tag_ty = self.cx.scalar_type(discr.value);
+                let uint = self.cx.unsigned_type(discr.value);
+                let tag = self
+                    .at_offset(place.unwrap(), layout.fields.offset(discr_index), tag_ty)
+                    .cast(uint);
+                let relative = tag
+                    .binary("-", self.cx.uint_literal(niche_start))
+                    .cast(uint);
+                let first = niche_variants.start().as_u32();
+                let last = niche_variants.end().as_u32();
+                Ok(relative
+                    .clone()
+                    .binary("<=", Expr::Uint((last - first).into()))
+                    .cond(
+                        relative.binary("+", Expr::Uint(first.into())),
+                        Expr::Uint(dataful_variant.as_u32().into()),
+                    )
+                    .cast(uint))
+            }
+        }
+    }
+
+    /// Sets the discriminant of the enum or generator in `place` to the one
+    /// of `variant`.
+    fn codegen_set_discr(&mut self, place: &Place<'tcx>, variant: VariantIdx) -> io::Result<()> {
+        let ty = self.monomorphize(&place.ty(self.mir, self.cx.tcx).ty);
+        let place = self.codegen_lvalue(place)?;
+        self.write_discr(place, ty, variant)
+    }
+
+    /// Sets the discriminant of the enum or generator of type `ty` in the
+    /// lvalue `place` (`None` if it is zero-sized) to the one of `variant`.
+    fn write_discr(
+        &mut self,
+        place: Option<Expr<'b>>,
+        ty: Ty<'tcx>,
+        variant: VariantIdx,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let layout = self.cx.layout_of(ty);
+        // Setting an uninhabited variant is unreachable, and the layout might
+        // not even have it
+        if layout.for_variant(&*self.cx, variant).abi.is_uninhabited() {
+            return Ok(());
+        }
+        let (tag, discr_index, value) = match layout.variants {
+            layout::Variants::Single { index } => {
+                assert_eq!(index, variant);
+                return Ok(());
+            }
+            layout::Variants::Multiple {
+                ref discr,
+                discr_kind: layout::DiscriminantKind::Tag,
+                discr_index,
+                ..
+            } => {
+                let value = ty.discriminant_for_variant(tcx, variant).unwrap().val;
+                (discr, discr_index, value)
+            }
+            layout::Variants::Multiple {
+                ref discr,
+                discr_kind:
+                    layout::DiscriminantKind::Niche {
+                        dataful_variant,
+                        ref niche_variants,
+                        niche_start,
+                    },
+                discr_index,
+                ..
+            } => {
+                // The dataful variant is identified by its data, so there's
+                // nothing to store
+                if variant == dataful_variant {
+                    return Ok(());
+                }
+                let niche = u128::from(variant.as_u32() - niche_variants.start().as_u32());
+                (discr, discr_index, niche.wrapping_add(niche_start))
+            }
+        };
+
+        let tag_ty = self.cx.scalar_type(tag.value);
+        let tag_place = self.at_offset(place.unwrap(), layout.fields.offset(discr_index), tag_ty);
+        let value = truncate(value, tag.value.size(&*self.cx));
+        self.bx
+            .assign(&tag_place, &self.cx.uint_literal(value).cast(tag_ty))
+    }
+
+    fn codegen_terminator(
+        &mut self,
+        bb: BasicBlock,
+        kind: &'tcx TerminatorKind<'tcx>,
+    ) -> io::Result<()> {
+        match *kind {
+            TerminatorKind::Goto { target } => self.bx.goto(&block_label(target)),
+            TerminatorKind::FalseEdges { real_target, .. } => {
+                self.bx.goto(&block_label(real_target))
+            }
+            TerminatorKind::FalseUnwind { real_target, .. } => {
+                self.bx.goto(&block_label(real_target))
+            }
+            TerminatorKind::SwitchInt {
+                ref discr,
+                switch_ty,
+                ref values,
+                ref targets,
+            } => {
+                // Switch on the unsigned representation, since that's how the
+                // values are stored
+                let switch_ty = self.monomorphize(&switch_ty);
+                let uint = self.cx.cast_type(self.cx.layout_of(switch_ty));
+                let discr = self.codegen_operand(discr)?.unwrap().cast(uint);
+                let cases = values
+                    .iter()
+                    .zip(targets.iter())
+                    .map(|(&value, &target)| (self.cx.uint_literal(value), block_label(target)))
+                    .collect::<Vec<_>>();
+                let otherwise = block_label(*targets.last().unwrap());
+                self.bx.switch(&discr, &cases, &otherwise)
+            }
+            TerminatorKind::Return => self.codegen_return(),
+            TerminatorKind::Unreachable => self.codegen_unreachable(),
+            TerminatorKind::Abort => self.codegen_abort(),
+            // Cleanup blocks are skipped, but be defensive in case a
+            // `Resume` shows up outside of one
+            TerminatorKind::Resume => self.codegen_abort(),
+            TerminatorKind::Call {
+                ref func,
+                ref args,
+                ref destination,
+                ..
+            } => self.codegen_call(func, args, destination),
+            TerminatorKind::Drop {
+                ref location,
+                target,
+                ..
+            } => {
+                self.codegen_drop(location)?;
+                self.bx.goto(&block_label(target))
+            }
+            TerminatorKind::DropAndReplace {
+                ref location,
+                ref value,
+                target,
+                ..
+            } => {
+                self.codegen_drop(location)?;
+                let dest = self.codegen_lvalue(location)?;
+                if let (Some(dest), Some(value)) = (dest, self.codegen_operand(value)?) {
+                    let ty = self.monomorphize(&location.ty(self.mir, self.cx.tcx).ty);
+                    self.copy(&dest, &value, ty)?;
+                }
+                self.bx.goto(&block_label(target))
+            }
+            TerminatorKind::Assert {
+                ref cond,
+                expected,
+                ref msg,
+                target,
+                ..
+            } => {
+                // Negation has no checked operation, so the assertion in
+                // `#[rustc_inherit_overflow_checks]` code is skipped if
+                // overflow checks are disabled. Division overflow is always
+                // checked.
+                let checked = match *msg {
+                    PanicInfo::OverflowNeg => self.cx.tcx.sess.overflow_checks(),
+                    _ => true,
+                };
+                if !checked {
+                    return self.bx.goto(&block_label(target));
+                }
+
+                let failed = format!("{}_failed", block_label(bb));
+                let cond = self.codegen_operand(cond)?.unwrap();
+                let cases = [(Expr::Uint(expected.into()), block_label(target))];
+                self.bx.switch(&cond, &cases, &failed)?;
+                self.bx.label(&failed)?;
+                self.codegen_assert_failure(msg)
+            }
+            _ => self.unsupported(&format!("the terminator `{:?}`", kind)),
+        }
+    }
+
+    /// Panics with the message of the failed assertion `msg`, by calling
+    /// the `panic` or `panic_bounds_check` lang item like LLVM's codegen.
+    fn codegen_assert_failure(&mut self, msg: &mir::AssertMessage<'tcx>) -> io::Result<()> {
+        match *msg {
+            PanicInfo::BoundsCheck { ref len, ref index } => {
+                let args = vec![self.codegen_operand(index)?, self.codegen_operand(len)?];
+                self.codegen_panic(PanicBoundsCheckFnLangItem, None, args)
+            }
+            _ => self.codegen_panic(PanicFnLangItem, Some(msg.description()), Vec::new()),
+        }
+    }
+
+    /// Calls the panic lang item `lang_item` with the message `msg` (if it
+    /// takes one), the location of the current statement and `args`.
+    ///
+    /// Like in `core::panicking`, the message comes before the location and
+    /// the other arguments after it.
+    fn codegen_panic(
+        &mut self,
+        lang_item: LangItem,
+        msg: Option<&str>,
+        args: Vec<Option<Expr<'b>>>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let mut panic_args = Vec::new();
+        if let Some(msg) = msg {
+            let str_c_ty = self.cx.c_type(tcx.mk_static_str())?.unwrap();
+            panic_args.push(Some(Expr::from(self.cx.const_str(msg, str_c_ty)?)));
+        }
+        panic_args.push(Some(self.caller_location()?));
+        panic_args.extend(args);
+
+        let def_id = tcx.require_lang_item(lang_item, Some(self.span));
+        let instance = Instance::mono(tcx, def_id);
+        let fn_abi = self.cx.fn_abi_of_instance(instance)?;
+        let mut c_args = Vec::new();
+        for (value, &(mode, layout)) in panic_args.into_iter().zip(&fn_abi.args) {
+            self.push_arg(&mut c_args, value, mode, layout)?;
+        }
+        let panic = Expr::from(self.cx.get_fn(instance)?);
+        self.bx.eval(&panic.call(c_args))?;
+        self.codegen_unreachable()
+    }
+
+    /// Lowers a reference to the `core::panic::Location` of the current
+    /// statement, with the column counted from 1 like LLVM's codegen.
+    fn caller_location(&mut self) -> io::Result<Expr<'b>> {
+        let tcx = self.cx.tcx;
+        let loc = tcx.sess.source_map().lookup_char_pos(self.span.lo());
+        let file = Symbol::intern(&loc.file.name.to_string());
+        let value = tcx.const_caller_location((file, loc.line as u32, loc.col_display as u32 + 1));
+        let c_ty = self.cx.c_type(value.ty)?.unwrap();
+        self.codegen_const(value, value.ty, c_ty)
+    }
+
+    /// Aborts the process.
+    ///
+    /// Freestanding TUs have no `abort`, so they trap instead, or hang if the
+    /// C compiler has no `__builtin_trap`.
+    fn codegen_abort(&mut self) -> io::Result<()> {
+        match self.cx.tu.environment() {
+            Environment::Hosted => self.bx.abort(),
+            Environment::Freestanding { .. } if self.cx.capabilities.builtins => self.bx.trap(),
+            Environment::Freestanding { .. } => self.bx.hang(),
+        }
+    }
+
+    /// Marks the current position as unreachable.
+    ///
+    /// Without `__builtin_unreachable`, this aborts instead, which at least
+    /// tells the C compiler that control doesn't continue.
+    fn codegen_unreachable(&mut self) -> io::Result<()> {
+        if self.cx.capabilities.builtins {
+            self.bx.unreachable()
+        } else {
+            self.codegen_abort()
+        }
+    }
+
+    fn codegen_return(&mut self) -> io::Result<()> {
+        let (mode, layout) = self.fn_abi.ret;
+        let ret = match (mode, self.locals[mir::RETURN_PLACE].clone()) {
+            (CPassMode::ByValue, ret) => ret,
+            (CPassMode::Pair, Some(ret)) => {
+                let ret_ty = self.cx.pair_return_type(layout)?;
+                let tmp = self.bx.declare_variable("tmp", ret_ty, None)?;
+                let tmp = Expr::from(tmp);
+                for (i, &field) in PAIR_RETURN_FIELDS.iter().enumerate() {
+                    let scalar = self.pair_field(ret.clone(), layout, i)?;
+                    self.bx.assign(&tmp.clone().field(field), &scalar)?;
+                }
+                Some(tmp)
+            }
+            (CPassMode::Cast, Some(ret)) => {
+                let cast_ty = self.cx.cast_target(layout);
+                let tmp = self.bx.declare_variable("tmp", cast_ty, None)?;
+                let tmp = Expr::from(tmp);
+                self.bx
+                    .memcpy(&tmp.clone().addr_of(), &ret.addr_of(), layout.size.bytes())?;
+                Some(tmp)
+            }
+            // Indirect return values were already written through the
+            // pointer
+            _ => None,
+        };
+        self.bx.ret(ret.as_ref())
+    }
+
+    /// Drops the value in `place`.
+    fn codegen_drop(&mut self, place: &Place<'tcx>) -> io::Result<()> {
+        let ty = self.monomorphize(&place.ty(self.mir, self.cx.tcx).ty);
+        let (ptr, meta) = match self.codegen_place(place)? {
+            Some(PlaceRef { expr, meta: None }) => (expr.addr_of(), None),
+            Some(PlaceRef {
+                expr,
+                meta: Some((_, meta)),
+            }) => (expr, Some(meta)),
+            // Any well-aligned pointer is a valid pointer to a zero-sized
+            // value
+            None => {
+                let align = self.cx.layout_of(ty).align.abi.bytes();
+                (Expr::Uint(align.into()), None)
+            }
+        };
+        self.call_drop_glue(ptr, meta, ty)
+    }
+
+    /// Calls the drop glue of `ty` on the value `ptr` points to.
+    ///
+    /// `meta` is the pointer metadata if `ty` is unsized.
+    fn call_drop_glue(
+        &mut self,
+        ptr: Expr<'b>,
+        meta: Option<Expr<'b>>,
+        ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        if !ty.needs_drop(tcx, ty::ParamEnv::reveal_all()) {
+            return Ok(());
+        }
+
+        let void = self.cx.tu.void();
+        let void_ptr = self.cx.tu.ptr_to(void);
+        if let ty::Dynamic(..) = ty.kind {
+            // The drop glue of the concrete type is in the vtable
+            let drop_fn = meth::DESTRUCTOR.get(meta.unwrap());
+            return self.bx.eval(&drop_fn.call(vec![ptr.cast(void_ptr)]));
+        }
+
+        let instance = Instance::resolve_drop_in_place(tcx, ty);
+        let drop_fn = Expr::from(self.cx.get_fn(instance)?);
+        let args = match meta {
+            None => {
+                let ptr_ty = self.cx.c_type(tcx.mk_mut_ptr(ty))?.unwrap();
+                vec![ptr.cast(ptr_ty)]
+            }
+            // Fat pointers are passed as a pair
+            Some(meta) => vec![ptr.cast(void_ptr), meta],
+        };
+        self.bx.eval(&drop_fn.call(args))
+    }
+
+    /// Lowers the drop glue of `ty`.
+    ///
+    /// The glue takes a (possibly fat) pointer to the value to drop as its
+    /// only argument, `_1`.
+    fn codegen_drop_glue(&mut self, ty: Ty<'tcx>) -> io::Result<()> {
+        let ptr = self.locals[Local::new(1)].clone().unwrap();
+        if self.is_sized(ty) {
+            self.drop_contents(ptr, None, ty)?;
+        } else {
+            let place = self.deref_fat_ptr(ptr, ty)?;
+            self.drop_contents(place.expr, place.meta, ty)?;
+        }
+        self.bx.ret(None)
+    }
+
+    /// Drops the value of type `ty` that `ptr` points to, by running its
+    /// `Drop` impl and dropping its contents.
+    ///
+    /// If `ty` is unsized, `ptr` is the unsized place (see `unsized_place`),
+    /// and `meta` its pointer metadata.
+    fn drop_contents(
+        &mut self,
+        ptr: Expr<'b>,
+        meta: Option<(PtrMeta, Expr<'b>)>,
+        ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        match ty.kind {
+            ty::Dynamic(..) => self.call_drop_glue(ptr, meta.map(|meta| meta.1), ty),
+            ty::Array(elem, len) => {
+                let len = len.eval_usize(tcx, ty::ParamEnv::reveal_all());
+                let elems = ptr.clone().deref();
+                self.drop_elements(ptr, elems, Expr::Uint(len.into()), elem)
+            }
+            // Unsized slice places are already pointers to their first element
+            ty::Slice(elem) => self.drop_elements(ptr.clone(), ptr, meta.unwrap().1, elem),
+            ty::Adt(def, _) if def.is_box() => self.drop_box(ptr.deref(), ty),
+            ty::Adt(def, substs) => {
+                if def.has_dtor(tcx) {
+                    self.call_drop_impl(ptr.clone(), meta.clone(), ty)?;
+                }
+                if def.is_union() {
+                    // Union fields are never dropped
+                    Ok(())
+                } else if def.is_enum() {
+                    self.drop_enum(ptr, ty, def, substs)
+                } else {
+                    self.drop_fields(ptr, meta, ty, None)
+                }
+            }
+            ty::Tuple(..) | ty::Closure(..) => self.drop_fields(ptr, meta, ty, None),
+            // Generators are dropped by their drop shim, and other types have
+            // no drop glue
+            _ => bug!("drop glue for {}", ty),
+        }
+    }
+
+    /// Drops `len` elements of type `elem` in the array lvalue (or element
+    /// pointer) `elems`, which `ptr` points to.
+    fn drop_elements(
+        &mut self,
+        ptr: Expr<'b>,
+        elems: Expr<'b>,
+        len: Expr<'b>,
+        elem: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let uintptr = self.cx.tu.uintptr();
+        let index = self.bx.declare_variable("i", uintptr, None)?;
+        self.bx.begin_loop(index, &len)?;
+        // All elements of zero-sized arrays share the same address
+        let elem_ptr = if self.cx.layout_of(elem).is_zst() {
+            ptr
+        } else {
+            elems.index(Expr::from(index)).addr_of()
+        };
+        self.call_drop_glue(elem_ptr, None, elem)?;
+        self.bx.end_loop()
+    }
+
+    /// Drops the contents of the `Box` lvalue `boxed`, and frees its memory.
+    fn drop_box(&mut self, boxed: Expr<'b>, ty: Ty<'tcx>) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let content_ty = ty.boxed_ty();
+        if self.is_sized(content_ty) {
+            self.call_drop_glue(boxed.clone(), None, content_ty)?;
+        } else {
+            let place = self.deref_fat_ptr(boxed.clone(), content_ty)?;
+            let meta = place.meta.map(|meta| meta.1);
+            let void = self.cx.tu.void();
+            let void_ptr = self.cx.tu.ptr_to(void);
+            self.call_drop_glue(place.expr.cast(void_ptr), meta, content_ty)?;
+        }
+
+        // The memory is freed by `box_free::<T>(Unique<T>)`, and the box's
+        // `Unique` is at its start
+        let box_free = tcx.lang_items().box_free_fn().unwrap();
+        let substs = tcx.intern_substs(&[content_ty.into()]);
+        let instance =
+            Instance::resolve(tcx, ty::ParamEnv::reveal_all(), box_free, substs).unwrap();
+        let fn_abi = self.cx.fn_abi_of_instance(instance)?;
+        let (mode, layout) = fn_abi.args[0];
+        let unique_ty = self.cx.c_type(layout.ty)?.unwrap();
+        let unique = self.at_offset(boxed, Size::ZERO, unique_ty);
+
+        let mut args = Vec::new();
+        self.push_arg(&mut args, Some(unique), mode, layout)?;
+        let box_free = Expr::from(self.cx.get_fn(instance)?);
+        self.bx.eval(&box_free.call(args))
+    }
+
+    /// Calls `<ty as Drop>::drop` on the value `ptr` points to.
+    ///
+    /// `meta` is the pointer metadata if `ty` is unsized.
+    fn call_drop_impl(
+        &mut self,
+        ptr: Expr<'b>,
+        meta: Option<(PtrMeta, Expr<'b>)>,
+        ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let drop_trait = tcx.lang_items().drop_trait().unwrap();
+        let drop_fn = tcx.associated_items(drop_trait).next().unwrap().def_id;
+        let substs = tcx.mk_substs_trait(ty, &[]);
+        let instance = Instance::resolve(tcx, ty::ParamEnv::reveal_all(), drop_fn, substs).unwrap();
+        let fn_abi = self.cx.fn_abi_of_instance(instance)?;
+        let (mode, layout) = fn_abi.args[0];
+
+        let mut args = Vec::new();
+        match meta {
+            // `&mut self` is a fat pointer, which is passed as a pair
+            Some((_, meta)) => {
+                let void = self.cx.tu.void();
+                let void_ptr = self.cx.tu.ptr_to(void);
+                args.push(ptr.cast(void_ptr));
+                args.push(meta);
+            }
+            None => {
+                let self_ty = self.cx.c_type(layout.ty)?.unwrap();
+                self.push_arg(&mut args, Some(ptr.cast(self_ty)), mode, layout)?;
+            }
+        }
+        let drop_fn = Expr::from(self.cx.get_fn(instance)?);
+        self.bx.eval(&drop_fn.call(args))
+    }
+
+    /// Drops the fields of the active variant of the enum `ptr` points to.
+    fn drop_enum(
+        &mut self,
+        ptr: Expr<'b>,
+        ty: Ty<'tcx>,
+        def: &'tcx ty::AdtDef,
+        substs: ty::subst::SubstsRef<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let layout = self.cx.layout_of(ty);
+        let (tag, is_niche) = match layout.variants {
+            layout::Variants::Single { index } => {
+                return self.drop_fields(ptr, None, ty, Some(index))
+            }
+            layout::Variants::Multiple {
+                ref discr,
+                ref discr_kind,
+                ..
+            } => (discr.value, *discr_kind != layout::DiscriminantKind::Tag),
+        };
+
+        // Niche-encoded discriminants are read as variant indices
+        let uint = self.cx.unsigned_type(tag);
+        let discr = self.read_discr(Some(ptr.clone().deref()), ty)?.cast(uint);
+        let mut variants = Vec::new();
+        let mut cases = Vec::new();
+        for (index, variant) in def.variants.iter_enumerated() {
+            let needs_drop = variant.fields.iter().any(|field| {
+                field
+                    .ty(tcx, substs)
+                    .needs_drop(tcx, ty::ParamEnv::reveal_all())
+            });
+            if !needs_drop {
+                continue;
+            }
+            let value = if is_niche {
+                index.as_u32().into()
+            } else {
+                let discr = ty.discriminant_for_variant(tcx, index).unwrap().val;
+                truncate(discr, tag.size(&*self.cx))
+            };
+            cases.push((
+                self.cx.uint_literal(value),
+                format!("variant{}", index.as_u32()),
+            ));
+            variants.push(index);
+        }
+
+        self.bx.switch(&discr, &cases, "done")?;
+        for (index, (_, label)) in variants.into_iter().zip(&cases) {
+            self.bx.label(label)?;
+            self.drop_fields(ptr.clone(), None, ty, Some(index))?;
+            self.bx.goto("done")?;
+        }
+        self.bx.label("done")
+    }
+
+    /// Drops the fields of the value of type `ty` that `ptr` points to, or
+    /// those of its variant `variant`.
+    ///
+    /// `meta` is the pointer metadata if `ty` is unsized.
+    fn drop_fields(
+        &mut self,
+        ptr: Expr<'b>,
+        meta: Option<(PtrMeta, Expr<'b>)>,
+        ty: Ty<'tcx>,
+        variant: Option<VariantIdx>,
+    ) -> io::Result<()> {
+        let layout = self.cx.layout_of(ty);
+        let layout = match variant {
+            Some(variant) => layout.for_variant(&*self.cx, variant),
+            None => layout,
+        };
+        let place_ty = mir::tcx::PlaceTy {
+            ty,
+            variant_index: variant,
+        };
+        for index in 0..layout.fields.count() {
+            let field = layout.field(&*self.cx, index);
+            if let Some(ref meta) = meta {
+                // The fields of unsized values are accessed by offset, and the
+                // last one is unsized itself
+                match self.unsized_field(ptr.clone(), meta, layout, index)? {
+                    Some(PlaceRef {
+                        expr,
+                        meta: Some((_, field_meta)),
+                    }) => self.call_drop_glue(expr, Some(field_meta), field.ty)?,
+                    Some(PlaceRef { expr, meta: None }) => {
+                        self.call_drop_glue(expr.addr_of(), None, field.ty)?
+                    }
+                    None => self.call_drop_glue(ptr.clone(), None, field.ty)?,
+                }
+                continue;
+            }
+            let field_ptr = if field.is_zst() {
+                ptr.clone()
+            } else {
+                let base = ptr.clone().deref();
+                self.codegen_field(base, place_ty, index, field.ty)?
+                    .addr_of()
+            };
+            self.call_drop_glue(field_ptr, None, field.ty)?;
+        }
+        Ok(())
+    }
+
+    fn codegen_call(
+        &mut self,
+        func: &Operand<'tcx>,
+        args: &[Operand<'tcx>],
+        destination: &Option<(Place<'tcx>, BasicBlock)>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let callee_ty = self.monomorphize(&func.ty(self.mir, tcx));
+
+        let (callee, fn_abi, vtable_index) = match callee_ty.kind {
+            ty::FnDef(def_id, substs) => {
+                let instance =
+                    Instance::resolve(tcx, ty::ParamEnv::reveal_all(), def_id, substs).unwrap();
+                if let ty::InstanceDef::Intrinsic(_) = instance.def {
+                    return self.codegen_intrinsic(instance, args, destination);
+                }
+                if let ty::InstanceDef::DropGlue(_, None) = instance.def {
+                    // Empty drop glue (eg. `drop_in_place::<[u32]>` in
+                    // `Vec`'s destructor) does nothing, so it isn't collected
+                    return match *destination {
+                        Some((_, target)) => self.bx.goto(&block_label(target)),
+                        None => self.codegen_unreachable(),
+                    };
+                }
+                let fn_abi = self.cx.fn_abi_of_instance(instance)?;
+                match instance.def {
+                    // The callee is looked up in the vtable of the trait
+                    // object (the `Self` type) below
+                    ty::InstanceDef::Virtual(_, index) => {
+                        (None, fn_abi, Some((index, instance.substs.type_at(0))))
+                    }
+                    _ => (Some(Expr::from(self.cx.get_fn(instance)?)), fn_abi, None),
+                }
+            }
+            ty::FnPtr(sig) => {
+                let fn_abi = self.cx.fn_abi_of_fn_ptr(sig)?;
+                (self.codegen_operand(func)?, fn_abi, None)
+            }
+            _ => bug!("call to non-function type {}", callee_ty),
+        };
+
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.codegen_operand(arg)?);
+        }
+        if callee_ty.fn_sig(tcx).abi() == Abi::RustCall && !args.is_empty() {
+            // The last argument is a tuple, whose fields are passed as
+            // separate arguments
+            let tuple = values.pop().unwrap();
+            let tuple_ty = self.monomorphize(&args.last().unwrap().ty(self.mir, tcx));
+            let layout = self.cx.layout_of(tuple_ty);
+            for index in 0..layout.fields.count() {
+                let field = match tuple {
+                    Some(ref tuple) if !layout.field(&*self.cx, index).is_zst() => {
+                        Some(tuple.clone().field(field_name(index)))
+                    }
+                    _ => None,
+                };
+                values.push(field);
+            }
+        }
+        if fn_abi.args.len() != values.len() {
+            bug!("argument count mismatch in call to {}", callee_ty);
+        }
+
+        let (ret_mode, ret_layout) = fn_abi.ret;
+        let dest = match *destination {
+            Some((ref place, _)) => self.codegen_lvalue(place)?,
+            None => None,
+        };
+
+        let mut c_args = Vec::new();
+        if ret_mode == CPassMode::Indirect {
+            c_args.push(dest.clone().unwrap().addr_of());
+        }
+
+        let mut callee = callee;
+        for (i, (value, &(mode, layout))) in values.into_iter().zip(&fn_abi.args).enumerate() {
+            if let (Some((index, dyn_ty)), 0) = (vtable_index, i) {
+                // The receiver is a pointer to a trait object. The method
+                // only gets its data pointer, and is looked up in the vtable.
+                let receiver = value.unwrap();
+                let fn_ptr_ty = self.cx.tu.fn_ptr(fn_abi.sig);
+                let vtable = receiver.clone().fat_vtable();
+                let index = VirtualIndex::from_index(index);
+                callee = Some(index.get_fn(self.cx, vtable, dyn_ty, fn_ptr_ty)?);
+                c_args.push(receiver.fat_data());
+                continue;
+            }
+            self.push_arg(&mut c_args, value, mode, layout)?;
+        }
+
+        let call = callee.unwrap().call(c_args);
+        match (ret_mode, dest) {
+            (CPassMode::ByValue, Some(dest)) => self.bx.assign(&dest, &call)?,
+            (CPassMode::Pair, Some(dest)) => {
+                let ret_ty = self.cx.pair_return_type(ret_layout)?;
+                let tmp = self.spill(&call, ret_ty)?;
+                for (i, &field) in PAIR_RETURN_FIELDS.iter().enumerate() {
+                    let dest_scalar = self.pair_field(dest.clone(), ret_layout, i)?;
+                    self.bx.assign(&dest_scalar, &tmp.clone().field(field))?;
+                }
+            }
+            (CPassMode::Cast, Some(dest)) => {
+                let cast_ty = self.cx.cast_target(ret_layout);
+                let tmp = self.spill(&call, cast_ty)?;
+                self.bx
+                    .memcpy(&dest.addr_of(), &tmp.addr_of(), ret_layout.size.bytes())?;
+            }
+            _ => self.bx.eval(&call)?,
+        }
+
+        match *destination {
+            Some((_, target)) => self.bx.goto(&block_label(target)),
+            None => self.codegen_unreachable(),
+        }
+    }
+
+    /// Lowers a call to the intrinsic `instance`.
+    fn codegen_intrinsic(
+        &mut self,
+        instance: Instance<'tcx>,
+        args: &[Operand<'tcx>],
+        destination: &Option<(Place<'tcx>, BasicBlock)>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let name = tcx.item_name(instance.def_id()).as_str();
+        let (place, target) = match *destination {
+            Some((ref place, target)) => (place, target),
+            None => {
+                return match &*name {
+                    "abort" => self.codegen_abort(),
+                    "unreachable" => self.codegen_unreachable(),
+                    _ => self.unsupported(&format!("the intrinsic `{}`", name)),
+                }
+            }
+        };
+        let dest_ty = self.monomorphize(&place.ty(self.mir, tcx).ty);
+        let dest = self.codegen_lvalue(place)?;
+        if name.starts_with("simd_") {
+            self.codegen_simd_intrinsic(&name, args, dest.unwrap(), dest_ty)?;
+        } else {
+            self.codegen_scalar_intrinsic(instance, &name, args, dest, dest_ty)?;
+        }
+        self.bx.goto(&block_label(target))
+    }
+
+    /// Lowers a call to the intrinsic `instance` named `name`, which isn't a
+    /// SIMD intrinsic, storing the result of type `dest_ty` in `dest` (`None`
+    /// if it is zero-sized).
+    ///
+    /// Like in LLVM's codegen, the intrinsics that only depend on their type
+    /// arguments (eg. `size_of`) are evaluated at compile time.
+    fn codegen_scalar_intrinsic(
+        &mut self,
+        instance: Instance<'tcx>,
+        name: &str,
+        args: &[Operand<'tcx>],
+        dest: Option<Expr<'b>>,
+        dest_ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        use rustc::mir::BinOp;
+
+        let tcx = self.cx.tcx;
+        let arg_tys = args
+            .iter()
+            .map(|arg| self.monomorphize(&arg.ty(self.mir, tcx)))
+            .collect::<Vec<_>>();
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.codegen_operand(arg)?);
+        }
+        let arg = |i: usize| values[i].clone().unwrap();
+
+        let value = if let Some(op) = intrinsic::binary_op(name) {
+            self.codegen_binop(op, arg(0), arg(1), arg_tys[0], arg_tys[1])?
+        } else if let Some(builtin) = intrinsic::float_builtin(name) {
+            if !self.cx.capabilities.builtins {
+                self.unsupported(&format!("`{}` without `{}`", name, builtin));
+            }
+            let args = values.iter().cloned().map(Option::unwrap).collect();
+            Expr::Ident(builtin.into()).call(args)
+        } else {
+            match name {
+                "size_of" | "min_align_of" | "pref_align_of" | "needs_drop" | "type_id"
+                | "type_name" => {
+                    let cid = GlobalId {
+                        instance,
+                        promoted: None,
+                    };
+                    let value = tcx
+                        .const_eval(ty::ParamEnv::reveal_all().and(cid))
+                        .unwrap_or_else(|_| {
+                            tcx.sess
+                                .span_fatal(self.span, "erroneous constant encountered")
+                        });
+                    match self.cx.c_type(dest_ty)? {
+                        Some(c_ty) => self.codegen_const(value, dest_ty, c_ty)?,
+                        None => return Ok(()),
+                    }
+                }
+                "caller_location" => self.caller_location()?,
+                "size_of_val" | "min_align_of_val" => {
+                    let pointee = arg_tys[0].builtin_deref(true).unwrap().ty;
+                    if self.is_sized(pointee) {
+                        let layout = self.cx.layout_of(pointee);
+                        match name {
+                            "size_of_val" => Expr::Uint(layout.size.bytes().into()),
+                            _ => Expr::Uint(layout.align.abi.bytes().into()),
+                        }
+                    } else if let ty::Dynamic(..) = pointee.kind {
+                        // The size and alignment of the concrete type are in
+                        // the vtable
+                        let vtable = arg(0).fat_vtable();
+                        match name {
+                            "size_of_val" => meth::SIZE.get(vtable),
+                            _ => meth::ALIGN.get(vtable),
+                        }
+                    } else if let ty::Slice(_) | ty::Str = pointee.kind {
+                        let elem = self.cx.layout_of(pointee.sequence_element_type(tcx));
+                        match name {
+                            "size_of_val" => arg(0)
+                                .fat_meta(PtrMeta::Len)
+                                .binary("*", Expr::Uint(elem.size.bytes().into())),
+                            _ => Expr::Uint(elem.align.abi.bytes().into()),
+                        }
+                    } else {
+                        self.unsupported(&format!("`{}` of `{}`", name, pointee))
+                    }
+                }
+                "add_with_overflow" | "sub_with_overflow" | "mul_with_overflow" => {
+                    if !self.cx.capabilities.overflow_builtins {
+                        self.unsupported(&format!("`{}` without `__builtin_*_overflow`", name));
+                    }
+                    let dest = dest.unwrap();
+                    let result = dest.clone().field(self.cx.c_field_name(dest_ty, 0));
+                    let overflowed = dest.field(self.cx.c_field_name(dest_ty, 1));
+                    return self
+                        .bx
+                        .overflow_op(&name[..3], &result, &overflowed, &arg(0), &arg(1));
+                }
+                "saturating_add" | "saturating_sub" => {
+                    self.codegen_saturating(&name[11..], arg(0), arg(1), arg_tys[0])?
+                }
+                "likely" | "unlikely" => arg(0),
+                "forget" | "assume" | "uninit" => return Ok(()),
+                "init" => {
+                    if let Some(dest) = dest {
+                        let size = self.cx.layout_of(dest_ty).size.bytes();
+                        let memset = Expr::Ident("memset".into());
+                        let args = vec![dest.addr_of(), Expr::Uint(0), Expr::Uint(size.into())];
+                        self.bx.eval(&memset.call(args))?;
+                    }
+                    return Ok(());
+                }
+                "panic_if_uninhabited" => {
+                    let ty = instance.substs.type_at(0);
+                    if self.cx.layout_of(ty).abi.is_uninhabited() {
+                        let msg = format!("Attempted to instantiate uninhabited type {}", ty);
+                        self.codegen_panic(PanicFnLangItem, Some(&msg), Vec::new())?;
+                    }
+                    return Ok(());
+                }
+                "transmute" => {
+                    let (dest, value) = match (dest, values[0].clone()) {
+                        (Some(dest), Some(value)) => (dest, value),
+                        _ => return Ok(()),
+                    };
+                    if arg_tys[0] == dest_ty {
+                        return self.copy(&dest, &value, dest_ty);
+                    }
+                    // Other types are reinterpreted through memory
+                    let src_c_ty = self.cx.c_type(arg_tys[0])?.unwrap();
+                    let tmp = Expr::from(self.bx.declare_variable("tmp", src_c_ty, None)?);
+                    self.copy(&tmp, &value, arg_tys[0])?;
+                    let size = self.cx.layout_of(dest_ty).size.bytes();
+                    return self.bx.memcpy(&dest.addr_of(), &tmp.addr_of(), size);
+                }
+                "move_val_init" => {
+                    if let Some(value) = values[1].clone() {
+                        self.copy(&arg(0).deref(), &value, arg_tys[1])?;
+                    }
+                    return Ok(());
+                }
+                "copy_nonoverlapping" | "copy" | "write_bytes" => {
+                    let size = self.cx.layout_of(instance.substs.type_at(0)).size;
+                    if size.bytes() == 0 {
+                        return Ok(());
+                    }
+                    let len = arg(2).binary("*", Expr::Uint(size.bytes().into()));
+                    // `copy` and `copy_nonoverlapping` take the source first
+                    let (function, args) = match name {
+                        "write_bytes" => ("memset", vec![arg(0), arg(1), len]),
+                        "copy" => ("memmove", vec![arg(1), arg(0), len]),
+                        _ => ("memcpy", vec![arg(1), arg(0), len]),
+                    };
+                    return self.bx.eval(&Expr::Ident(function.into()).call(args));
+                }
+                "offset" => {
+                    self.codegen_binop(BinOp::Offset, arg(0), arg(1), arg_tys[0], arg_tys[1])?
+                }
+                "arith_offset" => {
+                    // The result may be outside of the allocation, which is
+                    // undefined behavior for pointer arithmetic in C
+                    let pointee = arg_tys[0].builtin_deref(true).unwrap().ty;
+                    let size = self.cx.layout_of(pointee).size.bytes();
+                    let uintptr = self.cx.tu.uintptr();
+                    let offset = arg(1).cast(uintptr).binary("*", Expr::Uint(size.into()));
+                    arg(0).cast(uintptr).binary("+", offset)
+                }
+                "ptr_offset_from" => {
+                    let size = self.cx.layout_of(instance.substs.type_at(0)).size.bytes();
+                    let intptr = self.cx.tu.intptr();
+                    arg(0)
+                        .cast(intptr)
+                        .binary("-", arg(1).cast(intptr))
+                        .binary("/", Expr::Uint(size.into()).cast(intptr))
+                }
+                "discriminant_value" => {
+                    let pointee = arg_tys[0].builtin_deref(true).unwrap().ty;
+                    let place = if self.cx.layout_of(pointee).is_zst() {
+                        None
+                    } else {
+                        Some(arg(0).deref())
+                    };
+                    self.read_discr(place, pointee)?
+                }
+                "ctpop" | "ctlz" | "ctlz_nonzero" | "cttz" | "cttz_nonzero" | "bswap" => {
+                    self.codegen_bit_intrinsic(name, arg(0), arg_tys[0])?
+                }
+                "rotate_left" | "rotate_right" => {
+                    let ty = arg_tys[0];
+                    let bits = self.cx.layout_of(ty).size.bits();
+                    let uint = self.cx.unsigned_type(self.scalar_primitive(ty));
+                    // Integers narrower than `int` are rotated in `unsigned
+                    // int`, since they'd be promoted to `int` otherwise
+                    let wide = if bits < 32 { self.cx.tu.u32() } else { uint };
+                    let x = arg(0).cast(uint).cast(wide);
+                    let mask = Expr::Uint((bits - 1).into());
+                    let n = arg(1).cast(wide).binary("&", mask.clone());
+                    let rest = Expr::Uint(bits.into())
+                        .binary("-", n.clone())
+                        .binary("&", mask);
+                    let (first, second) = match name {
+                        "rotate_left" => ("<<", ">>"),
+                        _ => (">>", "<<"),
+                    };
+                    x.clone()
+                        .binary(first, n)
+                        .binary("|", x.binary(second, rest))
+                }
+                _ => self.unsupported(&format!("the intrinsic `{}`", name)),
+            }
+        };
+
+        let dest = match dest {
+            Some(dest) => dest,
+            None => return Ok(()),
+        };
+        let value = if abi::is_c_scalar(dest_ty) {
+            value.cast(self.cx.c_type(dest_ty)?.unwrap())
+        } else {
+            value
+        };
+        self.copy(&dest, &value, dest_ty)
+    }
+
+    /// Lowers the saturating addition or subtraction `op` (`add` or `sub`) of
+    /// the integers `lhs` and `rhs` of type `ty`.
+    fn codegen_saturating(
+        &mut self,
+        op: &str,
+        lhs: Expr<'b>,
+        rhs: Expr<'b>,
+        ty: Ty<'tcx>,
+    ) -> io::Result<Expr<'b>> {
+        if !self.cx.capabilities.overflow_builtins {
+            self.unsupported("saturating arithmetic without `__builtin_*_overflow`");
+        }
+        let c_ty = self.cx.c_type(ty)?.unwrap();
+        let bool = self.cx.tu.bool();
+        let result = Expr::from(self.bx.declare_variable("result", c_ty, None)?);
+        let overflowed = Expr::from(self.bx.declare_variable("overflowed", bool, None)?);
+        self.bx.overflow_op(op, &result, &overflowed, &lhs, &rhs)?;
+
+        let limit = match (self.scalar_primitive(ty), op) {
+            (Primitive::Int(_, false), "add") => self.all_bits_set(ty),
+            (Primitive::Int(_, false), _) => Expr::Uint(0),
+            (Primitive::Int(int, true), _) => {
+                // Signed additions overflow towards the sign of `rhs`, and
+                // subtractions away from it
+                let max = self
+                    .cx
+                    .uint_literal(u128::max_value() >> (129 - int.size().bits()))
+                    .cast(c_ty);
+                let min = max.clone().unary("~");
+                let towards_max = match op {
+                    "add" => ">",
+                    _ => "<",
+                };
+                rhs.binary(towards_max, Expr::Uint(0).cast(c_ty))
+                    .cond(max, min)
+            }
+            (prim, _) => bug!("saturating arithmetic on {:?}", prim),
+        };
+        Ok(overflowed.cond(limit.cast(c_ty), result))
+    }
+
+    /// Lowers the bit counting or byte swapping intrinsic `name` applied to
+    /// the integer `value` of type `ty`.
+    ///
+    /// The compiler builtins take `unsigned long long`, which is at least 64
+    /// bits wide.
+    fn codegen_bit_intrinsic(
+        &mut self,
+        name: &str,
+        value: Expr<'b>,
+        ty: Ty<'tcx>,
+    ) -> io::Result<Expr<'b>> {
+        let bits = self.cx.layout_of(ty).size.bits();
+        if !self.cx.capabilities.builtins || bits > 64 {
+            self.unsupported(&format!("`{}` of `{}`", name, ty));
+        }
+        let uint = self.cx.unsigned_type(self.scalar_primitive(ty));
+        let u64 = self.cx.tu.u64();
+        let x = value.cast(uint);
+        let builtin =
+            |builtin: &str, arg: Expr<'b>| Expr::Ident(builtin.to_string().into()).call(vec![arg]);
+        // The bits to count from are zero for `ctlz` and `cttz`, for which
+        // the builtins are undefined
+        let or_bits = |nonzero: bool, x: Expr<'b>, count: Expr<'b>| {
+            if nonzero {
+                count
+            } else {
+                x.binary("==", Expr::Uint(0))
+                    .cond(Expr::Uint(bits.into()), count)
+            }
+        };
+        Ok(match name {
+            "ctpop" => builtin("__builtin_popcountll", x.cast(u64)),
+            "ctlz" | "ctlz_nonzero" => {
+                let count = builtin("__builtin_clzll", x.clone().cast(u64))
+                    .binary("-", Expr::Uint((64 - bits).into()));
+                or_bits(name == "ctlz_nonzero", x, count)
+            }
+            "cttz" | "cttz_nonzero" => {
+                let count = builtin("__builtin_ctzll", x.clone().cast(u64));
+                or_bits(name == "cttz_nonzero", x, count)
+            }
+            _ if bits == 8 => x,
+            _ => builtin(&format!("__builtin_bswap{}", bits), x),
+        })
+    }
+
+    /// Lowers a call to the SIMD platform intrinsic `name`, storing the
+    /// result of type `dest_ty` in `dest`.
+    ///
+    /// Operations are applied to whole vectors using GCC's vector extensions
+    /// if the involved types are lowered to vector types, and lane by lane
+    /// otherwise.
+    fn codegen_simd_intrinsic(
+        &mut self,
+        name: &str,
+        args: &[Operand<'tcx>],
+        dest: Expr<'b>,
+        dest_ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let tcx = self.cx.tcx;
+        let arg_tys = args
+            .iter()
+            .map(|arg| self.monomorphize(&arg.ty(self.mir, tcx)))
+            .collect::<Vec<_>>();
+        let dest_c_ty = self.cx.c_type(dest_ty)?.unwrap();
+
+        if let Some(op) = simd::binary_op(name) {
+            let (lane_ty, count) = self.cx.simd_lanes(dest_ty);
+            if op == "%" && lane_ty.is_floating_point() {
+                self.unsupported("`simd_rem` on floats");
+            }
+            let a = self.codegen_operand(&args[0])?.unwrap();
+            let b = self.codegen_operand(&args[1])?.unwrap();
+
+            if dest_c_ty.vector_lanes().is_some() {
+                // Signed overflow is undefined behavior in C, so vectors of
+                // signed integers are operated on as unsigned vectors
+                let value = match self.scalar_primitive(lane_ty) {
+                    Primitive::Int(int, true) if simd::can_overflow(op) => {
+                        let unsigned = self.cx.vector_type(Primitive::Int(int, false), count)?;
+                        a.cast(unsigned)
+                            .binary(op, b.cast(unsigned))
+                            .cast(dest_c_ty)
+                    }
+                    _ => a.binary(op, b),
+                };
+                return self.bx.assign(&dest, &value);
+            }
+
+            let index = self.begin_lane_loop(count)?;
+            let lhs = self.lane(a, dest_ty, index.clone())?;
+            let rhs = self.lane(b, dest_ty, index.clone())?;
+            let value = self.scalar_binop(op, lhs, rhs, lane_ty)?;
+            let dest_lane = self.lane(dest, dest_ty, index)?;
+            self.bx.assign(&dest_lane, &value)?;
+            return self.bx.end_loop();
+        }
+
+        if let Some(op) = simd::comparison_op(name) {
+            let (in_lane, count) = self.cx.simd_lanes(arg_tys[0]);
+            let (out_lane, _) = self.cx.simd_lanes(dest_ty);
+            let in_c_ty = self.cx.c_type(arg_tys[0])?.unwrap();
+            let a = self.codegen_operand(&args[0])?.unwrap();
+            let b = self.codegen_operand(&args[1])?.unwrap();
+
+            // Vector comparisons produce a vector of signed integers of the
+            // lane size, with all bits set in the lanes where the comparison
+            // is true, just like the intrinsics
+            let same_size = self.cx.layout_of(in_lane).size == self.cx.layout_of(out_lane).size;
+            if same_size && in_c_ty.vector_lanes().is_some() && dest_c_ty.vector_lanes().is_some() {
+                return self.bx.assign(&dest, &a.binary(op, b).cast(dest_c_ty));
+            }
+
+            let out_c_ty = self.cx.c_type(out_lane)?.unwrap();
+            let all_set = self.all_bits_set(out_lane).cast(out_c_ty);
+            let index = self.begin_lane_loop(count)?;
+            let lhs = self.lane(a, arg_tys[0], index.clone())?;
+            let rhs = self.lane(b, arg_tys[0], index.clone())?;
+            let value = lhs
+                .binary(op, rhs)
+                .cond(all_set, Expr::Uint(0).cast(out_c_ty));
+            let dest_lane = self.lane(dest, dest_ty, index)?;
+            self.bx.assign(&dest_lane, &value)?;
+            return self.bx.end_loop();
+        }
+
+        if let Some((reduction, ordered)) = simd::reduction(name) {
+            return self.codegen_simd_reduction(reduction, ordered, args, &arg_tys, dest, dest_ty);
+        }
+
+        if name.starts_with("simd_shuffle") {
+            let (in_lane, in_count) = self.cx.simd_lanes(arg_tys[0]);
+            let (_, out_count) = self.cx.simd_lanes(dest_ty);
+            let indices = self.shuffle_indices(&args[2], out_count);
+            let in_c_ty = self.cx.c_type(arg_tys[0])?.unwrap();
+            let a = self.codegen_operand(&args[0])?.unwrap();
+            let b = self.codegen_operand(&args[1])?.unwrap();
+
+            // The shuffle builtins can't change the number of lanes
+            if self.cx.capabilities.builtins
+                && out_count == in_count
+                && in_c_ty.vector_lanes().is_some()
+                && dest_c_ty.vector_lanes().is_some()
+            {
+                let lane_bits = self.scalar_primitive(in_lane).size(&*self.cx).bits();
+                let mask_int = Integer::fit_unsigned(u128::max_value() >> (128 - lane_bits));
+                let mask_ty = self
+                    .cx
+                    .vector_type(Primitive::Int(mask_int, false), in_count)?;
+                return self.bx.shuffle(&dest, &a, &b, &indices, mask_ty);
+            }
+
+            for (i, &index) in indices.iter().enumerate() {
+                let index = u64::from(index);
+                let (src, index) = if index < in_count {
+                    (a.clone(), index)
+                } else {
+                    (b.clone(), index - in_count)
+                };
+                let value = self.lane(src, arg_tys[0], Expr::Uint(index.into()))?;
+                let dest_lane = self.lane(dest.clone(), dest_ty, Expr::Uint(i as u128))?;
+                self.bx.assign(&dest_lane, &value)?;
+            }
+            return Ok(());
+        }
+
+        match name {
+            "simd_extract" => {
+                let vector = self.codegen_operand(&args[0])?.unwrap();
+                let index = self.codegen_operand(&args[1])?.unwrap();
+                let value = self.lane(vector, arg_tys[0], index)?;
+                self.bx.assign(&dest, &value)
+            }
+            "simd_insert" => {
+                let vector = self.codegen_operand(&args[0])?.unwrap();
+                let index = self.codegen_operand(&args[1])?.unwrap();
+                let value = self.codegen_operand(&args[2])?.unwrap();
+                self.bx.assign(&dest, &vector)?;
+                let dest_lane = self.lane(dest, dest_ty, index)?;
+                self.bx.assign(&dest_lane, &value)
+            }
+            "simd_cast" => {
+                // C's conversions between scalars match `as`, except for
+                // out-of-range floats, which are UB for the intrinsic too
+                let (out_lane, count) = self.cx.simd_lanes(dest_ty);
+                let out_c_ty = self.cx.c_type(out_lane)?.unwrap();
+                let vector = self.codegen_operand(&args[0])?.unwrap();
+                let index = self.begin_lane_loop(count)?;
+                let value = self.lane(vector, arg_tys[0], index.clone())?;
+                let dest_lane = self.lane(dest, dest_ty, index)?;
+                self.bx.assign(&dest_lane, &value.cast(out_c_ty))?;
+                self.bx.end_loop()
+            }
+            "simd_select" => {
+                let (_, count) = self.cx.simd_lanes(dest_ty);
+                let mask = self.codegen_operand(&args[0])?.unwrap();
+                let a = self.codegen_operand(&args[1])?.unwrap();
+                let b = self.codegen_operand(&args[2])?.unwrap();
+                let index = self.begin_lane_loop(count)?;
+                let cond = self
+                    .lane(mask, arg_tys[0], index.clone())?
+                    .binary("!=", Expr::Uint(0));
+                let then = self.lane(a, dest_ty, index.clone())?;
+                let otherwise = self.lane(b, dest_ty, index.clone())?;
+                let dest_lane = self.lane(dest, dest_ty, index)?;
+                self.bx.assign(&dest_lane, &cond.cond(then, otherwise))?;
+                self.bx.end_loop()
+            }
+            _ => self.unsupported(&format!("the SIMD intrinsic `{}`", name)),
+        }
+    }
+
+    /// Lowers a reduction of the vector passed as the first of `args` to a
+    /// scalar, which is stored in `dest`.
+    ///
+    /// `ordered` reductions take the initial accumulator value as their
+    /// second argument.
+    fn codegen_simd_reduction(
+        &mut self,
+        reduction: Reduction,
+        ordered: bool,
+        args: &[Operand<'tcx>],
+        arg_tys: &[Ty<'tcx>],
+        dest: Expr<'b>,
+        dest_ty: Ty<'tcx>,
+    ) -> io::Result<()> {
+        let (lane_ty, count) = self.cx.simd_lanes(arg_tys[0]);
+        let acc_ty = self.cx.c_type(dest_ty)?.unwrap();
+        let vector = self.codegen_operand(&args[0])?.unwrap();
+
+        let init = match reduction {
+            _ if ordered => self.codegen_operand(&args[1])?.unwrap(),
+            Reduction::Fold("*") | Reduction::All => Expr::Uint(1).cast(acc_ty),
+            Reduction::Fold("&") => self.all_bits_set(lane_ty).cast(acc_ty),
+            Reduction::Fold(_) | Reduction::Any => Expr::Uint(0).cast(acc_ty),
+            // Comparing the first lane to itself is harmless
+            Reduction::Min | Reduction::Max => {
+                self.lane(vector.clone(), arg_tys[0], Expr::Uint(0))?
+            }
+        };
+        let acc = Expr::from(self.bx.declare_variable("acc", acc_ty, None)?);
+        self.bx.assign(&acc, &init)?;
+
+        let index = self.begin_lane_loop(count)?;
+        let lane = self.lane(vector, arg_tys[0], index)?;
+        let value = match reduction {
+            Reduction::Fold(op) => self.scalar_binop(op, acc.clone(), lane, lane_ty)?,
+            Reduction::Min => lane
+                .clone()
+                .binary("<", acc.clone())
+                .cond(lane, acc.clone()),
+            Reduction::Max => lane
+                .clone()
+                .binary(">", acc.clone())
+                .cond(lane, acc.clone()),
+            Reduction::All => acc.clone().binary("&&", lane.binary("!=", Expr::Uint(0))),
+            Reduction::Any => acc.clone().binary("||", lane.binary("!=", Expr::Uint(0))),
+        };
+        self.bx.assign(&acc, &value)?;
+        self.bx.end_loop()?;
+        self.bx.assign(&dest, &acc)
+    }
+
+    /// Reads the `count` lane indices of a `simd_shuffle` call from its
+    /// constant `indices` operand.
+    ///
+    /// Indices written in the call are promoted, and passed as a copy of the
+    /// promoted constant.
+    fn shuffle_indices(&self, indices: &Operand<'tcx>, count: u64) -> Vec<u32> {
+        let tcx = self.cx.tcx;
+        let param_env = ty::ParamEnv::reveal_all();
+        let indices = match *indices {
+            Operand::Constant(ref constant) => {
+                self.monomorphize(&constant.literal).eval(tcx, param_env)
+            }
+            Operand::Copy(ref place) | Operand::Move(ref place) => match place.base {
+                PlaceBase::Static(ref static_) if place.projection.is_empty() => {
+                    let (promoted, substs) = match static_.kind {
+                        StaticKind::Promoted(promoted, substs) => (promoted, substs),
+                        StaticKind::Static => bug!("`simd_shuffle` indices {:?}", indices),
+                    };
+                    let cid = GlobalId {
+                        instance: Instance::new(static_.def_id, self.monomorphize(&substs)),
+                        promoted: Some(promoted),
+                    };
+                    tcx.const_eval(param_env.and(cid)).unwrap_or_else(|_| {
+                        tcx.sess
+                            .span_fatal(self.span, "erroneous constant encountered")
+                    })
+                }
+                _ => bug!("non-constant `simd_shuffle` indices {:?}", indices),
+            },
+        };
+        (0..count as usize)
+            .map(|i| {
+                let index = tcx.const_field(param_env.and((indices, mir::Field::new(i))));
+                index.eval_bits(tcx, param_env, tcx.types.u32) as u32
+            })
+            .collect()
+    }
+
+    /// Starts a loop over the `count` lanes of a vector, returning the lane
+    /// index.
+    fn begin_lane_loop(&mut self, count: u64) -> io::Result<Expr<'b>> {
+        let uintptr = self.cx.tu.uintptr();
+        let index = self.bx.declare_variable("i", uintptr, None)?;
+        self.bx.begin_loop(index, &Expr::Uint(count.into()))?;
+        Ok(Expr::from(index))
+    }
+
+    /// Accesses lane `index` of the vector `vector`, which has the SIMD type
+    /// `ty`.
+    fn lane(&mut self, vector: Expr<'b>, ty: Ty<'tcx>, index: Expr<'b>) -> io::Result<Expr<'b>> {
+        let c_ty = self.cx.c_type(ty)?.unwrap();
+        Ok(match c_ty.vector_lanes() {
+            // Vector extensions allow subscripting vectors like arrays
+            Some(_) => vector.index(index),
+            None => vector.field(VECTOR_LANES).index(index),
+        })
+    }
+
+    /// Applies the C operator `op` to the scalars `lhs` and `rhs` of type
+    /// `ty`, wrapping around on integer overflow.
+    fn scalar_binop(
+        &mut self,
+        op: &'static str,
+        lhs: Expr<'b>,
+        rhs: Expr<'b>,
+        ty: Ty<'tcx>,
+    ) -> io::Result<Expr<'b>> {
+        let c_ty = self.cx.c_type(ty)?.unwrap();
+        let value = match self.scalar_primitive(ty) {
+            // Overflowing signed integers (including narrower integers
+            // promoted to `int`) is undefined behavior in C, so the
+            // operation is done on an unsigned type at least as wide as
+            // `unsigned int`
+            Primitive::Int(int, _) if simd::can_overflow(op) => {
+                let unsigned = if int.size().bytes() < 4 {
+                    self.cx.tu.u32()
+                } else {
+                    self.cx.unsigned_type(Primitive::Int(int, false))
+                };
+                lhs.cast(unsigned).binary(op, rhs.cast(unsigned))
+            }
+            _ => lhs.binary(op, rhs),
+        };
+        Ok(value.cast(c_ty))
+    }
+
+    /// Returns the primitive of the scalar type `ty`.
+    fn scalar_primitive(&self, ty: Ty<'tcx>) -> Primitive {
+        match self.cx.layout_of(ty).abi {
+            layout::Abi::Scalar(ref scalar) => scalar.value,
+            _ => bug!("scalar primitive of non-scalar type {}", ty),
+        }
+    }
+
+    /// Returns an unsigned integer literal with all bits of the integer type
+    /// `ty` set.
+    fn all_bits_set(&self, ty: Ty<'tcx>) -> Expr<'b> {
+        let bits = self.cx.layout_of(ty).size.bits();
+        self.cx.uint_literal(u128::max_value() >> (128 - bits))
+    }
+
+    /// Appends the call argument `value` passed as `mode` to `c_args`.
+    ///
+    /// `value` is `None` if the argument is zero-sized.
+    fn push_arg(
+        &mut self,
+        c_args: &mut Vec<Expr<'b>>,
+        value: Option<Expr<'b>>,
+        mode: CPassMode,
+        layout: TyLayout<'tcx>,
+    ) -> io::Result<()> {
+        let value = match (mode, value) {
+            (CPassMode::Ignore, _) | (_, None) => return Ok(()),
+            (CPassMode::ByValue, Some(value)) => {
+                c_args.push(value);
+                return Ok(());
+            }
+            (_, Some(value)) => value,
+        };
+
+        // The remaining modes need the argument in memory. Copying it also
+        // gives the callee its own copy for indirect arguments, which it is
+        // allowed to modify.
+        let c_ty = self.cx.c_type(layout.ty)?.unwrap();
+        let tmp = Expr::from(self.bx.declare_variable("tmp", c_ty, None)?);
+        self.copy(&tmp, &value, layout.ty)?;
+        let value = tmp;
+        match mode {
+            CPassMode::Pair => {
+                c_args.push(self.pair_field(value.clone(), layout, 0)?);
+                c_args.push(self.pair_field(value, layout, 1)?);
+            }
+            CPassMode::Cast => {
+                let cast_ty = self.cx.cast_target(layout);
+                let tmp = self.bx.declare_variable("tmp", cast_ty, None)?;
+                let tmp = Expr::from(tmp);
+                self.bx.memcpy(
+                    &tmp.clone().addr_of(),
+                    &value.addr_of(),
+                    layout.size.bytes(),
+                )?;
+                c_args.push(tmp);
+            }
+            _ => c_args.push(value.addr_of()),
+        }
+        Ok(())
+    }
+}
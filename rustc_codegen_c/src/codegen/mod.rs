@@ -1,39 +1,260 @@
 //! The actual C code generation.
 
+pub mod abi;
 pub mod allocator;
+pub mod asm;
+pub mod attributes;
+pub mod consts;
+pub mod context;
+pub mod intrinsic;
+pub mod meth;
+pub mod mir;
+pub mod simd;
+pub mod ty;
 
+use self::context::CodegenCx;
+use back;
+use builder::attrs::FnAttrs;
+use builder::dialect::Environment;
+use builder::expr::Expr;
+use builder::{Name, TranslationUnitBuilder};
+use compiler::{self, Capabilities, Compiler};
+use utils::{StringWriter, WriteStr};
+use CCodegenBackend;
+
+use toolshed::Arena;
+
+use rustc::dep_graph;
+use rustc::hir::def_id::LOCAL_CRATE;
+use rustc::middle::cstore::DepKind;
+use rustc::middle::lang_items::StartFnLangItem;
+use rustc::mir::mono::{CodegenUnit, MonoItem};
+use rustc::session::config::{CrateType, DebugInfo, EntryFnType, Lto};
 use rustc::session::Session;
-use rustc::ty::TyCtxt;
-use rustc_codegen_utils::check_for_rustc_errors_attr;
+use rustc::ty::{Instance, ParamEnv, TyCtxt};
+use rustc_codegen_ssa::back::write::submit_codegened_module_to_llvm;
+use rustc_codegen_ssa::{ModuleCodegen, ModuleKind};
+use rustc_target::spec::PanicStrategy;
+use syntax::symbol::Symbol;
 
 use std::any::Any;
+use std::io;
+use std::iter;
 use std::sync::mpsc;
+use std::time::Instant;
 
 /// C codegen module.
-pub struct Module;
+#[derive(Default)]
+pub struct Module {
+    /// The C source of the translation unit the module was lowered to.
+    pub source: String,
+}
 
-/// Token for an ongoing code generation for a crate.
-pub struct OngoingCodegen {}
+/// Checks that the crate can be compiled with the session's options, before
+/// code generation starts.
+pub fn check_options(tcx: TyCtxt<'_>, compiler: Result<&Compiler, String>) {
+    report_unsupported_flags(tcx.sess);
+    check_llvm_compat(tcx);
 
-/// Starts code generation for the current crate.
+    // Failing to run the C compiler is reported once it's needed
+    match compiler {
+        Ok(compiler) => compiler::check_target(tcx.sess, compiler),
+        Err(e) => debug!("couldn't check the C compiler's target: {}", e),
+    }
+}
+
+/// Lowers the codegen unit `cgu_name` to a C translation unit, and sends it
+/// to the workers compiling it to an object file.
 ///
-/// This can generate any number of artifacts (rlibs, dylibs, binaries) and
-/// object files (each representing a codegen unit).
-pub fn start_codegen_crate<'tcx>(
-    tcx: TyCtxt<'tcx>,
-    rx: mpsc::Receiver<Box<dyn Any + Send>>,
-) -> OngoingCodegen {
-    // abort if `#[rustc_error]` is used by tests - this could be done by rustc
-    // just before codegen is invoked (FIXME)
-    check_for_rustc_errors_attr(tcx);
+/// This is the counterpart of `compile_codegen_unit` in
+/// `librustc_codegen_llvm/base.rs`.
+pub(crate) fn compile_codegen_unit(
+    backend: &CCodegenBackend,
+    tcx: TyCtxt<'_>,
+    cgu_name: Symbol,
+    tx_to_llvm_workers: &mpsc::Sender<Box<dyn Any + Send>>,
+) {
+    let start_time = Instant::now();
 
-    report_unsupported_flags(tcx.sess);
+    let compiler = backend
+        .compiler(tcx.sess)
+        .unwrap_or_else(|e| tcx.sess.fatal(&e));
+    let dep_node = tcx.codegen_unit(cgu_name).codegen_dep_node(tcx);
+    let (module, _) = tcx.dep_graph.with_task(
+        dep_node,
+        tcx,
+        (cgu_name, compiler),
+        module_codegen,
+        dep_graph::hash_result,
+    );
+    let time_to_codegen = start_time.elapsed();
+
+    // We assume that the cost to run the C compiler on a CGU is proportional
+    // to the time we needed for lowering it.
+    let cost = time_to_codegen.as_secs() * 1_000_000_000 + time_to_codegen.subsec_nanos() as u64;
+
+    submit_codegened_module_to_llvm(backend, tx_to_llvm_workers, module, cost);
+}
+
+fn module_codegen(
+    tcx: TyCtxt<'_>,
+    (cgu_name, compiler): (Symbol, &Compiler),
+) -> ModuleCodegen<Module> {
+    let cgu = tcx.codegen_unit(cgu_name);
+
+    let arena = Arena::new();
+    let source = (|| {
+        let tu = TranslationUnitBuilder::create(
+            &arena,
+            StringWriter(String::new()),
+            compiler.dialect,
+            environment(tcx, compiler.capabilities()),
+        )?;
+        // The same path as `back::compile_module` writes the TU to
+        let c_file = if tcx.sess.opts.debuginfo == DebugInfo::None {
+            None
+        } else {
+            let outputs = tcx.output_filenames(LOCAL_CRATE);
+            let path = outputs.temp_path_ext("c", Some(&cgu_name.as_str()));
+            Some(path.to_string_lossy().into_owned())
+        };
+        let mut cx = CodegenCx::new(tcx, compiler.capabilities(), tu, c_file)?;
+
+        // Declare everything first, so that the definitions can refer to
+        // each other
+        let mono_items = cgu.items_in_deterministic_order(tcx);
+        for &(mono_item, (linkage, visibility)) in &mono_items {
+            match mono_item {
+                MonoItem::Fn(instance) => {
+                    cx.predefine_fn(instance, linkage, visibility)?;
+                }
+                MonoItem::Static(def_id) => {
+                    cx.predefine_static(def_id, linkage, visibility)?;
+                }
+                MonoItem::GlobalAsm(hir_id) => tcx.sess.span_fatal(
+                    tcx.hir().span(hir_id),
+                    "`global_asm!` isn't supported by the C codegen backend",
+                ),
+            }
+        }
+        for &(mono_item, (linkage, visibility)) in &mono_items {
+            match mono_item {
+                MonoItem::Fn(instance) => mir::codegen_instance(&mut cx, instance)?,
+                MonoItem::Static(def_id) => cx.codegen_static(def_id, linkage, visibility)?,
+                MonoItem::GlobalAsm(_) => {}
+            }
+        }
+
+        maybe_create_entry_wrapper(&mut cx, &cgu)?;
+        Ok(cx.tu.into_writer().0)
+    })()
+    .unwrap_or_else(|e: io::Error| {
+        tcx.sess
+            .fatal(&format!("couldn't write the C code of {}: {}", cgu_name, e))
+    });
+
+    ModuleCodegen {
+        name: cgu_name.to_string(),
+        module_llvm: Module { source },
+        kind: ModuleKind::Regular,
+    }
+}
+
+/// Defines the C `main` function if the codegen unit contains the crate's
+/// entry point.
+///
+/// Like in the LLVM backend, `main` calls the `start` lang item with the
+/// Rust `main` function, or the `#[start]` function directly.
+fn maybe_create_entry_wrapper<'a, 'tcx, W: WriteStr>(
+    cx: &mut CodegenCx<'a, 'tcx, W>,
+    cgu: &CodegenUnit<'tcx>,
+) -> io::Result<()> {
+    let tcx = cx.tcx;
+    let (main_def_id, entry_type) = match tcx.entry_fn(LOCAL_CRATE) {
+        Some(entry) => entry,
+        None => return Ok(()),
+    };
+    let main_instance = Instance::mono(tcx, main_def_id);
+    if !cgu.items().contains_key(&MonoItem::Fn(main_instance)) {
+        return Ok(());
+    }
+
+    let main_fn = Expr::from(cx.get_fn(main_instance)?);
+    let (start_fn, use_start_lang_item) = match entry_type {
+        EntryFnType::Main => {
+            let start_def_id = tcx.require_lang_item(StartFnLangItem, None);
+            let main_ret_ty = tcx.fn_sig(main_def_id).output();
+            // `main` has no generic parameters, and its return type no bound
+            // regions
+            let main_ret_ty = tcx.erase_regions(&main_ret_ty.no_bound_vars().unwrap());
+            let start_instance = Instance::resolve(
+                tcx,
+                ParamEnv::reveal_all(),
+                start_def_id,
+                tcx.intern_substs(&[main_ret_ty.into()]),
+            )
+            .unwrap();
+            (cx.get_fn(start_instance)?, true)
+        }
+        EntryFnType::Start => (cx.get_fn(main_instance)?, false),
+    };
+    let start_sig = start_fn.sig();
 
-    // Skip crate items and just output metadata in -Z no-codegen mode.
-    // FIXME: rustc can do this before invoking the codegen backend
-    if tcx.sess.opts.debugging_opts.no_codegen || !tcx.sess.opts.output_types.should_codegen() {}
+    // FIXME: `int` isn't 32 bits wide on 16-bit targets
+    let c_int = cx.tu.i32();
+    let u8_ptr = cx.tu.ptr_to(cx.tu.u8());
+    let argv_ty = cx.tu.ptr_to(u8_ptr);
+    let sig = cx.tu.fn_sig(Some(c_int), &[c_int, argv_ty]);
 
-    OngoingCodegen {}
+    let arena = Arena::new();
+    let mut bx = cx.tu.define_function(
+        &arena,
+        Name::for_symbol("main", "main", ""),
+        sig,
+        FnAttrs::default(),
+    )?;
+    let mut args = vec![Expr::from(bx.args[0]), Expr::from(bx.args[1])];
+    if use_start_lang_item {
+        args.insert(0, main_fn);
+    }
+    // Convert the arguments to the types of `start`'s parameters
+    let args = args
+        .into_iter()
+        .zip(start_sig.args)
+        .map(|(arg, &ty)| arg.cast(ty))
+        .collect();
+    let result = Expr::from(start_fn).call(args);
+    bx.ret(Some(&result.cast(c_int)))?;
+    bx.finish()
+}
+
+/// Returns the kind of C implementation the crate's TUs are compiled by.
+///
+/// Freestanding TUs only define `memcpy`, `memmove` and `memset` if no crate in the
+/// dependency graph (usually `compiler_builtins`) exports them, and only make
+/// the definitions weak if the compiler has `capabilities` for attributes.
+pub fn environment(tcx: TyCtxt<'_>, capabilities: Capabilities) -> Environment {
+    if !compiler::is_freestanding(tcx.sess).unwrap_or_else(|e| tcx.sess.fatal(&e)) {
+        return Environment::Hosted;
+    }
+    Environment::Freestanding {
+        memcpy_fallback: !exports_symbol(tcx, "memcpy"),
+        memmove_fallback: !exports_symbol(tcx, "memmove"),
+        memset_fallback: !exports_symbol(tcx, "memset"),
+        weak_fallbacks: capabilities.attributes,
+    }
+}
+
+/// Returns whether the current crate or one of its dependencies exports the
+/// symbol `name`.
+fn exports_symbol(tcx: TyCtxt<'_>, name: &str) -> bool {
+    iter::once(LOCAL_CRATE)
+        .chain(tcx.crates().iter().cloned())
+        .any(|cnum| {
+            tcx.exported_symbols(cnum)
+                .iter()
+                .any(|&(ref symbol, _)| *symbol.symbol_name(tcx).name.as_str() == *name)
+        })
 }
 
 /// Checks for command line flags that aren't or cannot be supported by the C
@@ -67,5 +288,83 @@ fn report_unsupported_flags(sess: &Session) {
         sess.fatal("linker plugin based LTO not supported by C codegen backend");
     }
 
+    match sess.lto() {
+        Lto::Fat | Lto::Thin => sess.fatal("the C codegen backend doesn't support LTO"),
+        Lto::No | Lto::ThinLocal => {}
+    }
+
+    // Rejects unknown TLS models
+    attributes::tls_model(sess);
+
+    back::check_target_features(sess);
+
     // TODO: incomplete
 }
+
+/// Checks that the objects we produce can be linked against crates compiled
+/// by the LLVM backend, in particular the prebuilt standard library.
+///
+/// Symbol names and `FnAbi`s are computed by rustc and shared with the LLVM
+/// backend, and metadata is stored the same way, so what's left to check is
+/// that the sysroot crates were compiled for the same platform and that no
+/// unsupported runtime behavior is required. The C compiler checks that its
+/// data layout matches the target's (see `CodegenCx::new`).
+fn check_llvm_compat(tcx: TyCtxt<'_>) {
+    let sess = tcx.sess;
+    let target = sess.opts.target_triple.triple();
+
+    // Sysroot crates are found in `lib/rustlib/<target>/lib`
+    let rustlib = sess.sysroot.join("lib").join("rustlib");
+    for &cnum in tcx.crates().iter() {
+        if tcx.dep_kind(cnum) == DepKind::MacrosOnly {
+            continue;
+        }
+        let source = tcx.used_crate_source(cnum);
+        let paths = source.rlib.iter().chain(&source.dylib).chain(&source.rmeta);
+        for (path, _) in paths {
+            let crate_target = match path.strip_prefix(&rustlib) {
+                Ok(rest) => rest.components().next(),
+                Err(_) => continue,
+            };
+            let crate_target = match crate_target {
+                Some(component) => component.as_os_str().to_string_lossy(),
+                None => continue,
+            };
+            if crate_target != target {
+                sess.err(&format!(
+                    "the sysroot crate `{}` was compiled for `{}`, but the crate is compiled \
+                     for `{}`",
+                    tcx.crate_name(cnum),
+                    crate_target,
+                    target,
+                ));
+                sess.note_without_error(&format!(
+                    "objects produced by the C compiler can't be linked with crates compiled \
+                     for a different target (found `{}`)",
+                    path.display(),
+                ));
+            }
+        }
+    }
+
+    // Dylibs and proc macros embed their metadata in a section of their
+    // object file, which we don't emit
+    for &crate_type in sess.crate_types.borrow().iter() {
+        if let CrateType::Dylib | CrateType::ProcMacro = crate_type {
+            sess.err(&format!(
+                "the C codegen backend can't compile `{}` crates",
+                crate_type
+            ));
+            sess.note_without_error("compile the crate as an `rlib` or `cdylib` instead");
+        }
+    }
+
+    // LLVM-compiled crates may unwind into our functions, which have no
+    // unwind tables or landing pads
+    if sess.panic_strategy() == PanicStrategy::Unwind {
+        sess.err("the C codegen backend doesn't support unwinding");
+        sess.note_without_error("compile with `-C panic=abort` instead");
+    }
+
+    sess.abort_if_errors();
+}
@@ -0,0 +1,82 @@
+//! Classification of the `simd_*` platform intrinsics.
+//!
+//! Lane-wise arithmetic and comparisons map directly to C operators, which
+//! GCC's vector extensions apply to every lane of a vector. Everything else
+//! (shuffles, lane access, casts, selects and reductions) is lowered lane by
+//! lane in `FunctionCx::codegen_simd_intrinsic`.
+
+/// Returns the C operator implementing the lane-wise arithmetic intrinsic
+/// `name`.
+pub fn binary_op(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "simd_add" => "+",
+        "simd_sub" => "-",
+        "simd_mul" => "*",
+        "simd_div" => "/",
+        "simd_rem" => "%",
+        "simd_shl" => "<<",
+        "simd_shr" => ">>",
+        "simd_and" => "&",
+        "simd_or" => "|",
+        "simd_xor" => "^",
+        _ => return None,
+    })
+}
+
+/// Returns the C operator implementing the lane-wise comparison intrinsic
+/// `name`.
+pub fn comparison_op(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "simd_eq" => "==",
+        "simd_ne" => "!=",
+        "simd_lt" => "<",
+        "simd_le" => "<=",
+        "simd_gt" => ">",
+        "simd_ge" => ">=",
+        _ => return None,
+    })
+}
+
+/// Returns whether the C operator `op` can overflow, which is undefined
+/// behavior for signed integers and integers promoted to `int`.
+pub fn can_overflow(op: &str) -> bool {
+    match op {
+        "+" | "-" | "*" | "<<" => true,
+        _ => false,
+    }
+}
+
+/// A reduction of all lanes of a vector to a single value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reduction {
+    /// Combines the lanes with a C operator, starting from an identity or
+    /// initial value.
+    Fold(&'static str),
+    /// Selects the smallest lane.
+    Min,
+    /// Selects the largest lane.
+    Max,
+    /// Checks whether all lanes of a mask are set.
+    All,
+    /// Checks whether any lane of a mask is set.
+    Any,
+}
+
+/// Returns the reduction performed by the intrinsic `name`, and whether it
+/// takes an initial accumulator value.
+pub fn reduction(name: &str) -> Option<(Reduction, bool)> {
+    Some(match name {
+        "simd_reduce_add_ordered" => (Reduction::Fold("+"), true),
+        "simd_reduce_add_unordered" => (Reduction::Fold("+"), false),
+        "simd_reduce_mul_ordered" => (Reduction::Fold("*"), true),
+        "simd_reduce_mul_unordered" => (Reduction::Fold("*"), false),
+        "simd_reduce_and" => (Reduction::Fold("&"), false),
+        "simd_reduce_or" => (Reduction::Fold("|"), false),
+        "simd_reduce_xor" => (Reduction::Fold("^"), false),
+        "simd_reduce_min" | "simd_reduce_min_nanless" => (Reduction::Min, false),
+        "simd_reduce_max" | "simd_reduce_max_nanless" => (Reduction::Max, false),
+        "simd_reduce_all" => (Reduction::All, false),
+        "simd_reduce_any" => (Reduction::Any, false),
+        _ => return None,
+    })
+}
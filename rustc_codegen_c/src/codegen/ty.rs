@@ -0,0 +1,553 @@
+//! Lowering of Rust types to C types.
+//!
+//! Aggregates are lowered to `struct`s whose fields are named after the Rust
+//! field index (`f0`, `f1`, ...) and laid out in memory order, with explicit
+//! padding fields where needed. Types whose layout can't be expressed this way
+//! (eg. enums) are lowered to opaque structs of the right size and alignment.
+//! `#[repr(packed)]` types get GCC's `packed` and `aligned` attributes, and
+//! types aligned beyond their fields (`#[repr(align)]`) the `aligned`
+//! attribute, which other C compilers may not support.
+//!
+//! Generators are lowered to tagged unions: a `union` with one `struct` member
+//! for the fields shared by all states (upvars, the state discriminant and
+//! locals saved across multiple states) and one per state. Each member `struct`
+//! is padded so that its fields are at their offset in the generator.
+//!
+//! With `-C debuginfo=2`, the fields of structs and unions are named after the
+//! Rust fields, and every aggregate gets a `typedef` alias named after the Rust
+//! type (eg. `std__vec__Vec_u8_`), which debuggers show instead of the opaque
+//! hash-based name.
+//!
+//! SIMD types (`#[repr(simd)]`) are lowered to vector types using GCC's vector
+//! extensions. Since those only support power-of-2 lane counts of integers and
+//! floats, other SIMD types (and all of them if the C compiler doesn't support
+//! vector extensions) are lowered to a `struct` wrapping an array of lanes.
+
+use super::context::CodegenCx;
+use builder::expr::Expr;
+use builder::types::{AsType, IncompleteTypeRef, TypeRef};
+use builder::{unreserved_ident, Name};
+use utils::WriteStr;
+
+use rustc::ty::layout::{self, Integer, LayoutOf, Primitive, TyLayout, VariantIdx};
+use rustc::ty::{self, Ty};
+use std::io;
+use syntax::ast;
+
+/// The kind of C aggregate a Rust type is lowered to.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum AggregateKind {
+    /// A `struct` with one field per non-zero-sized Rust field.
+    Struct,
+    /// A `union` with one field per non-zero-sized Rust field.
+    Union,
+    /// A `struct` containing only an integer array of the right size.
+    Opaque,
+    /// A `union` of the generator's prefix and its states.
+    Generator,
+}
+
+/// The array of lanes in a SIMD type lowered without vector extensions.
+pub const VECTOR_LANES: &str = "lanes";
+
+/// Returns the C field name of the Rust field with index `index`.
+pub fn field_name(index: usize) -> String {
+    format!("f{}", index)
+}
+
+/// Turns `name` into a valid C identifier by replacing all characters that
+/// aren't ASCII letters or digits with `_`.
+pub fn c_ident(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// The member of a lowered generator containing the fields shared by all
+/// states.
+pub const GENERATOR_PREFIX: &str = "prefix";
+
+/// Returns the member of a lowered generator containing the fields of the
+/// state (variant) `variant`.
+pub fn generator_variant(variant: VariantIdx) -> String {
+    format!("state{}", variant.as_usize())
+}
+
+impl<'a, 'tcx, W: WriteStr> CodegenCx<'a, 'tcx, W> {
+    /// Lowers a monomorphic Rust type to its C equivalent.
+    ///
+    /// Returns `None` for zero-sized types, which have no C representation.
+    pub fn c_type(&mut self, ty: Ty<'tcx>) -> io::Result<Option<TypeRef<'a>>> {
+        let layout = self.layout_of(ty);
+        if layout.is_zst() {
+            return Ok(None);
+        }
+        if let Some(&c_ty) = self.types.get(&ty) {
+            return Ok(Some(c_ty));
+        }
+
+        let c_ty = match ty.kind {
+            ty::Bool => self.tu.bool(),
+            ty::Char => self.tu.u32(),
+            ty::Int(ast::IntTy::I8) => self.tu.i8(),
+            ty::Int(ast::IntTy::I16) => self.tu.i16(),
+            ty::Int(ast::IntTy::I32) => self.tu.i32(),
+            ty::Int(ast::IntTy::I64) => self.tu.i64(),
+            ty::Int(ast::IntTy::Isize) => self.tu.intptr(),
+            ty::Uint(ast::UintTy::U8) => self.tu.u8(),
+            ty::Uint(ast::UintTy::U16) => self.tu.u16(),
+            ty::Uint(ast::UintTy::U32) => self.tu.u32(),
+            ty::Uint(ast::UintTy::U64) => self.tu.u64(),
+            ty::Uint(ast::UintTy::Usize) => self.tu.uintptr(),
+            ty::Int(ast::IntTy::I128) => self.int128_type(true),
+            ty::Uint(ast::UintTy::U128) => self.int128_type(false),
+            ty::Float(ast::FloatTy::F32) => self.tu.float(),
+            ty::Float(ast::FloatTy::F64) => self.tu.double(),
+            ty::Ref(_, pointee, _) | ty::RawPtr(ty::TypeAndMut { ty: pointee, .. }) => {
+                self.pointer_type(pointee)?
+            }
+            ty::Adt(def, _) if def.is_box() => self.pointer_type(ty.boxed_ty())?,
+            ty::FnPtr(sig) => {
+                let abi = self.fn_abi_of_fn_ptr(sig)?;
+                self.tu.fn_ptr(abi.sig)
+            }
+            ty::Array(elem, _) => {
+                let elem = self
+                    .c_type(elem)?
+                    .expect("non-zero-sized array of zero-sized elements");
+                self.tu.array_of(elem, layout.fields.count())
+            }
+            ty::Adt(def, _) if def.repr.simd() => match layout.abi {
+                layout::Abi::Vector { ref element, count } => {
+                    self.vector_type(element.value, count)?
+                }
+                _ => bug!("SIMD type {} without vector layout", ty),
+            },
+            _ => {
+                let c_ty = match aggregate_kind(ty) {
+                    AggregateKind::Opaque => self.define_opaque(ty, layout)?,
+                    AggregateKind::Generator => self.define_generator(ty, layout)?,
+                    kind => self.define_aggregate(ty, layout, kind)?,
+                };
+                self.alias(ty, c_ty)?
+            }
+        };
+
+        self.types.insert(ty, c_ty);
+        Ok(Some(c_ty))
+    }
+
+    /// Returns the C type of a scalar of primitive type `prim`.
+    ///
+    /// Pointers are lowered to `void *`.
+    pub fn scalar_type(&mut self, prim: Primitive) -> TypeRef<'a> {
+        match prim {
+            Primitive::Int(Integer::I8, true) => self.tu.i8(),
+            Primitive::Int(Integer::I16, true) => self.tu.i16(),
+            Primitive::Int(Integer::I32, true) => self.tu.i32(),
+            Primitive::Int(Integer::I64, true) => self.tu.i64(),
+            Primitive::Int(Integer::I8, false) => self.tu.u8(),
+            Primitive::Int(Integer::I16, false) => self.tu.u16(),
+            Primitive::Int(Integer::I32, false) => self.tu.u32(),
+            Primitive::Int(Integer::I64, false) => self.tu.u64(),
+            Primitive::Int(Integer::I128, signed) => self.int128_type(signed),
+            Primitive::F32 => self.tu.float(),
+            Primitive::F64 => self.tu.double(),
+            Primitive::Pointer => {
+                let void = self.tu.void();
+                self.tu.ptr_to(void)
+            }
+        }
+    }
+
+    /// Returns the C type of a 128-bit integer.
+    ///
+    /// C has no standard 128-bit integer type, so this requires `__int128`.
+    fn int128_type(&self, signed: bool) -> TypeRef<'a> {
+        if !self.capabilities.int128 {
+            self.tcx
+                .sess
+                .fatal("128-bit integers require a C compiler supporting `__int128`");
+        }
+        if signed {
+            self.tu.i128()
+        } else {
+            self.tu.u128()
+        }
+    }
+
+    /// Returns the unsigned integer literal `val`.
+    ///
+    /// Literals that don't fit in 64 bits are built from `unsigned __int128`
+    /// halves, so they need the same support as 128-bit integer types.
+    pub fn uint_literal<'e>(&self, val: u128) -> Expr<'e> {
+        if val > u128::from(u64::max_value()) {
+            self.int128_type(false);
+        }
+        Expr::Uint(val)
+    }
+
+    /// Returns the C type of a SIMD vector of `count` lanes of type `elem`,
+    /// defining it on first use.
+    pub fn vector_type(&mut self, elem: Primitive, count: u64) -> io::Result<TypeRef<'a>> {
+        if let Some(&c_ty) = self.vectors.get(&(elem, count)) {
+            return Ok(c_ty);
+        }
+
+        let lane_name = match elem {
+            Primitive::Int(int, true) => format!("i{}", int.size().bits()),
+            Primitive::Int(int, false) => format!("u{}", int.size().bits()),
+            Primitive::F32 => "f32".to_string(),
+            Primitive::F64 => "f64".to_string(),
+            Primitive::Pointer => "ptr".to_string(),
+        };
+        let c_name = format!("r2c2_{}x{}", lane_name, count);
+        let name = Name::internal(format!("SIMD vector of {} {}", count, lane_name), &c_name);
+        let lane = self.scalar_type(elem);
+        let c_ty = if self.capabilities.vector_extensions
+            && elem != Primitive::Pointer
+            && count.is_power_of_two()
+        {
+            self.tu.define_vector(name, lane, count as usize)?
+        } else {
+            let lanes = self.tu.array_of(lane, count as usize);
+            self.tu.define_struct(name, vec![(VECTOR_LANES, lanes)])?
+        };
+        self.vectors.insert((elem, count), c_ty);
+        Ok(c_ty)
+    }
+
+    /// Returns the lane type and lane count of the SIMD type `ty`.
+    pub fn simd_lanes(&self, ty: Ty<'tcx>) -> (Ty<'tcx>, u64) {
+        match self.layout_of(ty).abi {
+            layout::Abi::Vector { count, .. } => (ty.simd_type(self.tcx), count),
+            _ => bug!("SIMD type {} without vector layout", ty),
+        }
+    }
+
+    /// Returns the unsigned integer type with the size of the primitive
+    /// `prim`, which is an integer or pointer.
+    pub fn unsigned_type(&mut self, prim: Primitive) -> TypeRef<'a> {
+        match prim {
+            Primitive::Int(int, _) => self.scalar_type(Primitive::Int(int, false)),
+            Primitive::Pointer => self.tu.uintptr(),
+            _ => bug!("unsigned type for {:?}", prim),
+        }
+    }
+
+    /// Lowers a pointer to `pointee`.
+    fn pointer_type(&mut self, pointee: Ty<'tcx>) -> io::Result<TypeRef<'a>> {
+        let tail = self
+            .tcx
+            .struct_tail_erasing_lifetimes(pointee, ty::ParamEnv::reveal_all());
+        match tail.kind {
+            ty::Dynamic(..) => return Ok(self.tu.dyn_ptr()),
+            ty::Slice(_) | ty::Str => {
+                // The data pointer of `&[T]` points to the elements, but for
+                // custom DSTs ending in a slice, it points to the whole struct.
+                let elem = match pointee.kind {
+                    ty::Slice(elem) => self.c_type(elem)?,
+                    ty::Str => Some(self.tu.u8()),
+                    _ => None,
+                };
+                return Ok(match elem {
+                    Some(elem) => self.tu.slice_ptr(elem),
+                    None => {
+                        let void = self.tu.void();
+                        self.tu.slice_ptr(void)
+                    }
+                });
+            }
+            _ => {}
+        }
+
+        if self.layout_of(pointee).is_zst() {
+            let void = self.tu.void();
+            return Ok(self.tu.ptr_to(void));
+        }
+
+        // Pointers to aggregates go through a forward declaration, which
+        // allows lowering recursive types.
+        if !is_scalar_like(pointee) {
+            let fwd = self.fwd_type(pointee)?;
+            return Ok(self.tu.ptr_to(fwd));
+        }
+
+        let pointee = self.c_type(pointee)?.unwrap();
+        Ok(self.tu.ptr_to(pointee))
+    }
+
+    /// Forward-declares the C aggregate `ty` is lowered to.
+    fn fwd_type(&mut self, ty: Ty<'tcx>) -> io::Result<IncompleteTypeRef<'a>> {
+        if let Some(&fwd) = self.fwd_types.get(&ty) {
+            return Ok(fwd);
+        }
+
+        let name = Name::for_type(ty, self.tcx);
+        let fwd = match aggregate_kind(ty) {
+            AggregateKind::Union | AggregateKind::Generator => self.tu.fwd_declare_union(name)?,
+            AggregateKind::Struct | AggregateKind::Opaque => self.tu.fwd_declare_struct(name)?,
+        };
+        let fwd = self.alias(ty, fwd)?;
+        self.fwd_types.insert(ty, fwd);
+        Ok(fwd)
+    }
+
+    /// Returns the readable alias of the C aggregate `c_ty` that `ty` is
+    /// lowered to if `readable_names` is set, declaring it on first use.
+    ///
+    /// Otherwise, `c_ty` is returned unchanged.
+    fn alias<T: AsType<'a>>(&mut self, ty: Ty<'tcx>, c_ty: T) -> io::Result<T> {
+        if !self.readable_names {
+            return Ok(c_ty);
+        }
+        // The alias of a forward declaration also names the definition
+        if let Some(alias) = self.aliases.get(&ty) {
+            return Ok(c_ty.with_type(alias.0));
+        }
+
+        // Different types can have the same sanitized name
+        let rust_name = ty.to_string();
+        let mut alias_name = unreserved_ident(c_ident(&rust_name));
+        while self.alias_names.contains(&alias_name) {
+            alias_name.push('_');
+        }
+        let alias = self
+            .tu
+            .typedef(Name::internal(rust_name, &alias_name), c_ty)?;
+        self.alias_names.insert(alias_name);
+        self.aliases.insert(ty, TypeRef(alias.as_type()));
+        Ok(alias)
+    }
+
+    /// Returns the C name of field `index` of the aggregate `ty`.
+    ///
+    /// The fields of structs and unions are named after the Rust field if
+    /// `readable_names` is set. All other fields are named by `field_name`.
+    pub fn c_field_name(&self, ty: Ty<'tcx>, index: usize) -> String {
+        match ty.kind {
+            ty::Adt(def, _) if self.readable_names && !def.is_enum() => {
+                let ident = def.non_enum_variant().fields[index].ident.as_str();
+                // Tuple struct fields are named after their index
+                if ident.starts_with(|c: char| c.is_ascii_digit()) {
+                    return field_name(index);
+                }
+                let mut name = unreserved_ident(c_ident(&ident));
+                if name.starts_with("_pad") {
+                    name.push('_');
+                }
+                name
+            }
+            _ => field_name(index),
+        }
+    }
+
+    /// Defines a `struct` or `union` with one field per non-zero-sized field
+    /// of `ty`.
+    fn define_aggregate(
+        &mut self,
+        ty: Ty<'tcx>,
+        layout: TyLayout<'tcx>,
+        kind: AggregateKind,
+    ) -> io::Result<TypeRef<'a>> {
+        let packed = match ty.kind {
+            ty::Adt(def, _) if def.repr.packed() => {
+                if !self.capabilities.packed_attributes {
+                    self.tcx.sess.span_fatal(
+                        self.tcx.def_span(def.did),
+                        "`#[repr(packed)]` types aren't supported by the selected C compiler",
+                    );
+                }
+                true
+            }
+            _ => false,
+        };
+
+        // C aligns aggregates to their most aligned field, which is less
+        // than the Rust alignment for `#[repr(align)]` types.
+        let align = layout.align.abi.bytes();
+        let field_align = (0..layout.fields.count())
+            .map(|index| layout.field(self, index))
+            .filter(|field| !field.is_zst())
+            .map(|field| field.align.abi.bytes())
+            .max()
+            .unwrap_or(1);
+        let aligned = !packed && align > field_align;
+        if aligned {
+            self.require_aligned_attribute(ty, align);
+        }
+
+        let fields = self.fields(layout, kind == AggregateKind::Struct)?;
+        let name = Name::for_type(ty, self.tcx);
+        let fields = fields.iter().map(|(name, ty)| (&**name, *ty));
+        let union = kind == AggregateKind::Union;
+        // The padding fields put the fields of packed structs at their
+        // offsets, since the C compiler doesn't pad them
+        if packed {
+            return self.tu.define_packed(union, name, fields, align);
+        }
+        if aligned {
+            return self.tu.define_aligned(union, name, fields, align);
+        }
+        match kind {
+            AggregateKind::Union => self.tu.define_union(name, fields),
+            _ => self.tu.define_struct(name, fields),
+        }
+    }
+
+    /// Lowers the non-zero-sized fields of `layout`.
+    ///
+    /// If `padded` is set, padding fields are inserted so that each field is
+    /// at its offset when the fields are laid out as a C `struct`.
+    fn fields(
+        &mut self,
+        layout: TyLayout<'tcx>,
+        padded: bool,
+    ) -> io::Result<Vec<(String, TypeRef<'a>)>> {
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        for index in layout.fields.index_by_increasing_offset() {
+            let field = layout.field(self, index);
+            let field_ty = match self.c_type(field.ty)? {
+                Some(field_ty) => field_ty,
+                None => continue,
+            };
+
+            let field_offset = layout.fields.offset(index).bytes();
+            if padded && field_offset > offset {
+                fields.push(self.padding(fields.len(), field_offset - offset));
+            }
+            fields.push((self.c_field_name(layout.ty, index), field_ty));
+            offset = field_offset + field.size.bytes();
+        }
+
+        // The tail padding gives the type its Rust size even when C would
+        // round it up to a smaller alignment (`#[repr(align)]`).
+        if padded && layout.size.bytes() > offset {
+            fields.push(self.padding(fields.len(), layout.size.bytes() - offset));
+        }
+        Ok(fields)
+    }
+
+    /// Defines the tagged `union` a generator is lowered to.
+    fn define_generator(
+        &mut self,
+        ty: Ty<'tcx>,
+        layout: TyLayout<'tcx>,
+    ) -> io::Result<TypeRef<'a>> {
+        let variants = match layout.variants {
+            layout::Variants::Multiple { ref variants, .. } => variants.indices(),
+            layout::Variants::Single { .. } => bug!("generator with a single state: {}", ty),
+        };
+
+        let mut members = Vec::new();
+        let prefix = self.fields(layout, true)?;
+        let prefix_name = Name::for_type_part(ty, GENERATOR_PREFIX, self.tcx);
+        let prefix = self
+            .tu
+            .define_struct(prefix_name, prefix.iter().map(|(n, t)| (&**n, *t)))?;
+        members.push((GENERATOR_PREFIX.to_string(), prefix));
+
+        for variant in variants {
+            // Only the state's own fields are part of the variant layout. Its
+            // offsets are relative to the start of the generator, so padding
+            // puts them at the right place.
+            let fields = self.fields(layout.for_variant(self, variant), true)?;
+            if fields.is_empty() {
+                continue;
+            }
+
+            let member = generator_variant(variant);
+            let name = Name::for_type_part(ty, &member, self.tcx);
+            let state = self
+                .tu
+                .define_struct(name, fields.iter().map(|(n, t)| (&**n, *t)))?;
+            members.push((member, state));
+        }
+
+        let name = Name::for_type(ty, self.tcx);
+        self.tu
+            .define_union(name, members.iter().map(|(n, t)| (&**n, *t)))
+    }
+
+    /// Defines an opaque `struct` with the size and alignment of `layout`.
+    fn define_opaque(&mut self, ty: Ty<'tcx>, layout: TyLayout<'tcx>) -> io::Result<TypeRef<'a>> {
+        // An integer array whose element has the alignment of the type gives
+        // the struct the right alignment without needing any attributes, up
+        // to the alignment of `uint64_t`.
+        let align = layout.align.abi.bytes();
+        let elem_align = align.min(8);
+        let elem = match elem_align {
+            1 => self.tu.u8(),
+            2 => self.tu.u16(),
+            4 => self.tu.u32(),
+            _ => self.tu.u64(),
+        };
+        let bytes = self
+            .tu
+            .array_of(elem, (layout.size.bytes() / elem_align) as usize);
+
+        let name = Name::for_type(ty, self.tcx);
+        if align > elem_align {
+            self.require_aligned_attribute(ty, align);
+            return self
+                .tu
+                .define_aligned(false, name, vec![("bytes", bytes)], align);
+        }
+        self.tu.define_struct(name, vec![("bytes", bytes)])
+    }
+
+    /// Aborts compilation if the C compiler doesn't support the `aligned`
+    /// attribute, which `ty` needs to get its alignment of `align` bytes.
+    fn require_aligned_attribute(&self, ty: Ty<'tcx>, align: u64) {
+        if self.capabilities.packed_attributes {
+            return;
+        }
+        let msg = format!(
+            "`{}` is aligned to {} bytes, which isn't supported by the selected C compiler",
+            ty, align
+        );
+        match ty.kind {
+            ty::Adt(def, _) => self.tcx.sess.span_fatal(self.tcx.def_span(def.did), &msg),
+            _ => self.tcx.sess.fatal(&msg),
+        }
+    }
+
+    /// Creates a padding field of `size` bytes.
+    fn padding(&mut self, index: usize, size: u64) -> (String, TypeRef<'a>) {
+        let u8 = self.tu.u8();
+        (
+            format!("_pad{}", index),
+            self.tu.array_of(u8, size as usize),
+        )
+    }
+}
+
+/// Determines how `ty` is represented in C, if it's an aggregate.
+fn aggregate_kind(ty: Ty<'_>) -> AggregateKind {
+    match ty.kind {
+        ty::Adt(def, _) if def.is_union() => AggregateKind::Union,
+        ty::Adt(def, _) if def.is_struct() => AggregateKind::Struct,
+        ty::Tuple(_) | ty::Closure(..) => AggregateKind::Struct,
+        ty::Generator(..) => AggregateKind::Generator,
+        _ => AggregateKind::Opaque,
+    }
+}
+
+/// Returns whether `ty` is lowered to a non-aggregate C type.
+fn is_scalar_like(ty: Ty<'_>) -> bool {
+    match ty.kind {
+        ty::Bool
+        | ty::Char
+        | ty::Int(_)
+        | ty::Uint(_)
+        | ty::Float(_)
+        | ty::Ref(..)
+        | ty::RawPtr(_)
+        | ty::FnPtr(_)
+        | ty::Array(..) => true,
+        // SIMD types only contain scalars, so they can't be recursive
+        ty::Adt(def, _) => def.is_box() || def.repr.simd(),
+        _ => false,
+    }
+}
@@ -0,0 +1,476 @@
+//! Selection of the C compiler, and of the language extensions the generated
+//! code may use.
+//!
+//! By default, the backend invokes whatever C compiler `cc` finds for the
+//! target (honoring `CC` and `CC_<target>`) and guesses its family from its
+//! name. A family can be selected explicitly with
+//! `-C llvm-args=-cc-family=<family>` or the `R2C2_CC_FAMILY` environment
+//! variable, which also makes the backend invoke that family's compiler.
+//!
+//! Every family has a capability profile, which codegen consults before
+//! emitting anything that isn't ISO C, and a default C dialect, which can be
+//! overridden with `-C llvm-args=-c-dialect=<dialect>` or `R2C2_C_DIALECT`.
+//!
+//! The capability profile is only an upper bound: extensions are dropped if
+//! the installed compiler's version predates them and, unless disabled with
+//! `-C llvm-args=-probe=no` or `R2C2_PROBE=no`, the compiler is probed for
+//! the extensions it actually supports (see `probe`).
+//!
+//! Targets without an OS are compiled for a freestanding C implementation,
+//! which can also be requested (or disabled) with
+//! `-C llvm-args=-freestanding=<yes|no>` or `R2C2_FREESTANDING`.
+//!
+//! Selecting, identifying and probing the compiler happens once per session,
+//! the first time the backend needs it.
+
+use back;
+use builder::dialect::Dialect;
+use probe::{self, ProbeConfig};
+use utils::{self, CcVersion};
+
+use rustc::session::Session;
+use rustc_target::spec::Target;
+use std::path::Path;
+use std::process::Command;
+
+/// The backend option selecting the compiler family.
+const FAMILY_OPTION: &str = "cc-family";
+
+/// The backend option selecting the C dialect.
+const DIALECT_OPTION: &str = "c-dialect";
+
+/// The backend option disabling capability probing.
+const PROBE_OPTION: &str = "probe";
+
+/// The backend option selecting a freestanding C implementation.
+const FREESTANDING_OPTION: &str = "freestanding";
+
+/// Returns whether the generated code is compiled by a freestanding C
+/// implementation, which lacks the C standard library.
+pub fn is_freestanding(sess: &Session) -> Result<bool, String> {
+    match utils::backend_option(Some(sess), FREESTANDING_OPTION)
+        .as_ref()
+        .map(|value| &**value)
+    {
+        Some("yes") => Ok(true),
+        Some("no") => Ok(false),
+        Some(value) => Err(format!(
+            "invalid value `{}` for `-{}` (expected `yes` or `no`)",
+            value, FREESTANDING_OPTION
+        )),
+        None => Ok(sess.target.target.target_os == "none"),
+    }
+}
+
+/// Warns if the C compiler selected by the options of `sess` doesn't
+/// compile for rustc's target.
+pub fn check_target(sess: &Session, compiler: &Compiler) {
+    let version = match compiler.version {
+        Some(ref version) => version,
+        None => return,
+    };
+    match version.target {
+        Some(ref triple) if !triple_matches(triple, &sess.target.target) => {
+            sess.warn(&format!(
+                "the C compiler `{}` compiles for `{}`, but rustc compiles for `{}`",
+                version.path.display(),
+                triple,
+                sess.opts.target_triple
+            ));
+        }
+        _ => {}
+    }
+}
+
+/// Returns whether the target triple `triple` reported by a C compiler has
+/// the architecture and OS of `target`.
+///
+/// The vendor and environment are ignored, since C compilers name them
+/// inconsistently (eg. `x86_64-linux-gnu` on Debian).
+fn triple_matches(triple: &str, target: &Target) -> bool {
+    let arch = triple.split('-').next().unwrap_or("");
+    let arch_matches = match &*target.arch {
+        "x86" => arch.len() == 4 && arch.starts_with('i') && arch.ends_with("86"),
+        "x86_64" => arch == "x86_64" || arch == "amd64",
+        "aarch64" => arch == "aarch64" || arch == "arm64",
+        "arm" => arch.starts_with("arm") || arch.starts_with("thumb"),
+        target_arch => arch.starts_with(target_arch),
+    };
+    let os_matches = match &*target.target_os {
+        "none" => true,
+        "macos" | "ios" => triple.contains("darwin") || triple.contains("apple"),
+        "windows" => triple.contains("mingw") || triple.contains("windows"),
+        os => triple.contains(os),
+    };
+    arch_matches && os_matches
+}
+
+/// Returns the C dialect selected by the options of `sess`, or the default
+/// of `family`.
+fn dialect(sess: &Session, family: CompilerFamily) -> Result<Dialect, String> {
+    match utils::backend_option(Some(sess), DIALECT_OPTION) {
+        Some(name) => Dialect::from_name(&name).ok_or_else(|| {
+            format!(
+                "unknown C dialect `{}` (expected one of {})",
+                name,
+                Dialect::ALL
+                    .iter()
+                    .map(|dialect| format!("`{}`", dialect.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }),
+        None => Ok(family.default_dialect()),
+    }
+}
+
+/// A family of C compilers that accept the same flags and extensions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompilerFamily {
+    Gcc,
+    Clang,
+    /// The Tiny C Compiler.
+    Tcc,
+    /// Any C99 compiler, assumed to support no extensions at all.
+    C99,
+}
+
+impl CompilerFamily {
+    pub const ALL: [CompilerFamily; 4] = [
+        CompilerFamily::Gcc,
+        CompilerFamily::Clang,
+        CompilerFamily::Tcc,
+        CompilerFamily::C99,
+    ];
+
+    /// Returns the name selecting the family with `-cc-family`.
+    pub fn name(self) -> &'static str {
+        match self {
+            CompilerFamily::Gcc => "gcc",
+            CompilerFamily::Clang => "clang",
+            CompilerFamily::Tcc => "tcc",
+            CompilerFamily::C99 => "c99",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .cloned()
+            .find(|family| family.name() == name)
+    }
+
+    /// Returns the compiler executable to invoke when the family is selected
+    /// explicitly.
+    ///
+    /// For `C99`, this is the compiler POSIX requires to be installed as
+    /// `c99`.
+    pub fn executable(self) -> &'static str {
+        self.name()
+    }
+
+    /// Returns whether the compiler accepts GCC's command line flags.
+    pub fn is_like_gnu(self) -> bool {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => true,
+            CompilerFamily::Tcc | CompilerFamily::C99 => false,
+        }
+    }
+
+    /// Returns the dialect the generated code is written in, unless another
+    /// one is selected.
+    pub fn default_dialect(self) -> Dialect {
+        match self {
+            CompilerFamily::C99 => Dialect::C99,
+            _ => Dialect::default(),
+        }
+    }
+
+    /// Returns the capability profile of the family, ie. the extensions its
+    /// compilers may support.
+    pub fn capabilities(self) -> Capabilities {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => Capabilities {
+                attributes: true,
+                asm_labels: true,
+                builtins: true,
+                overflow_builtins: true,
+                int128: true,
+                vector_extensions: true,
+                section_attributes: true,
+                thread_local: true,
+                inline_asm: true,
+                byte_order_macros: true,
+                packed_attributes: true,
+            },
+            CompilerFamily::Tcc => Capabilities {
+                attributes: true,
+                asm_labels: true,
+                builtins: false,
+                overflow_builtins: false,
+                int128: false,
+                vector_extensions: false,
+                section_attributes: true,
+                thread_local: false,
+                inline_asm: true,
+                byte_order_macros: true,
+                packed_attributes: true,
+            },
+            CompilerFamily::C99 => Capabilities {
+                attributes: false,
+                asm_labels: false,
+                builtins: false,
+                overflow_builtins: false,
+                int128: false,
+                vector_extensions: false,
+                section_attributes: false,
+                thread_local: false,
+                inline_asm: false,
+                byte_order_macros: false,
+                packed_attributes: false,
+            },
+        }
+    }
+}
+
+/// Returns `capabilities` without the extensions the compiler identified as
+/// `version` is too old to support.
+fn version_capabilities(mut capabilities: Capabilities, version: &CcVersion) -> Capabilities {
+    // GCC 5 and Clang 3.8 added the generic overflow builtins
+    if version.is_older_than(CompilerFamily::Gcc, 5, 0)
+        || version.is_older_than(CompilerFamily::Clang, 3, 8)
+    {
+        capabilities.overflow_builtins = false;
+    }
+    // GCC 4.9 added `_Thread_local`
+    if version.is_older_than(CompilerFamily::Gcc, 4, 9) {
+        capabilities.thread_local = false;
+    }
+    // GCC 4.6 added `__int128` and `__BYTE_ORDER__`
+    if version.is_older_than(CompilerFamily::Gcc, 4, 6) {
+        capabilities.int128 = false;
+        capabilities.byte_order_macros = false;
+    }
+    capabilities
+}
+
+/// The non-standard C features a compiler supports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// GCC's `__attribute__((..))` on function and global declarations.
+    /// Without it, hints like `cold` and `visibility` are dropped.
+    pub attributes: bool,
+    /// Assembler labels (`__asm__("symbol")`), which give declarations a
+    /// symbol name that isn't a C identifier.
+    pub asm_labels: bool,
+    /// GCC's `__builtin_*` functions (`__builtin_trap`,
+    /// `__builtin_unreachable`, `__builtin_fmod` and the other math builtins,
+    /// the bit counting builtins, and `__builtin_shuffle` or
+    /// `__builtin_shufflevector`).
+    pub builtins: bool,
+    /// `__builtin_add_overflow`, `__builtin_sub_overflow` and
+    /// `__builtin_mul_overflow`, which GCC added much later than the others.
+    pub overflow_builtins: bool,
+    /// The `__int128` and `unsigned __int128` types.
+    pub int128: bool,
+    /// GCC's vector extensions (`__attribute__((vector_size(N)))`).
+    pub vector_extensions: bool,
+    /// Placing functions and globals in object file sections with
+    /// `__attribute__((section(..)))`.
+    pub section_attributes: bool,
+    /// Thread-local storage (`_Thread_local` or `__thread`).
+    pub thread_local: bool,
+    /// GNU C extended `asm` statements.
+    pub inline_asm: bool,
+    /// The predefined macros `__BYTE_ORDER__`, `__ORDER_LITTLE_ENDIAN__`
+    /// and `__ORDER_BIG_ENDIAN__`.
+    pub byte_order_macros: bool,
+    /// GCC's `packed` and `aligned` type attributes, which `#[repr(packed)]`
+    /// types are lowered with.
+    pub packed_attributes: bool,
+}
+
+/// The C compiler the generated code is compiled with.
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    pub family: CompilerFamily,
+    /// Whether the family was selected explicitly, rather than detected from
+    /// the compiler `cc` found.
+    explicit: bool,
+    /// The extensions the compiler supports.
+    capabilities: Capabilities,
+    /// The C dialect the generated code is written in.
+    pub dialect: Dialect,
+    /// The compiler's identity, if it could be determined.
+    pub version: Option<CcVersion>,
+}
+
+impl Compiler {
+    /// Selects the compiler as configured by the options of `sess`, with its
+    /// dialect, and determines its capabilities from its version and, unless
+    /// that's disabled, by probing.
+    ///
+    /// This runs the compiler, so the backend only calls it once per session
+    /// (see `CCodegenBackend::compiler`).
+    pub fn from_session(sess: &Session) -> Result<Self, String> {
+        let mut compiler = Self::select(utils::backend_option(Some(sess), FAMILY_OPTION))?;
+        compiler.dialect = dialect(sess, compiler.family)?;
+        let probing = match utils::backend_option(Some(sess), PROBE_OPTION)
+            .as_ref()
+            .map(|value| &**value)
+        {
+            None | Some("yes") => true,
+            Some("no") => false,
+            Some(value) => {
+                return Err(format!(
+                    "invalid value `{}` for `-{}` (expected `yes` or `no`)",
+                    value, PROBE_OPTION
+                ))
+            }
+        };
+
+        // POSIX doesn't require `c99` to report its version
+        let version = match utils::cc_version(&compiler) {
+            Ok(version) => version,
+            Err(e) => {
+                info!(
+                    "couldn't identify the C compiler, assuming its family's capabilities: {}",
+                    e
+                );
+                return Ok(compiler);
+            }
+        };
+        utils::set_selected_cc(&version);
+        compiler.capabilities = version_capabilities(compiler.capabilities, &version);
+        if probing {
+            let flags = if compiler.family.is_like_gnu() {
+                back::gnu_target_flags(sess, compiler.dialect)?
+            } else {
+                Vec::new()
+            };
+            let config = ProbeConfig {
+                dialect: compiler.dialect,
+                flags,
+                is_like_osx: sess.target.target.options.is_like_osx,
+            };
+            compiler.capabilities =
+                probe::capabilities(&compiler, &version, config, &sess.jobserver)?;
+        }
+        compiler.version = Some(version);
+        Ok(compiler)
+    }
+
+    /// Selects the compiler as configured by the environment, assuming it
+    /// has the family's capabilities.
+    ///
+    /// This is used when there's no session to take options from (eg. by
+    /// `--version`).
+    pub fn from_env() -> Result<Self, String> {
+        Self::select(utils::backend_option(None, FAMILY_OPTION))
+    }
+
+    /// Returns the family of the compiler selected by the options of `sess`,
+    /// without probing it.
+    ///
+    /// This is used where the backend isn't available (eg. by query
+    /// providers).
+    pub fn family_from_session(sess: &Session) -> Result<CompilerFamily, String> {
+        Self::select(utils::backend_option(Some(sess), FAMILY_OPTION))
+            .map(|compiler| compiler.family)
+    }
+
+    fn select(family: Option<String>) -> Result<Self, String> {
+        match family {
+            Some(name) => match CompilerFamily::from_name(&name) {
+                Some(family) => Ok(Compiler {
+                    family,
+                    explicit: true,
+                    capabilities: family.capabilities(),
+                    dialect: family.default_dialect(),
+                    version: None,
+                }),
+                None => Err(format!(
+                    "unknown C compiler family `{}` (expected one of {})",
+                    name,
+                    CompilerFamily::ALL
+                        .iter()
+                        .map(|family| format!("`{}`", family.name()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+            None => Self::detect(),
+        }
+    }
+
+    /// Determines the family of the compiler `cc` finds for the target.
+    ///
+    /// The family named in the compiler's version output is used if there
+    /// is one, since eg. `cc` is often Clang. Otherwise, it's guessed from the
+    /// compiler's name.
+    fn detect() -> Result<Self, String> {
+        let tool = find_tool(&utils::cc_build())?;
+        let stem = Path::new(tool.path())
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+
+        let reported = utils::tool_version(&tool)
+            .ok()
+            .and_then(|version| version.family);
+        let family = if let Some(family) = reported {
+            family
+        } else if stem.ends_with("tcc") {
+            CompilerFamily::Tcc
+        } else if tool.is_like_clang() {
+            CompilerFamily::Clang
+        } else if tool.is_like_gnu() {
+            CompilerFamily::Gcc
+        } else {
+            return Err(format!(
+                "the C compiler `{}` isn't supported (select a family with `-C llvm-args=-{}=<family>`)",
+                tool.path().display(),
+                FAMILY_OPTION
+            ));
+        };
+        Ok(Compiler {
+            family,
+            explicit: false,
+            capabilities: family.capabilities(),
+            dialect: family.default_dialect(),
+            version: None,
+        })
+    }
+
+    /// Returns the extensions the generated code may use.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Returns a `cc::Build` invoking this compiler.
+    pub fn cc_build(&self) -> cc::Build {
+        let mut build = utils::cc_build();
+        if self.explicit {
+            build.compiler(self.family.executable());
+        }
+        build
+    }
+
+    /// Returns the `cc::Tool` invoking this compiler.
+    pub fn tool(&self) -> Result<cc::Tool, String> {
+        find_tool(&self.cc_build())
+    }
+
+    /// Returns a command invoking this compiler, with the flags `cc` adds for
+    /// the target.
+    pub fn command(&self) -> Result<Command, String> {
+        Ok(self.tool()?.to_command())
+    }
+}
+
+/// Returns the compiler `build` invokes.
+fn find_tool(build: &cc::Build) -> Result<cc::Tool, String> {
+    // FIXME: cc::Error doesn't impl Display and Error
+    build
+        .try_get_compiler()
+        .map_err(|e| format!("couldn't find a C compiler: {:?}", e))
+}
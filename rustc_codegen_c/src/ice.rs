@@ -1,16 +1,18 @@
 //! Custom ICE panic hook.
 
+use utils;
+
 use git_version::git_version;
-use once_cell::sync::Lazy;
 
 use rustc::ty::TyCtxt;
 use std::borrow::Cow;
-use std::{panic, thread};
+use std::panic;
 
 const BUG_REPORT_URL: &str = "https://github.com/jonas-schievink/r2c2";
 
 pub fn register_hook() {
-    let old_hook = panic::take_hook();
+    // Replace rustc's hook, which asks for bug reports against rustc
+    drop(panic::take_hook());
     let default_hook = panic::take_hook();
     panic::set_hook(Box::new(move |info| report_ice(info, &default_hook)));
 }
@@ -41,11 +43,14 @@ fn report_ice(
         handler.abort_if_errors_and_should_abort();
     }
 
-    let xs: Vec<Cow<'static, str>> = vec![
+    let mut xs: Vec<Cow<'static, str>> = vec![
         "the compiler unexpectedly panicked. this is a bug.".into(),
         format!("we would appreciate a bug report: {}", BUG_REPORT_URL).into(),
         format!("r2c2 commit: {}", git_version!()).into(),
     ];
+    if let Some(version) = utils::identified_cc() {
+        xs.push(format!("C compiler: {}", version).into());
+    }
 
     for note in &xs {
         handler.note_without_error(&note);
@@ -1,7 +1,6 @@
 //! A rustc code generation backend that outputs C code.
 
 // Much of the code in this crate is copied from the reference LLVM backend
-#![allow(warnings)] // FIXME remove once this does something
 #![feature(rustc_private)]
 
 #[macro_use]
@@ -16,9 +15,8 @@ extern crate rustc_codegen_utils;
 extern crate rustc_errors;
 extern crate rustc_incremental;
 extern crate rustc_index;
-#[macro_use]
-extern crate syntax;
 extern crate serialize;
+extern crate syntax;
 extern crate syntax_pos;
 
 extern crate git_version;
@@ -28,6 +26,7 @@ extern crate log;
 extern crate ar;
 extern crate cc;
 extern crate hashbrown;
+extern crate num_cpus;
 extern crate object;
 extern crate tempfile;
 extern crate toolshed;
@@ -36,34 +35,40 @@ extern crate toolshed;
 extern crate insta;
 extern crate bitflags;
 
+mod archive;
+mod back;
 mod builder;
 mod codegen;
+mod compiler;
 mod ice;
 mod metadata;
+mod print;
+mod probe;
 mod providers;
+mod target_features;
 mod utils;
 
+use archive::ArArchiveBuilder;
+use compiler::Compiler;
+
+use once_cell::sync::OnceCell;
 use rustc::dep_graph::{DepGraph, WorkProduct};
-use rustc::hir::def_id::CrateNum;
-use rustc::middle::cstore::{
-    CrateSource, EncodedMetadata, LibSource, MetadataLoader, NativeLibrary,
-};
-use rustc::middle::lang_items::LangItem;
+use rustc::middle::cstore::{EncodedMetadata, MetadataLoader};
 use rustc::session::config::{OptLevel, OutputFilenames, OutputType, PrintRequest};
 use rustc::session::Session;
 use rustc::ty::{self, TyCtxt};
-use rustc::util::common::ErrorReported;
-use rustc::util::nodemap::{FxHashMap, FxHashSet};
-use rustc_codegen_ssa::back::lto::{LtoModuleCodegen, SerializedModule, ThinModule};
-use rustc_codegen_ssa::back::write::{CodegenContext, FatLTOInput, ModuleConfig};
+use rustc::util::common::{time, ErrorReported};
+use rustc_codegen_ssa::back::link::link_binary;
+use rustc_codegen_ssa::back::lto::{LtoModuleCodegen, SerializedModule, ThinModule, ThinShared};
+use rustc_codegen_ssa::back::write::{CodegenContext, FatLTOInput, ModuleConfig, OngoingCodegen};
 use rustc_codegen_ssa::traits::{
     ExtraBackendMethods, ModuleBufferMethods, ThinBufferMethods, WriteBackendMethods,
 };
-use rustc_codegen_ssa::{CompiledModule, ModuleCodegen};
+use rustc_codegen_ssa::{CompiledModule, ModuleCodegen, ModuleKind};
 use rustc_codegen_utils::codegen_backend::CodegenBackend;
 use rustc_errors::{FatalError, Handler};
-use rustc_mir::monomorphize;
 use std::any::Any;
+use std::ffi::CString;
 use std::panic;
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -78,17 +83,33 @@ pub fn __rustc_codegen_backend() -> Box<dyn CodegenBackend> {
 
 /// A codegen backend that translates the Rust codegen unit to C code and
 /// invokes the system's C compiler to compile it.
-#[derive(Clone, Copy)]
-struct CCodegenBackend {}
+///
+/// rustc creates a backend for every session.
+#[derive(Clone)]
+struct CCodegenBackend {
+    /// The C compiler selected by the session's options.
+    compiler: OnceCell<Result<Compiler, String>>,
+}
 
 impl CCodegenBackend {
     fn new() -> Self {
-        Self {}
+        Self {
+            compiler: OnceCell::new(),
+        }
+    }
+
+    /// Returns the C compiler selected by the options of `sess`, which is
+    /// only selected, identified and probed the first time.
+    fn compiler(&self, sess: &Session) -> Result<&Compiler, String> {
+        self.compiler
+            .get_or_init(|| Compiler::from_session(sess))
+            .as_ref()
+            .map_err(Clone::clone)
     }
 }
 
 impl CodegenBackend for CCodegenBackend {
-    fn init(&self, sess: &Session) {
+    fn init(&self, _sess: &Session) {
         env_logger::init();
         info!("r2c2 C codegen backend initializing!");
 
@@ -96,35 +117,11 @@ impl CodegenBackend for CCodegenBackend {
     }
 
     fn print(&self, req: PrintRequest, sess: &Session) {
-        // FIXME: This is a dummy implementation
-        match req {
-            PrintRequest::RelocationModels => {
-                println!("Available relocation models:");
-                println!("    {}", "default");
-            }
-            PrintRequest::CodeModels => {
-                println!("Available code models:");
-                println!("    {}", "small");
-            }
-            PrintRequest::TlsModels => {
-                println!("Available TLS models:");
-                println!("    {}", "global-dynamic"); // I assume?
-            }
-            PrintRequest::TargetCPUs => {
-                println!("Available CPUs for this target:");
-                println!("    native         - Select the CPU of the current host");
-            }
-            PrintRequest::TargetFeatures => {
-                println!("Available features for this target:");
-                println!("    <none>");
-            }
-            _ => unimplemented!("print request {:?}", req),
-        }
+        print::print(req, sess, self.compiler(sess))
     }
 
     fn print_passes(&self) {
-        unimplemented!("list of passes supported by the C compiler");
-        // TODO: Parse output of `gcc --help=optimizers` and `clang --help`, resp.
+        print::print_passes()
     }
 
     fn print_version(&self) {
@@ -134,15 +131,22 @@ impl CodegenBackend for CCodegenBackend {
             env!("CARGO_PKG_VERSION")
         );
 
-        match utils::cc_version() {
-            Ok(out) => println!("{}", out),
+        match Compiler::from_env().and_then(|compiler| utils::cc_version(&compiler)) {
+            Ok(version) => {
+                println!("C compiler: {}", version);
+                println!("{}", version.output.trim_end());
+            }
             Err(err) => eprintln!("failed to determine C compiler version: {}", err),
         }
     }
 
     fn target_features(&self, sess: &Session) -> Vec<Symbol> {
-        // TODO
-        vec![]
+        self.compiler(sess)
+            .and_then(|compiler| target_features::enabled_features(sess, compiler))
+            .unwrap_or_else(|e| sess.fatal(&e))
+            .iter()
+            .map(|feature| Symbol::intern(feature.name))
+            .collect()
     }
 
     fn metadata_loader(&self) -> Box<dyn MetadataLoader + Sync> {
@@ -170,10 +174,12 @@ impl CodegenBackend for CCodegenBackend {
         metadata: EncodedMetadata,
         need_metadata_module: bool,
     ) -> Box<dyn Any> {
+        codegen::check_options(tcx, self.compiler(tcx.sess));
+
         // Let `rustc_codegen_ssa` do this. For this to work we have to
         // implement loads of traits from there (see below).
         Box::new(rustc_codegen_ssa::base::codegen_crate(
-            Self {},
+            self.clone(),
             tcx,
             metadata,
             need_metadata_module,
@@ -187,39 +193,42 @@ impl CodegenBackend for CCodegenBackend {
         dep_graph: &DepGraph,
         outputs: &OutputFilenames,
     ) -> Result<(), ErrorReported> {
-        /*use rustc::util::common::time;
-        let (ongoing_codegen, work_products) =
-            ongoing_codegen.downcast::<::back::write::OngoingCodegen>()
-                .expect("Expected LlvmCodegenBackend's OngoingCodegen, found Box<Any>")
-                .join(sess);
+        let (codegen_results, work_products) = ongoing_codegen
+            .downcast::<OngoingCodegen<CCodegenBackend>>()
+            .expect("Expected CCodegenBackend's OngoingCodegen, found Box<Any>")
+            .join(sess);
         if sess.opts.debugging_opts.incremental_info {
-            back::write::dump_incremental_data(&ongoing_codegen);
+            rustc_codegen_ssa::back::write::dump_incremental_data(&codegen_results);
         }
 
-        time(sess,
-             "serialize work products",
-             move || rustc_incremental::save_work_product_index(sess, &dep_graph, work_products));
+        time(sess, "serialize work products", move || {
+            rustc_incremental::save_work_product_index(sess, &dep_graph, work_products)
+        });
 
         sess.compile_status()?;
 
-        if !sess.opts.output_types.keys().any(|&i| i == OutputType::Exe ||
-            i == OutputType::Metadata) {
+        // Joining already copied the object files to the `--emit=obj` output
+        if !sess
+            .opts
+            .output_types
+            .keys()
+            .any(|&i| i == OutputType::Exe || i == OutputType::Metadata)
+        {
             return Ok(());
         }
 
-        // Run the linker on any artifacts that resulted from the LLVM run.
-        // This should produce either a finished executable or library.
         time(sess, "linking", || {
-            back::link::link_binary(sess, &ongoing_codegen,
-                                    outputs, &ongoing_codegen.crate_name.as_str());
+            let target_cpu = self.target_cpu(sess);
+            link_binary::<ArArchiveBuilder<'_>>(
+                sess,
+                &codegen_results,
+                outputs,
+                &codegen_results.crate_name.as_str(),
+                target_cpu,
+            );
         });
 
-        // Now that we won't touch anything in the incremental compilation directory
-        // any more, we can finalize it (which involves renaming it)
-        rustc_incremental::finalize_session_directory(sess, ongoing_codegen.link.crate_hash);
-
-        Ok(())*/
-        unimplemented!("join_codegen_and_link")
+        Ok(())
     }
 }
 
@@ -227,26 +236,58 @@ impl CodegenBackend for CCodegenBackend {
 
 impl WriteBackendMethods for CCodegenBackend {
     type Module = codegen::Module;
-    type TargetMachine = ();
+    type TargetMachine = back::CcConfig;
     type ModuleBuffer = NoModuleBuffer;
     type Context = ();
     type ThinData = ();
-    type ThinBuffer = NoThinBuffer;
+    type ThinBuffer = SourceBuffer;
 
     fn run_fat_lto(
-        cgcx: &CodegenContext<Self>,
-        modules: Vec<FatLTOInput<Self>>,
-        cached_modules: Vec<(SerializedModule<Self::ModuleBuffer>, WorkProduct)>,
+        _cgcx: &CodegenContext<Self>,
+        _modules: Vec<FatLTOInput<Self>>,
+        _cached_modules: Vec<(SerializedModule<Self::ModuleBuffer>, WorkProduct)>,
     ) -> Result<LtoModuleCodegen<Self>, FatalError> {
-        unimplemented!("C backend fat LTO")
+        bug!("fat LTO is rejected by `check_options`")
     }
 
+    /// Passes the modules through unchanged, since there's nothing to import
+    /// between C translation units. This is what `-C opt-level` with several
+    /// codegen units does by default (`Lto::ThinLocal`).
+    ///
+    /// Modules reused by incremental compilation hold the C source saved from
+    /// the `ThinBuffer` of the previous session, so they're compiled again.
     fn run_thin_lto(
-        cgcx: &CodegenContext<Self>,
+        _cgcx: &CodegenContext<Self>,
         modules: Vec<(String, Self::ThinBuffer)>,
         cached_modules: Vec<(SerializedModule<Self::ModuleBuffer>, WorkProduct)>,
     ) -> Result<(Vec<LtoModuleCodegen<Self>>, Vec<WorkProduct>), FatalError> {
-        unimplemented!("C backend thin LTO")
+        let mut module_names = Vec::with_capacity(modules.len() + cached_modules.len());
+        let mut thin_buffers = Vec::with_capacity(modules.len());
+        for (name, buffer) in modules {
+            module_names.push(CString::new(name).unwrap());
+            thin_buffers.push(buffer);
+        }
+        let mut serialized_modules = Vec::with_capacity(cached_modules.len());
+        for (module, work_product) in cached_modules {
+            module_names.push(CString::new(work_product.cgu_name).unwrap());
+            serialized_modules.push(module);
+        }
+
+        let shared = Arc::new(ThinShared {
+            data: (),
+            thin_buffers,
+            serialized_modules,
+            module_names,
+        });
+        let modules = (0..shared.module_names.len())
+            .map(|idx| {
+                LtoModuleCodegen::Thin(ThinModule {
+                    shared: shared.clone(),
+                    idx,
+                })
+            })
+            .collect();
+        Ok((modules, vec![]))
     }
 
     fn print_pass_timings(&self) {
@@ -254,66 +295,79 @@ impl WriteBackendMethods for CCodegenBackend {
     }
 
     unsafe fn optimize(
-        cgcx: &CodegenContext<Self>,
-        diag_handler: &Handler,
-        module: &ModuleCodegen<Self::Module>,
-        config: &ModuleConfig,
+        _cgcx: &CodegenContext<Self>,
+        _diag_handler: &Handler,
+        _module: &ModuleCodegen<Self::Module>,
+        _config: &ModuleConfig,
     ) -> Result<(), FatalError> {
-        unimplemented!()
+        // The C compiler optimizes the module while compiling it in `codegen`
+        Ok(())
     }
 
+    /// Turns the module back into C source for `codegen`.
     unsafe fn optimize_thin(
         cgcx: &CodegenContext<Self>,
         thin: &mut ThinModule<Self>,
     ) -> Result<ModuleCodegen<Self::Module>, FatalError> {
-        unimplemented!()
+        let source = String::from_utf8(thin.data().to_vec()).map_err(|e| {
+            let diag_handler = cgcx.create_diag_handler();
+            diag_handler.fatal(&format!("module `{}` isn't C source: {}", thin.name(), e))
+        })?;
+        Ok(ModuleCodegen {
+            name: thin.name().to_string(),
+            module_llvm: codegen::Module { source },
+            kind: ModuleKind::Regular,
+        })
     }
 
     unsafe fn codegen(
         cgcx: &CodegenContext<Self>,
         diag_handler: &Handler,
         module: ModuleCodegen<Self::Module>,
-        config: &ModuleConfig,
+        _config: &ModuleConfig,
     ) -> Result<CompiledModule, FatalError> {
-        unimplemented!()
+        back::compile_module(cgcx, diag_handler, module)
     }
 
+    /// Hands over the module's C source as the buffer, see `run_thin_lto`.
     fn prepare_thin(module: ModuleCodegen<Self::Module>) -> (String, Self::ThinBuffer) {
-        unimplemented!("C backend ThinLTO")
+        (module.name, SourceBuffer(module.module_llvm.source))
     }
 
     fn run_lto_pass_manager(
-        cgcx: &CodegenContext<Self>,
-        llmod: &ModuleCodegen<Self::Module>,
-        config: &ModuleConfig,
-        thin: bool,
+        _cgcx: &CodegenContext<Self>,
+        _llmod: &ModuleCodegen<Self::Module>,
+        _config: &ModuleConfig,
+        _thin: bool,
     ) {
         unimplemented!("C backend LTO")
     }
 
-    fn serialize_module(module: ModuleCodegen<Self::Module>) -> (String, Self::ModuleBuffer) {
+    fn serialize_module(_module: ModuleCodegen<Self::Module>) -> (String, Self::ModuleBuffer) {
         unimplemented!("C backend serialize_module")
     }
 }
 
 impl ExtraBackendMethods for CCodegenBackend {
     /// Create a new `Module` for storing metadata.
-    fn new_metadata(&self, sess: TyCtxt, mod_name: &str) -> Self::Module {
-        //unimplemented!("new_metadata")
-        codegen::Module
+    fn new_metadata(&self, _tcx: TyCtxt, _mod_name: &str) -> Self::Module {
+        codegen::Module::default()
     }
 
     fn write_compressed_metadata<'tcx>(
         &self,
-        tcx: TyCtxt<'tcx>,
-        metadata: &EncodedMetadata,
-        llvm_module: &mut Self::Module,
+        _tcx: TyCtxt<'tcx>,
+        _metadata: &EncodedMetadata,
+        _llvm_module: &mut Self::Module,
     ) {
-        unimplemented!();
+        bug!("crate types with compressed metadata are rejected by `check_options`")
     }
 
     fn codegen_allocator(&self, tcx: TyCtxt, mods: &mut Self::Module, kind: AllocatorKind) {
-        unimplemented!("codegen_allocator")
+        let compiler = self
+            .compiler(tcx.sess)
+            .unwrap_or_else(|e| tcx.sess.fatal(&e));
+        codegen::allocator::codegen(tcx, compiler, mods, kind)
     }
 
     fn compile_codegen_unit<'tcx>(
@@ -322,20 +376,30 @@ impl ExtraBackendMethods for CCodegenBackend {
         cgu_name: Symbol,
         tx_to_llvm_workers: &mpsc::Sender<Box<dyn Any + Send>>,
     ) {
-        unimplemented!()
+        codegen::compile_codegen_unit(self, tcx, cgu_name, tx_to_llvm_workers)
     }
 
     fn target_machine_factory(
         &self,
         sess: &Session,
         opt_level: OptLevel,
-        find_features: bool,
-    ) -> Arc<Fn() -> Result<Self::TargetMachine, String> + Send + Sync> {
-        Arc::new(|| Err("unimplemented".to_string()))
+        _find_features: bool,
+    ) -> Arc<dyn Fn() -> Result<Self::TargetMachine, String> + Send + Sync> {
+        let config = self
+            .compiler(sess)
+            .and_then(|compiler| back::cc_config(sess, compiler, opt_level));
+        Arc::new(move || config.clone())
     }
 
+    /// Returns the CPU selected with `-C target-cpu`, or the target's default.
+    ///
+    /// Without `-C target-cpu`, the C compiler actually compiles for its own
+    /// default CPU, which is usually the same.
     fn target_cpu<'b>(&self, sess: &'b Session) -> &'b str {
-        unimplemented!()
+        match sess.opts.cg.target_cpu {
+            Some(ref cpu) => cpu,
+            None => &sess.target.target.options.cpu,
+        }
     }
 }
 
@@ -347,10 +411,11 @@ impl ModuleBufferMethods for NoModuleBuffer {
     }
 }
 
-struct NoThinBuffer;
+/// The C source of a module, passed through ThinLTO.
+struct SourceBuffer(String);
 
-impl ThinBufferMethods for NoThinBuffer {
+impl ThinBufferMethods for SourceBuffer {
     fn data(&self) -> &[u8] {
-        &[]
+        self.0.as_bytes()
     }
 }
@@ -5,11 +5,7 @@ use utils::ResultExt;
 use ar::Archive;
 use object::{File as ObjectFile, Object, ObjectSection};
 
-use rustc::hir::def_id::LOCAL_CRATE;
-use rustc::middle::cstore::EncodedMetadata;
 use rustc::middle::cstore::MetadataLoader;
-use rustc::session::config::CrateType;
-use rustc::ty::TyCtxt;
 use rustc_data_structures::owning_ref::OwningRef;
 use rustc_data_structures::sync::MetadataRef;
 use rustc_target::spec::Target;
@@ -18,7 +14,10 @@ use std::io::Read;
 use std::path::Path;
 
 /// Metadata filename for metadata stored in an rlib.
-const METADATA_FILENAME: &str = "lib.rmeta";
+///
+/// This must match the LLVM backend, so that we can load the metadata of the
+/// prebuilt standard library.
+pub const METADATA_FILENAME: &str = "lib.rmeta";
 
 /// Section name for metadata stored in a dylib.
 const METADATA_SECTION_NAME: &str = ".rustc";
@@ -60,7 +59,7 @@ impl MetadataLoader for Loader {
 
 fn get_rlib_metadata(_target: &Target, filename: &Path) -> Result<MetadataRef, String> {
     // A `.rlib` file is just a `.a`-archive that contains the metadata in a
-    // `rust.metadata.bin` file. Use the `ar` crate to read that file.
+    // `lib.rmeta` file. Use the `ar` crate to read that file.
 
     info!(
         "loading rlib metadata from archive file '{}'",
@@ -107,15 +106,5 @@ fn get_dylib_metadata(_target: &Target, file: &Path) -> Result<MetadataRef, Stri
     let content = metadata_section.data();
     let data: OwningRef<_, [u8]> =
         OwningRef::new(content.into_owned().into_boxed_slice()).map_owner_box();
-    return Ok(rustc_erase_owner!(data));
-}
-
-/// Encode metadata into an appropriate blob for a given `crate_type`.
-///
-/// This will also perform any compression required by the crate type.
-///
-/// Returns `None` if the `crate_type` doesn't carry metadata (eg.
-/// executables, C-compatible libs).
-pub fn encode(tcx: TyCtxt<'_>) -> EncodedMetadata {
-    tcx.encode_metadata()
+    Ok(rustc_erase_owner!(data))
 }
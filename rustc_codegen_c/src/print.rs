@@ -0,0 +1,373 @@
+//! Answers to the `--print` requests rustc leaves to the codegen backend.
+//!
+//! Where possible, the answers come from the C compiler: GCC lists the
+//! valid CPUs and code models in `--target-help`, along with the options
+//! enabling target features, and Clang lists its CPUs with
+//! `--print-supported-cpus` and its options in `--help`. Other compilers
+//! don't accept GCC's flags, so the backend can't select a CPU, target
+//! features or models for them.
+//!
+//! `-C passes=list` lists the `-f` flags controlling optimizations instead of
+//! LLVM passes, which GCC lists in `--help=optimizers`. Clang doesn't tell
+//! them apart from its other `-f` flags, so all of those are listed.
+
+use back;
+use builder::attrs::TlsModel;
+use compiler::{Compiler, CompilerFamily};
+use target_features;
+use utils::ResultExt;
+
+use rustc::session::config::PrintRequest;
+use rustc::session::Session;
+
+/// Prints the answer to `req`.
+pub fn print(req: PrintRequest, sess: &Session, compiler: Result<&Compiler, String>) {
+    let print: fn(&Session, &Compiler) -> Result<(), String> = match req {
+        PrintRequest::TargetCPUs => print_target_cpus,
+        PrintRequest::TargetFeatures => print_target_features,
+        PrintRequest::RelocationModels => print_relocation_models,
+        PrintRequest::CodeModels => print_code_models,
+        PrintRequest::TlsModels => print_tls_models,
+        // rustc answers all other requests itself
+        _ => {
+            sess.warn(&format!(
+                "the C codegen backend can't answer `--print` request {:?}",
+                req
+            ));
+            return;
+        }
+    };
+
+    let compiler = compiler.unwrap_or_else(|e| sess.fatal(&e));
+    if let Err(e) = print(sess, compiler) {
+        sess.fatal(&e);
+    }
+    print_cc_version(sess, compiler);
+}
+
+/// Prints which C compiler an answer came from, since the backend's own
+/// version doesn't say which compiler the session selects.
+fn print_cc_version(sess: &Session, compiler: &Compiler) {
+    match compiler.version {
+        Some(ref version) => {
+            println!("As reported by the C compiler {}.", version);
+            println!();
+        }
+        None => sess.warn("failed to determine the C compiler's version"),
+    }
+}
+
+/// Runs the C compiler with `args`, returning everything it printed.
+///
+/// Some compilers print help to stderr, so both outputs are returned. The
+/// exit status is ignored, since compilers may complain about the missing
+/// input files after printing the help.
+fn help_output(compiler: &Compiler, args: &[&str]) -> Result<String, String> {
+    let output = compiler.command()?.args(args).output().err_to_string()?;
+    let mut help = String::from_utf8_lossy(&output.stdout).into_owned();
+    help.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(help)
+}
+
+/// Returns the values GCC's `--target-help` lists as valid for `option`.
+///
+/// They're listed after a line like `Known valid arguments for -march=
+/// option:`, and end at the next empty line.
+fn gcc_known_values(target_help: &str, option: &str) -> Option<Vec<String>> {
+    let mut lines = target_help.lines().skip_while(|line| {
+        !(line.trim_start().starts_with("Known") && line.contains(&format!("{}=", option)))
+    });
+    lines.next()?;
+    Some(
+        lines
+            .take_while(|line| !line.trim().is_empty())
+            .flat_map(|line| line.split_whitespace())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Returns the description of the option `option` in the help output
+/// `help`, or `None` if the compiler doesn't list it.
+fn option_description(help: &str, option: &str) -> Option<String> {
+    let mut lines = help
+        .lines()
+        .skip_while(|line| line.split_whitespace().next() != Some(option));
+    let mut description = lines.next()?.trim_start()[option.len()..]
+        .trim()
+        .to_string();
+    // Long descriptions are continued on more deeply indented lines
+    for line in lines.take_while(|line| {
+        line.starts_with("   ") && !line.trim().is_empty() && !line.trim().starts_with('-')
+    }) {
+        description.push(' ');
+        description.push_str(line.trim());
+    }
+    Some(description)
+}
+
+/// Returns the passes of the C compiler with their descriptions, ie. the
+/// names of the `-f<pass>` flags controlling optimizations.
+pub fn optimization_passes(compiler: &Compiler) -> Result<Vec<(String, String)>, String> {
+    let help = match compiler.family {
+        CompilerFamily::Gcc => help_output(compiler, &["--help=optimizers"])?,
+        CompilerFamily::Clang => help_output(compiler, &["--help"])?,
+        CompilerFamily::Tcc | CompilerFamily::C99 => return Ok(Vec::new()),
+    };
+
+    let mut passes = Vec::<(String, String)>::new();
+    for line in help.lines() {
+        let option = match line.split_whitespace().next() {
+            Some(option) if option.starts_with("-f") && !option.starts_with("-fno-") => option,
+            _ => continue,
+        };
+        // Flags taking a value are listed as eg. `-fname=` or `-fname=<value>`
+        let pass = option[2..]
+            .split(|c| c == '=' || c == '<' || c == '[')
+            .next()
+            .unwrap();
+        if pass.is_empty() || passes.iter().any(|(name, _)| name == pass) {
+            continue;
+        }
+        let description = option_description(&help, option).unwrap_or_default();
+        passes.push((pass.to_string(), description));
+    }
+    Ok(passes)
+}
+
+/// Prints the passes `-C passes` can enable, for `-C passes=list`.
+///
+/// There's no session to take options from, so the C compiler is selected
+/// by the environment.
+pub fn print_passes() {
+    match Compiler::from_env().and_then(|compiler| optimization_passes(&compiler)) {
+        Ok(passes) => {
+            println!("Available passes of the C compiler:");
+            if passes.is_empty() {
+                println!("    <none>");
+            }
+            for (pass, description) in passes {
+                println!("    {:<40} - {}", pass, description);
+            }
+            println!();
+            println!(
+                "Use -C passes=<pass> to enable a pass, or -C passes=no-<pass> to disable it."
+            );
+            println!();
+        }
+        Err(err) => eprintln!("failed to list the C compiler's passes: {}", err),
+    }
+}
+
+/// Returns the CPUs the C compiler can compile for.
+fn target_cpus(compiler: &Compiler, arch: &str) -> Result<Vec<String>, String> {
+    match compiler.family {
+        CompilerFamily::Gcc => {
+            let help = help_output(compiler, &["--target-help"])?;
+            Ok(gcc_known_values(&help, back::target_cpu_flag(arch)).unwrap_or_default())
+        }
+        CompilerFamily::Clang => {
+            // The CPUs follow a header, and are followed by usage hints
+            let help = help_output(compiler, &["--print-supported-cpus"])?;
+            Ok(help
+                .lines()
+                .skip_while(|line| !line.starts_with("Available CPUs"))
+                .skip(1)
+                .map(|line| line.trim())
+                .take_while(|line| !line.starts_with("Use "))
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect())
+        }
+        CompilerFamily::Tcc | CompilerFamily::C99 => Ok(Vec::new()),
+    }
+}
+
+fn print_target_cpus(sess: &Session, compiler: &Compiler) -> Result<(), String> {
+    println!("Available CPUs for this target:");
+    if !compiler.family.is_like_gnu() {
+        println!("    <none>");
+        return Ok(());
+    }
+    println!("    native         - Select the CPU of the current host");
+    for cpu in target_cpus(&compiler, &sess.target.target.arch)? {
+        if cpu != "native" {
+            println!("    {}", cpu);
+        }
+    }
+    println!();
+    Ok(())
+}
+
+fn print_target_features(sess: &Session, compiler: &Compiler) -> Result<(), String> {
+    let help = match compiler.family {
+        CompilerFamily::Gcc => help_output(&compiler, &["--target-help"])?,
+        CompilerFamily::Clang => help_output(&compiler, &["--help"])?,
+        CompilerFamily::Tcc | CompilerFamily::C99 => String::new(),
+    };
+
+    let known = target_features::known_features(&sess.target.target.arch);
+    let mut features = known
+        .iter()
+        .filter_map(|feature| {
+            let description = option_description(&help, &format!("-m{}", feature.gcc_name()))?;
+            Some((feature, description))
+        })
+        .collect::<Vec<_>>();
+    // Not all compilers list their target options, but the ones accepting
+    // GCC's flags accept those of all known features
+    if features.is_empty() && compiler.family.is_like_gnu() {
+        features = known
+            .iter()
+            .map(|feature| (feature, String::new()))
+            .collect();
+    }
+
+    println!("Available features for this target:");
+    if features.is_empty() {
+        println!("    <none>");
+        return Ok(());
+    }
+    for (feature, description) in features {
+        if description.is_empty() {
+            println!(
+                "    {:<16} - Enabled by -m{}",
+                feature.name,
+                feature.gcc_name()
+            );
+        } else {
+            println!("    {:<16} - {}", feature.name, description);
+        }
+    }
+    println!();
+
+    let enabled = target_features::enabled_features(sess, compiler)?
+        .iter()
+        .map(|feature| feature.name)
+        .collect::<Vec<_>>();
+    println!("Enabled with the current options: {}", enabled.join(", "));
+    println!();
+    println!("Use +feature to enable a feature, or -feature to disable it.");
+    println!("For example, rustc -C target-cpu=mycpu -C target-feature=+feature1,-feature2");
+    println!();
+    Ok(())
+}
+
+fn print_relocation_models(sess: &Session, compiler: &Compiler) -> Result<(), String> {
+    println!("Available relocation models:");
+    for &(model, _) in back::RELOCATION_MODELS {
+        let supported = match model {
+            "default" => true,
+            "dynamic-no-pic" => {
+                compiler.family.is_like_gnu() && sess.target.target.options.is_like_osx
+            }
+            _ => compiler.family.is_like_gnu(),
+        };
+        if supported {
+            println!("    {}", model);
+        }
+    }
+    println!();
+    Ok(())
+}
+
+fn print_code_models(_sess: &Session, compiler: &Compiler) -> Result<(), String> {
+    println!("Available code models:");
+    println!("    default");
+    if compiler.family.is_like_gnu() {
+        // GCC lists the code models of some architectures
+        let known = match compiler.family {
+            CompilerFamily::Gcc => {
+                gcc_known_values(&help_output(&compiler, &["--target-help"])?, "-mcmodel")
+            }
+            _ => None,
+        };
+        for &model in back::CODE_MODELS {
+            if known
+                .as_ref()
+                .map_or(true, |known| known.iter().any(|m| m == model))
+            {
+                println!("    {}", model);
+            }
+        }
+    }
+    println!();
+    Ok(())
+}
+
+fn print_tls_models(_sess: &Session, compiler: &Compiler) -> Result<(), String> {
+    let capabilities = compiler.capabilities();
+    let supported = capabilities.thread_local
+        && capabilities.attributes
+        && compiler.dialect.thread_local_keyword().is_some();
+    println!("Available TLS models:");
+    if supported {
+        // All of them can be requested with GCC's `tls_model` attribute
+        for model in &TlsModel::ALL {
+            println!("    {}", model.name());
+        }
+    } else {
+        println!("    (none, the C compiler or dialect doesn't support selecting them)");
+    }
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GCC_TARGET_HELP: &str = "\
+Target specific options:
+  -m128bit-long-double        sizeof(long double) is 16.
+  -mavx2                      Support MMX, SSE, SSE2, SSE3, SSSE3, SSE4.1,
+                              SSE4.2, AVX and AVX2 built-in functions and code
+                              generation.
+  -mbmi                       Support BMI built-in functions and code
+                              generation.
+
+  Known valid arguments for -march= option:
+    i386 i486 i586 pentium
+    x86-64 native
+
+  Known valid arguments for -mcmodel= option:
+    32 kernel large medium small
+
+";
+
+    #[test]
+    fn gcc_known_values_lists() {
+        assert_eq!(
+            gcc_known_values(GCC_TARGET_HELP, "-march"),
+            Some(
+                ["i386", "i486", "i586", "pentium", "x86-64", "native"]
+                    .iter()
+                    .map(|&s| s.to_string())
+                    .collect()
+            )
+        );
+        assert_eq!(
+            gcc_known_values(GCC_TARGET_HELP, "-mcmodel").map(|models| models.len()),
+            Some(5)
+        );
+        assert_eq!(gcc_known_values(GCC_TARGET_HELP, "-mtune"), None);
+    }
+
+    #[test]
+    fn option_descriptions() {
+        assert_eq!(
+            option_description(GCC_TARGET_HELP, "-mbmi"),
+            Some("Support BMI built-in functions and code generation.".to_string())
+        );
+        assert_eq!(
+            option_description(GCC_TARGET_HELP, "-mavx2"),
+            Some(
+                "Support MMX, SSE, SSE2, SSE3, SSSE3, SSE4.1, SSE4.2, AVX and AVX2 built-in \
+                 functions and code generation."
+                    .to_string()
+            )
+        );
+        // Options are only matched as a whole
+        assert_eq!(option_description(GCC_TARGET_HELP, "-mavx"), None);
+    }
+}
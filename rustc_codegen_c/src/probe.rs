@@ -0,0 +1,482 @@
+//! Probing the C compiler for the extensions it supports.
+//!
+//! Each probe compiles a small test program using one extension, with the
+//! same dialect and target flags as the generated code. Since this is slow,
+//! the results are cached in a file per compiler (as identified by
+//! `utils::cc_version`) and flags, in `R2C2_CACHE_DIR` or the user's cache
+//! directory (`$XDG_CACHE_HOME/r2c2` or `~/.cache/r2c2`).
+//!
+//! Cache files contain the compiler's identity (its path, target and version
+//! output) and the flags, with each line prefixed by `# `, followed by one
+//! `<probe> <yes|no>` line per probe. Probes missing
+//! from the file (eg. because it was written by an older r2c2) are rerun.
+//!
+//! Missing probes are run in parallel, as far as rustc's jobserver (usually
+//! Cargo's) has tokens to spare.
+
+use builder::dialect::Dialect;
+use compiler::{Capabilities, Compiler};
+use utils::{CcVersion, ResultExt};
+
+use rustc::util::nodemap::FxHashMap;
+use rustc_data_structures::jobserver::Client;
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use tempfile::{NamedTempFile, TempDir};
+
+/// How the test programs are compiled, which must match how the generated
+/// code is compiled.
+pub struct ProbeConfig {
+    /// The dialect the generated code is written in.
+    pub dialect: Dialect,
+    /// The dialect and target flags passed to the compiler.
+    pub flags: Vec<String>,
+    /// Whether the target uses Mach-O object files.
+    pub is_like_osx: bool,
+}
+
+/// A test program checking for support of an extension.
+struct Probe {
+    name: &'static str,
+    source: &'static str,
+}
+
+impl Probe {
+    /// Returns the test program for the dialect and target of `config`.
+    fn source(&self, config: &ProbeConfig) -> Cow<'static, str> {
+        match self.name {
+            "thread-local" => match config.dialect.thread_local_keyword() {
+                Some(keyword) => {
+                    format!("static {} int x;\nint f(void) {{ return x; }}\n", keyword).into()
+                }
+                // The dialect can't declare thread-local variables at all
+                None => "#error\n".into(),
+            },
+            // Mach-O section names also name the segment
+            "section-attributes" if config.is_like_osx => {
+                "__attribute__((section(\"__DATA,__r2c2\"))) int x;\n".into()
+            }
+            _ => self.source.into(),
+        }
+    }
+}
+
+const PROBES: &[Probe] = &[
+    Probe {
+        name: "attributes",
+        source: "__attribute__((visibility(\"hidden\"), cold, noinline)) void f(void) {}\n",
+    },
+    Probe {
+        name: "asm-labels",
+        source: "extern int x __asm__(\"r2c2$x\");\nint *f(void) { return &x; }\n",
+    },
+    Probe {
+        name: "builtins",
+        source: "typedef int v4 __attribute__((vector_size(16)));\n\
+                 typedef unsigned m4 __attribute__((vector_size(16)));\n\
+                 v4 shuffle(v4 a, v4 b) {\n\
+                 #ifdef __clang__\n\
+                 return __builtin_shufflevector(a, b, 0, 5, 2, 7);\n\
+                 #else\n\
+                 return __builtin_shuffle(a, b, (m4) { 0, 5, 2, 7 });\n\
+                 #endif\n\
+                 }\n\
+                 double rem(double a, double b) { return __builtin_fmod(a, b); }\n\
+                 float remf(float a, float b) { return __builtin_fmodf(a, b); }\n\
+                 double root(double a) { return __builtin_sqrt(a) + __builtin_powi(a, 2); }\n\
+                 unsigned long long bits(unsigned long long x) {\n\
+                 return __builtin_popcountll(x) + __builtin_clzll(x) + __builtin_ctzll(x)\n\
+                 + __builtin_bswap64(x);\n\
+                 }\n\
+                 void trap(int x) { if (x) __builtin_trap(); __builtin_unreachable(); }\n",
+    },
+    Probe {
+        name: "overflow-builtins",
+        source: "int f(int a, int b, int *r) { return __builtin_add_overflow(a, b, r); }\n",
+    },
+    Probe {
+        name: "int128",
+        source: "unsigned __int128 f(unsigned __int128 a) { return a * a; }\n",
+    },
+    Probe {
+        name: "thread-local",
+        // Depends on the dialect, see `Probe::source`
+        source: "",
+    },
+    Probe {
+        name: "section-attributes",
+        source: "__attribute__((section(\".data.r2c2\"))) int x;\n",
+    },
+    Probe {
+        name: "vector-extensions",
+        source: "typedef int v4 __attribute__((vector_size(16)));\n\
+                 v4 f(v4 a, v4 b) { return a + b; }\n",
+    },
+    Probe {
+        name: "byte-order-macros",
+        source: "#if !defined(__BYTE_ORDER__) || !defined(__ORDER_BIG_ENDIAN__)\n\
+                 #error\n\
+                 #endif\n\
+                 int x;\n",
+    },
+    Probe {
+        name: "packed-attributes",
+        source: "struct s { char c; int i; } __attribute__((packed, aligned(2)));\n\
+                 typedef char check[sizeof(struct s) == 6 ? 1 : -1];\n",
+    },
+];
+
+/// Returns the capability that is only available if `probe` succeeds.
+fn capability<'c>(caps: &'c mut Capabilities, probe: &Probe) -> &'c mut bool {
+    match probe.name {
+        "attributes" => &mut caps.attributes,
+        "asm-labels" => &mut caps.asm_labels,
+        "builtins" => &mut caps.builtins,
+        "overflow-builtins" => &mut caps.overflow_builtins,
+        "int128" => &mut caps.int128,
+        "thread-local" => &mut caps.thread_local,
+        "section-attributes" => &mut caps.section_attributes,
+        "vector-extensions" => &mut caps.vector_extensions,
+        "byte-order-macros" => &mut caps.byte_order_macros,
+        "packed-attributes" => &mut caps.packed_attributes,
+        name => bug!("unknown probe {}", name),
+    }
+}
+
+/// Returns the capabilities of `compiler`, identified as `version`, probing
+/// for those it may have with `config` unless the results are cached.
+///
+/// The calling thread must hold a token of `jobserver`.
+pub fn capabilities(
+    compiler: &Compiler,
+    version: &CcVersion,
+    config: ProbeConfig,
+    jobserver: &Client,
+) -> Result<Capabilities, String> {
+    let mut caps = compiler.capabilities();
+    // Probes for extensions the family doesn't have can only fail
+    let probes = PROBES
+        .iter()
+        .filter(|probe| *capability(&mut caps, probe))
+        .collect::<Vec<_>>();
+    if probes.is_empty() {
+        return Ok(caps);
+    }
+
+    let key = format!("{}\nflags: {}", version.key(), config.flags.join(" "));
+    let cache = cache_path(&key);
+    let mut results = cache
+        .as_ref()
+        .map_or_else(FxHashMap::default, |path| read_cache(path, &key));
+
+    let missing = probes
+        .iter()
+        .cloned()
+        .filter(|probe| !results.contains_key(probe.name))
+        .collect::<Vec<_>>();
+    let updated = !missing.is_empty();
+    if updated {
+        let outcomes = run_probes(compiler.clone(), config, jobserver, missing.clone())?;
+        for (probe, ok) in missing.into_iter().zip(outcomes) {
+            debug!("probe {}: {}", probe.name, ok);
+            results.insert(probe.name.to_string(), ok);
+        }
+    }
+    for probe in &probes {
+        *capability(&mut caps, probe) &= results[probe.name];
+    }
+
+    if let (true, Some(path)) = (updated, cache) {
+        // The cache is only an optimization
+        if let Err(e) = write_cache(&path, &key, &results) {
+            info!("couldn't write probe cache {}: {}", path.display(), e);
+        }
+    }
+    Ok(caps)
+}
+
+/// Probes waiting to be run by any of several threads.
+struct Queue {
+    compiler: Compiler,
+    config: ProbeConfig,
+    probes: Vec<&'static Probe>,
+    /// The index of the next probe to run.
+    next: AtomicUsize,
+}
+
+impl Queue {
+    /// Runs probes until none are left, returning their indices and results.
+    fn run(&self) -> Result<Vec<(usize, bool)>, String> {
+        let mut results = Vec::new();
+        loop {
+            let index = self.next.fetch_add(1, Ordering::SeqCst);
+            match self.probes.get(index) {
+                Some(probe) => {
+                    results.push((index, run_probe(&self.compiler, &self.config, probe)?))
+                }
+                None => return Ok(results),
+            }
+        }
+    }
+}
+
+/// Runs `probes`, returning whether each one succeeded.
+///
+/// The calling thread runs probes using the token it holds. Up to one thread
+/// per additional CPU is spawned to help, each once it has acquired a token
+/// from `jobserver`, so that every running C compiler counts as one job.
+fn run_probes(
+    compiler: Compiler,
+    config: ProbeConfig,
+    jobserver: &Client,
+    probes: Vec<&'static Probe>,
+) -> Result<Vec<bool>, String> {
+    let helpers = cmp::min(probes.len(), num_cpus::get()).saturating_sub(1);
+    let queue = Arc::new(Queue {
+        compiler,
+        config,
+        probes,
+        next: AtomicUsize::new(0),
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let helper = {
+        let queue = queue.clone();
+        jobserver
+            .clone()
+            .into_helper_thread(move |token| {
+                let queue = queue.clone();
+                // The probes may all be done by the time the token arrives,
+                // in which case it's released right away
+                let _ = tx.send(thread::spawn(move || {
+                    let _token = token.err_to_string()?;
+                    queue.run()
+                }));
+            })
+            .err_to_string()?
+    };
+    for _ in 0..helpers {
+        helper.request_token();
+    }
+    let mut results = queue.run()?;
+
+    // Stops the helper thread, so that no threads are spawned anymore
+    drop(helper);
+    for thread in rx.try_iter() {
+        results.extend(thread.join().unwrap()?);
+    }
+    results.sort();
+    Ok(results.into_iter().map(|(_, ok)| ok).collect())
+}
+
+/// Returns whether `compiler` compiles the test program of `probe` with
+/// `config`.
+fn run_probe(compiler: &Compiler, config: &ProbeConfig, probe: &Probe) -> Result<bool, String> {
+    let dir = TempDir::new().err_to_string()?;
+    let src = dir.path().join(format!("{}.c", probe.name));
+    fs::write(&src, probe.source(config).as_bytes()).err_to_string()?;
+
+    let output = compiler
+        .command()?
+        .args(&config.flags)
+        .arg("-c")
+        .arg(&src)
+        .arg("-o")
+        .arg(dir.path().join(format!("{}.o", probe.name)))
+        .output()
+        .err_to_string()?;
+    Ok(output.status.success())
+}
+
+/// Returns the cache file for the compiler identified by `key`,
+/// or `None` if there's no cache directory.
+fn cache_path(key: &str) -> Option<PathBuf> {
+    let dir = env::var_os("R2C2_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("XDG_CACHE_HOME").map(|dir| PathBuf::from(dir).join("r2c2")))
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("r2c2"))
+        })?;
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    Some(dir.join(format!("probes-{:016x}", hasher.finish())))
+}
+
+/// Reads the probe results cached in `path`, ignoring the file if it
+/// doesn't exist, is malformed, or belongs to another compiler (whose
+/// identity has the same hash).
+fn read_cache(path: &Path, key: &str) -> FxHashMap<String, bool> {
+    let mut results = FxHashMap::default();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return results,
+    };
+
+    let mut cached_key = String::new();
+    for line in contents.lines() {
+        if line.starts_with("# ") {
+            cached_key.push_str(&line[2..]);
+            cached_key.push('\n');
+            continue;
+        }
+        let mut parts = line.split(' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some("yes"), None) => results.insert(name.to_string(), true),
+            (Some(name), Some("no"), None) => results.insert(name.to_string(), false),
+            _ => return FxHashMap::default(),
+        };
+    }
+    if cached_key.trim_end() != key.trim_end() {
+        return FxHashMap::default();
+    }
+    results
+}
+
+/// Atomically replaces the cache file `path` with `results`.
+fn write_cache(path: &Path, key: &str, results: &FxHashMap<String, bool>) -> Result<(), String> {
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).err_to_string()?;
+
+    let mut file = NamedTempFile::new_in(dir).err_to_string()?;
+    for line in key.trim_end().lines() {
+        writeln!(file, "# {}", line).err_to_string()?;
+    }
+    let mut results = results.iter().collect::<Vec<_>>();
+    results.sort();
+    for (name, &ok) in results {
+        writeln!(file, "{} {}", name, if ok { "yes" } else { "no" }).err_to_string()?;
+    }
+    file.persist(path).err_to_string()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiler::CompilerFamily;
+
+    #[test]
+    fn probes_map_to_distinct_capabilities() {
+        let mut caps = CompilerFamily::Gcc.capabilities();
+        for probe in PROBES {
+            let before = caps;
+            *capability(&mut caps, probe) = false;
+            assert_ne!(caps, before, "{}", probe.name);
+        }
+    }
+
+    fn config(flags: &[&str]) -> ProbeConfig {
+        ProbeConfig {
+            dialect: Dialect::C11,
+            flags: flags.iter().map(|&flag| flag.to_string()).collect(),
+            is_like_osx: cfg!(target_os = "macos"),
+        }
+    }
+
+    #[test]
+    fn probes_pass_with_host_compiler() {
+        let compiler = Compiler::from_env().unwrap();
+        if !compiler.family.is_like_gnu() {
+            return;
+        }
+        let mut config = config(&["-std=gnu11"]);
+        config.dialect = Dialect::Gnu11;
+        for probe in PROBES {
+            assert!(
+                run_probe(&compiler, &config, probe).unwrap(),
+                "{}",
+                probe.name
+            );
+        }
+    }
+
+    #[test]
+    fn probes_use_flags() {
+        let compiler = Compiler::from_env().unwrap();
+        if !compiler.family.is_like_gnu() {
+            return;
+        }
+        // `_Thread_local` is C11
+        let thread_local = PROBES.iter().find(|p| p.name == "thread-local").unwrap();
+        assert!(!run_probe(
+            &compiler,
+            &config(&["-std=c99", "-pedantic-errors"]),
+            thread_local
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn thread_local_keyword() {
+        let thread_local = PROBES.iter().find(|p| p.name == "thread-local").unwrap();
+        let mut config = config(&["-std=gnu99"]);
+        config.dialect = Dialect::Gnu99;
+        assert!(thread_local
+            .source(&config)
+            .contains("static __thread int x;"));
+        config.dialect = Dialect::C99;
+        assert!(!thread_local.source(&config).contains("int x;"));
+
+        let compiler = Compiler::from_env().unwrap();
+        if compiler.family.is_like_gnu() {
+            config.dialect = Dialect::Gnu99;
+            assert!(run_probe(&compiler, &config, thread_local).unwrap());
+        }
+    }
+
+    #[test]
+    fn failing_probe() {
+        let compiler = Compiler::from_env().unwrap();
+        let probe = Probe {
+            name: "invalid",
+            source: "int f(void) { return __r2c2_undeclared; }\n",
+        };
+        assert!(!run_probe(&compiler, &config(&[]), &probe).unwrap());
+    }
+
+    #[test]
+    fn mach_o_sections() {
+        let probe = PROBES
+            .iter()
+            .find(|p| p.name == "section-attributes")
+            .unwrap();
+        let mut config = config(&[]);
+        config.is_like_osx = true;
+        assert!(probe.source(&config).contains("__DATA,"));
+        config.is_like_osx = false;
+        assert!(!probe.source(&config).contains("__DATA,"));
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("probes");
+        let key = "/usr/bin/cc\nx86_64-unknown-linux-gnu\ncc 1.0\nsecond line\n";
+        let mut results = FxHashMap::default();
+        results.insert("int128".to_string(), true);
+        results.insert("thread-local".to_string(), false);
+        write_cache(&path, key, &results).unwrap();
+
+        assert_eq!(read_cache(&path, key), results);
+        // Results of another compiler are ignored
+        assert!(read_cache(&path, "/usr/bin/cc\n\ncc 2.0").is_empty());
+        assert!(read_cache(&dir.path().join("missing"), key).is_empty());
+    }
+
+    #[test]
+    fn malformed_cache() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("probes");
+        fs::write(&path, "# cc\nint128 maybe\n").unwrap();
+        assert!(read_cache(&path, "cc").is_empty());
+    }
+}
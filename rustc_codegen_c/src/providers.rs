@@ -5,9 +5,10 @@
 //! providers in the LLVM codegen backend. In fact, we *must* register providers
 //! for the same queries that the LLVM backend provides.
 
-use rustc::hir::def_id::{DefId, LOCAL_CRATE};
+use target_features;
+
+use rustc::hir::def_id::LOCAL_CRATE;
 use rustc::ty;
-use rustc_data_structures::sync::Lrc;
 
 pub fn provide(providers: &mut ty::query::Providers) {
     // from librustc_codegen_llvm/attributes.rs
@@ -16,18 +17,17 @@ pub fn provide(providers: &mut ty::query::Providers) {
         if tcx.sess.opts.actually_rustdoc {
             // rustdoc needs to be able to document functions that use all the features, so
             // whitelist them all
-            // FIXME: This seems incompatible with other codegen backends: They don't know which
-            // features LLVM supports
-            tcx.arena.alloc(Default::default())
+            tcx.arena.alloc(target_features::all_known_features())
         } else {
-            // TODO: figure this out - we should support the same features as the llvm backend
-            tcx.arena.alloc(Default::default())
+            let whitelist =
+                target_features::whitelist(tcx.sess).unwrap_or_else(|e| tcx.sess.fatal(&e));
+            tcx.arena.alloc(whitelist)
         }
     };
 
     provide_extern(providers);
 }
 
-pub fn provide_extern(providers: &mut ty::query::Providers) {
+pub fn provide_extern(_providers: &mut ty::query::Providers) {
     // FIXME the LLVM backend sets `providers.wasm_import_module_map` here, figure out if we have to
 }
@@ -0,0 +1,230 @@
+//! Target features, as far as the C compiler can enable them.
+//!
+//! The C compiler decides which features the generated code may use, based
+//! on the `-march` and `-m<feature>` flags `back` translates `-C target-cpu`
+//! and `-C target-feature` to. The features reported to rustc for
+//! `cfg(target_feature)` are read back from the macros the compiler
+//! predefines with those flags, so they match what the C code is compiled
+//! with.
+//!
+//! Only x86 features are known so far.
+
+use back;
+use compiler::Compiler;
+use utils::ResultExt;
+
+use rustc::session::Session;
+use rustc::util::nodemap::{FxHashMap, FxHashSet};
+use std::process::Stdio;
+use syntax::symbol::Symbol;
+
+/// A target feature rustc knows.
+#[derive(Debug)]
+pub struct TargetFeature {
+    /// The name used by `-C target-feature` and `#[target_feature]`.
+    pub name: &'static str,
+    /// The macro the C compiler predefines if the feature is enabled.
+    pub macro_name: &'static str,
+}
+
+impl TargetFeature {
+    /// Returns the name used by GCC's `-m<name>` flags and `target`
+    /// attribute, which is mostly the same.
+    pub fn gcc_name(&self) -> &'static str {
+        match self.name {
+            "bmi1" => "bmi",
+            "cmpxchg16b" => "cx16",
+            "pclmulqdq" => "pclmul",
+            "rdrand" => "rdrnd",
+            name => name,
+        }
+    }
+
+    /// Returns the feature gate of `#[target_feature]` for the feature, if
+    /// it's unstable.
+    pub fn gate(&self) -> Option<&'static str> {
+        match self.name {
+            "adx" => Some("adx_target_feature"),
+            name if name.starts_with("avx512") => Some("avx512_target_feature"),
+            "cmpxchg16b" => Some("cmpxchg16b_target_feature"),
+            "f16c" => Some("f16c_target_feature"),
+            "mmx" => Some("mmx_target_feature"),
+            "movbe" => Some("movbe_target_feature"),
+            "rtm" => Some("rtm_target_feature"),
+            "sse4a" => Some("sse4a_target_feature"),
+            "tbm" => Some("tbm_target_feature"),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! feature {
+    ($name:expr, $macro_name:expr) => {
+        TargetFeature {
+            name: $name,
+            macro_name: $macro_name,
+        }
+    };
+}
+
+/// The x86 features of the LLVM backend's whitelist.
+const X86_FEATURES: &[TargetFeature] = &[
+    feature!("adx", "__ADX__"),
+    feature!("aes", "__AES__"),
+    feature!("avx", "__AVX__"),
+    feature!("avx2", "__AVX2__"),
+    feature!("avx512bw", "__AVX512BW__"),
+    feature!("avx512cd", "__AVX512CD__"),
+    feature!("avx512dq", "__AVX512DQ__"),
+    feature!("avx512er", "__AVX512ER__"),
+    feature!("avx512f", "__AVX512F__"),
+    feature!("avx512ifma", "__AVX512IFMA__"),
+    feature!("avx512pf", "__AVX512PF__"),
+    feature!("avx512vbmi", "__AVX512VBMI__"),
+    feature!("avx512vl", "__AVX512VL__"),
+    feature!("avx512vpopcntdq", "__AVX512VPOPCNTDQ__"),
+    feature!("bmi1", "__BMI__"),
+    feature!("bmi2", "__BMI2__"),
+    feature!("cmpxchg16b", "__GCC_HAVE_SYNC_COMPARE_AND_SWAP_16"),
+    feature!("f16c", "__F16C__"),
+    feature!("fma", "__FMA__"),
+    feature!("fxsr", "__FXSR__"),
+    feature!("lzcnt", "__LZCNT__"),
+    feature!("mmx", "__MMX__"),
+    feature!("movbe", "__MOVBE__"),
+    feature!("pclmulqdq", "__PCLMUL__"),
+    feature!("popcnt", "__POPCNT__"),
+    feature!("rdrand", "__RDRND__"),
+    feature!("rdseed", "__RDSEED__"),
+    feature!("rtm", "__RTM__"),
+    feature!("sha", "__SHA__"),
+    feature!("sse", "__SSE__"),
+    feature!("sse2", "__SSE2__"),
+    feature!("sse3", "__SSE3__"),
+    feature!("sse4.1", "__SSE4_1__"),
+    feature!("sse4.2", "__SSE4_2__"),
+    feature!("sse4a", "__SSE4A__"),
+    feature!("ssse3", "__SSSE3__"),
+    feature!("tbm", "__TBM__"),
+    feature!("xsave", "__XSAVE__"),
+    feature!("xsavec", "__XSAVEC__"),
+    feature!("xsaveopt", "__XSAVEOPT__"),
+    feature!("xsaves", "__XSAVES__"),
+];
+
+/// Returns the target features of `arch` the backend knows.
+pub fn known_features(arch: &str) -> &'static [TargetFeature] {
+    match arch {
+        "x86" | "x86_64" => X86_FEATURES,
+        _ => &[],
+    }
+}
+
+/// Returns the target feature `name` of `arch`, if the backend knows it.
+pub fn find(arch: &str, name: &str) -> Option<&'static TargetFeature> {
+    known_features(arch)
+        .iter()
+        .find(|feature| feature.name == name)
+}
+
+/// Maps the names of `features` to their feature gates.
+fn gates<'a>(
+    features: impl Iterator<Item = &'a TargetFeature>,
+) -> FxHashMap<String, Option<Symbol>> {
+    features
+        .map(|feature| (feature.name.to_string(), feature.gate().map(Symbol::intern)))
+        .collect()
+}
+
+/// Returns the features of all targets with their feature gates, for
+/// rustdoc.
+pub fn all_known_features() -> FxHashMap<String, Option<Symbol>> {
+    gates(X86_FEATURES.iter())
+}
+
+/// Returns the features `#[target_feature]` may enable with their feature
+/// gates.
+///
+/// Only compilers accepting GCC's flags can enable features for a single
+/// function (with the `target` attribute).
+pub fn whitelist(sess: &Session) -> Result<FxHashMap<String, Option<Symbol>>, String> {
+    if !Compiler::family_from_session(sess)?.is_like_gnu() {
+        return Ok(FxHashMap::default());
+    }
+    Ok(gates(known_features(&sess.target.target.arch).iter()))
+}
+
+/// Returns the features the C compiler enables when compiling the crate.
+///
+/// Compilers that don't accept GCC's flags can't list their predefined
+/// macros, so no features are reported for them.
+pub fn enabled_features(
+    sess: &Session,
+    compiler: &Compiler,
+) -> Result<Vec<&'static TargetFeature>, String> {
+    let features = known_features(&sess.target.target.arch);
+    let config = back::cc_config(sess, compiler, sess.opts.optimize)?;
+    if features.is_empty() || !config.compiler.family.is_like_gnu() {
+        return Ok(Vec::new());
+    }
+
+    let output = config
+        .compiler
+        .command()?
+        .args(&config.flags)
+        .args(&["-dM", "-E", "-x", "c", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .err_to_string()?;
+    if !output.status.success() {
+        return Err(format!(
+            "couldn't list the C compiler's predefined macros: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    // Every line is `#define <macro> <value>`
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let macros = stdout
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .collect::<FxHashSet<_>>();
+    Ok(features
+        .iter()
+        .filter(|feature| macros.contains(feature.macro_name))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax_pos::edition::DEFAULT_EDITION;
+    use syntax_pos::{Globals, GLOBALS};
+
+    #[test]
+    fn gcc_names() {
+        assert_eq!(find("x86_64", "bmi1").unwrap().gcc_name(), "bmi");
+        assert_eq!(find("x86", "pclmulqdq").unwrap().gcc_name(), "pclmul");
+        assert_eq!(find("x86_64", "avx2").unwrap().gcc_name(), "avx2");
+    }
+
+    #[test]
+    fn unknown_features() {
+        assert!(find("x86_64", "neon").is_none());
+        assert!(find("arm", "avx2").is_none());
+        assert!(known_features("aarch64").is_empty());
+    }
+
+    #[test]
+    fn feature_gates() {
+        GLOBALS.set(&Globals::new(DEFAULT_EDITION), || {
+            let gates = all_known_features();
+            assert_eq!(gates["avx2"], None);
+            assert_eq!(
+                gates["avx512f"],
+                Some(Symbol::intern("avx512_target_feature"))
+            );
+            assert_eq!(gates.len(), X86_FEATURES.len());
+        })
+    }
+}
@@ -1,9 +1,16 @@
-use rustc::ty::TyCtxt;
+use compiler::{Compiler, CompilerFamily};
+
+use once_cell::sync::Lazy;
+use rustc::session::Session;
+use rustc::util::nodemap::FxHashMap;
+use std::env;
 use std::fmt::{self, Display};
 use std::io::{self, Write};
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::path::PathBuf;
 use std::str;
+use std::sync::Mutex;
 
 // Import the host/target triple, set by the build script
 include!(concat!(env!("OUT_DIR"), "/triples.rs"));
@@ -22,25 +29,198 @@ impl<T, E: Display> ResultExt<T, E> for Result<T, E> {
     }
 }
 
-/// Obtains the version information of the system's C compiler.
+/// Returns a `cc::Build` that finds the C compiler for the target the
+/// backend was built for.
+///
+/// The build is configured explicitly, since `cc` otherwise expects to run in
+/// a build script. Flags added by `cc` can be overridden by appending flags
+/// to the compiler command.
+pub fn cc_build() -> cc::Build {
+    let mut build = cc::Build::new();
+    build
+        .target(TARGET)
+        .host(HOST)
+        .opt_level(0)
+        .debug(false)
+        .cargo_metadata(false);
+    build
+}
+
+/// Returns the value of the backend option `name`.
+///
+/// rustc has no way to pass options to codegen backends, so they are passed
+/// as `-C llvm-args=-<name>=<value>`, or in the environment variable
+/// `R2C2_<NAME>` (with dashes replaced by underscores). The former takes
+/// precedence, and is only available if there's a session.
+pub fn backend_option(sess: Option<&Session>, name: &str) -> Option<String> {
+    let prefix = format!("-{}=", name);
+    let arg = sess.and_then(|sess| {
+        sess.opts
+            .cg
+            .llvm_args
+            .iter()
+            .rev()
+            .find(|arg| arg.starts_with(&prefix))
+            .map(|arg| arg[prefix.len()..].to_string())
+    });
+    arg.or_else(|| {
+        let var = format!("R2C2_{}", name.to_uppercase().replace('-', "_"));
+        env::var(var).ok()
+    })
+}
+
+/// The identity of a C compiler, as reported by the compiler itself.
+#[derive(Debug, Clone)]
+pub struct CcVersion {
+    /// The compiler executable.
+    pub path: PathBuf,
+    /// The family named in the version output, if it's a known one.
+    pub family: Option<CompilerFamily>,
+    /// The major, minor and patch version, if they could be parsed.
+    pub version: Option<(u32, u32, u32)>,
+    /// The target triple the compiler compiles for, as reported by
+    /// `-dumpmachine` (which not all compilers support).
+    pub target: Option<String>,
+    /// The complete output of `--version`.
+    pub output: String,
+}
+
+impl CcVersion {
+    /// Returns whether the compiler is known to be a version of `family`
+    /// older than `major.minor`.
+    pub fn is_older_than(&self, family: CompilerFamily, major: u32, minor: u32) -> bool {
+        self.family == Some(family)
+            && self.version.map_or(false, |(v_major, v_minor, _)| {
+                (v_major, v_minor) < (major, minor)
+            })
+    }
+
+    /// Returns a string identifying the compiler, eg. to key caches with.
+    pub fn key(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.path.display(),
+            self.target.as_ref().map_or("", |target| &**target),
+            self.output.trim_end()
+        )
+    }
+}
+
+impl Display for CcVersion {
+    /// Formats a summary like `gcc 9.2.1 (/usr/bin/cc, x86_64-linux-gnu)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.family
+                .map_or("unknown compiler", |family| family.name())
+        )?;
+        if let Some((major, minor, patch)) = self.version {
+            write!(f, " {}.{}.{}", major, minor, patch)?;
+        }
+        write!(f, " ({}", self.path.display())?;
+        if let Some(ref target) = self.target {
+            write!(f, ", {}", target)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Compilers identified so far, by executable.
+static CC_VERSIONS: Lazy<Mutex<FxHashMap<PathBuf, CcVersion>>> = Lazy::new(Default::default);
+
+/// The executable of the compiler selected by the session's options.
+static SELECTED_CC: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(Default::default);
+
+/// Identifies the C compiler `compiler` by running it with `--version` and
+/// `-dumpmachine`.
+///
+/// Every executable is only run once per process.
 ///
 /// TODO: Upstream this to the `cc` crate
-pub fn cc_version() -> Result<String, String> {
-    // FIXME: cc::Error doesn't impl Display and Error
-    let mut compiler = cc::Build::new()
-        .try_get_compiler()
-        .map_err(|e| format!("{:?}", e))?
-        .to_command();
-    let output = compiler.arg("--version").output().err_to_string()?;
+pub fn cc_version(compiler: &Compiler) -> Result<CcVersion, String> {
+    tool_version(&compiler.tool()?)
+}
 
+/// Identifies the C compiler `tool`, like `cc_version`.
+pub fn tool_version(tool: &cc::Tool) -> Result<CcVersion, String> {
+    if let Some(version) = CC_VERSIONS.lock().unwrap().get(tool.path()) {
+        return Ok(version.clone());
+    }
+
+    let output = tool
+        .to_command()
+        .arg("--version")
+        .output()
+        .err_to_string()?;
     let mut out = String::from_utf8(output.stdout).err_to_string()?;
     out.push_str(str::from_utf8(&output.stderr).err_to_string()?);
+    if !output.status.success() {
+        return Err(out);
+    }
 
-    if output.status.success() {
-        Ok(out)
+    let first_line = out.lines().next().unwrap_or("");
+    let family = if first_line.contains("clang") {
+        Some(CompilerFamily::Clang)
+    } else if first_line.contains("tcc") {
+        Some(CompilerFamily::Tcc)
+    } else if first_line.contains("gcc") || out.contains("Free Software Foundation") {
+        Some(CompilerFamily::Gcc)
     } else {
-        Err(out)
-    }
+        None
+    };
+    let target = tool
+        .to_command()
+        .arg("-dumpmachine")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|target| target.trim().to_string())
+        .filter(|target| !target.is_empty());
+
+    let version = CcVersion {
+        path: tool.path().to_path_buf(),
+        family,
+        version: first_line.split_whitespace().find_map(parse_version),
+        target,
+        output: out,
+    };
+    CC_VERSIONS
+        .lock()
+        .unwrap()
+        .insert(version.path.clone(), version.clone());
+    Ok(version)
+}
+
+/// Parses a version number like `9.2.1` or `3.8`, ignoring anything after
+/// it (eg. `12.2.0-14`).
+fn parse_version(s: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = s.split('.').map(|part| {
+        let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        part[..digits].parse::<u32>().ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().and_then(|patch| patch).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Records that `version` identifies the C compiler selected by the
+/// session's options, for `identified_cc`.
+pub fn set_selected_cc(version: &CcVersion) {
+    *SELECTED_CC.lock().unwrap() = Some(version.path.clone());
+}
+
+/// Returns the C compiler selected by the session's options, if it has
+/// already been identified.
+///
+/// This never runs a compiler or blocks, so it can be used when reporting
+/// an ICE.
+pub fn identified_cc() -> Option<CcVersion> {
+    let selected = SELECTED_CC.try_lock().ok()?.clone()?;
+    let versions = CC_VERSIONS.try_lock().ok()?;
+    versions.get(&selected).cloned()
 }
 
 /// Trait for writing UTF-8 data to a sink.
@@ -62,7 +242,8 @@ pub struct StringWriter(pub String);
 
 impl WriteStr for StringWriter {
     fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
-        Ok(<String as fmt::Write>::write_fmt(&mut self.0, args).expect("writing to string failed"))
+        <String as fmt::Write>::write_fmt(&mut self.0, args).expect("writing to string failed");
+        Ok(())
     }
 }
 
@@ -85,3 +266,45 @@ impl AsRef<str> for StringWriter {
         &self.0
     }
 }
+
+/// A writer that counts the lines written through it.
+pub struct LineCounter<W> {
+    writer: W,
+    lines: usize,
+}
+
+impl<W> LineCounter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, lines: 0 }
+    }
+
+    /// Returns the number of complete lines written so far.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: WriteStr> WriteStr for LineCounter<W> {
+    fn write_fmt(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        self.write_str(&fmt::format(args))
+    }
+
+    fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.lines += s.matches('\n').count();
+        self.writer.write_str(s)
+    }
+}
+
+/// Gives read access to the underlying writer. Writes have to go through the
+/// `LineCounter`, so there's no `DerefMut`.
+impl<W> Deref for LineCounter<W> {
+    type Target = W;
+
+    fn deref(&self) -> &W {
+        &self.writer
+    }
+}
@@ -35,7 +35,7 @@ fn main() {
 
     info!("using C codegen backend at {}", backend_path.display());
 
-    let rustc = env::var_os("RUSTC").unwrap_or(OsString::from("rustc"));
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc"));
     info!("using rust compiler '{}'", rustc.to_string_lossy());
 
     // forward our args to rustc
@@ -0,0 +1,459 @@
+//! End-to-end tests: crates are compiled to object files with the C codegen
+//! backend (through the `r2c2` wrapper), and linked into C programs calling
+//! them. The answers to `--print` requests are checked as well.
+//!
+//! Most crates in `tests/crates` are `#![no_core]`, since the C backend can't
+//! build the standard library yet. The C programs exit with a non-zero
+//! status if a check fails. `std_program.rs` is a program linked against the
+//! prebuilt standard library instead.
+
+#[cfg(unix)]
+extern crate jobserver;
+extern crate tempfile;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// Returns the path of the `r2c2` wrapper, which Cargo builds into the parent
+/// of the directory containing the test executable.
+fn r2c2() -> PathBuf {
+    let mut path = env::current_exe().unwrap();
+    path.pop();
+    path.pop();
+    path.push("r2c2");
+    path
+}
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("crates")
+        .join(name)
+}
+
+/// Runs `cmd`, panicking with its output if it fails, and returns its
+/// standard output.
+fn run(cmd: &mut Command) -> String {
+    let output = cmd
+        .output()
+        .unwrap_or_else(|e| panic!("couldn't run {:?}: {}", cmd, e));
+    if !output.status.success() {
+        panic!(
+            "{:?} failed ({}):\n{}{}",
+            cmd,
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Compiles the crate `tests/crates/<name>.rs` to an object file in `dir`,
+/// with the C backend or with rustc's own LLVM backend.
+fn compile_crate(dir: &Path, name: &str, c_backend: bool) -> PathBuf {
+    compile_crate_with(dir, name, c_backend, &[])
+}
+
+/// Like `compile_crate`, passing `args` to rustc.
+fn compile_crate_with(dir: &Path, name: &str, c_backend: bool, args: &[&str]) -> PathBuf {
+    let obj = dir.join(format!("{}.o", name));
+    let mut cmd = if c_backend {
+        Command::new(r2c2())
+    } else {
+        Command::new(env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc")))
+    };
+    cmd.arg(fixture(&format!("{}.rs", name)))
+        .args(&["--crate-type=lib", "--emit=obj"])
+        .args(&["-C", "panic=abort", "-C", "codegen-units=1"])
+        .args(args)
+        .arg("-o")
+        .arg(&obj);
+    run(&mut cmd);
+    obj
+}
+
+/// Returns the C code the C backend generated in `dir`, which it keeps with
+/// `-C save-temps`.
+fn generated_c(dir: &Path) -> String {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "c"))
+        .map(|path| fs::read_to_string(path).unwrap())
+        .collect()
+}
+
+/// Links `objs` into the C program `tests/crates/<name>.c`, and runs it.
+fn link_and_run(dir: &Path, name: &str, objs: &[PathBuf]) {
+    link_and_run_with(dir, name, objs, &[])
+}
+
+/// Like `link_and_run`, passing `cflags` to the C compiler.
+fn link_and_run_with(dir: &Path, name: &str, objs: &[PathBuf], cflags: &[&str]) {
+    let exe = dir.join(name);
+    let cc = env::var_os("CC").unwrap_or_else(|| OsString::from("cc"));
+    run(Command::new(cc)
+        .args(cflags)
+        .arg(fixture(&format!("{}.c", name)))
+        .args(objs)
+        // float remainders are lowered to calls to `fmod`
+        .arg("-lm")
+        .arg("-o")
+        .arg(&exe));
+    run(&mut Command::new(&exe));
+}
+
+#[test]
+fn basics() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate(dir.path(), "basics", true);
+    link_and_run(dir.path(), "basics", &[obj]);
+}
+
+#[test]
+fn pair_return() {
+    let dir = TempDir::new().unwrap();
+    let objs = [
+        compile_crate(dir.path(), "pair_return", true),
+        compile_crate(dir.path(), "pair_return_llvm", false),
+    ];
+    link_and_run(dir.path(), "pair_return", &objs);
+}
+
+#[test]
+fn abi() {
+    let dir = TempDir::new().unwrap();
+    let objs = [
+        compile_crate(dir.path(), "abi", true),
+        compile_crate(dir.path(), "abi_llvm", false),
+    ];
+    link_and_run(dir.path(), "abi", &objs);
+}
+
+#[test]
+fn dyn_trait() {
+    let dir = TempDir::new().unwrap();
+    let objs = [
+        compile_crate(dir.path(), "dyn_trait", true),
+        compile_crate(dir.path(), "dyn_trait_llvm", false),
+    ];
+    link_and_run(dir.path(), "dyn_trait", &objs);
+}
+
+#[test]
+fn mir() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate(dir.path(), "mir", true);
+    link_and_run(dir.path(), "mir", &[obj]);
+}
+
+/// Optimized builds with several codegen units go through ThinLTO, which
+/// passes the C modules through.
+#[test]
+fn thin_local_lto() {
+    let dir = TempDir::new().unwrap();
+    // rustc uses a single codegen unit for `--emit=obj` with `-o`
+    run(Command::new(r2c2())
+        .arg(fixture("mir.rs"))
+        .args(&["--crate-type=lib", "--emit=obj"])
+        .args(&[
+            "-C",
+            "panic=abort",
+            "-C",
+            "opt-level=2",
+            "-C",
+            "codegen-units=4",
+        ])
+        .arg("--out-dir")
+        .arg(dir.path()));
+    link_and_run(dir.path(), "mir", &[dir.path().join("mir.o")]);
+}
+
+#[test]
+fn ops() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate(dir.path(), "ops", true);
+    link_and_run(dir.path(), "ops", &[obj]);
+}
+
+#[test]
+fn ops_without_overflow_checks() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate_with(dir.path(), "ops", true, &["-C", "overflow-checks=off"]);
+    link_and_run_with(dir.path(), "ops", &[obj], &["-DNO_OVERFLOW_CHECKS"]);
+}
+
+#[test]
+fn closures() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate(dir.path(), "closures", true);
+    link_and_run(dir.path(), "closures", &[obj]);
+}
+
+#[test]
+fn simd() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate_with(dir.path(), "simd", true, &["-C", "save-temps"]);
+    link_and_run(dir.path(), "simd", &[obj]);
+    assert!(generated_c(dir.path()).contains("vector_size"));
+}
+
+/// Without vector extensions, every SIMD operation is a loop over the lanes.
+#[test]
+fn simd_without_vector_extensions() {
+    let dir = TempDir::new().unwrap();
+    let args = ["-C", "llvm-args=-cc-family=c99", "-C", "save-temps"];
+    let obj = compile_crate_with(dir.path(), "simd", true, &args);
+    link_and_run(dir.path(), "simd", &[obj]);
+    assert!(!generated_c(dir.path()).contains("vector_size"));
+}
+
+/// `std_program.rs` is compiled into an executable linked against the
+/// prebuilt standard library, and run.
+#[test]
+fn std_program() {
+    let dir = TempDir::new().unwrap();
+    let exe = dir.path().join("std_program");
+    run(Command::new(r2c2())
+        .arg(fixture("std_program.rs"))
+        .args(&["-C", "panic=abort", "-o"])
+        .arg(&exe));
+    assert_eq!(run(&mut Command::new(&exe)), "one+three-[1, 4, 9]\n");
+
+    // Panics report their location in the Rust source
+    let output = Command::new(&exe)
+        .arg("x")
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("panicked at 'asked to panic with \"x\"', ")
+            && stderr.contains("std_program.rs:62:9"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn statics() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate(dir.path(), "statics", true);
+    link_and_run(dir.path(), "statics", &[obj]);
+}
+
+#[test]
+fn attributes() {
+    let dir = TempDir::new().unwrap();
+    let obj = compile_crate_with(dir.path(), "attributes", true, &["-C", "save-temps"]);
+    link_and_run(dir.path(), "attributes", &[obj]);
+
+    let c = generated_c(dir.path());
+    assert!(c.contains("__attribute__((cold, noinline)) uint32_t r2c2_cold("));
+    assert!(c.contains("__attribute__((always_inline)) static inline"));
+}
+
+/// With `-C debuginfo`, functions are attributed to the Rust source, and
+/// the rest of the generated C to the C file itself.
+#[test]
+fn line_directives() {
+    let dir = TempDir::new().unwrap();
+    let args = ["-C", "debuginfo=2", "-C", "save-temps"];
+    let obj = compile_crate_with(dir.path(), "basics", true, &args);
+    link_and_run(dir.path(), "basics", &[obj]);
+
+    let c_file = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().ends_with(".rcgu.c"))
+        .unwrap();
+    let c = fs::read_to_string(&c_file).unwrap();
+    assert!(c.contains("basics.rs\"\n"));
+    let c_line = format!("\"{}\"", c_file.display());
+    let mut c_lines = 0;
+    for (i, line) in c.lines().enumerate() {
+        if line.starts_with("#line ") && line.ends_with(&c_line) {
+            // Numbers the line after the directive
+            assert_eq!(line, format!("#line {} {}", i + 2, c_line));
+            c_lines += 1;
+        }
+    }
+    assert!(c_lines > 0, "{}", c);
+
+    // Diagnostics after the last function point into the C file
+    let mut c = c;
+    c.push_str("#error after the last function\n");
+    fs::write(&c_file, &c).unwrap();
+    let cc = env::var_os("CC").unwrap_or_else(|| OsString::from("cc"));
+    let output = Command::new(cc)
+        .arg("-fsyntax-only")
+        .arg(&c_file)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let location = format!("{}:{}:", c_file.display(), c.lines().count());
+    assert!(stderr.contains(&location), "{}", stderr);
+}
+
+/// The `c99` family has no extensions, so the generated code is ISO C.
+#[test]
+fn c99_family() {
+    let dir = TempDir::new().unwrap();
+    let args = ["-C", "llvm-args=-cc-family=c99", "-C", "save-temps"];
+    let obj = compile_crate_with(dir.path(), "basics", true, &args);
+    link_and_run(dir.path(), "basics", &[obj]);
+
+    let c = generated_c(dir.path());
+    assert!(!c.contains("__attribute__"));
+    assert!(!c.contains("__asm__"));
+
+    // Vtables are constant expressions in ISO C
+    let dir = TempDir::new().unwrap();
+    let objs = [
+        compile_crate_with(dir.path(), "dyn_trait", true, &args),
+        compile_crate(dir.path(), "dyn_trait_llvm", false),
+    ];
+    link_and_run(dir.path(), "dyn_trait", &objs);
+    let c = generated_c(dir.path());
+    assert!(c.contains("struct r2c2_vtable_"), "{}", c);
+    assert!(!c.contains("__attribute__"));
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn target_features() {
+    let dir = TempDir::new().unwrap();
+    let args = ["-C", "target-feature=+avx2", "-C", "save-temps"];
+    let obj = compile_crate_with(dir.path(), "target_features", true, &args);
+    link_and_run(dir.path(), "target_features", &[obj]);
+
+    let c = generated_c(dir.path());
+    assert!(c.contains("__attribute__((target(\"bmi2\"))) uint32_t r2c2_with_bmi2("));
+}
+
+#[test]
+fn print() {
+    let print = |request: &str| run(Command::new(r2c2()).args(&["--print", request]));
+
+    let tls_models = print("tls-models");
+    assert!(tls_models.starts_with("Available TLS models:"));
+    assert!(tls_models.contains("    initial-exec\n"));
+
+    let relocation_models = print("relocation-models");
+    assert!(relocation_models.contains("    default\n"));
+    assert!(print("code-models").contains("    default\n"));
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn print_target_features() {
+    let features = run(Command::new(r2c2()).args(&[
+        "--print",
+        "target-features",
+        "-C",
+        "target-feature=+avx2",
+    ]));
+    assert!(features.contains("    avx2 "));
+    let enabled = features
+        .lines()
+        .find(|line| line.starts_with("Enabled with the current options: "))
+        .unwrap();
+    assert!(enabled.split(", ").any(|feature| feature.ends_with("avx2")));
+}
+
+/// Configurations the C backend can't link against LLVM-compiled crates are
+/// rejected with an error instead of an ICE.
+#[test]
+fn unsupported_configurations() {
+    let dir = TempDir::new().unwrap();
+    let rejected = [
+        (&["--crate-type=dylib"][..], "can't compile `dylib` crates"),
+        (
+            &["--crate-type=staticlib", "-C", "lto"][..],
+            "doesn't support LTO",
+        ),
+    ];
+    for &(args, error) in &rejected {
+        let output = Command::new(r2c2())
+            .arg(fixture("basics.rs"))
+            .args(&["-C", "panic=abort"])
+            .args(args)
+            .arg("--out-dir")
+            .arg(dir.path())
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{:?} succeeded", args);
+        assert!(stderr.contains(error), "{:?}:\n{}", args, stderr);
+        assert!(!stderr.contains("panicked"), "{:?}:\n{}", args, stderr);
+    }
+}
+
+/// Compiles `cgus.rs` with 4 codegen units under the jobserver `client`,
+/// returning the `start` and `end` events of the compiles of the modules.
+#[cfg(unix)]
+fn compile_with_jobserver(dir: &Path, client: &jobserver::Client) -> Vec<String> {
+    let log = dir.join("cc.log");
+    // Logs when compiles of the crate's modules start and end
+    let wrapper = dir.join("cc-wrapper");
+    fs::write(
+        &wrapper,
+        format!(
+            "#!/bin/sh\n\
+             case \"$*\" in *rcgu.c*)\n\
+             echo start >> {log}; sleep 0.2; echo end >> {log};;\n\
+             esac\n\
+             exec {cc} \"$@\"\n",
+            log = log.display(),
+            cc = env::var("CC").unwrap_or_else(|_| "cc".to_string()),
+        ),
+    )
+    .unwrap();
+    run(Command::new("chmod").arg("+x").arg(&wrapper));
+
+    let mut cmd = Command::new(r2c2());
+    client.configure(&mut cmd);
+    cmd.arg(fixture("cgus.rs"))
+        .args(&["--crate-type=lib", "--emit=obj"])
+        .args(&["-C", "panic=abort", "-C", "codegen-units=4"])
+        .arg("--out-dir")
+        .arg(dir)
+        .env("CC", &wrapper);
+    run(&mut cmd);
+
+    let log = fs::read_to_string(&log).unwrap();
+    let events = log.lines().map(str::to_string).collect::<Vec<_>>();
+    assert_eq!(events.len(), 8, "{}", log);
+    events
+}
+
+/// Compiling codegen units with the C compiler takes a jobserver token each,
+/// so with no tokens to spare, they're compiled one after another.
+#[test]
+#[cfg(unix)]
+fn jobserver() {
+    let dir = TempDir::new().unwrap();
+    // Like `cargo build -j1`: rustc's implicit token is the only one
+    let client = jobserver::Client::new(0).unwrap();
+    let events = compile_with_jobserver(dir.path(), &client);
+    for compile in events.chunks(2) {
+        assert_eq!(compile, ["start", "end"], "{:?}", events);
+    }
+}
+
+/// With tokens to spare, modules are compiled in parallel.
+#[test]
+#[cfg(unix)]
+fn jobserver_with_spare_tokens() {
+    let dir = TempDir::new().unwrap();
+    let client = jobserver::Client::new(3).unwrap();
+    let events = compile_with_jobserver(dir.path(), &client);
+    let started = events.iter().take_while(|&event| event == "start").count();
+    assert!(started >= 2, "{:?}", events);
+}
@@ -0,0 +1,44 @@
+#include <stdint.h>
+#include <stdio.h>
+
+uint64_t r2c2_big_round_trip(uint64_t a, uint64_t b, uint64_t c, uint64_t d);
+uint16_t r2c2_small_round_trip(uint16_t a, uint16_t b);
+uint32_t r2c2_empty_round_trip(uint32_t x);
+uint64_t llvm_big_round_trip(uint64_t a, uint64_t b, uint64_t c, uint64_t d);
+uint16_t llvm_small_round_trip(uint16_t a, uint16_t b);
+uint32_t llvm_empty_round_trip(uint32_t x);
+uint64_t r2c2_array_round_trip(uint64_t a, uint64_t b, uint64_t c, uint64_t d);
+uint8_t r2c2_bytes_round_trip(uint8_t a, uint8_t b, uint8_t c, uint8_t d);
+uint64_t llvm_array_round_trip(uint64_t a, uint64_t b, uint64_t c, uint64_t d);
+uint8_t llvm_bytes_round_trip(uint8_t a, uint8_t b, uint8_t c, uint8_t d);
+uint32_t r2c2_wrap(uint32_t x);
+uint32_t r2c2_unwrap(uint32_t wrapper);
+int r2c2_level(_Bool high);
+
+static int failures = 0;
+
+static void check(const char *what, uint64_t actual, uint64_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %llu, expected %llu\n", what,
+                (unsigned long long) actual, (unsigned long long) expected);
+        failures++;
+    }
+}
+
+int main(void) {
+    check("r2c2_big_round_trip", r2c2_big_round_trip(1, 2, 3, 4), 3);
+    check("llvm_big_round_trip", llvm_big_round_trip(1, 2, 3, 4), 3);
+    check("r2c2_small_round_trip", r2c2_small_round_trip(5, 6), 6);
+    check("llvm_small_round_trip", llvm_small_round_trip(5, 6), 6);
+    check("r2c2_empty_round_trip", r2c2_empty_round_trip(7), 7);
+    check("llvm_empty_round_trip", llvm_empty_round_trip(7), 7);
+    check("r2c2_array_round_trip", r2c2_array_round_trip(1, 2, 3, 4), 3);
+    check("llvm_array_round_trip", llvm_array_round_trip(1, 2, 3, 4), 3);
+    check("r2c2_bytes_round_trip", r2c2_bytes_round_trip(5, 6, 7, 8), 6);
+    check("llvm_bytes_round_trip", llvm_bytes_round_trip(5, 6, 7, 8), 6);
+    check("r2c2_wrap", r2c2_wrap(8), 8);
+    check("r2c2_unwrap", r2c2_unwrap(9), 9);
+    check("r2c2_level(0)", r2c2_level(0), 1);
+    check("r2c2_level(1)", r2c2_level(1), 5);
+    return failures != 0;
+}
@@ -0,0 +1,155 @@
+//! Rust ABI calls between the C backend and LLVM: aggregates and arrays
+//! passed and returned indirectly or cast to integers, and ignored zero-sized
+//! arguments. The LLVM-compiled half is `abi_llvm.rs`, and every value makes
+//! a round trip through both backends. Aggregates with a scalar layout are
+//! also returned to C through `extern "C"` functions.
+
+#![feature(no_core, lang_items, optin_builtin_traits)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+impl Copy for bool {}
+impl Copy for u8 {}
+impl Copy for u16 {}
+impl Copy for u32 {}
+impl Copy for u64 {}
+
+/// Passed and returned by pointer.
+pub struct Big {
+    pub a: u64,
+    pub b: u64,
+    pub c: u64,
+    pub d: u64,
+}
+
+/// Passed and returned as an `i32`.
+pub struct Small {
+    pub a: u16,
+    pub b: u16,
+}
+
+pub struct Empty;
+
+/// Returned as a `uint32_t`.
+#[repr(transparent)]
+pub struct Wrapper(pub u32);
+
+/// Returned as an `int`.
+#[repr(C)]
+pub enum Level {
+    Low = 1,
+    High = 5,
+}
+
+extern "Rust" {
+    fn llvm_make_big(a: u64, b: u64, c: u64, d: u64) -> Big;
+    fn llvm_big_third(big: Big) -> u64;
+    fn llvm_make_small(a: u16, b: u16) -> Small;
+    fn llvm_small_second(small: Small) -> u16;
+    fn llvm_skip_empty(a: Empty, x: u32, b: Empty) -> u32;
+    fn llvm_make_array(a: u64, b: u64, c: u64, d: u64) -> [u64; 4];
+    fn llvm_array_third(array: [u64; 4]) -> u64;
+    fn llvm_make_bytes(a: u8, b: u8, c: u8, d: u8) -> [u8; 4];
+    fn llvm_bytes_second(bytes: [u8; 4]) -> u8;
+}
+
+#[no_mangle]
+pub fn r2c2_make_big(a: u64, b: u64, c: u64, d: u64) -> Big {
+    Big { a, b, c, d }
+}
+
+#[no_mangle]
+pub fn r2c2_big_third(big: Big) -> u64 {
+    big.c
+}
+
+#[no_mangle]
+pub fn r2c2_make_small(a: u16, b: u16) -> Small {
+    Small { a, b }
+}
+
+#[no_mangle]
+pub fn r2c2_small_second(small: Small) -> u16 {
+    small.b
+}
+
+#[no_mangle]
+pub fn r2c2_skip_empty(_: Empty, x: u32, _: Empty) -> u32 {
+    x
+}
+
+/// Passed and returned by pointer.
+#[no_mangle]
+pub fn r2c2_make_array(a: u64, b: u64, c: u64, d: u64) -> [u64; 4] {
+    [a, b, c, d]
+}
+
+#[no_mangle]
+pub fn r2c2_array_third(array: [u64; 4]) -> u64 {
+    let [_, _, c, _] = array;
+    c
+}
+
+/// Passed and returned as an `i32`.
+#[no_mangle]
+pub fn r2c2_make_bytes(a: u8, b: u8, c: u8, d: u8) -> [u8; 4] {
+    [a, b, c, d]
+}
+
+#[no_mangle]
+pub fn r2c2_bytes_second(bytes: [u8; 4]) -> u8 {
+    let [_, b, _, _] = bytes;
+    b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_big_round_trip(a: u64, b: u64, c: u64, d: u64) -> u64 {
+    unsafe { llvm_big_third(llvm_make_big(a, b, c, d)) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_small_round_trip(a: u16, b: u16) -> u16 {
+    unsafe { llvm_small_second(llvm_make_small(a, b)) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_empty_round_trip(x: u32) -> u32 {
+    unsafe { llvm_skip_empty(Empty, x, Empty) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_array_round_trip(a: u64, b: u64, c: u64, d: u64) -> u64 {
+    unsafe { llvm_array_third(llvm_make_array(a, b, c, d)) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_bytes_round_trip(a: u8, b: u8, c: u8, d: u8) -> u8 {
+    unsafe { llvm_bytes_second(llvm_make_bytes(a, b, c, d)) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_wrap(x: u32) -> Wrapper {
+    Wrapper(x)
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_unwrap(wrapper: Wrapper) -> u32 {
+    wrapper.0
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_level(high: bool) -> Level {
+    if high {
+        Level::High
+    } else {
+        Level::Low
+    }
+}
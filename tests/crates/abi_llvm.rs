@@ -0,0 +1,116 @@
+//! The LLVM-compiled half of the `abi` test (see `abi.rs`).
+
+#![feature(no_core, lang_items, optin_builtin_traits)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+impl Copy for u8 {}
+impl Copy for u16 {}
+impl Copy for u32 {}
+impl Copy for u64 {}
+
+pub struct Big {
+    pub a: u64,
+    pub b: u64,
+    pub c: u64,
+    pub d: u64,
+}
+
+pub struct Small {
+    pub a: u16,
+    pub b: u16,
+}
+
+pub struct Empty;
+
+extern "Rust" {
+    fn r2c2_make_big(a: u64, b: u64, c: u64, d: u64) -> Big;
+    fn r2c2_big_third(big: Big) -> u64;
+    fn r2c2_make_small(a: u16, b: u16) -> Small;
+    fn r2c2_small_second(small: Small) -> u16;
+    fn r2c2_skip_empty(a: Empty, x: u32, b: Empty) -> u32;
+    fn r2c2_make_array(a: u64, b: u64, c: u64, d: u64) -> [u64; 4];
+    fn r2c2_array_third(array: [u64; 4]) -> u64;
+    fn r2c2_make_bytes(a: u8, b: u8, c: u8, d: u8) -> [u8; 4];
+    fn r2c2_bytes_second(bytes: [u8; 4]) -> u8;
+}
+
+#[no_mangle]
+pub fn llvm_make_big(a: u64, b: u64, c: u64, d: u64) -> Big {
+    Big { a, b, c, d }
+}
+
+#[no_mangle]
+pub fn llvm_big_third(big: Big) -> u64 {
+    big.c
+}
+
+#[no_mangle]
+pub fn llvm_make_small(a: u16, b: u16) -> Small {
+    Small { a, b }
+}
+
+#[no_mangle]
+pub fn llvm_small_second(small: Small) -> u16 {
+    small.b
+}
+
+#[no_mangle]
+pub fn llvm_skip_empty(_: Empty, x: u32, _: Empty) -> u32 {
+    x
+}
+
+#[no_mangle]
+pub fn llvm_make_array(a: u64, b: u64, c: u64, d: u64) -> [u64; 4] {
+    [a, b, c, d]
+}
+
+#[no_mangle]
+pub fn llvm_array_third(array: [u64; 4]) -> u64 {
+    let [_, _, c, _] = array;
+    c
+}
+
+#[no_mangle]
+pub fn llvm_make_bytes(a: u8, b: u8, c: u8, d: u8) -> [u8; 4] {
+    [a, b, c, d]
+}
+
+#[no_mangle]
+pub fn llvm_bytes_second(bytes: [u8; 4]) -> u8 {
+    let [_, b, _, _] = bytes;
+    b
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_big_round_trip(a: u64, b: u64, c: u64, d: u64) -> u64 {
+    unsafe { r2c2_big_third(r2c2_make_big(a, b, c, d)) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_small_round_trip(a: u16, b: u16) -> u16 {
+    unsafe { r2c2_small_second(r2c2_make_small(a, b)) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_empty_round_trip(x: u32) -> u32 {
+    unsafe { r2c2_skip_empty(Empty, x, Empty) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_array_round_trip(a: u64, b: u64, c: u64, d: u64) -> u64 {
+    unsafe { r2c2_array_third(r2c2_make_array(a, b, c, d)) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_bytes_round_trip(a: u8, b: u8, c: u8, d: u8) -> u8 {
+    unsafe { r2c2_bytes_second(r2c2_make_bytes(a, b, c, d)) }
+}
@@ -0,0 +1,31 @@
+#include <stdint.h>
+#include <stdio.h>
+
+/* Defined by the linker for sections named like C identifiers */
+extern const char __start_r2c2_section[];
+extern const char __stop_r2c2_section[];
+
+uint32_t r2c2_in_section(uint32_t x);
+uint32_t r2c2_exported(uint32_t x);
+uint32_t r2c2_cold(uint32_t x);
+uint32_t r2c2_call_always(uint32_t x);
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    if (!ok) {
+        fprintf(stderr, "%s failed\n", what);
+        failures++;
+    }
+}
+
+int main(void) {
+    const char *in_section = (const char *) &r2c2_in_section;
+    check("r2c2_in_section is in r2c2_section",
+          in_section >= __start_r2c2_section && in_section < __stop_r2c2_section);
+    check("r2c2_in_section", r2c2_in_section(1) == 1);
+    check("r2c2_exported", r2c2_exported(2) == 2);
+    check("r2c2_cold", r2c2_cold(3) == 3);
+    check("r2c2_call_always", r2c2_call_always(4) == 4);
+    return failures != 0;
+}
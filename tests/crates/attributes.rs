@@ -0,0 +1,41 @@
+//! Function attributes: `#[link_section]` and `#[export_name]` are checked
+//! by the C program, and the optimization hints in the generated C code.
+
+#![feature(no_core, lang_items)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+impl Copy for u32 {}
+
+#[no_mangle]
+#[link_section = "r2c2_section"]
+pub extern "C" fn r2c2_in_section(x: u32) -> u32 {
+    x
+}
+
+#[export_name = "r2c2_exported"]
+pub extern "C" fn exported(x: u32) -> u32 {
+    x
+}
+
+#[no_mangle]
+#[cold]
+#[inline(never)]
+pub extern "C" fn r2c2_cold(x: u32) -> u32 {
+    x
+}
+
+#[inline(always)]
+fn always(x: u32) -> u32 {
+    r2c2_cold(x)
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_call_always(x: u32) -> u32 {
+    always(x)
+}
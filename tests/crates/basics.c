@@ -0,0 +1,19 @@
+#include <stdbool.h>
+#include <stdint.h>
+
+uint32_t r2c2_second(uint32_t a, uint32_t b);
+uint32_t r2c2_select(bool cond, uint32_t a, uint32_t b);
+uint32_t r2c2_unwrap_or(bool some, uint32_t x, uint32_t d);
+
+int main(void) {
+    if (r2c2_second(1, 2) != 2) {
+        return 1;
+    }
+    if (r2c2_select(true, 3, 4) != 3 || r2c2_select(false, 3, 4) != 4) {
+        return 2;
+    }
+    if (r2c2_unwrap_or(true, 5, 6) != 5 || r2c2_unwrap_or(false, 5, 6) != 6) {
+        return 3;
+    }
+    return 0;
+}
@@ -0,0 +1,53 @@
+//! Functions lowered without any support from `core`: moves, aggregates,
+//! references, branches, enums and calls to generic functions.
+
+#![feature(no_core, lang_items)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+impl Copy for u32 {}
+impl Copy for bool {}
+
+pub struct Pair {
+    pub a: u32,
+    pub b: u32,
+}
+
+pub enum Opt {
+    None,
+    Some(u32),
+}
+
+fn identity<T>(x: T) -> T {
+    x
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_second(a: u32, b: u32) -> u32 {
+    let pair = Pair { a, b };
+    let r = &pair;
+    identity(r.b)
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_select(cond: bool, a: u32, b: u32) -> u32 {
+    if cond {
+        a
+    } else {
+        b
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_unwrap_or(some: bool, x: u32, default: u32) -> u32 {
+    let opt = if some { Opt::Some(x) } else { Opt::None };
+    match opt {
+        Opt::Some(x) => x,
+        Opt::None => default,
+    }
+}
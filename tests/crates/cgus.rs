@@ -0,0 +1,28 @@
+//! A crate split into several codegen units, one per module, for the
+//! `jobserver` test.
+
+#![feature(no_core, lang_items)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+pub mod a {
+    #[no_mangle]
+    pub extern "C" fn r2c2_cgu_a() {}
+}
+
+pub mod b {
+    #[no_mangle]
+    pub extern "C" fn r2c2_cgu_b() {}
+}
+
+pub mod c {
+    #[no_mangle]
+    pub extern "C" fn r2c2_cgu_c() {}
+}
+
+pub mod d {
+    #[no_mangle]
+    pub extern "C" fn r2c2_cgu_d() {}
+}
@@ -0,0 +1,39 @@
+#include <stdint.h>
+#include <stdio.h>
+
+struct yields {
+    uint32_t first;
+    uint32_t second;
+    uint32_t third;
+};
+
+uint32_t r2c2_apply(uint32_t offset, uint32_t x);
+uint32_t r2c2_accumulate(uint32_t start);
+uint32_t r2c2_consume(uint32_t a, uint32_t b);
+uint32_t r2c2_fn_ptr(uint32_t x);
+uint32_t r2c2_generator(uint32_t start, uint32_t step, struct yields *out);
+
+static int failures = 0;
+
+static void check(const char *what, uint32_t actual, uint32_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %u, expected %u\n", what, actual, expected);
+        failures++;
+    }
+}
+
+int main(void) {
+    struct yields yields = {0, 0, 0};
+
+    check("apply", r2c2_apply(10, 5), 15);
+    check("accumulate", r2c2_accumulate(3), 312);
+    check("consume", r2c2_consume(6, 7), 42);
+    check("fn_ptr", r2c2_fn_ptr(4), 12);
+
+    check("generator", r2c2_generator(5, 3, &yields), 22);
+    check("yield 1", yields.first, 5);
+    check("yield 2", yields.second, 8);
+    check("yield 3", yields.third, 11);
+
+    return failures != 0;
+}
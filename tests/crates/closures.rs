@@ -0,0 +1,226 @@
+//! Closures and generators: capturing by reference and by value, calls
+//! through the `Fn*` traits and through function pointers, and a generator
+//! resumed until it completes, with state kept across `yield`s.
+
+#![feature(
+    no_core,
+    lang_items,
+    optin_builtin_traits,
+    unboxed_closures,
+    generators,
+    untagged_unions
+)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+#[lang = "receiver"]
+trait Receiver {}
+
+impl<'a, T: ?Sized> Receiver for &'a T {}
+impl<'a, T: ?Sized> Receiver for &'a mut T {}
+impl<P: Receiver> Receiver for Pin<P> {}
+
+#[lang = "drop_in_place"]
+#[allow(unconditional_recursion)]
+unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
+    drop_in_place(to_drop)
+}
+
+impl Copy for u32 {}
+impl<'a, T: ?Sized> Copy for &'a T {}
+
+#[lang = "fn_once"]
+#[rustc_paren_sugar]
+trait FnOnce<Args> {
+    type Output;
+    extern "rust-call" fn call_once(self, args: Args) -> Self::Output;
+}
+
+#[lang = "fn_mut"]
+#[rustc_paren_sugar]
+trait FnMut<Args>: FnOnce<Args> {
+    extern "rust-call" fn call_mut(&mut self, args: Args) -> Self::Output;
+}
+
+#[lang = "fn"]
+#[rustc_paren_sugar]
+trait Fn<Args>: FnMut<Args> {
+    extern "rust-call" fn call(&self, args: Args) -> Self::Output;
+}
+
+#[lang = "pin"]
+#[repr(transparent)]
+struct Pin<P> {
+    pointer: P,
+}
+
+#[lang = "deref"]
+trait Deref {
+    type Target: ?Sized;
+    fn deref(&self) -> &Self::Target;
+}
+
+impl<'a, T: ?Sized> Deref for &'a mut T {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &**self
+    }
+}
+
+impl<P: Deref> Deref for Pin<P> {
+    type Target = P::Target;
+    fn deref(&self) -> &P::Target {
+        &*self.pointer
+    }
+}
+
+// Generators keep their saved locals in `MaybeUninit`s
+#[lang = "manually_drop"]
+#[allow(dead_code)]
+#[repr(transparent)]
+struct ManuallyDrop<T: ?Sized> {
+    value: T,
+}
+
+#[lang = "maybe_uninit"]
+#[allow(dead_code)]
+union MaybeUninit<T> {
+    uninit: (),
+    value: ManuallyDrop<T>,
+}
+
+#[lang = "generator_state"]
+enum GeneratorState<Y, R> {
+    Yielded(Y),
+    Complete(R),
+}
+
+#[lang = "generator"]
+trait Generator {
+    type Yield;
+    type Return;
+    fn resume(self: Pin<&mut Self>) -> GeneratorState<Self::Yield, Self::Return>;
+}
+
+#[lang = "panic_location"]
+struct Location<'a> {
+    _file: &'a str,
+    _line: u32,
+    _col: u32,
+}
+
+#[lang = "panic"]
+fn panic(_expr: &str, _location: &Location) -> ! {
+    loop {}
+}
+
+#[lang = "add"]
+trait Add<Rhs = Self> {
+    type Output;
+    fn add(self, rhs: Rhs) -> Self::Output;
+}
+
+impl Add for u32 {
+    type Output = u32;
+    fn add(self, rhs: u32) -> u32 {
+        self + rhs
+    }
+}
+
+#[lang = "mul"]
+trait Mul<Rhs = Self> {
+    type Output;
+    fn mul(self, rhs: Rhs) -> Self::Output;
+}
+
+impl Mul for u32 {
+    type Output = u32;
+    fn mul(self, rhs: u32) -> u32 {
+        self * rhs
+    }
+}
+
+fn apply<F: Fn(u32) -> u32>(f: F, x: u32) -> u32 {
+    f(x)
+}
+
+fn apply_twice<F: FnMut(u32)>(mut f: F) {
+    f(1);
+    f(2);
+}
+
+fn consume<F: FnOnce() -> u32>(f: F) -> u32 {
+    f()
+}
+
+/// Calls a closure capturing `offset` by reference through `Fn`.
+#[no_mangle]
+pub extern "C" fn r2c2_apply(offset: u32, x: u32) -> u32 {
+    apply(|y| y + offset, x)
+}
+
+/// Calls a closure mutating `total` through `FnMut`.
+#[no_mangle]
+pub extern "C" fn r2c2_accumulate(start: u32) -> u32 {
+    let mut total = start;
+    apply_twice(|y| total = total * 10 + y);
+    total
+}
+
+/// Calls a closure owning its captures through `FnOnce`.
+#[no_mangle]
+pub extern "C" fn r2c2_consume(a: u32, b: u32) -> u32 {
+    consume(move || a * b)
+}
+
+/// Calls a non-capturing closure through a function pointer.
+#[no_mangle]
+pub extern "C" fn r2c2_fn_ptr(x: u32) -> u32 {
+    let f: fn(u32) -> u32 = |y| y * 3;
+    f(x)
+}
+
+#[repr(C)]
+pub struct Yields {
+    first: u32,
+    second: u32,
+    third: u32,
+}
+
+/// Resumes a generator yielding `start`, `start + step`, ... three times,
+/// writing the yielded values to `out` and returning its return value.
+#[no_mangle]
+pub extern "C" fn r2c2_generator(start: u32, step: u32, out: &mut Yields) -> u32 {
+    let mut gen = move || {
+        let mut value = start;
+        yield value;
+        value = value + step;
+        yield value;
+        value = value + step;
+        yield value;
+        value * 2
+    };
+    let mut count = 0;
+    loop {
+        let pinned = Pin { pointer: &mut gen };
+        match pinned.resume() {
+            GeneratorState::Yielded(value) => {
+                match count {
+                    0 => out.first = value,
+                    1 => out.second = value,
+                    _ => out.third = value,
+                }
+                count = count + 1;
+            }
+            GeneratorState::Complete(value) => return value,
+        }
+    }
+}
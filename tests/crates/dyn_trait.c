@@ -0,0 +1,33 @@
+#include <stdint.h>
+#include <stdio.h>
+
+uint32_t r2c2_id_round_trip(uint32_t x);
+size_t r2c2_size_round_trip(void);
+size_t r2c2_align_round_trip(void);
+_Bool r2c2_drop_round_trip(void);
+uint32_t llvm_id_round_trip(uint32_t x);
+size_t llvm_size_round_trip(void);
+size_t llvm_align_round_trip(void);
+_Bool llvm_drop_round_trip(void);
+
+static int failures = 0;
+
+static void check(const char *what, uint64_t actual, uint64_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %llu, expected %llu\n", what,
+                (unsigned long long) actual, (unsigned long long) expected);
+        failures++;
+    }
+}
+
+int main(void) {
+    check("r2c2_id_round_trip", r2c2_id_round_trip(7), 7);
+    check("llvm_id_round_trip", llvm_id_round_trip(7), 7);
+    check("r2c2_size_round_trip", r2c2_size_round_trip(), 8);
+    check("llvm_size_round_trip", llvm_size_round_trip(), 8);
+    check("r2c2_align_round_trip", r2c2_align_round_trip(), 4);
+    check("llvm_align_round_trip", llvm_align_round_trip(), 4);
+    check("r2c2_drop_round_trip", r2c2_drop_round_trip(), 1);
+    check("llvm_drop_round_trip", llvm_drop_round_trip(), 1);
+    return failures != 0;
+}
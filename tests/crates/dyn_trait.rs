@@ -0,0 +1,169 @@
+//! Trait objects across the C backend and LLVM: method calls through
+//! vtables, `size_of_val` and `min_align_of_val` of trait objects, and drop
+//! glue run through a vtable. The LLVM-compiled half is `dyn_trait_llvm.rs`,
+//! and every vtable built by one backend is used by the other.
+//!
+//! The methods are `#[inline]`, so that they have internal linkage and the
+//! crate can be compiled by compilers without assembler labels.
+
+#![feature(no_core, lang_items, optin_builtin_traits, intrinsics)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "receiver"]
+trait Receiver {}
+
+impl<'a, T: ?Sized> Receiver for &'a T {}
+impl<'a, T: ?Sized> Receiver for &'a mut T {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+#[lang = "unsize"]
+trait Unsize<T: ?Sized> {}
+
+#[lang = "coerce_unsized"]
+trait CoerceUnsized<T> {}
+
+impl<'a, 'b: 'a, T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<&'a U> for &'b T {}
+impl<'a, T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<&'a mut U> for &'a mut T {}
+
+#[lang = "dispatch_from_dyn"]
+trait DispatchFromDyn<T> {}
+
+impl<'a, T: ?Sized + Unsize<U>, U: ?Sized> DispatchFromDyn<&'a U> for &'a T {}
+
+#[lang = "drop"]
+trait Drop {
+    fn drop(&mut self);
+}
+
+#[lang = "drop_in_place"]
+#[allow(unconditional_recursion)]
+unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
+    drop_in_place(to_drop)
+}
+
+#[lang = "manually_drop"]
+#[repr(transparent)]
+struct ManuallyDrop<T: ?Sized> {
+    value: T,
+}
+
+extern "rust-intrinsic" {
+    fn size_of_val<T: ?Sized>(value: &T) -> usize;
+    fn min_align_of_val<T: ?Sized>(value: &T) -> usize;
+}
+
+impl Copy for bool {}
+impl Copy for u8 {}
+impl Copy for u32 {}
+impl Copy for usize {}
+impl<T: ?Sized> Copy for *mut T {}
+
+pub trait Shape {
+    fn id(&self) -> u32;
+}
+
+/// 4 bytes, aligned to 4.
+pub struct Small(pub u32);
+
+/// 8 bytes, aligned to 4.
+pub struct Padded {
+    pub a: u32,
+    pub b: u8,
+}
+
+/// Sets `*dropped` when dropped.
+pub struct Dropped {
+    pub dropped: *mut bool,
+}
+
+impl Shape for Small {
+    #[inline]
+    fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Shape for Padded {
+    #[inline]
+    fn id(&self) -> u32 {
+        self.b as u32
+    }
+}
+
+impl Shape for Dropped {
+    #[inline]
+    fn id(&self) -> u32 {
+        0
+    }
+}
+
+impl Drop for Dropped {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { *self.dropped = true }
+    }
+}
+
+extern "Rust" {
+    fn llvm_id(shape: &dyn Shape) -> u32;
+    fn llvm_size(shape: &dyn Shape) -> usize;
+    fn llvm_align(shape: &dyn Shape) -> usize;
+    fn llvm_drop(shape: &mut dyn Shape);
+}
+
+#[no_mangle]
+pub fn r2c2_id(shape: &dyn Shape) -> u32 {
+    shape.id()
+}
+
+#[no_mangle]
+pub fn r2c2_size(shape: &dyn Shape) -> usize {
+    unsafe { size_of_val(shape) }
+}
+
+#[no_mangle]
+pub fn r2c2_align(shape: &dyn Shape) -> usize {
+    unsafe { min_align_of_val(shape) }
+}
+
+#[no_mangle]
+pub fn r2c2_drop(shape: &mut dyn Shape) {
+    unsafe { drop_in_place(shape) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_id_round_trip(x: u32) -> u32 {
+    unsafe { llvm_id(&Small(x)) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_size_round_trip() -> usize {
+    unsafe { llvm_size(&Padded { a: 1, b: 2 }) }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_align_round_trip() -> usize {
+    unsafe { llvm_align(&Padded { a: 1, b: 2 }) }
+}
+
+/// Drops a value through its vtable, which must run its `Drop` impl exactly
+/// once: the value is in a `ManuallyDrop`, so it isn't dropped again.
+#[no_mangle]
+pub extern "C" fn r2c2_drop_round_trip() -> bool {
+    let mut dropped = false;
+    let mut value = ManuallyDrop {
+        value: Dropped {
+            dropped: &mut dropped,
+        },
+    };
+    unsafe { llvm_drop(&mut value.value) };
+    dropped
+}
@@ -0,0 +1,154 @@
+//! The LLVM-compiled half of the `dyn_trait` test (see `dyn_trait.rs`).
+
+#![feature(no_core, lang_items, optin_builtin_traits, intrinsics)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "receiver"]
+trait Receiver {}
+
+impl<'a, T: ?Sized> Receiver for &'a T {}
+impl<'a, T: ?Sized> Receiver for &'a mut T {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+#[lang = "unsize"]
+trait Unsize<T: ?Sized> {}
+
+#[lang = "coerce_unsized"]
+trait CoerceUnsized<T> {}
+
+impl<'a, 'b: 'a, T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<&'a U> for &'b T {}
+impl<'a, T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<&'a mut U> for &'a mut T {}
+
+#[lang = "dispatch_from_dyn"]
+trait DispatchFromDyn<T> {}
+
+impl<'a, T: ?Sized + Unsize<U>, U: ?Sized> DispatchFromDyn<&'a U> for &'a T {}
+
+#[lang = "drop"]
+trait Drop {
+    fn drop(&mut self);
+}
+
+#[lang = "drop_in_place"]
+#[allow(unconditional_recursion)]
+unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
+    drop_in_place(to_drop)
+}
+
+#[lang = "manually_drop"]
+#[repr(transparent)]
+struct ManuallyDrop<T: ?Sized> {
+    value: T,
+}
+
+extern "rust-intrinsic" {
+    fn size_of_val<T: ?Sized>(value: &T) -> usize;
+    fn min_align_of_val<T: ?Sized>(value: &T) -> usize;
+}
+
+impl Copy for bool {}
+impl Copy for u8 {}
+impl Copy for u32 {}
+impl Copy for usize {}
+impl<T: ?Sized> Copy for *mut T {}
+
+pub trait Shape {
+    fn id(&self) -> u32;
+}
+
+pub struct Small(pub u32);
+
+pub struct Padded {
+    pub a: u32,
+    pub b: u8,
+}
+
+pub struct Dropped {
+    pub dropped: *mut bool,
+}
+
+impl Shape for Small {
+    fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Shape for Padded {
+    fn id(&self) -> u32 {
+        self.b as u32
+    }
+}
+
+impl Shape for Dropped {
+    fn id(&self) -> u32 {
+        0
+    }
+}
+
+impl Drop for Dropped {
+    fn drop(&mut self) {
+        unsafe { *self.dropped = true }
+    }
+}
+
+extern "Rust" {
+    fn r2c2_id(shape: &dyn Shape) -> u32;
+    fn r2c2_size(shape: &dyn Shape) -> usize;
+    fn r2c2_align(shape: &dyn Shape) -> usize;
+    fn r2c2_drop(shape: &mut dyn Shape);
+}
+
+#[no_mangle]
+pub fn llvm_id(shape: &dyn Shape) -> u32 {
+    shape.id()
+}
+
+#[no_mangle]
+pub fn llvm_size(shape: &dyn Shape) -> usize {
+    unsafe { size_of_val(shape) }
+}
+
+#[no_mangle]
+pub fn llvm_align(shape: &dyn Shape) -> usize {
+    unsafe { min_align_of_val(shape) }
+}
+
+#[no_mangle]
+pub fn llvm_drop(shape: &mut dyn Shape) {
+    unsafe { drop_in_place(shape) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_id_round_trip(x: u32) -> u32 {
+    unsafe { r2c2_id(&Small(x)) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_size_round_trip() -> usize {
+    unsafe { r2c2_size(&Padded { a: 1, b: 2 }) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_align_round_trip() -> usize {
+    unsafe { r2c2_align(&Padded { a: 1, b: 2 }) }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_drop_round_trip() -> bool {
+    let mut dropped = false;
+    let mut value = ManuallyDrop {
+        value: Dropped {
+            dropped: &mut dropped,
+        },
+    };
+    unsafe { r2c2_drop(&mut value.value) };
+    dropped
+}
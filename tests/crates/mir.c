@@ -0,0 +1,57 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+uint32_t r2c2_classify(uint32_t x);
+uint32_t r2c2_last(uint32_t a, uint32_t b, uint32_t c);
+uint32_t r2c2_swapped_first(uint32_t a, uint32_t b);
+uint32_t r2c2_call_fn_ptr(uint32_t a, uint32_t b);
+uint32_t r2c2_packed_b(const uint8_t *packed);
+void r2c2_drop_order(uint32_t *log);
+void r2c2_drop_fields(uint32_t *log);
+uint32_t r2c2_drop_moved(uint32_t *log, bool keep);
+uint32_t r2c2_drop_replaced(uint32_t *log);
+
+static int failures = 0;
+
+static void check(const char *what, uint32_t actual, uint32_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %u, expected %u\n", what, actual, expected);
+        failures++;
+    }
+}
+
+int main(void) {
+    uint32_t log;
+    uint32_t b = 0x12345678;
+    uint8_t packed[5] = {1};
+
+    memcpy(&packed[1], &b, sizeof(b));
+
+    check("classify(0)", r2c2_classify(0), 10);
+    check("classify(1)", r2c2_classify(1), 20);
+    check("classify(7)", r2c2_classify(7), 70);
+    check("classify(3)", r2c2_classify(3), 99);
+    check("last", r2c2_last(1, 2, 3), 3);
+    check("swapped_first", r2c2_swapped_first(4, 5), 5);
+    check("call_fn_ptr", r2c2_call_fn_ptr(4, 5), 5);
+    check("packed_b", r2c2_packed_b(packed), 0x12345678);
+
+    log = 0;
+    r2c2_drop_order(&log);
+    check("drop_order", log, 1);
+    log = 0;
+    r2c2_drop_fields(&log);
+    check("drop_fields", log, 2);
+    log = 0;
+    check("drop_moved(true)", r2c2_drop_moved(&log, true), 3);
+    check("drop_moved(true) log", log, 3);
+    log = 0;
+    check("drop_moved(false)", r2c2_drop_moved(&log, false), 0);
+    check("drop_moved(false) log", log, 3);
+    log = 0;
+    check("drop_replaced", r2c2_drop_replaced(&log), 5);
+    check("drop_replaced log", log, 7);
+    return failures != 0;
+}
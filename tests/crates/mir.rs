@@ -0,0 +1,179 @@
+//! MIR constructs beyond `basics`: integer switches, loops, in-place
+//! mutation through references and raw pointers, function pointers, packed
+//! structs, and drop glue.
+
+#![feature(no_core, lang_items, optin_builtin_traits)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+#[lang = "receiver"]
+trait Receiver {}
+
+impl<'a, T: ?Sized> Receiver for &'a mut T {}
+
+#[lang = "drop"]
+trait Drop {
+    fn drop(&mut self);
+}
+
+#[lang = "drop_in_place"]
+#[allow(unconditional_recursion)]
+unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
+    drop_in_place(to_drop)
+}
+
+impl Copy for u32 {}
+impl Copy for bool {}
+impl<'a, T: ?Sized> Copy for &'a T {}
+impl<T: ?Sized> Copy for *mut T {}
+
+enum Opt<T> {
+    None,
+    Some(T),
+}
+
+struct Node<'a> {
+    value: u32,
+    next: Opt<&'a Node<'a>>,
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_classify(x: u32) -> u32 {
+    match x {
+        0 => 10,
+        1 => 20,
+        7 => 70,
+        _ => 99,
+    }
+}
+
+/// Walks to the end of a linked list, with `Opt<&Node>` using the null
+/// pointer niche.
+#[no_mangle]
+pub extern "C" fn r2c2_last(a: u32, b: u32, c: u32) -> u32 {
+    let third = Node {
+        value: c,
+        next: Opt::None,
+    };
+    let second = Node {
+        value: b,
+        next: Opt::Some(&third),
+    };
+    let mut node = &Node {
+        value: a,
+        next: Opt::Some(&second),
+    };
+    loop {
+        match node.next {
+            Opt::Some(next) => node = next,
+            Opt::None => return node.value,
+        }
+    }
+}
+
+fn swap(pair: &mut (u32, u32)) {
+    let first = pair.0;
+    pair.0 = pair.1;
+    pair.1 = first;
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_swapped_first(a: u32, b: u32) -> u32 {
+    let mut pair = (a, b);
+    swap(&mut pair);
+    pair.0
+}
+
+/// Reads a field through a reference, so that the C type of `Pair` has to be
+/// defined without a local of that type. Only compiled, not called.
+#[no_mangle]
+pub extern "C" fn r2c2_second_id(pair: &Pair) -> u32 {
+    pair.second.id
+}
+
+/// Has no padding, so `b` is unaligned.
+#[repr(C, packed)]
+pub struct Packed {
+    pub a: u8,
+    pub b: u32,
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_packed_b(packed: &Packed) -> u32 {
+    packed.b
+}
+
+fn second(_: u32, b: u32) -> u32 {
+    b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_call_fn_ptr(a: u32, b: u32) -> u32 {
+    let f: fn(u32, u32) -> u32 = second;
+    f(a, b)
+}
+
+/// Writes its `id` to `log` when dropped.
+pub struct Guard {
+    pub log: *mut u32,
+    pub id: u32,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        unsafe {
+            *self.log = self.id;
+        }
+    }
+}
+
+pub struct Pair {
+    pub first: Guard,
+    pub second: Guard,
+}
+
+/// Locals are dropped in reverse order of declaration, so the first guard
+/// writes last.
+#[no_mangle]
+pub extern "C" fn r2c2_drop_order(log: *mut u32) {
+    let _a = Guard { log, id: 1 };
+    let _b = Guard { log, id: 2 };
+}
+
+/// Fields are dropped in order of declaration, so the second field writes
+/// last.
+#[no_mangle]
+pub extern "C" fn r2c2_drop_fields(log: *mut u32) {
+    let _pair = Pair {
+        first: Guard { log, id: 1 },
+        second: Guard { log, id: 2 },
+    };
+}
+
+/// Only the moved-out guard's new owner drops it.
+#[no_mangle]
+pub extern "C" fn r2c2_drop_moved(log: *mut u32, keep: bool) -> u32 {
+    let guard = Guard { log, id: 3 };
+    if keep {
+        let kept = guard;
+        kept.id
+    } else {
+        0
+    }
+}
+
+/// Replacing an array of guards drops the old guards first.
+#[no_mangle]
+pub extern "C" fn r2c2_drop_replaced(log: *mut u32) -> u32 {
+    let mut guards = [Guard { log, id: 4 }, Guard { log, id: 5 }];
+    guards = [Guard { log, id: 6 }, Guard { log, id: 7 }];
+    unsafe { *log }
+}
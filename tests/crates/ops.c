@@ -0,0 +1,192 @@
+#include <math.h>
+#include <setjmp.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+struct str {
+    const char *data;
+    size_t len;
+};
+
+struct location {
+    struct str file;
+    uint32_t line;
+    uint32_t col;
+};
+
+uint32_t r2c2_add(uint32_t a, uint32_t b);
+uint8_t r2c2_mul(uint8_t a, uint8_t b);
+uint32_t r2c2_shl(uint32_t a, uint32_t b);
+uint32_t r2c2_div(uint32_t a, uint32_t b);
+int32_t r2c2_signed_div(int32_t a, int32_t b);
+int32_t r2c2_signed_rem(int32_t a, int32_t b);
+void r2c2_int_ops(uint32_t a, uint32_t b, uint32_t *out);
+void r2c2_signed_ops(int32_t a, int32_t b, int32_t *out);
+void r2c2_float_ops(double x, double *out);
+double r2c2_infinity(void);
+double r2c2_nan(void);
+void r2c2_casts(int32_t x, int64_t *out);
+uint32_t r2c2_char(uint8_t byte);
+void r2c2_lookup(size_t index, uint32_t *out);
+void r2c2_nested(size_t index, uint32_t *out);
+uint32_t r2c2_boxed(uint32_t value);
+
+static int failures = 0;
+static jmp_buf panic_env;
+static char panic_msg[64];
+static size_t panic_index, panic_len;
+static int allocations = 0;
+
+static void check(const char *what, int64_t actual, int64_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %lld, expected %lld\n", what, (long long)actual,
+                (long long)expected);
+        failures++;
+    }
+}
+
+static void check_float(const char *what, double actual, double expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %g, expected %g\n", what, actual, expected);
+        failures++;
+    }
+}
+
+static void check_location(const char *what, struct str file, uint32_t line) {
+    if (file.len < 6 || memcmp(file.data + file.len - 6, "ops.rs", 6) != 0 || line == 0) {
+        fprintf(stderr, "%s: panicked at %.*s:%u\n", what, (int)file.len, file.data, line);
+        failures++;
+    }
+}
+
+void r2c2_panic(const struct str *msg, const struct location *loc) {
+    snprintf(panic_msg, sizeof(panic_msg), "%.*s", (int)msg->len, msg->data);
+    check_location(panic_msg, loc->file, loc->line);
+    longjmp(panic_env, 1);
+}
+
+void r2c2_panic_bounds_check(const struct location *loc, size_t index, size_t len) {
+    snprintf(panic_msg, sizeof(panic_msg), "index out of bounds");
+    panic_index = index;
+    panic_len = len;
+    check_location(panic_msg, loc->file, loc->line);
+    longjmp(panic_env, 1);
+}
+
+uint8_t *r2c2_malloc(size_t size, size_t align) {
+    check("r2c2_malloc size", size, 4);
+    check("r2c2_malloc align", align, 4);
+    allocations++;
+    return malloc(size);
+}
+
+void r2c2_free(uint8_t *ptr) {
+    allocations--;
+    free(ptr);
+}
+
+/* Checks that the last call through `setjmp` panicked with `msg` */
+static void check_panic(const char *what, int panicked, const char *msg) {
+    if (!panicked) {
+        fprintf(stderr, "%s: didn't panic\n", what);
+        failures++;
+    } else if (strcmp(panic_msg, msg) != 0) {
+        fprintf(stderr, "%s: panicked with \"%s\", expected \"%s\"\n", what, panic_msg, msg);
+        failures++;
+    }
+}
+
+int main(void) {
+    uint32_t ints[10];
+    int32_t signed_ints[5];
+    double floats[6];
+    int64_t casts[6];
+    uint32_t lookup[5];
+    uint32_t nested[2];
+
+    check("r2c2_add(2, 3)", r2c2_add(2, 3), 5);
+    check("r2c2_mul(15, 17)", r2c2_mul(15, 17), 255);
+    check("r2c2_shl(1, 31)", r2c2_shl(1, 31), 0x80000000);
+#ifdef NO_OVERFLOW_CHECKS
+    check("r2c2_add(UINT32_MAX, 1)", r2c2_add(UINT32_MAX, 1), 0);
+    check("r2c2_mul(16, 16)", r2c2_mul(16, 16), 0);
+    check("r2c2_shl(1, 32)", r2c2_shl(1, 32), 1);
+#else
+    check_panic("r2c2_add(UINT32_MAX, 1)",
+                setjmp(panic_env) || (r2c2_add(UINT32_MAX, 1), 0),
+                "attempt to add with overflow");
+    check_panic("r2c2_mul(16, 16)", setjmp(panic_env) || (r2c2_mul(16, 16), 0),
+                "attempt to multiply with overflow");
+    check_panic("r2c2_shl(1, 32)", setjmp(panic_env) || (r2c2_shl(1, 32), 0),
+                "attempt to shift left with overflow");
+#endif
+    check("r2c2_div(7, 2)", r2c2_div(7, 2), 3);
+    check_panic("r2c2_div(7, 0)", setjmp(panic_env) || (r2c2_div(7, 0), 0),
+                "attempt to divide by zero");
+    check_panic("r2c2_signed_div(INT32_MIN, -1)",
+                setjmp(panic_env) || (r2c2_signed_div(INT32_MIN, -1), 0),
+                "attempt to divide with overflow");
+    check_panic("r2c2_signed_rem(INT32_MIN, -1)",
+                setjmp(panic_env) || (r2c2_signed_rem(INT32_MIN, -1), 0),
+                "attempt to calculate the remainder with overflow");
+
+    r2c2_int_ops(100, 7, ints);
+    check("100 - 7", ints[0], 93);
+    check("100 % 7", ints[1], 2);
+    check("100 & 7", ints[2], 4);
+    check("100 | 7", ints[3], 103);
+    check("100 ^ 7", ints[4], 99);
+    check("100 >> 4", ints[5], 6);
+    check("!100", ints[6], (uint32_t)~100u);
+    check("100 < 7, 100 <= 7, 100 > 7", ints[7], 4);
+    check("100 >= 7, 100 == 7, 100 != 7", ints[8], 5);
+    check("!(100 == 7) & true | false", ints[9], 1);
+
+    r2c2_signed_ops(-7, 2, signed_ints);
+    check("-7 / 2", signed_ints[0], -3);
+    check("-7 % 2", signed_ints[1], -1);
+    check("-7 >> 2", signed_ints[2], -2);
+    check("-(-7)", signed_ints[3], 7);
+    check("-7 < 2", signed_ints[4], 1);
+
+    r2c2_float_ops(-3.5, floats);
+    check_float("-3.5 * 1.5 + 0.25", floats[0], -5.0);
+    check_float("-3.5 % 2.0", floats[1], -1.5);
+    check_float("-(-3.5)", floats[2], 3.5);
+    check_float("-3.5 as i32", floats[3], -3.0);
+    check_float("-3.5 as f32 * 0.5", floats[4], -1.75);
+    check_float("-3.5 < 0.0", floats[5], 1.0);
+    check_float("-INFINITY", r2c2_infinity(), -HUGE_VAL);
+    check("NAN is NaN", isnan(r2c2_nan()), 1);
+
+    r2c2_casts(-200, casts);
+    check("-200 as u8", casts[0], 56);
+    check("-200 as i64", casts[1], -200);
+    check("-200 as u32", casts[2], 4294967096);
+    check("Level::Low as i64", casts[3], -2);
+    check("Level::High as i32", casts[4], 5);
+    check("pointer casts", casts[5], 1);
+    check("r2c2_char(65)", r2c2_char(65), 65);
+
+    r2c2_lookup(2, lookup);
+    check("TABLE[2]", lookup[0], 4);
+    check("promoted[2]", lookup[1], 30);
+    check("PAIR.b + pair.b", lookup[2], 11);
+    check("local[2]", lookup[3], 50);
+    check("repeated[2]", lookup[4], 6);
+    check_panic("r2c2_lookup(4)", setjmp(panic_env) || (r2c2_lookup(4, lookup), 0),
+                "index out of bounds");
+    check("r2c2_lookup(4) index", panic_index, 4);
+    check("r2c2_lookup(4) len", panic_len, 4);
+
+    r2c2_nested(1, nested);
+    check("repeated[1][1]", nested[0], 7);
+    check("rows[1][0]", nested[1], 8);
+
+    check("r2c2_boxed(41)", r2c2_boxed(41), 42);
+    check("allocations", allocations, 0);
+
+    return failures != 0;
+}
@@ -0,0 +1,387 @@
+//! Operators, casts and constants: arithmetic with and without overflow
+//! checks, comparisons, shifts, float arithmetic and constants, numeric and
+//! pointer casts, promoted and by-reference constants, array expressions,
+//! `box`, and the panics of failed assertions.
+//!
+//! The crate is also compiled with `-C overflow-checks=off`, in which case
+//! `ops.c` is compiled with `NO_OVERFLOW_CHECKS` defined.
+
+#![feature(no_core, lang_items, optin_builtin_traits, box_syntax)]
+#![no_core]
+#![allow(improper_ctypes)]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+#[lang = "receiver"]
+trait Receiver {}
+
+impl<'a, T: ?Sized> Receiver for &'a T {}
+impl<'a, T: ?Sized> Receiver for &'a mut T {}
+
+#[lang = "index"]
+trait Index<Idx> {
+    type Output: ?Sized;
+    fn index(&self, index: Idx) -> &Self::Output;
+}
+
+#[lang = "index_mut"]
+trait IndexMut<Idx>: Index<Idx> {
+    fn index_mut(&mut self, index: Idx) -> &mut Self::Output;
+}
+
+impl<T> Index<usize> for [T] {
+    type Output = T;
+    fn index(&self, index: usize) -> &T {
+        &self[index]
+    }
+}
+
+impl<T> IndexMut<usize> for [T] {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self[index]
+    }
+}
+
+#[lang = "drop_in_place"]
+#[allow(unconditional_recursion)]
+unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
+    drop_in_place(to_drop)
+}
+
+impl Copy for bool {}
+impl Copy for u8 {}
+impl Copy for u32 {}
+impl Copy for i32 {}
+impl Copy for i64 {}
+impl Copy for usize {}
+impl Copy for f32 {}
+impl Copy for f64 {}
+impl<'a, T: ?Sized> Copy for &'a T {}
+impl<T: ?Sized> Copy for *const T {}
+
+macro_rules! binary_ops {
+    ($($lang:tt $trait_:ident $method:ident ($($ty:ty)*);)*) => {$(
+        #[lang = $lang]
+        trait $trait_<Rhs = Self> {
+            type Output;
+            fn $method(self, rhs: Rhs) -> Self::Output;
+        }
+
+        $(impl $trait_ for $ty {
+            type Output = $ty;
+            fn $method(self, _rhs: $ty) -> $ty {
+                loop {}
+            }
+        })*
+    )*};
+}
+
+binary_ops! {
+    "add" Add add (u8 u32 i32 i64 usize f32 f64);
+    "sub" Sub sub (u8 u32 i32 i64 usize f32 f64);
+    "mul" Mul mul (u8 u32 i32 i64 usize f32 f64);
+    "div" Div div (u32 i32 f64);
+    "rem" Rem rem (u32 i32 f64);
+    "bitand" BitAnd bitand (bool u32);
+    "bitor" BitOr bitor (bool u32);
+    "bitxor" BitXor bitxor (u32);
+    "shl" Shl shl (u8 u32 i32);
+    "shr" Shr shr (u8 u32 i32);
+}
+
+#[lang = "neg"]
+trait Neg {
+    type Output;
+    fn neg(self) -> Self::Output;
+}
+
+impl Neg for i8 {
+    type Output = i8;
+    fn neg(self) -> i8 {
+        loop {}
+    }
+}
+
+impl Neg for i32 {
+    type Output = i32;
+    fn neg(self) -> i32 {
+        loop {}
+    }
+}
+
+impl Neg for f64 {
+    type Output = f64;
+    fn neg(self) -> f64 {
+        loop {}
+    }
+}
+
+#[lang = "not"]
+trait Not {
+    type Output;
+    fn not(self) -> Self::Output;
+}
+
+impl Not for bool {
+    type Output = bool;
+    fn not(self) -> bool {
+        loop {}
+    }
+}
+
+impl Not for u32 {
+    type Output = u32;
+    fn not(self) -> u32 {
+        loop {}
+    }
+}
+
+#[lang = "eq"]
+trait PartialEq<Rhs: ?Sized = Self> {
+    fn eq(&self, other: &Rhs) -> bool;
+    fn ne(&self, other: &Rhs) -> bool;
+}
+
+#[lang = "partial_ord"]
+trait PartialOrd<Rhs: ?Sized = Self>: PartialEq<Rhs> {
+    fn lt(&self, other: &Rhs) -> bool;
+    fn le(&self, other: &Rhs) -> bool;
+    fn gt(&self, other: &Rhs) -> bool;
+    fn ge(&self, other: &Rhs) -> bool;
+}
+
+macro_rules! comparisons {
+    ($($ty:ty)*) => {$(
+        impl PartialEq for $ty {
+            fn eq(&self, _other: &$ty) -> bool {
+                loop {}
+            }
+            fn ne(&self, _other: &$ty) -> bool {
+                loop {}
+            }
+        }
+
+        impl PartialOrd for $ty {
+            fn lt(&self, _other: &$ty) -> bool {
+                loop {}
+            }
+            fn le(&self, _other: &$ty) -> bool {
+                loop {}
+            }
+            fn gt(&self, _other: &$ty) -> bool {
+                loop {}
+            }
+            fn ge(&self, _other: &$ty) -> bool {
+                loop {}
+            }
+        }
+    )*};
+}
+
+comparisons!(u32 i32 usize f64);
+
+impl<T: ?Sized> PartialEq for *const T {
+    fn eq(&self, _other: &*const T) -> bool {
+        loop {}
+    }
+    fn ne(&self, _other: &*const T) -> bool {
+        loop {}
+    }
+}
+
+#[lang = "drop"]
+trait Drop {
+    fn drop(&mut self);
+}
+
+#[lang = "owned_box"]
+pub struct Box<T: ?Sized>(*mut T);
+
+// Like liballoc's, so that dropping a box frees it through `box_free`
+impl<T: ?Sized> Drop for Box<T> {
+    fn drop(&mut self) {}
+}
+
+// Like libcore's, but with a C layout
+#[lang = "panic_location"]
+#[repr(C)]
+pub struct Location<'a> {
+    file: &'a str,
+    line: u32,
+    col: u32,
+}
+
+extern "C" {
+    fn r2c2_panic(expr: &&str, location: &Location) -> !;
+    fn r2c2_panic_bounds_check(location: &Location, index: usize, len: usize) -> !;
+    fn r2c2_malloc(size: usize, align: usize) -> *mut u8;
+    fn r2c2_free(ptr: *mut u8);
+}
+
+#[lang = "panic"]
+fn panic(expr: &str, location: &Location) -> ! {
+    unsafe { r2c2_panic(&expr, location) }
+}
+
+#[lang = "panic_bounds_check"]
+fn panic_bounds_check(location: &Location, index: usize, len: usize) -> ! {
+    unsafe { r2c2_panic_bounds_check(location, index, len) }
+}
+
+#[lang = "exchange_malloc"]
+unsafe fn exchange_malloc(size: usize, align: usize) -> *mut u8 {
+    r2c2_malloc(size, align)
+}
+
+#[lang = "box_free"]
+unsafe fn box_free<T: ?Sized>(ptr: *mut T) {
+    r2c2_free(ptr as *mut u8)
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_mul(a: u8, b: u8) -> u8 {
+    a * b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_shl(a: u32, b: u32) -> u32 {
+    a << b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_div(a: u32, b: u32) -> u32 {
+    a / b
+}
+
+/// Signed division overflow panics even without overflow checks.
+#[no_mangle]
+pub extern "C" fn r2c2_signed_div(a: i32, b: i32) -> i32 {
+    a / b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_signed_rem(a: i32, b: i32) -> i32 {
+    a % b
+}
+
+/// Writes the results of the integer operators on `a` and `b` to `out`.
+#[no_mangle]
+pub extern "C" fn r2c2_int_ops(a: u32, b: u32, out: &mut [u32; 10]) {
+    out[0] = a - b;
+    out[1] = a % b;
+    out[2] = a & b;
+    out[3] = a | b;
+    out[4] = a ^ b;
+    out[5] = a >> 4;
+    out[6] = !a;
+    out[7] = (a < b) as u32 | ((a <= b) as u32) << 1 | ((a > b) as u32) << 2;
+    out[8] = (a >= b) as u32 | ((a == b) as u32) << 1 | ((a != b) as u32) << 2;
+    out[9] = (!(a == b) & true | false) as u32;
+}
+
+/// Writes the results of the signed operators on `a` and `b` to `out`.
+#[no_mangle]
+pub extern "C" fn r2c2_signed_ops(a: i32, b: i32, out: &mut [i32; 5]) {
+    out[0] = a / b;
+    out[1] = a % b;
+    out[2] = a >> 2;
+    out[3] = -a;
+    out[4] = (a < b) as i32;
+}
+
+/// Writes the results of the float operators on `x` to `out`.
+#[no_mangle]
+pub extern "C" fn r2c2_float_ops(x: f64, out: &mut [f64; 6]) {
+    out[0] = x * 1.5 + 0.25;
+    out[1] = x % 2.0;
+    out[2] = -x;
+    out[3] = (x as i32) as f64;
+    out[4] = (x as f32 * 0.5f32) as f64;
+    out[5] = (x < 0.0) as u32 as f64;
+}
+
+const INFINITY: f64 = 1.0 / 0.0;
+const NAN: f64 = 0.0 / 0.0;
+
+#[no_mangle]
+pub extern "C" fn r2c2_infinity() -> f64 {
+    -INFINITY
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_nan() -> f64 {
+    NAN
+}
+
+#[repr(i8)]
+pub enum Level {
+    Low = -2,
+    High = 5,
+}
+
+/// Writes the results of numeric and pointer casts of `x` to `out`.
+#[no_mangle]
+pub extern "C" fn r2c2_casts(x: i32, out: &mut [i64; 6]) {
+    out[0] = x as u8 as i64;
+    out[1] = x as i64;
+    out[2] = x as u32 as i64;
+    out[3] = Level::Low as i64;
+    out[4] = Level::High as i32 as i64;
+    let ptr = &x as *const i32;
+    out[5] = (ptr as usize != 0 && ptr == &x as *const i32) as i64;
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_char(byte: u8) -> u32 {
+    byte as char as u32
+}
+
+pub struct Pair {
+    pub a: u32,
+    pub b: u32,
+}
+
+const TABLE: [u32; 4] = [3, 1, 4, 1];
+const PAIR: Pair = Pair { a: 7, b: 9 };
+
+/// Looks `index` up in a constant, a promoted constant and local arrays.
+#[no_mangle]
+pub extern "C" fn r2c2_lookup(index: usize, out: &mut [u32; 5]) {
+    let promoted: &'static [u32; 3] = &[10, 20, 30];
+    let pair: &'static Pair = &Pair { a: 1, b: 2 };
+    let local = [index as u32, 40, 50];
+    let repeated = [6u32; 3];
+    out[0] = TABLE[index];
+    out[1] = promoted[index];
+    out[2] = PAIR.b + pair.b;
+    out[3] = local[index];
+    out[4] = repeated[index];
+}
+
+/// Looks `index` up in arrays of arrays, which are copied with `memcpy`.
+#[no_mangle]
+pub extern "C" fn r2c2_nested(index: usize, out: &mut [u32; 2]) {
+    let row = [index as u32, 7];
+    let repeated = [row; 3];
+    let rows = [row, [8, 9]];
+    out[0] = repeated[index][1];
+    out[1] = rows[index][0];
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_boxed(value: u32) -> u32 {
+    let boxed = box value;
+    *boxed + 1
+}
@@ -0,0 +1,31 @@
+#include <stdbool.h>
+#include <stdint.h>
+
+uint32_t r2c2_unwrap_llvm_opt(bool some, uint32_t x, uint32_t d);
+float r2c2_second_llvm_float(float a, float b);
+double r2c2_second_llvm_mixed(bool a, double b);
+uint32_t llvm_unwrap_r2c2_opt(bool some, uint32_t x, uint32_t d);
+float llvm_second_r2c2_float(float a, float b);
+double llvm_second_r2c2_mixed(bool a, double b);
+
+int main(void) {
+    if (r2c2_unwrap_llvm_opt(true, 5, 6) != 5 || r2c2_unwrap_llvm_opt(false, 5, 6) != 6) {
+        return 1;
+    }
+    if (r2c2_second_llvm_float(1.5f, 2.5f) != 2.5f) {
+        return 2;
+    }
+    if (r2c2_second_llvm_mixed(true, 3.5) != 3.5) {
+        return 3;
+    }
+    if (llvm_unwrap_r2c2_opt(true, 5, 6) != 5 || llvm_unwrap_r2c2_opt(false, 5, 6) != 6) {
+        return 4;
+    }
+    if (llvm_second_r2c2_float(1.5f, 2.5f) != 2.5f) {
+        return 5;
+    }
+    if (llvm_second_r2c2_mixed(true, 3.5) != 3.5) {
+        return 6;
+    }
+    return 0;
+}
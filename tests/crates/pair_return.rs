@@ -0,0 +1,64 @@
+//! Calls between functions compiled by the C backend and by LLVM, which
+//! return scalar pairs (`pair_return_llvm.rs` is compiled by LLVM).
+
+#![feature(no_core, lang_items)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+impl Copy for u32 {}
+impl Copy for bool {}
+
+pub enum Opt {
+    None,
+    Some(u32),
+}
+
+extern "Rust" {
+    fn llvm_make_opt(some: bool, x: u32) -> Opt;
+    fn llvm_make_floats(a: f32, b: f32) -> (f32, f32);
+    fn llvm_make_mixed(a: bool, b: f64) -> (bool, f64);
+}
+
+#[no_mangle]
+pub fn r2c2_make_opt(some: bool, x: u32) -> Opt {
+    if some {
+        Opt::Some(x)
+    } else {
+        Opt::None
+    }
+}
+
+#[no_mangle]
+pub fn r2c2_make_floats(a: f32, b: f32) -> (f32, f32) {
+    (a, b)
+}
+
+#[no_mangle]
+pub fn r2c2_make_mixed(a: bool, b: f64) -> (bool, f64) {
+    (a, b)
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_unwrap_llvm_opt(some: bool, x: u32, default: u32) -> u32 {
+    match unsafe { llvm_make_opt(some, x) } {
+        Opt::Some(x) => x,
+        Opt::None => default,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_second_llvm_float(a: f32, b: f32) -> f32 {
+    let (_, b) = unsafe { llvm_make_floats(a, b) };
+    b
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_second_llvm_mixed(a: bool, b: f64) -> f64 {
+    let (_, b) = unsafe { llvm_make_mixed(a, b) };
+    b
+}
@@ -0,0 +1,63 @@
+//! The LLVM-compiled half of the `pair_return` test (see `pair_return.rs`).
+
+#![feature(no_core, lang_items)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+impl Copy for u32 {}
+impl Copy for bool {}
+
+pub enum Opt {
+    None,
+    Some(u32),
+}
+
+extern "Rust" {
+    fn r2c2_make_opt(some: bool, x: u32) -> Opt;
+    fn r2c2_make_floats(a: f32, b: f32) -> (f32, f32);
+    fn r2c2_make_mixed(a: bool, b: f64) -> (bool, f64);
+}
+
+#[no_mangle]
+pub fn llvm_make_opt(some: bool, x: u32) -> Opt {
+    if some {
+        Opt::Some(x)
+    } else {
+        Opt::None
+    }
+}
+
+#[no_mangle]
+pub fn llvm_make_floats(a: f32, b: f32) -> (f32, f32) {
+    (a, b)
+}
+
+#[no_mangle]
+pub fn llvm_make_mixed(a: bool, b: f64) -> (bool, f64) {
+    (a, b)
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_unwrap_r2c2_opt(some: bool, x: u32, default: u32) -> u32 {
+    match unsafe { r2c2_make_opt(some, x) } {
+        Opt::Some(x) => x,
+        Opt::None => default,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_second_r2c2_float(a: f32, b: f32) -> f32 {
+    let (_, b) = unsafe { r2c2_make_floats(a, b) };
+    b
+}
+
+#[no_mangle]
+pub extern "C" fn llvm_second_r2c2_mixed(a: bool, b: f64) -> f64 {
+    let (_, b) = unsafe { r2c2_make_mixed(a, b) };
+    b
+}
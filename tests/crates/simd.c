@@ -0,0 +1,107 @@
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+
+void r2c2_int_ops(const uint32_t a[4], const uint32_t b[4], uint32_t out[7][4]);
+void r2c2_signed_ops(const int32_t a[4], const int32_t b[4], int32_t out[3][4]);
+void r2c2_float_ops(const float a[4], const float b[4], float out[2][4]);
+void r2c2_comparisons(const float a[4], const float b[4], int32_t out[3][4]);
+void r2c2_shuffles(const uint32_t a[4], const uint32_t b[4], uint32_t out[4], uint32_t narrow[2]);
+void r2c2_reductions(const uint32_t a[4], uint32_t out[6], bool all_any[2]);
+float r2c2_float_sum(const float a[4], float init);
+void r2c2_select(const int32_t a[4], const int32_t b[4], const int32_t mask[4], float out[4]);
+void r2c2_three_lanes(const uint32_t a[3], const uint32_t b[3], uint32_t out[3]);
+
+static int failures = 0;
+
+static void check(const char *what, int64_t actual, int64_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %lld, expected %lld\n", what, (long long) actual,
+                (long long) expected);
+        failures++;
+    }
+}
+
+static void check_lanes(const char *what, const int64_t *actual, const int64_t *expected,
+                        int count) {
+    int i;
+    for (i = 0; i < count; i++) {
+        if (actual[i] != expected[i]) {
+            fprintf(stderr, "%s lane %d: got %lld, expected %lld\n", what, i,
+                    (long long) actual[i], (long long) expected[i]);
+            failures++;
+        }
+    }
+}
+
+#define CHECK_LANES(what, actual, ...)                                          \
+    do {                                                                       \
+        int64_t expected[] = {__VA_ARGS__};                                    \
+        int64_t widened[sizeof(expected) / sizeof(expected[0])];               \
+        int i;                                                                 \
+        for (i = 0; i < (int) (sizeof(expected) / sizeof(expected[0])); i++) { \
+            widened[i] = (int64_t) (actual)[i];                                \
+        }                                                                      \
+        check_lanes(what, widened, expected, i);                               \
+    } while (0)
+
+int main(void) {
+    uint32_t a[4] = {10, 200, 0xffffffff, 7}, b[4] = {3, 100, 2, 7};
+    uint32_t int_out[7][4];
+    int32_t sa[4] = {-7, INT32_MAX, -8, 9}, sb[4] = {2, 1, -3, -2};
+    int32_t signed_out[3][4];
+    float fa[4] = {1.5f, 2, -3, 4}, fb[4] = {0.5f, 2, 1, 8};
+    float float_out[2][4];
+    float ca[4] = {1, 2, 3, 4}, cb[4] = {1, 3, 2, 4};
+    int32_t masks[3][4];
+    uint32_t sha[4] = {1, 2, 3, 4}, shb[4] = {5, 6, 7, 8}, shuffled[4], narrow[2];
+    uint32_t ra[4] = {2, 3, 5, 6}, rb[4] = {0, 4, 0, 1}, reduced[6];
+    bool all_any[2];
+    int32_t sel_a[4] = {1, 2, 3, 4}, sel_b[4] = {10, 20, 30, 40}, mask[4] = {-1, 0, -5, 7};
+    float selected[4];
+    uint32_t ta[3] = {1, 2, 3}, tb[3] = {4, 5, 0xffffffff}, three[3];
+
+    r2c2_int_ops(a, b, int_out);
+    CHECK_LANES("add", int_out[0], 13, 300, 1, 14);
+    CHECK_LANES("sub", int_out[1], 7, 100, 0xfffffffd, 0);
+    CHECK_LANES("mul", int_out[2], 30, 20000, 0xfffffffe, 49);
+    CHECK_LANES("div", int_out[3], 3, 2, 0x7fffffff, 1);
+    CHECK_LANES("shl", int_out[4], 20, 800, 0xfffffff8, 112);
+    CHECK_LANES("shr", int_out[5], 5, 50, 0x1fffffff, 0);
+    CHECK_LANES("and xor", int_out[6], 8, 136, 0xfffffffd, 0);
+
+    r2c2_signed_ops(sa, sb, signed_out);
+    CHECK_LANES("signed add", signed_out[0], -5, INT32_MIN, -11, 7);
+    CHECK_LANES("signed div", signed_out[1], -3, INT32_MAX, 2, -4);
+    CHECK_LANES("signed shr", signed_out[2], -4, 0x3fffffff, -4, 4);
+
+    r2c2_float_ops(fa, fb, float_out);
+    CHECK_LANES("float add", float_out[0], 2, 4, -2, 12);
+    CHECK_LANES("float mul div", float_out[1], 1, 2, -3, 4);
+    check("float mul div half", float_out[1][0] == 1.5f, 1);
+
+    r2c2_comparisons(ca, cb, masks);
+    CHECK_LANES("eq", masks[0], -1, 0, 0, -1);
+    CHECK_LANES("lt", masks[1], 0, -1, 0, 0);
+    CHECK_LANES("ge", masks[2], -1, 0, -1, -1);
+
+    r2c2_shuffles(sha, shb, shuffled, narrow);
+    CHECK_LANES("shuffle", shuffled, 8, 1, 6, 3);
+    CHECK_LANES("narrowing shuffle", narrow, 4, 5);
+
+    r2c2_reductions(ra, reduced, all_any);
+    CHECK_LANES("reductions", reduced, 16, 180, 0, 7, 2, 6);
+    CHECK_LANES("all any", all_any, 1, 1);
+    r2c2_reductions(rb, reduced, all_any);
+    CHECK_LANES("reductions with zeros", reduced, 5, 0, 0, 5, 0, 4);
+    CHECK_LANES("all any with zeros", all_any, 0, 1);
+    check("float sum", r2c2_float_sum(ca, 0.5f) == 10.5f, 1);
+
+    r2c2_select(sel_a, sel_b, mask, selected);
+    CHECK_LANES("select", selected, 10, 2, 30, 4);
+
+    r2c2_three_lanes(ta, tb, three);
+    CHECK_LANES("three lanes", three, 5, 7, 2);
+
+    return failures != 0;
+}
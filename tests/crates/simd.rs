@@ -0,0 +1,211 @@
+//! SIMD types and the `simd_*` platform intrinsics: arithmetic, comparisons,
+//! shuffles, reductions, lane accesses, casts and selects.
+//!
+//! The vectors are built from and stored to arrays, since their ABI isn't
+//! stable. `U32x3` has a lane count vector extensions don't support, so it's
+//! operated on lane by lane even when the others aren't.
+
+#![feature(no_core, lang_items, optin_builtin_traits, repr_simd, platform_intrinsics)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+impl Copy for u32 {}
+impl Copy for i32 {}
+impl Copy for f32 {}
+impl Copy for bool {}
+
+#[repr(simd)]
+pub struct U32x4(u32, u32, u32, u32);
+
+#[repr(simd)]
+pub struct U32x2(u32, u32);
+
+#[repr(simd)]
+pub struct U32x3(u32, u32, u32);
+
+#[repr(simd)]
+pub struct I32x4(i32, i32, i32, i32);
+
+#[repr(simd)]
+pub struct F32x4(f32, f32, f32, f32);
+
+impl Copy for U32x4 {}
+impl Copy for U32x2 {}
+impl Copy for U32x3 {}
+impl Copy for I32x4 {}
+impl Copy for F32x4 {}
+
+extern "platform-intrinsic" {
+    fn simd_add<T>(a: T, b: T) -> T;
+    fn simd_sub<T>(a: T, b: T) -> T;
+    fn simd_mul<T>(a: T, b: T) -> T;
+    fn simd_div<T>(a: T, b: T) -> T;
+    fn simd_shl<T>(a: T, b: T) -> T;
+    fn simd_shr<T>(a: T, b: T) -> T;
+    fn simd_and<T>(a: T, b: T) -> T;
+    fn simd_xor<T>(a: T, b: T) -> T;
+
+    fn simd_eq<T, U>(a: T, b: T) -> U;
+    fn simd_lt<T, U>(a: T, b: T) -> U;
+    fn simd_ge<T, U>(a: T, b: T) -> U;
+
+    fn simd_shuffle2<T, U>(a: T, b: T, indices: [u32; 2]) -> U;
+    fn simd_shuffle4<T, U>(a: T, b: T, indices: [u32; 4]) -> U;
+
+    fn simd_reduce_add_unordered<T, U>(x: T) -> U;
+    fn simd_reduce_add_ordered<T, U>(x: T, acc: U) -> U;
+    fn simd_reduce_mul_unordered<T, U>(x: T) -> U;
+    fn simd_reduce_and<T, U>(x: T) -> U;
+    fn simd_reduce_or<T, U>(x: T) -> U;
+    fn simd_reduce_min<T, U>(x: T) -> U;
+    fn simd_reduce_max<T, U>(x: T) -> U;
+    fn simd_reduce_all<T>(x: T) -> bool;
+    fn simd_reduce_any<T>(x: T) -> bool;
+
+    fn simd_extract<T, U>(x: T, index: u32) -> U;
+    fn simd_insert<T, U>(x: T, index: u32, value: U) -> T;
+    fn simd_cast<T, U>(x: T) -> U;
+    fn simd_select<M, T>(mask: M, a: T, b: T) -> T;
+}
+
+fn u32x4(a: &[u32; 4]) -> U32x4 {
+    let [a0, a1, a2, a3] = *a;
+    U32x4(a0, a1, a2, a3)
+}
+
+fn i32x4(a: &[i32; 4]) -> I32x4 {
+    let [a0, a1, a2, a3] = *a;
+    I32x4(a0, a1, a2, a3)
+}
+
+fn f32x4(a: &[f32; 4]) -> F32x4 {
+    let [a0, a1, a2, a3] = *a;
+    F32x4(a0, a1, a2, a3)
+}
+
+unsafe fn lanes<T: Copy, U: Copy>(x: T) -> [U; 4] {
+    [
+        simd_extract(x, 0),
+        simd_extract(x, 1),
+        simd_extract(x, 2),
+        simd_extract(x, 3),
+    ]
+}
+
+/// Writes the results of the integer operations on `a` and `b` to `out`,
+/// which wrap around on overflow.
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_int_ops(a: &[u32; 4], b: &[u32; 4], out: &mut [[u32; 4]; 7]) {
+    let (a, b) = (u32x4(a), u32x4(b));
+    let shift = U32x4(1, 2, 3, 4);
+    *out = [
+        lanes(simd_add(a, b)),
+        lanes(simd_sub(a, b)),
+        lanes(simd_mul(a, b)),
+        lanes(simd_div(a, b)),
+        lanes(simd_shl(a, shift)),
+        lanes(simd_shr(a, shift)),
+        lanes(simd_xor(simd_and(a, b), a)),
+    ];
+}
+
+/// Writes the results of the signed operations on `a` and `b` to `out`.
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_signed_ops(a: &[i32; 4], b: &[i32; 4], out: &mut [[i32; 4]; 3]) {
+    let (a, b) = (i32x4(a), i32x4(b));
+    *out = [
+        lanes(simd_add(a, b)),
+        lanes(simd_div(a, b)),
+        lanes(simd_shr(a, I32x4(1, 1, 1, 1))),
+    ];
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_float_ops(a: &[f32; 4], b: &[f32; 4], out: &mut [[f32; 4]; 2]) {
+    let (a, b) = (f32x4(a), f32x4(b));
+    *out = [lanes(simd_add(a, b)), lanes(simd_div(simd_mul(a, b), b))];
+}
+
+/// Writes the masks of the comparisons of `a` and `b` to `out`.
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_comparisons(a: &[f32; 4], b: &[f32; 4], out: &mut [[i32; 4]; 3]) {
+    let (a, b) = (f32x4(a), f32x4(b));
+    let eq: I32x4 = simd_eq(a, b);
+    let lt: I32x4 = simd_lt(a, b);
+    let ge: I32x4 = simd_ge(a, b);
+    *out = [lanes(eq), lanes(lt), lanes(ge)];
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_shuffles(
+    a: &[u32; 4],
+    b: &[u32; 4],
+    out: &mut [u32; 4],
+    narrow: &mut [u32; 2],
+) {
+    let (a, b) = (u32x4(a), u32x4(b));
+    let shuffled: U32x4 = simd_shuffle4(a, b, [7, 0, 5, 2]);
+    let halves: U32x2 = simd_shuffle2(a, b, [3, 4]);
+    *out = lanes(shuffled);
+    *narrow = [simd_extract(halves, 0), simd_extract(halves, 1)];
+}
+
+/// Writes the reductions of `a` to `out`, and whether all and any of its
+/// lanes are non-zero to `all_any`.
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_reductions(
+    a: &[u32; 4],
+    out: &mut [u32; 6],
+    all_any: &mut [bool; 2],
+) {
+    let a = u32x4(a);
+    *out = [
+        simd_reduce_add_unordered(a),
+        simd_reduce_mul_unordered(a),
+        simd_reduce_and(a),
+        simd_reduce_or(a),
+        simd_reduce_min(a),
+        simd_reduce_max(a),
+    ];
+    *all_any = [simd_reduce_all(a), simd_reduce_any(a)];
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_float_sum(a: &[f32; 4], init: f32) -> f32 {
+    simd_reduce_add_ordered(f32x4(a), init)
+}
+
+/// Replaces lane 2 of `a` with the lanes of `b` selected by the sign of the
+/// lanes of `mask`, and converts the result to floats.
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_select(
+    a: &[i32; 4],
+    b: &[i32; 4],
+    mask: &[i32; 4],
+    out: &mut [f32; 4],
+) {
+    let a = simd_insert(i32x4(a), 2, 100);
+    let mask: I32x4 = simd_lt(i32x4(mask), I32x4(0, 0, 0, 0));
+    let selected = simd_select(mask, i32x4(b), a);
+    *out = lanes(simd_cast::<I32x4, F32x4>(selected));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn r2c2_three_lanes(a: &[u32; 3], b: &[u32; 3], out: &mut [u32; 3]) {
+    let [a0, a1, a2] = *a;
+    let [b0, b1, b2] = *b;
+    let sum = simd_add(U32x3(a0, a1, a2), U32x3(b0, b1, b2));
+    *out = [
+        simd_extract(sum, 0),
+        simd_extract(sum, 1),
+        simd_extract(sum, 2),
+    ];
+}
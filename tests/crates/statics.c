@@ -0,0 +1,84 @@
+#include <pthread.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+struct str {
+    const char *data;
+    size_t len;
+};
+
+extern uint32_t r2c2_counter;
+extern _Thread_local uint32_t r2c2_tls;
+
+uint32_t r2c2_pair_first(void);
+uint32_t r2c2_pair_second(void);
+uint32_t r2c2_answer(void);
+uint32_t r2c2_counter_value(void);
+uint32_t r2c2_tls_value(void);
+void r2c2_set_tls(uint32_t value);
+uint32_t r2c2_callback(void);
+void r2c2_greeting(struct str *out);
+void r2c2_name(struct str *out);
+void r2c2_empty(struct str *out);
+void r2c2_empty_name(struct str *out);
+
+static int failures = 0;
+
+static void check(const char *what, uint32_t actual, uint32_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %u, expected %u\n", what, actual, expected);
+        failures++;
+    }
+}
+
+static void check_str(const char *what, struct str actual, const char *expected) {
+    if (actual.len != strlen(expected) || memcmp(actual.data, expected, actual.len) != 0) {
+        fprintf(stderr, "%s: got \"%.*s\", expected \"%s\"\n", what, (int)actual.len,
+                actual.data, expected);
+        failures++;
+    }
+}
+
+/* Checks that a new thread sees the initial value of the thread-local */
+static void *other_thread(void *arg) {
+    (void)arg;
+    check("r2c2_tls_value() in another thread", r2c2_tls_value(), 5);
+    r2c2_set_tls(11);
+    check("r2c2_tls in another thread", r2c2_tls, 11);
+    return NULL;
+}
+
+int main(void) {
+    pthread_t thread;
+    struct str str;
+
+    check("r2c2_pair_first()", r2c2_pair_first(), 1);
+    check("r2c2_pair_second()", r2c2_pair_second(), 42);
+    check("r2c2_answer()", r2c2_answer(), 7);
+    r2c2_greeting(&str);
+    check_str("r2c2_greeting()", str, "hello");
+    r2c2_name(&str);
+    check_str("r2c2_name()", str, "r2c2");
+    r2c2_empty(&str);
+    check_str("r2c2_empty()", str, "");
+    r2c2_empty_name(&str);
+    check_str("r2c2_empty_name()", str, "");
+
+    check("r2c2_counter", r2c2_counter, 3);
+    r2c2_counter = 4;
+    check("r2c2_counter_value()", r2c2_counter_value(), 4);
+
+    check("r2c2_tls_value()", r2c2_tls_value(), 5);
+    r2c2_set_tls(9);
+    check("r2c2_tls", r2c2_tls, 9);
+    check("r2c2_callback()", r2c2_callback(), 9);
+    if (pthread_create(&thread, NULL, other_thread, NULL) != 0 ||
+        pthread_join(thread, NULL) != 0) {
+        fprintf(stderr, "couldn't run another thread\n");
+        return 1;
+    }
+    check("r2c2_tls_value() after the other thread", r2c2_tls_value(), 9);
+
+    return failures != 0;
+}
@@ -0,0 +1,105 @@
+//! Statics and constant memory: initializers pointing to other statics,
+//! constant allocations and functions, mutable statics written from C,
+//! thread-locals, and string literals, including empty ones.
+
+#![feature(no_core, lang_items, optin_builtin_traits, thread_local)]
+#![no_core]
+#![allow(non_upper_case_globals)]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+#[lang = "freeze"]
+unsafe auto trait Freeze {}
+
+#[lang = "sync"]
+unsafe auto trait Sync {}
+
+#[lang = "drop_in_place"]
+#[allow(unconditional_recursion)]
+unsafe fn drop_in_place<T: ?Sized>(to_drop: *mut T) {
+    drop_in_place(to_drop)
+}
+
+impl Copy for u32 {}
+impl<'a, T: ?Sized> Copy for &'a T {}
+
+struct Pair {
+    first: u32,
+    second: &'static u32,
+}
+
+static TARGET: u32 = 42;
+static PAIR: Pair = Pair {
+    first: 1,
+    second: &TARGET,
+};
+static ANSWER: &u32 = &7;
+static CALLBACK: extern "C" fn() -> u32 = r2c2_tls_value;
+static NAME: &str = "r2c2";
+static EMPTY_NAME: &str = "";
+
+#[no_mangle]
+pub static mut r2c2_counter: u32 = 3;
+
+#[no_mangle]
+#[thread_local]
+pub static mut r2c2_tls: u32 = 5;
+
+#[no_mangle]
+pub extern "C" fn r2c2_pair_first() -> u32 {
+    PAIR.first
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_pair_second() -> u32 {
+    *PAIR.second
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_answer() -> u32 {
+    *ANSWER
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_counter_value() -> u32 {
+    unsafe { r2c2_counter }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_tls_value() -> u32 {
+    unsafe { r2c2_tls }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_set_tls(value: u32) {
+    unsafe { r2c2_tls = value }
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_callback() -> u32 {
+    CALLBACK()
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_greeting(out: &mut &'static str) {
+    *out = "hello";
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_name(out: &mut &'static str) {
+    *out = NAME;
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_empty(out: &mut &'static str) {
+    *out = "";
+}
+
+#[no_mangle]
+pub extern "C" fn r2c2_empty_name(out: &mut &'static str) {
+    *out = EMPTY_NAME;
+}
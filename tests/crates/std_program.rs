@@ -0,0 +1,65 @@
+//! A program using the standard library, which is linked against the
+//! prebuilt (LLVM-compiled) `std`. Its generic code (collections, formatting,
+//! iterators) is instantiated in this crate, and so lowered to C.
+//!
+//! It panics if it's given an argument.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ptr;
+
+trait Shape {
+    fn area(&self) -> u32;
+}
+
+struct Square(u32);
+
+struct Rect(u32, u32);
+
+impl Shape for Square {
+    fn area(&self) -> u32 {
+        self.0 * self.0
+    }
+}
+
+impl Shape for Rect {
+    fn area(&self) -> u32 {
+        self.0 * self.1
+    }
+}
+
+fn main() {
+    let mut squares = Vec::new();
+    for i in 0..10u32 {
+        squares.push(i * i);
+    }
+    assert_eq!(squares.iter().sum::<u32>(), 285);
+
+    let mut words = vec![String::from("one"), String::from("two")];
+    unsafe {
+        let last = words.as_mut_ptr().add(1);
+        ptr::drop_in_place(last);
+        ptr::write(last, "three".to_string());
+    }
+    let mut joined = String::new();
+    write!(joined, "{}-{:?}", words.join("+"), &squares[1..4]).unwrap();
+    assert_eq!(joined, "one+three-[1, 4, 9]");
+
+    let shapes: Vec<Box<dyn Shape>> = vec![Box::new(Square(3)), Box::new(Rect(2, 5))];
+    let areas = shapes.iter().map(|shape| shape.area()).collect::<Vec<_>>();
+    assert_eq!(areas, [9, 10]);
+
+    let mut counts = BTreeMap::new();
+    for word in "a b a c b a".split(' ') {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    assert_eq!(
+        counts.into_iter().collect::<Vec<_>>(),
+        [("a", 3), ("b", 2), ("c", 1)]
+    );
+
+    if std::env::args().count() > 1 {
+        panic!("asked to panic with {:?}", std::env::args().nth(1).unwrap());
+    }
+    println!("{}", joined);
+}
@@ -0,0 +1,22 @@
+#include <stdint.h>
+#include <stdio.h>
+
+uint32_t r2c2_has_avx2(void);
+uint32_t r2c2_has_sse2(void);
+uint32_t r2c2_has_avx512f(void);
+
+static int failures = 0;
+
+static void check(const char *what, uint32_t actual, uint32_t expected) {
+    if (actual != expected) {
+        fprintf(stderr, "%s: got %u, expected %u\n", what, actual, expected);
+        failures++;
+    }
+}
+
+int main(void) {
+    check("avx2 (enabled)", r2c2_has_avx2(), 1);
+    check("sse2 (implied)", r2c2_has_sse2(), 1);
+    check("avx512f (disabled)", r2c2_has_avx512f(), 0);
+    return failures != 0;
+}
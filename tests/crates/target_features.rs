@@ -0,0 +1,55 @@
+//! `cfg(target_feature)` as reported by the C compiler, compiled with
+//! `-C target-feature=+avx2`, and `#[target_feature]` functions.
+
+#![feature(no_core, lang_items)]
+#![no_core]
+
+#[lang = "sized"]
+trait Sized {}
+
+#[lang = "copy"]
+trait Copy {}
+
+impl Copy for u32 {}
+
+#[cfg(target_feature = "avx2")]
+#[no_mangle]
+pub extern "C" fn r2c2_has_avx2() -> u32 {
+    1
+}
+
+#[cfg(not(target_feature = "avx2"))]
+#[no_mangle]
+pub extern "C" fn r2c2_has_avx2() -> u32 {
+    0
+}
+
+#[cfg(target_feature = "sse2")]
+#[no_mangle]
+pub extern "C" fn r2c2_has_sse2() -> u32 {
+    1
+}
+
+#[cfg(not(target_feature = "sse2"))]
+#[no_mangle]
+pub extern "C" fn r2c2_has_sse2() -> u32 {
+    0
+}
+
+#[cfg(target_feature = "avx512f")]
+#[no_mangle]
+pub extern "C" fn r2c2_has_avx512f() -> u32 {
+    1
+}
+
+#[cfg(not(target_feature = "avx512f"))]
+#[no_mangle]
+pub extern "C" fn r2c2_has_avx512f() -> u32 {
+    0
+}
+
+#[no_mangle]
+#[target_feature(enable = "bmi2")]
+pub unsafe extern "C" fn r2c2_with_bmi2(x: u32) -> u32 {
+    x
+}